@@ -0,0 +1,48 @@
+// Times loading the game data, which is mostly planar decoding, and the decoding itself against the bit-at-a-time way
+// it was reworked from. Run with 'cargo bench --bench load --features reference'. planar.rs's tests check the outputs
+// are the same.
+
+use std::time::{Duration, Instant};
+
+use rusty_lemmings::lemmings::loader;
+use rusty_lemmings::lemmings::parsers::{planar, planar_reference};
+
+const RUNS: usize = 5;
+const SPECIAL_SECTION_PIXELS: usize = 960 * 40; // A quarter of a special level, the biggest thing that's decoded.
+
+// The best of a few runs, as that's the least affected by whatever else the machine is doing.
+fn time_best<T, F: FnMut() -> T>(mut run: F) -> Duration {
+    (0..RUNS).map(|_| {
+        let start = Instant::now();
+        std::hint::black_box(run());
+        start.elapsed()
+    }).min().unwrap_or_default()
+}
+
+fn made_up_bytes(count: usize) -> Vec<u8> {
+    let mut state: u32 = 1;
+    (0..count).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    }).collect()
+}
+
+fn main() {
+    println!("Best of {} runs:", RUNS);
+    if loader::load().is_ok_and(|games| games.lemmings.is_some()) {
+        let time = time_best(loader::load);
+        println!("  loading: {:.2}ms", time.as_secs_f64() * 1000.);
+    } else {
+        println!("  Couldn't find the Lemmings game data, so only timing the decoding");
+    }
+    let data = made_up_bytes(SPECIAL_SECTION_PIXELS * 3 / 8);
+    let palette = [0u32; 8];
+    let reference = time_best(|| planar_reference::decode(&data, 0, SPECIAL_SECTION_PIXELS, 3, &palette));
+    let reworked = time_best(|| planar::decode(&data, 0, SPECIAL_SECTION_PIXELS, 3, &palette));
+    println!("  decoding a special level section: reference {:.2}ms, reworked {:.2}ms, {:.2}x as fast",
+        reference.as_secs_f64() * 1000.,
+        reworked.as_secs_f64() * 1000.,
+        reference.as_secs_f64() / reworked.as_secs_f64());
+}
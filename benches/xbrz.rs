@@ -0,0 +1,45 @@
+// Times xbrz.rs against the straight port it was reworked from, on something the size of a typical level, which is the
+// biggest thing that gets scaled. Run with 'cargo bench --bench xbrz --features reference'. xbrz.rs's tests check the
+// outputs are the same.
+
+use std::time::{Duration, Instant};
+
+use rusty_lemmings::{xbrz, xbrz_reference};
+
+const FACTORS: [u8; 5] = [2, 3, 4, 5, 6];
+const RUNS: usize = 5;
+const WIDTH: usize = 1600;
+const HEIGHT: usize = 160;
+
+// The best of a few runs, as that's the least affected by whatever else the machine is doing.
+fn time_best<F: FnMut() -> Vec<u32>>(mut scale: F) -> Duration {
+    (0..RUNS).map(|_| {
+        let start = Instant::now();
+        std::hint::black_box(scale());
+        start.elapsed()
+    }).min().unwrap_or_default()
+}
+
+// Blocky shapes in a few colours, with some see-through, so there's plenty for xbrz to blend.
+fn made_up_level() -> Vec<u32> {
+    let palette = [0xff000000, 0x00000000, 0x8b4513ff, 0x228b22ff, 0xc0c0c0ff, 0x4169e1ff];
+    (0..WIDTH * HEIGHT).map(|i| {
+        let (x, y) = (i % WIDTH, i / WIDTH);
+        palette[((x / 7) * 31 + (y / 5) * 17 + (x * y) / 97) % palette.len()]
+    }).collect()
+}
+
+fn main() {
+    let level = made_up_level();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    println!("Scaling a made-up {}x{} level, best of {} runs, {} threads available:", WIDTH, HEIGHT, RUNS, threads);
+    for factor in FACTORS {
+        let reference = time_best(|| xbrz_reference::scale(factor, &level, WIDTH as u32, HEIGHT as u32));
+        let reworked = time_best(|| xbrz::scale(factor, &level, WIDTH as u32, HEIGHT as u32));
+        println!("  {}x: reference {:.1}ms, reworked {:.1}ms, {:.2}x as fast",
+            factor,
+            reference.as_secs_f64() * 1000.,
+            reworked.as_secs_f64() * 1000.,
+            reference.as_secs_f64() / reworked.as_secs_f64());
+    }
+}
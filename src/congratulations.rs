@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+use crate::fadeout::*;
+use crate::{GameTextures, GameState};
+use crate::menu_common::{spawn_menu_background, text_size, spawn_text};
+use crate::level_preview::LevelSelectionResource;
+use crate::level_selection_menu::MainMenuSkillSelection;
+use crate::lemmings::campaign::Campaign;
+use crate::lemmings::levels_per_game_and_skill::rating_name;
+use crate::lemmings::models::Game;
+
+// This is the screen after the last level of a rating, before moving on to the next rating.
+
+pub struct CongratulationsPlugin;
+
+impl Plugin for CongratulationsPlugin {
+	fn build(&self, app: &mut App) {
+		app.insert_resource(CompletedRatingResource::default());
+		app.add_systems((
+            spawn_background,
+            spawn_message,
+        ).in_schedule(OnEnter(GameState::Congratulations)));
+        app.add_systems((
+            button_system.run_if(screen_fade_is_not_transitioning),
+        ).in_set(OnUpdate(GameState::Congratulations)));
+        app.add_systems((
+            exit,
+        ).in_schedule(OnExit(GameState::Congratulations)));
+	}
+}
+
+#[derive(Component)]
+struct CongratulationsComponent;
+
+/// Filled in by the postview when the last level of a rating is won.
+#[derive(Resource, Default)]
+pub struct CompletedRatingResource {
+	pub rating: usize,
+	pub next_rating: Option<usize>, // None if that was the last one.
+}
+
+fn exit(
+    mut commands: Commands,
+    components: Query<Entity, With<CongratulationsComponent>>,
+) {
+    for e in components.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+// Onwards to the first level of the next rating, or back to the main menu if they've done them all.
+fn button_system(
+	mut fadeout: Fadeout,
+    mouse_buttons: Res<Input<MouseButton>>,
+	completed_rating: Res<CompletedRatingResource>,
+	game: Res<Game>,
+	mut level_selection: ResMut<LevelSelectionResource>,
+	mut skill_selection: ResMut<MainMenuSkillSelection>,
+) {
+    if !mouse_buttons.just_released(MouseButton::Left) { return }
+	let campaign = Campaign::for_game(&game);
+	let first_of_next = completed_rating.next_rating.and_then(|r| campaign.first_in_rating(r));
+	let Some(level) = first_of_next.and_then(|p| campaign.level(p)) else {
+		fadeout.start(GameState::MainMenu);
+		return
+	};
+	level_selection.level_key = level.key;
+	level_selection.level_name = level.name.clone();
+	level_selection.skill = completed_rating.next_rating.unwrap_or(0) as isize;
+	skill_selection.0 = level_selection.skill;
+	fadeout.start(GameState::LevelPreview);
+}
+
+fn spawn_background(
+	mut commands: Commands,
+	game_textures: Res<GameTextures>,
+) {
+	commands
+		.spawn(SpatialBundle::default())
+		.insert(CongratulationsComponent)
+		.with_children(|parent| {
+			spawn_menu_background(parent, &game_textures);
+		});
+}
+
+fn spawn_message(
+	mut commands: Commands,
+	game_textures: Res<GameTextures>,
+	completed_rating: Res<CompletedRatingResource>,
+	game: Res<Game>,
+) {
+	let mut text: Vec<String> = vec![
+		"Congratulations!".to_string(),
+		"".to_string(),
+		format!("You have completed every {} level.", rating_name(&game.id, completed_rating.rating)),
+		"".to_string(),
+	];
+	if let Some(next_rating) = completed_rating.next_rating {
+		text.push(format!("Now try the {} levels!", rating_name(&game.id, next_rating)));
+	} else {
+		text.push("You are a master Lemmings player.".to_string());
+	}
+	text.push("".to_string());
+	text.push("Press mouse button to continue".to_string());
+
+	let size = text_size();
+	let gap = (size / 2.).round();
+	let all_height = (size + gap) * ((text.len() - 1) as f32); // From center of topmost to center of bottom-most.
+	commands
+		.spawn(SpatialBundle::default())
+		.insert(CongratulationsComponent)
+		.with_children(|parent| {
+			for (i, t) in text.iter().enumerate() {
+				parent.spawn(SpatialBundle{
+					transform: Transform::from_xyz(0., all_height / 2. - ((i as f32) * (size + gap)), 2.),
+					..default()
+				}).with_children(|parent| {
+					spawn_text(t, parent, &game_textures);
+				});
+			}
+		});
+}
@@ -0,0 +1,158 @@
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use crate::json::json_string;
+use crate::lemmings::geometry::Rect;
+use crate::lemmings::loader;
+use crate::lemmings::models::{AnimationOrImage, Game, Mask, ObjectInfo, PaletteSource};
+use crate::lemmings::png::png_data;
+
+// This is the 'extract <dir>' command: it writes every sprite in the game data to PNGs, for modding and for checking
+// the parsers by eye. That's main.dat's animations, masks, fonts and menu images, every ground's terrain and objects,
+// and the special levels' backgrounds. Animations get a file per frame, named {asset}.{frame}.png.
+// Alongside goes manifest.json, listing each asset's size, frames, palette, files and for objects their trigger area.
+// Exits with 0 if all went well, 2 if the game data couldn't be loaded or the files couldn't be written.
+
+const MASK_COLOUR: u32 = 0xffffffff; // Masks come out as white where they take terrain away, see-through elsewhere.
+
+struct Extracted {
+    name: String,
+    kind: &'static str, // image, animation or mask.
+    width: usize,
+    height: usize,
+    palette: String,
+    files: Vec<String>,
+    trigger: Option<(Rect, u8)>, // The area and the effect id.
+}
+
+pub fn run(out_dir: &str) -> i32 {
+    let Some(game) = loader::load_lemmings() else { return 2 };
+    match extract(&game, out_dir) {
+        Ok(count) => {
+            println!("Extracted {} assets to {}", count, out_dir);
+            0
+        },
+        Err(e) => {
+            println!("Couldn't extract: {}", e);
+            2
+        },
+    }
+}
+
+fn extract(game: &Game, out_dir: &str) -> Result<usize> {
+    let dir = Path::new(out_dir);
+    fs::create_dir_all(dir)?;
+    let mut extracted = Vec::<Extracted>::new();
+
+    for asset in game.all_assets() {
+        let palette = asset.palette.describe();
+        extracted.push(match asset.content {
+            AnimationOrImage::Image(image) => write_image(dir, asset.name, palette, image.width, image.height, &image.bitmap)?,
+            AnimationOrImage::Animation(animation) => write_animation(dir, asset.name, palette, animation.width, animation.height, &animation.frames)?,
+        });
+    }
+
+    let masks = &game.main.masks;
+    for (name, mask) in [
+        ("bash_right", &masks.bash_right),
+        ("bash_left", &masks.bash_left),
+        ("mine_right", &masks.mine_right),
+        ("mine_left", &masks.mine_left),
+        ("explosion", &masks.explosion),
+    ] {
+        extracted.push(write_mask(dir, format!("mask.{}", name), mask)?);
+    }
+
+    for ground_id in game.index.ground_ids() {
+        let ground = game.index.ground(ground_id)?;
+        let palette = PaletteSource::Ground(ground_id).describe();
+        let mut terrain_ids: Vec<&i32> = ground.terrain_sprites.keys().collect();
+        terrain_ids.sort();
+        for id in terrain_ids {
+            let image = &ground.terrain_sprites[id];
+            let name = format!("ground{}.terrain.{}", ground_id, id);
+            extracted.push(write_image(dir, name, palette.clone(), image.width, image.height, &image.bitmap)?);
+        }
+        let mut object_ids: Vec<&i32> = ground.object_sprites.keys().collect();
+        object_ids.sort();
+        for id in object_ids {
+            let animation = &ground.object_sprites[id];
+            let name = format!("ground{}.object.{}", ground_id, id);
+            let mut entry = write_animation(dir, name, palette.clone(), animation.width, animation.height, &animation.frames)?;
+            entry.trigger = ground.ground.object_info.get(*id as usize).and_then(trigger_of);
+            extracted.push(entry);
+        }
+    }
+
+    for special_id in game.index.special_ids() {
+        let image = game.index.special(special_id)?;
+        let name = format!("special.{}", special_id);
+        let palette = PaletteSource::Special(special_id).describe();
+        extracted.push(write_image(dir, name, palette, image.width, image.height, &image.bitmap)?);
+    }
+
+    fs::write(dir.join("manifest.json"), manifest(game, &extracted))?;
+    Ok(extracted.len())
+}
+
+// Objects that do nothing when touched have no trigger area worth listing.
+pub fn trigger_of(info: &ObjectInfo) -> Option<(Rect, u8)> {
+    if !info.is_valid() || info.trigger_effect_id == 0 { return None }
+    Some((info.trigger_rect(), info.trigger_effect_id))
+}
+
+fn write_png(dir: &Path, file_name: &str, width: usize, height: usize, pixels: &[u32]) -> Result<()> {
+    fs::write(dir.join(file_name), png_data(width as u32, height as u32, pixels))
+}
+
+fn write_image(dir: &Path, name: String, palette: String, width: usize, height: usize, pixels: &[u32]) -> Result<Extracted> {
+    let file_name = format!("{}.png", name);
+    write_png(dir, &file_name, width, height, pixels)?;
+    Ok(Extracted { name, kind: "image", width, height, palette, files: vec![file_name], trigger: None })
+}
+
+fn write_animation(dir: &Path, name: String, palette: String, width: usize, height: usize, frames: &[Vec<u32>]) -> Result<Extracted> {
+    let mut files = Vec::<String>::with_capacity(frames.len());
+    for (i, frame) in frames.iter().enumerate() {
+        let file_name = format!("{}.{}.png", name, i);
+        write_png(dir, &file_name, width, height, frame)?;
+        files.push(file_name);
+    }
+    Ok(Extracted { name, kind: "animation", width, height, palette, files, trigger: None })
+}
+
+fn write_mask(dir: &Path, name: String, mask: &Mask) -> Result<Extracted> {
+    let (width, height) = (mask.width as usize, mask.height as usize);
+    let mut files = Vec::<String>::with_capacity(mask.frames.len());
+    for (i, frame) in mask.frames.iter().enumerate() {
+        let pixels: Vec<u32> = frame.iter().map(|&m| if m != 0 { MASK_COLOUR } else { 0 }).collect();
+        let file_name = format!("{}.{}.png", name, i);
+        write_png(dir, &file_name, width, height, &pixels)?;
+        files.push(file_name);
+    }
+    Ok(Extracted { name, kind: "mask", width, height, palette: "mask".to_string(), files, trigger: None })
+}
+
+fn manifest(game: &Game, extracted: &[Extracted]) -> String {
+    let assets: Vec<String> = extracted.iter().map(|e| {
+        let files: Vec<String> = e.files.iter().map(|f| json_string(f)).collect();
+        let trigger = match &e.trigger {
+            Some((rect, effect)) => format!(
+                ",\"trigger\":{{\"x\":{},\"y\":{},\"width\":{},\"height\":{},\"effect\":{}}}",
+                rect.x, rect.y, rect.width, rect.height, effect),
+            None => String::new(),
+        };
+        format!(
+            "    {{\"name\":{},\"kind\":{},\"width\":{},\"height\":{},\"frames\":{},\"palette\":{},\"files\":[{}]{}}}",
+            json_string(&e.name),
+            json_string(e.kind),
+            e.width,
+            e.height,
+            e.files.len(),
+            json_string(&e.palette),
+            files.join(","),
+            trigger,
+        )
+    }).collect();
+    format!("{{\n  \"game\":{},\n  \"assets\":[\n{}\n  ]\n}}\n", json_string(&game.id), assets.join(",\n"))
+}
@@ -1,4 +1,5 @@
 // Based on: github.com/mwbryant/rpg-bevy-tutorial
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use crate::{GameState, GameTextures};
 
@@ -58,12 +59,35 @@ fn fadeout(
     }
 }
 
+// For systems that only need commands to fade out, so they can ask for this rather than for what fading needs.
+#[derive(SystemParam)]
+pub struct Fadeout<'w, 's> {
+    commands: Commands<'w, 's>,
+    game_textures: Res<'w, GameTextures>,
+    is_transitioning: ResMut<'w, ScreenFadeIsTransitioning>,
+}
+
+impl<'w, 's> Fadeout<'w, 's> {
+    pub fn start(&mut self, next_state: GameState) {
+        spawn_fadeout(&mut self.commands, next_state, &self.game_textures, &mut self.is_transitioning);
+    }
+}
+
 // TODO consider the pros and cons of making fadeouts happen using Events rather than calling this directly.
 pub fn create_fadeout(
     commands: &mut Commands,
     next_state: GameState,
     game_textures: &Res<GameTextures>,
     mut is_transitioning: ResMut<ScreenFadeIsTransitioning>,
+) {
+    spawn_fadeout(commands, next_state, game_textures, &mut is_transitioning);
+}
+
+fn spawn_fadeout(
+    commands: &mut Commands,
+    next_state: GameState,
+    game_textures: &GameTextures,
+    is_transitioning: &mut ScreenFadeIsTransitioning,
 ) {
     // Stop eg mouse clicks while transitioning.
     is_transitioning.0 = true;
@@ -1,8 +1,10 @@
 use bevy::prelude::*;
 use bevy::math::Rect;
 use bevy::render::render_resource::Extent3d;
-use crate::xbrz;
-use crate::{SCALE, SCALE_A, SCALE_B};
+use bevy::render::texture::ImageSampler;
+use crate::scaler::ScalerKind;
+use crate::scale_cache::{self, ScaleKey};
+use crate::scale;
 use crate::lemmings::models::Animation;
 
 /// Convert our lemmings images into bevy compatible ones.
@@ -37,12 +39,13 @@ fn add_1_margin(image: &[u32], width: usize, height: usize) -> (Vec<u32>, usize,
 }
 
 fn remove_scale_margin(image: &[u32], width: usize, height: usize) -> Vec<u32> {
-    let smaller_width = width - 2 * SCALE;
-    let smaller_height = height - 2 * SCALE;
+    let scale = scale();
+    let smaller_width = width - 2 * scale;
+    let smaller_height = height - 2 * scale;
     let smaller_pixels = smaller_width * smaller_height;
     let mut smaller_image = Vec::<u32>::with_capacity(smaller_pixels);
     smaller_image.resize(smaller_pixels, 0);
-    let mut in_offset: usize = width * SCALE + SCALE;
+    let mut in_offset: usize = width * scale + scale;
     let mut out_offset: usize = 0;
     for _y in 0..smaller_height {
         for _x in 0..smaller_width {
@@ -50,7 +53,7 @@ fn remove_scale_margin(image: &[u32], width: usize, height: usize) -> Vec<u32> {
             out_offset += 1;
             in_offset += 1;
         }
-        in_offset += 2 * SCALE;
+        in_offset += 2 * scale;
     }
     smaller_image
 }
@@ -58,22 +61,31 @@ fn remove_scale_margin(image: &[u32], width: usize, height: usize) -> Vec<u32> {
 // Multi-step scale-up.
 // should_add_then_remove_margin removes artifacts from sprites (eg not things that are expected to tile) where they don't
 // 'round off' near the edge properly.
-pub fn multi_scale(image: &[u32], width: usize, height: usize, should_add_then_remove_margin: bool) -> Vec<u32> {
+pub fn multi_scale(image: &[u32], width: usize, height: usize, should_add_then_remove_margin: bool, scaler: ScalerKind) -> Vec<u32> {
     if should_add_then_remove_margin {
         let (image_with_margin, margin_width, margin_height) = add_1_margin(image, width, height);
-        let scaled = multi_scale(&image_with_margin, margin_width, margin_height, false);
-        return remove_scale_margin(&scaled, margin_width * SCALE, margin_height * SCALE);
+        let scaled = multi_scale(&image_with_margin, margin_width, margin_height, false, scaler);
+        return remove_scale_margin(&scaled, margin_width * scale(), margin_height * scale());
     }
-    let bigger = xbrz::scale(SCALE_A as u8, image, width as u32, height as u32);
-    xbrz::scale(SCALE_B as u8, &bigger, (width * SCALE_A) as u32, (height * SCALE_A) as u32)
+    scaler.scaler().scale(scale(), image, width, height)
+}
+
+// Same as multi_scale, but goes via the disk cache. Not worth it for small patches that'll never be seen again.
+pub fn multi_scale_cached(image: &[u32], width: usize, height: usize, should_add_then_remove_margin: bool, scaler: ScalerKind) -> Vec<u32> {
+    let kind = if should_add_then_remove_margin { "margin" } else { "plain" };
+    let key = ScaleKey::new(scaler, kind, width, height, &[image]);
+    scale_cache::get_or_scale(scaler, key, width * scale() * height * scale(), || {
+        multi_scale(image, width, height, should_add_then_remove_margin, scaler)
+    })
 }
 
 pub fn make_image(
     image: &crate::lemmings::models::Image,
     images: &mut ResMut<Assets<Image>>,
     should_add_then_remove_margin: bool,
+    scaler: ScalerKind,
 ) -> Handle<Image> {
-    make_image_from_bitmap(&image.bitmap, image.width, image.height, images, should_add_then_remove_margin)
+    make_image_from_bitmap(&image.bitmap, image.width, image.height, images, should_add_then_remove_margin, scaler)
 }
 
 pub fn make_image_from_bitmap(
@@ -82,28 +94,25 @@ pub fn make_image_from_bitmap(
     height: usize,
     images: &mut ResMut<Assets<Image>>,
     should_add_then_remove_margin: bool,
+    scaler: ScalerKind,
 ) -> Handle<Image> {
-    let scaled = multi_scale(bitmap, width, height, should_add_then_remove_margin);
-    let u8_data = u32_to_rgba_u8(&scaled);
-    let image = Image::new(Extent3d{width: (width * SCALE) as u32, height: (height * SCALE) as u32, depth_or_array_layers: 1},
-        bevy::render::render_resource::TextureDimension::D2,
-        u8_data,
-        bevy::render::render_resource::TextureFormat::Rgba8UnormSrgb);
-    let image_handle = images.add(image);
-    image_handle
+    let scaled = multi_scale_cached(bitmap, width, height, should_add_then_remove_margin, scaler);
+    images.add(bevy_image(&scaled, width * scale(), height * scale()))
 }
 
 pub fn make_image_unscaled(
     image: &crate::lemmings::models::Image,
     images: &mut ResMut<Assets<Image>>,
 ) -> Handle<Image> {
-    let u8_data = u32_to_rgba_u8(&image.bitmap);
-    let image = Image::new(Extent3d{width: image.width as u32, height: image.height as u32, depth_or_array_layers: 1},
+    images.add(bevy_image(&image.bitmap, image.width, image.height))
+}
+
+pub fn bevy_image(bitmap: &[u32], width: usize, height: usize) -> Image {
+    let u8_data = u32_to_rgba_u8(bitmap);
+    Image::new(Extent3d{width: width as u32, height: height as u32, depth_or_array_layers: 1},
         bevy::render::render_resource::TextureDimension::D2,
         u8_data,
-        bevy::render::render_resource::TextureFormat::Rgba8UnormSrgb);
-    let image_handle = images.add(image);
-    image_handle
+        bevy::render::render_resource::TextureFormat::Rgba8UnormSrgb)
 }
 
 // Figure out a neat way to layout the grid.
@@ -115,59 +124,94 @@ fn cols_rows_for_frames(frame_count: usize) -> (usize, usize) {
     (cols, rows)
 }
 
+// Where each frame goes in an atlas. Worked out up front so the atlas can be made before its pixels are ready.
+pub struct AtlasLayout {
+    pub width: usize,
+    pub height: usize,
+    pub frame_width: usize,
+    pub frame_height: usize,
+    pub rects: Vec<Rect>,
+}
+
+impl AtlasLayout {
+    pub fn new(frame_count: usize, frame_width: usize, frame_height: usize) -> AtlasLayout {
+        let (cols, rows) = cols_rows_for_frames(frame_count);
+        let width = (frame_width + 2) * cols - 2; // 2px gap between each (1px still leaves artifacts).
+        let height = (frame_height + 2) * rows - 2;
+        let mut rects = Vec::<Rect>::with_capacity(frame_count);
+        for index in 0..frame_count {
+            let x = (index % cols) * (frame_width + 2);
+            let y = (index / cols) * (frame_height + 2);
+            rects.push(Rect { 
+                min: Vec2 { x: x as f32, y: y as f32 },
+                max: Vec2 { x: (x + frame_width) as f32, y: (y + frame_height) as f32 } });
+        }
+        AtlasLayout { width, height, frame_width, frame_height, rects }
+    }
+
+    pub fn for_scaled_animation(animation: &Animation) -> AtlasLayout {
+        AtlasLayout::new(animation.frames.len(), animation.width * scale(), animation.height * scale())
+    }
+
+    // Copies the frames into their places.
+    pub fn pack(&self, frames: &[Vec<u32>]) -> Vec<u32> {
+        let mut atlas = vec![0; self.width * self.height];
+        for (frame, rect) in frames.iter().zip(&self.rects) {
+            let start_atlas_x = rect.min.x as usize;
+            let start_atlas_y = rect.min.y as usize;
+            for (frame_y, frame_row) in frame.chunks_exact(self.frame_width).take(self.frame_height).enumerate() {
+                let atlas_start = (start_atlas_y + frame_y) * self.width + start_atlas_x;
+                atlas[atlas_start..atlas_start + self.frame_width].copy_from_slice(frame_row);
+            }
+        }
+        atlas
+    }
+
+    pub fn make_atlas(&self, image_handle: Handle<Image>) -> TextureAtlas {
+        let mut ta = TextureAtlas::new_empty(image_handle, Vec2::new(self.width as f32, self.height as f32));    
+        for rect in &self.rects {
+            ta.add_texture(*rect);
+        }
+        ta
+    }
+}
+
+// The slow part of make_atlas_from_animation, which is safe to run on another thread.
+pub fn scale_animation_to_atlas(animation: &Animation, layout: &AtlasLayout, should_add_then_remove_margin: bool, scaler: ScalerKind) -> Vec<u32> {
+    // The whole atlas is cached as one, rather than a file per frame.
+    let kind = if should_add_then_remove_margin { "atlas margin" } else { "atlas plain" };
+    let frames: Vec<&[u32]> = animation.frames.iter().map(|f| f.as_slice()).collect();
+    let key = ScaleKey::new(scaler, kind, animation.width, animation.height, &frames);
+    scale_cache::get_or_scale(scaler, key, layout.width * layout.height, || {
+        let scaled_frames: Vec<Vec<u32>> = animation.frames.iter()
+            .map(|frame| multi_scale(frame, animation.width, animation.height, should_add_then_remove_margin, scaler))
+            .collect();
+        layout.pack(&scaled_frames)
+    })
+}
+
 pub fn make_atlas_from_animation(
     animation: &Animation,
     images: &mut ResMut<Assets<Image>>,
 	texture_atlases: &mut ResMut<Assets<TextureAtlas>>,
     should_add_then_remove_margin: bool,
+    scaler: ScalerKind,
 ) -> Handle<TextureAtlas> {
-    let (cols, rows) = cols_rows_for_frames(animation.frames.len());
-    let scaled_width = animation.width * SCALE;
-    let scaled_height = animation.height * SCALE;
-    let atlas_width = (scaled_width + 2) * cols - 2; // 2px gap between each (1px still leaves artifacts).
-    let atlas_height = (scaled_height + 2) * rows - 2;
-    let atlas_pixels = atlas_width * atlas_height;
-    let mut atlas = Vec::<u32>::with_capacity(atlas_pixels);
-    atlas.resize(atlas_pixels, 0);
-    let mut col: usize = 0;
-    let mut row: usize = 0;
-    let mut sprite_rects = Vec::<Rect>::with_capacity(animation.frames.len());
-    for small_frame in &animation.frames {
-        let scaled_frame = multi_scale(small_frame, animation.width, animation.height, should_add_then_remove_margin);
-        let start_atlas_x = col * (scaled_width + 2);
-        let mut atlas_y = row * (scaled_height + 2);
-
-        sprite_rects.push(Rect { 
-            min: Vec2 { x: start_atlas_x as f32, y: atlas_y as f32 },
-            max: Vec2 { x: (start_atlas_x + scaled_width) as f32, y: (atlas_y + scaled_height) as f32 } });
-
-        for frame_y in 0..scaled_height {
-            let mut atlas_x = start_atlas_x;
-            for frame_x in 0..scaled_width {
-                atlas[atlas_y * atlas_width + atlas_x] = scaled_frame[frame_y * scaled_width + frame_x];
-                atlas_x += 1;
-            }
-            atlas_y += 1;
-        }
+    let layout = AtlasLayout::for_scaled_animation(animation);
+    let atlas = scale_animation_to_atlas(animation, &layout, should_add_then_remove_margin, scaler);
+    let image_handle = images.add(bevy_image(&atlas, layout.width, layout.height));
+    texture_atlases.add(layout.make_atlas(image_handle))
+}
 
-        // Move to the next slot.
-        col += 1;
-        if col >= cols {
-            col = 0;
-            row += 1;
-        }
-    }
-    // Convert it for bevy now.
-    let u8_data = u32_to_rgba_u8(&atlas);
-    let image = Image::new(Extent3d{width: atlas_width as u32, height: atlas_height as u32, depth_or_array_layers: 1},
-        bevy::render::render_resource::TextureDimension::D2,
-        u8_data,
-        bevy::render::render_resource::TextureFormat::Rgba8UnormSrgb);
+// For when it's needed straight away, eg the loading screen's font. Nearest neighbour so it stays crisp.
+pub fn make_atlas_from_animation_unscaled(
+    animation: &Animation,
+    images: &mut ResMut<Assets<Image>>,
+	texture_atlases: &mut ResMut<Assets<TextureAtlas>>,
+) -> Handle<TextureAtlas> {
+    let layout = AtlasLayout::new(animation.frames.len(), animation.width, animation.height);
+    let mut image = bevy_image(&layout.pack(&animation.frames), layout.width, layout.height);
+    image.sampler_descriptor = ImageSampler::nearest();
     let image_handle = images.add(image);
-    let mut ta = TextureAtlas::new_empty(image_handle, Vec2::new(atlas_width as f32, atlas_height as f32));    
-    for rect in sprite_rects {
-        ta.add_texture(rect);
-    }
-    let ta_handle = texture_atlases.add(ta);
-    ta_handle
-}
\ No newline at end of file
+    texture_atlases.add(layout.make_atlas(image_handle))
+}
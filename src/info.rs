@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::io::Result;
+use crate::json::json_string;
+use crate::lemmings::level_renderer::size_of_level;
+use crate::lemmings::levels_per_game_and_skill::{level_keys_per_game_and_skill, rating_name, ratings_per_game};
+use crate::lemmings::loader;
+use crate::lemmings::models::{Game, Ground, Level, ObjectInfo, Palettes, Skills, TerrainInfo};
+
+// This is the 'info --json [--game id]' command: it prints everything the parsers make of the installed games as JSON,
+// so other tooling, eg the website's generator, can read it rather than parse the DAT and LVL files itself:
+//   games[]: id, name, path, then
+//     levels[]: the index key, name, rating (name, number from 1, and place in it from 1, or null if it's in none),
+//       globals and skills as in the file, the object, terrain and steel counts, the ground and special (or null) it
+//       uses, and min_x and width in game points of the terrain, as rendered.
+//     grounds[]: id, the valid entries of the object and terrain info tables with their ids, and the palettes, both as
+//       in the file and as the 16 0xRRGGBBAA colours the game draws with.
+// Without --json it prints a line per game. Exits with 0 if all went well, 2 if the game data couldn't be loaded.
+
+struct Options {
+    is_json: bool,
+    game_id: Option<String>,
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_options(args) {
+        Ok(options) => options,
+        Err(message) => {
+            println!("{}", message);
+            return 2
+        },
+    };
+    let games = match loader::load() {
+        Ok(games) => games,
+        Err(e) => {
+            println!("Couldn't load the game data: {}", e);
+            return 2
+        },
+    };
+    let games: Vec<&Game> = games.as_vec().into_iter().filter(|g| options.game_id.as_ref().is_none_or(|id| *id == g.id)).collect();
+    if games.is_empty() {
+        println!("Couldn't find the game data");
+        return 2
+    }
+    if !options.is_json {
+        for game in games {
+            println!("{} ({}): {} levels, {} grounds, {} specials, in {}",
+                game.name, game.id, game.index.level_keys().len(), game.index.ground_ids().len(), game.index.special_ids().len(), game.path);
+        }
+        return 0
+    }
+    match games.iter().map(|game| game_json(game)).collect::<Result<Vec<String>>>() {
+        Ok(games) => {
+            println!("{{\"games\":[\n{}\n]}}", games.join(",\n"));
+            0
+        },
+        Err(e) => {
+            println!("Couldn't read the game data: {}", e);
+            2
+        },
+    }
+}
+
+fn parse_options(args: &[String]) -> std::result::Result<Options, String> {
+    let mut options = Options { is_json: false, game_id: None };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--json" => options.is_json = true,
+            "--game" => options.game_id = Some(value()?),
+            _ => return Err(format!("Unexpected {}\nUsage: info [--json] [--game id]", arg)),
+        }
+    }
+    Ok(options)
+}
+
+// Level key to its rating and place in it, from 0, from the slots the game plays them in.
+fn level_ratings(game: &Game) -> HashMap<i32, (usize, usize)> {
+    (0..ratings_per_game(&game.id))
+        .flat_map(|rating| level_keys_per_game_and_skill(game, rating as isize).into_iter().enumerate().map(move |(i, key)| (key, (rating, i))))
+        .collect()
+}
+
+fn game_json(game: &Game) -> Result<String> {
+    let ratings = level_ratings(game);
+    let mut levels = Vec::<String>::new();
+    for key in game.index.level_keys() {
+        let level = game.index.level(key)?;
+        levels.push(level_json(game, key, &level, ratings.get(&key).copied())?);
+    }
+    let mut grounds = Vec::<String>::new();
+    for id in game.index.ground_ids() {
+        grounds.push(ground_json(id, &game.index.ground(id)?.ground));
+    }
+    Ok(format!("{{\"id\":{},\"name\":{},\"path\":{},\n\"levels\":[\n{}\n],\n\"grounds\":[\n{}\n]}}",
+        json_string(&game.id),
+        json_string(&game.name),
+        json_string(&game.path),
+        levels.join(",\n"),
+        grounds.join(",\n")))
+}
+
+fn level_json(game: &Game, key: i32, level: &Level, rating: Option<(usize, usize)>) -> Result<String> {
+    let globals = &level.globals;
+    let rating = match rating {
+        Some((rating, i)) => format!("{{\"name\":{},\"number\":{},\"position\":{}}}", json_string(rating_name(&game.id, rating)), rating + 1, i + 1),
+        None => "null".to_string(),
+    };
+    let special = if globals.extended_graphic_set == 0 { "null".to_string() } else { (globals.extended_graphic_set - 1).to_string() };
+    let size = size_of_level(level, &*game.ground_for(level)?);
+    Ok(format!(
+        "{{\"key\":{},\"name\":{},\"rating\":{},\"release_rate\":{},\"num_of_lemmings\":{},\"num_to_rescue\":{},\"time_limit\":{},\"skills\":{},\"start_screen_xpos\":{},\"objects\":{},\"terrain\":{},\"steel\":{},\"ground\":{},\"special\":{},\"min_x\":{},\"width\":{}}}",
+        key,
+        json_string(level.name.trim()),
+        rating,
+        globals.release_rate,
+        globals.num_of_lemmings,
+        globals.num_to_rescue,
+        globals.time_limit,
+        skills_json(&globals.skills),
+        globals.start_screen_xpos,
+        level.objects.len(),
+        level.terrain.len(),
+        level.steel.len(),
+        globals.normal_graphic_set,
+        special,
+        size.min_x,
+        size.width(),
+    ))
+}
+
+fn skills_json(skills: &Skills) -> String {
+    format!("{{\"climbers\":{},\"floaters\":{},\"bombers\":{},\"blockers\":{},\"builders\":{},\"bashers\":{},\"miners\":{},\"diggers\":{}}}",
+        skills.climbers, skills.floaters, skills.bombers, skills.blockers, skills.builders, skills.bashers, skills.miners, skills.diggers)
+}
+
+fn ground_json(id: i32, ground: &Ground) -> String {
+    let objects: Vec<String> = ground.object_info.iter().enumerate().filter(|(_, info)| info.is_valid()).map(|(i, info)| object_info_json(i, info)).collect();
+    let terrain: Vec<String> = ground.terrain_info.iter().enumerate().filter(|(_, info)| info.is_valid()).map(|(i, info)| terrain_info_json(i, info)).collect();
+    format!("{{\"id\":{},\n\"objects\":[\n{}\n],\n\"terrain\":[\n{}\n],\n\"palettes\":{}}}",
+        id,
+        objects.join(",\n"),
+        terrain.join(",\n"),
+        palettes_json(&ground.palettes))
+}
+
+fn object_info_json(id: usize, info: &ObjectInfo) -> String {
+    format!(
+        "{{\"id\":{},\"is_exit\":{},\"is_entrance\":{},\"animation_flags\":{},\"start_animation_frame_index\":{},\"frame_count\":{},\"width\":{},\"height\":{},\"animation_frame_data_size\":{},\"mask_offset_from_image\":{},\"trigger_left\":{},\"trigger_top\":{},\"trigger_width\":{},\"trigger_height\":{},\"trigger_effect_id\":{},\"animation_frames_base_loc\":{},\"preview_image_index\":{},\"trap_sound_effect_id\":{}}}",
+        id,
+        info.is_exit,
+        info.is_entrance,
+        info.animation_flags,
+        info.start_animation_frame_index,
+        info.frame_count,
+        info.width,
+        info.height,
+        info.animation_frame_data_size,
+        info.mask_offset_from_image,
+        info.trigger_left,
+        info.trigger_top,
+        info.trigger_width,
+        info.trigger_height,
+        info.trigger_effect_id,
+        info.animation_frames_base_loc,
+        info.preview_image_index,
+        info.trap_sound_effect_id,
+    )
+}
+
+fn terrain_info_json(id: usize, info: &TerrainInfo) -> String {
+    format!("{{\"id\":{},\"width\":{},\"height\":{},\"image_loc\":{},\"mask_loc\":{}}}", id, info.width, info.height, info.image_loc, info.mask_loc)
+}
+
+fn numbers_json<T: ToString>(numbers: &[T]) -> String {
+    let numbers: Vec<String> = numbers.iter().map(|n| n.to_string()).collect();
+    format!("[{}]", numbers.join(","))
+}
+
+fn palettes_json(palettes: &Palettes) -> String {
+    let rgba: Vec<String> = palettes.as_rgba().iter().map(|c| format!("\"{:08x}\"", c)).collect();
+    format!("{{\"ega_custom\":{},\"ega_standard\":{},\"ega_preview\":{},\"vga_custom\":{},\"vga_standard\":{},\"vga_preview\":{},\"rgba\":[{}]}}",
+        numbers_json(&palettes.ega_custom),
+        numbers_json(&palettes.ega_standard),
+        numbers_json(&palettes.ega_preview),
+        numbers_json(&palettes.vga_custom),
+        numbers_json(&palettes.vga_standard),
+        numbers_json(&palettes.vga_preview),
+        rgba.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "Lemmings 1's odd-table levels have no rating until the slots they go in are known, see levels_per_game_and_skill.rs"]
+    fn every_level_has_a_rating() {
+        let Ok(games) = loader::load() else { return }; // Only checked where there's game data.
+        for game in games {
+            let ratings = level_ratings(&game);
+            for key in game.index.level_keys() {
+                assert!(ratings.contains_key(&key), "{} level {}", game.id, key);
+            }
+        }
+    }
+}
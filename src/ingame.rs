@@ -1,21 +1,39 @@
 use std::time::Duration;
 use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::{Arc, Mutex};
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
 use bevy::render::render_resource::Extent3d;
-use crate::{GameTextures, GameState, TEXTURE_SCALE, SCALE, POINT_SIZE, FPS};
-use crate::lemmings::models::{Game, ObjectInfo};
+use bevy::tasks::AsyncComputeTaskPool;
+use crate::{GameTextures, GameState, texture_scale, scale, POINT_SIZE, FPS};
+use crate::lemmings::models::{Animation, Game, GroundCombined, LemmingAnimations, Level, ObjectInfo, ObjectModifier};
+use crate::lemmings::compositor::{self, Scene, SceneLemming, SceneObject, ScenePanel};
 use crate::level_preview::LevelSelectionResource;
 use crate::lemmings::level_renderer;
-use crate::helpers::{multi_scale, u32_to_rgba_u8};
+use crate::lemmings::terrain_mask::TerrainMask;
+use crate::lemmings::dirty_rects::DirtyRects;
+use crate::lemmings::geometry::Rect;
+use crate::lemmings::lemming_grid::LemmingGrid;
+use crate::helpers::{multi_scale, multi_scale_cached, u32_to_rgba_u8};
 use crate::helpers::{make_image_from_bitmap, make_atlas_from_animation};
 use crate::{ORIGINAL_GAME_W, FRAME_DURATION};
 use crate::lemmings::sizes;
 use crate::mouse_cursor::{MouseCursorShouldBecomeSelectorEvent, update_mouse_cursor_style_system, reset_mouse_cursor_system};
+use crate::fadeout::{screen_fade_is_not_transitioning, Fadeout};
+use crate::postview::LevelResultResource;
+use crate::settings::Settings;
+use crate::screen::{Cursor, ScreenLayout, CANVAS_H};
+use crate::replay::{Replay, ReplayAction, ReplayActionKind};
 
 const DROP_POINTS_PER_FRAME: f32 = 2.;
 const LEMMING_NOMINAL_HEIGHT_HALF: i32 = 5; // Usual height for a lemming sprite in game points. Halved for use later.
+const BLOCKER_REACH: i32 = 6; // How close a walker gets to a blocker before turning around, in game points.
 const LEMMING_WIDTH_FOR_BASE: f32 = 3.; // How many points under it to check to see if any land exists.
+const TRIGGER_EFFECT_EXIT: u8 = 1;
+const DIG_HALF_WIDTH: i32 = 4; // A digger's hole is this many points either side of it.
+const DIG_FRAMES_PER_ROW: usize = 8; // Diggers dig a row twice per cycle of their animation.
 
 pub struct InGamePlugin;
 
@@ -56,6 +74,16 @@ impl SkillPanelSelection {
             _ => None,
         }
     }
+
+    // The skills update_lemmings can play so far. The others can be picked on the panel, but not given to a lemming.
+    fn is_playable(self) -> bool {
+        matches!(self, SkillPanelSelection::Block | SkillPanelSelection::DigVertical)
+    }
+}
+
+// For replays, which name skills by their index.
+pub fn is_playable_skill_index(skill_index: isize) -> bool {
+    SkillPanelSelection::from_index(skill_index).is_some_and(|skill| skill.is_playable())
 }
 
 /// Resource.
@@ -70,7 +98,21 @@ struct InGameDropCountdown(i32); // Countdown between dropping lemmings. -1 if h
 #[derive(Resource)]
 struct InGameLemmingsContainerId(Entity); // The entity id of the lemmings container.
 #[derive(Resource)]
-struct InGameSlices(Option<Slices>);
+struct InGameTerrainMask(Option<TerrainMask>); // What the lemmings collide with.
+#[derive(Resource, Default)]
+struct InGameLevel(Option<(Arc<Level>, Arc<GroundCombined>)>); // What's being played and its ground, looked up once in enter.
+#[derive(Resource, Default)]
+struct InGameLemmingGrid(LemmingGrid<GridLemming>); // Where the lemmings are, for picking and for them to find each other.
+#[derive(Resource, Default)]
+struct InGameTerrain { // What's displayed, and what's needed to redraw it when it changes.
+    bitmap: Vec<u32>, // The unscaled render.
+    width: usize,
+    height: usize,
+    min_x: isize, // Game point x of the bitmap's first column.
+    slices: Vec<Slice>,
+    dirty: DirtyRects, // Changed areas still to be re-scaled.
+    rescale_job: Option<RescaleJob>,
+}
 #[derive(Resource)]
 struct InGameBottomPanelId(Entity); // The id of the skill selection / map panel.
 #[derive(Resource)]
@@ -91,6 +133,33 @@ struct InGameSkillCounts(HashMap<SkillPanelSelection, isize>);
 struct InGameReleaseRate(isize); // Current release rate 0-99.
 #[derive(Resource)]
 struct InGameIsPaused(bool);
+#[derive(Resource, Default)]
+pub struct InGameLemmingCounts {
+    pub to_drop: i32, // How many the level starts with.
+    pub dropped: i32,
+    pub saved: i32,
+    pub died: i32,
+    pub skills_used: i32,
+    pub frames: i32, // Game frames played, for timing the level.
+}
+#[derive(Resource)]
+pub struct InGameReplayPlayback(pub Option<Replay>); // If set, its actions are played out as the level runs.
+#[derive(Resource, Default)]
+struct InGameReplayRecording(Replay); // What the player has done so far this level.
+/// Insert this to step one game frame per update instead of going by the clock, eg when running headless.
+#[derive(Resource)]
+pub struct InGameFixedFrameStep;
+
+/// Insert this to have every game frame drawn on the CPU too, see compositor.rs, eg for replay videos.
+#[derive(Resource, Default)]
+pub struct InGameCpuFrame {
+    pub is_full_width: bool, // The whole level, rather than the screen's worth that's scrolled to.
+    pub image: Option<crate::lemmings::models::Image>, // The latest frame, for whoever's using it to take.
+}
+
+/// The per-frame game systems, so others can run before or after them.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct InGameUpdateSet;
 
 // Even though we refer to some entities by Id, we have to give them components so bevy doesn't panic when
 // querying 2+ of them in the one func.
@@ -117,7 +186,10 @@ impl Plugin for InGamePlugin {
         app.insert_resource(InGameStartCountdown(FPS as i32));
         app.insert_resource(InGameDropCountdown(-1));
         app.insert_resource(InGameLemmingsContainerId(Entity::from_raw(0)));
-        app.insert_resource(InGameSlices(None));
+        app.insert_resource(InGameTerrainMask(None));
+        app.init_resource::<InGameLevel>();
+        app.init_resource::<InGameLemmingGrid>();
+        app.insert_resource(InGameTerrain::default());
         app.insert_resource(InGameBottomPanelId(Entity::from_raw(0)));
         app.insert_resource(InGamePanelDigits(vec![]));
         app.insert_resource(InGameSkillSelectionIndicatorId(Entity::from_raw(0)));
@@ -128,6 +200,9 @@ impl Plugin for InGamePlugin {
         app.insert_resource(InGameSkillCounts(HashMap::new()));
         app.insert_resource(InGameReleaseRate(50));
         app.insert_resource(InGameIsPaused(false));
+        app.insert_resource(InGameLemmingCounts::default());
+        app.insert_resource(InGameReplayPlayback(None));
+        app.insert_resource(InGameReplayRecording::default());
         app.add_event::<UpdatePanelDigitsEvent>();
         app.add_event::<LemmingUnderPointerEvent>();
 
@@ -140,12 +215,13 @@ impl Plugin for InGamePlugin {
         // so i'm not sure how to reproduce ordered groups of parallel systems. Perhaps, efficiency-wise, it
         // doesn't matter.
         app.add_systems((
-            tick,            
+            tick,
             scroll, determine_lemming_under_mouse_system,
-            mouse_click_system, do_countdown, drop_lemmings,
-            update_objects, update_lemmings,
-            update_panel_digits_system, update_mouse_cursor_style_system,
-        ).chain().in_set(OnUpdate(GameState::InGame)));
+            mouse_click_system, play_replay, do_countdown, drop_lemmings,
+            update_objects, update_lemmings, update_lemming_grid, rescale_dirty_terrain,
+            update_panel_digits_system, update_mouse_cursor_style_system, compose_cpu_frame,
+        ).chain().in_set(OnUpdate(GameState::InGame)).in_set(InGameUpdateSet));
+        app.add_system(check_level_is_over.run_if(screen_fade_is_not_transitioning).in_set(OnUpdate(GameState::InGame)));
 
         app.add_systems((
             exit,
@@ -160,6 +236,21 @@ struct InGameComponent; // Marker component so it can be despawned.
 #[derive(Component)]
 struct ObjectComponent {
     pub info: ObjectInfo,
+    pub obj_id: usize,
+    pub x: i32, // Top-left in game points.
+    pub y: i32,
+    pub modifier: ObjectModifier,
+    pub is_upside_down: bool,
+}
+
+impl ObjectComponent {
+    // Whether a lemming's feet are where they'd trigger the object, eg to exit.
+    fn is_in_trigger_area(&self, x: i32, y: i32) -> bool {
+        let area = self.info.trigger_rect();
+        let (left, top) = (self.x as isize + area.x, self.y as isize + area.y);
+        let (x, y) = (x as isize, y as isize);
+        left <= x && x < left + area.width && top <= y && y < top + area.height
+    }
 }
 
 #[derive(Component)]
@@ -169,6 +260,8 @@ struct LemmingComponent {
     has_umbrella: bool,
     can_climb: bool,
     builder_bricks_remaining: i8,
+    is_exiting: bool,
+    number: usize, // In the order they dropped, so replays can refer to them.
 }
 
 impl Default for LemmingComponent {
@@ -179,10 +272,26 @@ impl Default for LemmingComponent {
             has_umbrella: false,
             can_climb: false,
             builder_bricks_remaining: 0,
+            is_exiting: false,
+            number: 0,
         }
     }
 }
 
+// What the grid knows about each lemming.
+#[derive(Clone, Copy)]
+struct GridLemming {
+    entity: Entity,
+    skill_in_use: Option<SkillPanelSelection>,
+    is_exiting: bool,
+}
+
+impl GridLemming {
+    fn is_blocker(&self) -> bool {
+        self.skill_in_use == Some(SkillPanelSelection::Block)
+    }
+}
+
 #[derive(Component)]
 struct MapContainerComponent { // Controls the x/y scroll of the map.
     pub min_x: f32, // In bevy transform coords.
@@ -230,27 +339,18 @@ fn slice(image: &[u32], width: usize, height: usize, min_x: isize) -> Vec<SliceW
     slices
 }
 
-struct Slices {
-    slices: Vec<Slice>,
-    x_to_slice_index_lookup: HashMap<i32, u32>, // eg x_to_slice_index[x] = index in slices.
-}
-
+// These are only for display, collisions use the terrain mask.
 struct Slice {
-    pub bitmap: Vec<u32>,
     pub texture: Handle<Image>,
 
     // The following are in scaled-up pixels:
     pub x: isize, 
     pub width: usize,
     pub height: usize,
-
-    // In game points:
-    game_points_x: isize,
-    game_points_width: usize,
 }
 
-fn convert_slices_to_bevy(in_slices: Vec<SliceWithoutHandle>, images: &mut ResMut<Assets<Image>>) -> Slices {
-    let slices: Vec<Slice> = in_slices.into_iter().map(|s| {
+fn convert_slices_to_bevy(in_slices: Vec<SliceWithoutHandle>, images: &mut ResMut<Assets<Image>>) -> Vec<Slice> {
+    in_slices.into_iter().map(|s| {
         let u8_data = u32_to_rgba_u8(&s.bitmap);
         let image = Image::new(Extent3d{width: s.width as u32, height: s.height as u32, depth_or_array_layers: 1},
             bevy::render::render_resource::TextureDimension::D2,
@@ -258,29 +358,111 @@ fn convert_slices_to_bevy(in_slices: Vec<SliceWithoutHandle>, images: &mut ResMu
             bevy::render::render_resource::TextureFormat::Rgba8UnormSrgb);    
         let texture = images.add(image);
         Slice{
-            bitmap: s.bitmap,
             texture,
             x: s.x,
             width: s.width,
             height: s.height,
-            game_points_x: s.x / SCALE as isize,
-            game_points_width: s.width / SCALE,
         }
-    }).collect();
-
-    // Make a lookup so its easy to find the slices.
-    // Not using a simple array because the x positions might be negative.
-    let mut x_to_slice_index_lookup = HashMap::<i32, u32>::new();
-    for (slice_index, slice) in slices.iter().enumerate() {
-        for delta in 0..slice.game_points_width {
-            x_to_slice_index_lookup.insert(slice.game_points_x as i32 + delta as i32, slice_index as u32);
+    }).collect()
+}
+
+// How many points around a changed area to re-scale too, so xBRZ blends its edges the same as the rest of the level.
+const RESCALE_MARGIN: isize = 4;
+
+// A changed area of terrain being re-scaled on another thread.
+struct RescaleJob {
+    area: Rect, // In game points, what will be patched.
+    scaled_area: Rect, // The area plus the margin, which is what is being scaled.
+    result: Arc<Mutex<Option<Vec<u32>>>>, // Filled in when done.
+}
+
+// Changes a point of terrain, eg for digging (None) or building (Some(colour)). It gets redrawn by rescale_dirty_terrain.
+fn change_terrain(terrain: &mut InGameTerrain, mask: &mut TerrainMask, x: i32, y: i32, pixel: Option<u32>) {
+    let column = x as isize - terrain.min_x;
+    if column < 0 || column >= terrain.width as isize || y < 0 || y as usize >= terrain.height { return }
+    terrain.bitmap[y as usize * terrain.width + column as usize] = pixel.unwrap_or(level_renderer::LEVEL_BACKGROUND);
+    mask.set_solid(x, y, pixel.is_some());
+    terrain.dirty.add(Rect::point(x as isize, y as isize));
+}
+
+// Digs out the row a digger is standing on. Returns false if there was nothing left to dig.
+fn dig_row(terrain: &mut InGameTerrain, mask: &mut TerrainMask, x: i32, y: i32) -> bool {
+    let columns = x - DIG_HALF_WIDTH..=x + DIG_HALF_WIDTH;
+    if !columns.clone().any(|x| mask.is_solid(x, y)) { return false }
+    for x in columns {
+        change_terrain(terrain, mask, x, y, None);
+    }
+    true
+}
+
+// Scaling the whole level again would stall the game, so only the changed areas get re-scaled, one at a time, off the
+// main thread. When one is done, its pixels are copied into the slice textures it overlaps.
+fn rescale_dirty_terrain(
+    settings: Res<Settings>,
+    mut terrain: ResMut<InGameTerrain>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if let Some(job) = &terrain.rescale_job {
+        let Some(scaled) = job.result.lock().unwrap().take() else { return }; // Still going.
+        let (area, scaled_area) = (job.area, job.scaled_area);
+        terrain.rescale_job = None;
+        patch_slices(&terrain.slices, &scaled, area, scaled_area, &mut images);
+    }
+
+    let Some(area) = terrain.dirty.pop() else { return };
+    let level = Rect { x: terrain.min_x, y: 0, width: terrain.width as isize, height: terrain.height as isize };
+    let area = area.intersection(&level);
+    let scaled_area = area.expanded(RESCALE_MARGIN).intersection(&level);
+    if area.is_empty() { return }
+
+    // Copy out the area to scale, so the thread doesn't need the resource.
+    let mut crop = Vec::<u32>::with_capacity((scaled_area.width * scaled_area.height) as usize);
+    for y in scaled_area.y..scaled_area.bottom() {
+        let start = y as usize * terrain.width + (scaled_area.x - terrain.min_x) as usize;
+        crop.extend_from_slice(&terrain.bitmap[start..start + scaled_area.width as usize]);
+    }
+    let result = Arc::new(Mutex::new(None));
+    let thread_result = result.clone();
+    let scaler = settings.terrain_scaler;
+    AsyncComputeTaskPool::get().spawn(async move {
+        let scaled = multi_scale(&crop, scaled_area.width as usize, scaled_area.height as usize, false, scaler);
+        *thread_result.lock().unwrap() = Some(scaled);
+    }).detach();
+    terrain.rescale_job = Some(RescaleJob { area, scaled_area, result });
+}
+
+// Copies the area (not the margin) from the re-scaled pixels into whichever slices it overlaps.
+fn patch_slices(slices: &[Slice], scaled: &[u32], area: Rect, scaled_area: Rect, images: &mut ResMut<Assets<Image>>) {
+    for slice in slices {
+        let Some(image) = images.get_mut(&slice.texture) else { continue };
+        if let Err(e) = patch_slice_data(&mut image.data, slice.x, slice.width, scaled, area, scaled_area, scale() as isize) {
+            println!("Couldn't update the terrain: {}", e);
         }
     }
+}
 
-    Slices {
-        slices,
-        x_to_slice_index_lookup,
+// Copies what of the area falls in a slice into its RGBA texture data. Errors, rather than writing anywhere else, if the
+// sizes don't add up, eg the scale changed while the area was being re-scaled.
+fn patch_slice_data(data: &mut [u8], slice_x: isize, slice_width: usize, scaled: &[u32], area: Rect, scaled_area: Rect, scale: isize) -> Result<()> {
+    let bad = |what: &str| Error::new(ErrorKind::InvalidInput, format!("{} (area {:?}, scaled {:?})", what, area, scaled_area));
+    if scale < 1 || slice_width == 0 { return Err(bad("Bad scale or slice width")) }
+    if area.intersection(&scaled_area) != area { return Err(bad("The area isn't in the re-scaled area")) }
+    if scaled.len() as isize != scaled_area.width * scaled_area.height * scale * scale { return Err(bad("Wrong number of re-scaled pixels")) }
+    if !data.len().is_multiple_of(slice_width * 4) { return Err(bad("The slice isn't whole rows")) }
+    let slice_height = (data.len() / (slice_width * 4)) as isize;
+    if area.y < 0 || area.bottom() * scale > slice_height { return Err(bad("The area is outside the slice")) }
+    let scaled_width = scaled_area.width * scale;
+    let left = (area.x * scale).max(slice_x);
+    let right = (area.right() * scale).min(slice_x + slice_width as isize);
+    if left >= right { return Ok(()) }
+    for y in area.y * scale..area.bottom() * scale {
+        for x in left..right {
+            let source = scaled[((y - scaled_area.y * scale) * scaled_width + x - scaled_area.x * scale) as usize];
+            let destination = ((y * slice_width as isize + x - slice_x) * 4) as usize;
+            data[destination..destination + 4].copy_from_slice(&u32_to_rgba_u8(&[source]));
+        }
     }
+    Ok(())
 }
 
 // Drop lemmings every now and again.
@@ -293,6 +475,7 @@ fn drop_lemmings(
     lemmings_container_id: Res<InGameLemmingsContainerId>,
     is_paused: Res<InGameIsPaused>,
     release_rate: Res<InGameReleaseRate>,
+    mut counts: ResMut<InGameLemmingCounts>,
 ) {
     if is_paused.0 { return }
     if drop_countdown.0 < 0 { return } // hasn't started yet or is complete.
@@ -304,12 +487,13 @@ fn drop_lemmings(
                 let t: &Transform = t;
                 let o: &ObjectComponent = o;
                 if o.info.is_entrance {
-                    // TODO only spawn if we haven't run out of lemmings to spawn, otherwise break the for.
-                    spawn_a_lemming(&mut commands, &t.translation, &game_textures, &lemmings_container_id.0);
+                    if counts.dropped >= counts.to_drop { break }
+                    spawn_a_lemming(&mut commands, &t.translation, &game_textures, &lemmings_container_id.0, counts.dropped as usize);
+                    counts.dropped += 1;
                 }
             }
             // 99 = 4 frames, 50 = about 2s.
-            drop_countdown.0 = ((100 - release_rate.0 as i32) * FPS as i32 / 25).max(4);
+            drop_countdown.0 = if counts.dropped >= counts.to_drop { -1 } else { ((100 - release_rate.0 as i32) * FPS as i32 / 25).max(4) };
         } else {
             drop_countdown.0 = new_countdown;
         }
@@ -322,17 +506,18 @@ fn spawn_a_lemming(
     entrance: &Vec3,
     game_textures: &Res<GameTextures>,
     lemmings_container_id: &Entity,
+    number: usize,
 ) {
     commands.entity(*lemmings_container_id).with_children(|parent| {
         parent.spawn(SpriteSheetBundle{
             texture_atlas: game_textures.falling_right.clone(),
             transform: Transform{
-                scale: Vec3::new(TEXTURE_SCALE, TEXTURE_SCALE, 1.),
+                scale: Vec3::new(texture_scale(), texture_scale(), 1.),
                 translation: Vec3::new(round_to_nearest_point(entrance.x), round_to_nearest_point(entrance.y), 0.),
                 ..default()
             },
             ..default()
-        }).insert(LemmingComponent::default());
+        }).insert(LemmingComponent { number, ..default() });
     });
 }
 
@@ -384,79 +569,140 @@ fn do_countdown(
 fn tick(
     time: Res<Time>,
     mut timer: ResMut<GameTimer>,
+    fixed_frame_step: Option<Res<InGameFixedFrameStep>>,
+) {
+    if fixed_frame_step.is_some() {
+        timer.0.tick(Duration::from_secs_f32(FRAME_DURATION));
+    } else {
+        timer.0.tick(time.delta());
+    }
+}
+
+// Gives a lemming a skill if there's any left, and it's one that can be played. Returns whether it worked.
+fn assign_skill(lemming: &mut LemmingComponent, skill: SkillPanelSelection, skill_counts: &mut InGameSkillCounts) -> bool {
+    if !skill.is_playable() { return false }
+    if lemming.skill_in_use == Some(skill) { return false }
+    let Some(skill_count) = skill_counts.0.get_mut(&skill) else { return false };
+    if *skill_count <= 0 { return false }
+    *skill_count -= 1;
+    lemming.skill_in_use = Some(skill);
+    true
+}
+
+// What a replay's actions change.
+#[derive(SystemParam)]
+struct ReplayTargets<'w> {
+    counts: ResMut<'w, InGameLemmingCounts>,
+    skill_counts: ResMut<'w, InGameSkillCounts>,
+    release_rate: ResMut<'w, InGameReleaseRate>,
+    update_panel_digits_events: EventWriter<'w, UpdatePanelDigitsEvent>,
+}
+
+// Does whatever the replay says happened on this frame.
+// This runs where the player's clicks would take effect, so that playing back gives the same result.
+fn play_replay(
+    timer: Res<GameTimer>,
+    playback: Res<InGameReplayPlayback>,
+    is_paused: Res<InGameIsPaused>,
+    mut lemmings_query: Query<&mut LemmingComponent>,
+    mut targets: ReplayTargets,
+) {
+    let Some(replay) = &playback.0 else { return };
+    if is_paused.0 { return }
+    if !timer.0.just_finished() { return }
+    let ReplayTargets { counts, skill_counts, release_rate, update_panel_digits_events } = &mut targets;
+    let frame = counts.frames;
+    for action in replay.actions.iter().filter(|a| a.frame == frame) {
+        match action.kind {
+            ReplayActionKind::AssignSkill { lemming, skill_index } => {
+                let Some(skill) = SkillPanelSelection::from_index(skill_index) else { continue };
+                let Some(mut l) = lemmings_query.iter_mut().find(|l| l.number == lemming) else { continue };
+                if assign_skill(&mut l, skill, skill_counts) {
+                    counts.skills_used += 1;
+                }
+            },
+            ReplayActionKind::ReleaseRate(rate) => {
+                release_rate.0 = rate.clamp(1, 99);
+            },
+        }
+        update_panel_digits_events.send(UpdatePanelDigitsEvent);
+    }
+}
+
+// Rebuilt every frame once they've moved, rather than kept up to date as they move, as there are at most a hundred or so
+// lemmings. So picking and blockers see where they are now.
+fn update_lemming_grid(
+    mut grid: ResMut<InGameLemmingGrid>,
+    lemmings_query: Query<(Entity, &Transform, &LemmingComponent)>,
 ) {
-    timer.0.tick(time.delta());
+    grid.0.clear();
+    for (entity, t, l) in lemmings_query.iter() {
+        let (x, y) = game_xy_from_translation(&t.translation);
+        grid.0.insert(x, y, GridLemming { entity, skill_in_use: l.skill_in_use, is_exiting: l.is_exiting });
+    }
 }
 
 fn determine_lemming_under_mouse_system(
-    windows: Query<&Window>,
+    cursor: Cursor,
     map_query: Query<&Transform, &MapContainerComponent>,
-    lemmings_query: Query<(Entity, &Transform), &LemmingComponent>,
+    grid: Res<InGameLemmingGrid>,
+    skill_selection: Res<InGameSkillSelection>,
     mut event: EventWriter<LemmingUnderPointerEvent>,
     mut mouse_should_become_selector: EventWriter<MouseCursorShouldBecomeSelectorEvent>,
 ) {
-    let closest = determine_lemming_under_mouse(windows, map_query, lemmings_query);
+    let closest = determine_lemming_under_mouse(&cursor, map_query, &grid.0, skill_selection.0);
     event.send(LemmingUnderPointerEvent(closest));    
     mouse_should_become_selector.send(MouseCursorShouldBecomeSelectorEvent(closest.is_some()));
 }
 
+// Of the lemmings in the cursor's selection box, the ones that could take the selected skill come first, then the one
+// closest to the cursor. Lemmings walking into the exit can't be picked.
 fn determine_lemming_under_mouse(
-    windows: Query<&Window>,
+    cursor: &Cursor,
     map_query: Query<&Transform, &MapContainerComponent>,
-    lemmings_query: Query<(Entity, &Transform), &LemmingComponent>,
+    grid: &LemmingGrid<GridLemming>,
+    skill_selection: Option<SkillPanelSelection>,
 ) -> Option<Entity> {
-    let Some(window) = windows.iter().next() else { return None };
-    let Some(position) = window.cursor_position() else { return None };
+    let position = cursor.position()?;
     let Ok(map) = map_query.get_single() else { return None };
 
-    // Locate the mouse.
-    let mouse_x = position.x - window.width() / 2.;
-    let mouse_y = position.y - window.height() / 2.; // +=top, -=bottom.
-    let mouse_x_in_game = mouse_x - map.translation.x;
-    let mouse_y_in_game = mouse_y - map.translation.y; // +=top, -=bot, 0=mid of game area.
-
-    // Find closest lemming.
-    let min_delta = 10. * POINT_SIZE;
-    let mut closest: Option<Entity> = None;
-    let mut closest_delta: f32 = 99999.;
-    for (e, &t) in lemmings_query.iter() {
-        let dx = (t.translation.x - mouse_x_in_game).abs();
-        let dy = (t.translation.y - mouse_y_in_game).abs();
-        if dx <= min_delta && dy <= min_delta {
-            let this_delta = dx + dy;
-            if this_delta < closest_delta {
-                closest = Some(e);
-                closest_delta = this_delta;
-            }
-        }
-    }
-    closest
+    // Locate the mouse, relative to the map so it's in the same coords as the lemmings.
+    let mouse_in_game = position.extend(0.) - map.translation;
+    let (mouse_x, mouse_y) = game_xy_from_translation(&mouse_in_game);
+
+    grid.pickable_at(mouse_x, mouse_y, |e| {
+        if e.lemming.is_exiting { return None }
+        Some(skill_selection.is_none() || e.lemming.skill_in_use != skill_selection)
+    }).map(|l| l.entity)
 }
 
 /// Scroll left and right if your mouse is at the edge.
 fn scroll(
     time: Res<Time>,
-    windows: Query<&Window>,
+    cursor: Cursor,
+    layout: Res<ScreenLayout>,
     mut query: Query<(&mut Transform, &MapContainerComponent)>,
 ) {
-    let Some(window) = windows.iter().next() else { return };
-    let Some(position) = window.cursor_position() else { return };
+    let Some(position) = cursor.position() else { return };
+    let visible_width = layout.visible_size().x;
     
+    // As a fraction of the visible width, so 0 is the left edge and 1 the right. Past the edges is over the letterbox.
+    let x = position.x / visible_width + 0.5;
     let delta: isize;
-    if position.x < window.width() * 0.05 {
+    if x < 0.05 {
         delta = 2;
-    } else if position.x < window.width() * 0.1 {
+    } else if x < 0.1 {
         delta = 1;
-    } else if position.x > window.width() * 0.95 {
+    } else if x > 0.95 {
         delta = -2;
-    } else if position.x > window.width() * 0.9 {
+    } else if x > 0.9 {
         delta = -1;
     } else {
         delta = 0;
     }
     if delta != 0 {
         for (mut transform, container) in query.iter_mut() {
-            let new_x = transform.translation.x + (delta as f32 * time.delta().as_secs_f32() * window.width() * 0.3).round();
+            let new_x = transform.translation.x + (delta as f32 * time.delta().as_secs_f32() * visible_width * 0.3).round();
             let clamped_x = new_x.min(container.max_x).max(container.min_x);
             transform.translation.x = clamped_x;
         }
@@ -464,7 +710,7 @@ fn scroll(
 }
 
 fn mouse_click_system(
-    windows: Query<&Window>,
+    cursor: Cursor,
     mouse_button_input: Res<Input<MouseButton>>,
     bottom_panel_id: Res<InGameBottomPanelId>,
     bottom_panel_query: Query<&Transform, With<InGameBottomPanelComponent>>,
@@ -488,7 +734,7 @@ fn mouse_click_system(
             Query<&mut Transform, (With<InGameSpeedSelectionIndicatorComponent>, Without<InGameBottomPanelComponent>, Without<InGameSkillSelectionIndicatorComponent>, Without<InGamePauseSelectionIndicatorComponent>)>,
             Query<&mut Transform, (With<InGameNukeSelectionIndicatorComponent>, Without<InGameBottomPanelComponent>, Without<InGameSkillSelectionIndicatorComponent>, Without<InGamePauseSelectionIndicatorComponent>, Without<InGameSpeedSelectionIndicatorComponent>)>
         ),
-    (mut in_game_skill_selection, mut in_game_skill_counts): (ResMut<InGameSkillSelection>, ResMut<InGameSkillCounts>),
+    (mut in_game_skill_selection, mut in_game_skill_counts, mut lemming_counts, mut recording): (ResMut<InGameSkillSelection>, ResMut<InGameSkillCounts>, ResMut<InGameLemmingCounts>, ResMut<InGameReplayRecording>),
     mut is_paused: ResMut<InGameIsPaused>,
     mut release_rate: ResMut<InGameReleaseRate>,
     mut update_panel_digits_events: EventWriter<UpdatePanelDigitsEvent>,
@@ -496,10 +742,9 @@ fn mouse_click_system(
     mut lemmings_query: Query<&mut LemmingComponent>,
 ) {
     if mouse_button_input.just_pressed(MouseButton::Left) {
-        let Some(window) = windows.iter().next() else { return };
-        let Some(position) = window.cursor_position() else { return };
-        let mouse_x = position.x - window.width() / 2.;
-        let mouse_y = position.y - window.height() / 2.;
+        let Some(position) = cursor.position() else { return };
+        let mouse_x = position.x;
+        let mouse_y = position.y;
 
         let Ok(bottom_panel) = bottom_panel_query.get(bottom_panel_id.0) else { return };
         let bottom_panel_top = bottom_panel.translation.y + sizes::SKILL_PANEL_CLICKABLE_HEIGHT as f32 * POINT_SIZE;
@@ -517,11 +762,12 @@ fn mouse_click_system(
                             if release_rate.0 % 10 != 0 { release_rate.0 = ((release_rate.0 as f32 / 10.).round() as isize) * 10 } 
                             if release_rate.0 < 1 { release_rate.0 = 1 }
                             if release_rate.0 > 99 { release_rate.0 = 99 }
+                            recording.0.actions.push(ReplayAction { frame: lemming_counts.frames, kind: ReplayActionKind::ReleaseRate(release_rate.0) });
                             update_panel_digits_events.send(UpdatePanelDigitsEvent);
                             let index: f32 = if selection == SkillPanelSelection::SpeedMinus { 0. } else { 1. };
                             if let Ok(mut speed_indicator) = speed_selection_indicator_query.get_mut(speed_selection_indicator_id.0) {
                                 speed_indicator.translation = Vec3::new(
-                                    (leftmost_skill + index * sizes::SKILL_PANEL_BUTTON_WIDTH as f32) * POINT_SIZE / TEXTURE_SCALE,
+                                    (leftmost_skill + index * sizes::SKILL_PANEL_BUTTON_WIDTH as f32) * POINT_SIZE / texture_scale(),
                                     0.,
                                     11.);
                             }
@@ -531,7 +777,7 @@ fn mouse_click_system(
                             if let Ok(mut pause_indicator) = pause_selection_indicator_query.get_mut(pause_selection_indicator_id.0) {
                                 if is_paused.0 {
                                     pause_indicator.translation = Vec3::new(
-                                        (leftmost_skill + 10. * sizes::SKILL_PANEL_BUTTON_WIDTH as f32) * POINT_SIZE / TEXTURE_SCALE,
+                                        (leftmost_skill + 10. * sizes::SKILL_PANEL_BUTTON_WIDTH as f32) * POINT_SIZE / texture_scale(),
                                         0.,
                                         11.);
                                 } else {
@@ -542,7 +788,7 @@ fn mouse_click_system(
                         SkillPanelSelection::Nuke => {
                             if let Ok(mut nuke_indicator) = nuke_selection_indicator_query.get_mut(nuke_selection_indicator_id.0) {
                                 nuke_indicator.translation = Vec3::new(
-                                    (leftmost_skill + 11. * sizes::SKILL_PANEL_BUTTON_WIDTH as f32) * POINT_SIZE / TEXTURE_SCALE,
+                                    (leftmost_skill + 11. * sizes::SKILL_PANEL_BUTTON_WIDTH as f32) * POINT_SIZE / texture_scale(),
                                     0.,
                                     11.);
                             }
@@ -551,7 +797,7 @@ fn mouse_click_system(
                             in_game_skill_selection.0 = Some(selection);
                             if let Ok(mut skill_selection_indicator) = skill_selection_indicator_query.get_mut(skill_selection_indicator_id.0) {
                                 skill_selection_indicator.translation = Vec3::new(
-                                    (leftmost_skill + button_index as f32 * sizes::SKILL_PANEL_BUTTON_WIDTH as f32) * POINT_SIZE / TEXTURE_SCALE, 
+                                    (leftmost_skill + button_index as f32 * sizes::SKILL_PANEL_BUTTON_WIDTH as f32) * POINT_SIZE / texture_scale(), 
                                     0.,
                                     11.);
                             }                    
@@ -564,12 +810,13 @@ fn mouse_click_system(
             let Some(lemming_id) = event.0 else { return };
             let Ok(mut lemming) = lemmings_query.get_mut(lemming_id) else { return };
             let Some(selected_skill) = in_game_skill_selection.0 else { return };
-            if lemming.skill_in_use == Some(selected_skill) { return };
-            let Some(skill_count) = in_game_skill_counts.0.get_mut(&selected_skill) else { return };
-            if *skill_count <= 0 { return };
-            *skill_count -= 1;
+            if !assign_skill(&mut lemming, selected_skill, &mut in_game_skill_counts) { return }
+            lemming_counts.skills_used += 1;
+            recording.0.actions.push(ReplayAction {
+                frame: lemming_counts.frames,
+                kind: ReplayActionKind::AssignSkill { lemming: lemming.number, skill_index: selected_skill as isize },
+            });
             update_panel_digits_events.send(UpdatePanelDigitsEvent);
-            lemming.skill_in_use = Some(selected_skill);
         }
     } else if mouse_button_input.just_released(MouseButton::Left) { // Release the momentaries if any.
         if let Ok(mut speed_indicator) = speed_selection_indicator_query.get_mut(speed_selection_indicator_id.0) {
@@ -585,7 +832,7 @@ fn mouse_click_system(
 fn enter(
     level_selection: Res<LevelSelectionResource>,
 	game: Res<Game>,
-	windows: Query<&Window>,
+    settings: Res<Settings>,
 	mut commands: Commands,
     mut timer: ResMut<GameTimer>,
     mut start_countdown: ResMut<InGameStartCountdown>,
@@ -593,13 +840,24 @@ fn enter(
 	mut images: ResMut<Assets<Image>>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
     mut lemmings_container_id: ResMut<InGameLemmingsContainerId>,
-    mut slices_resource: ResMut<InGameSlices>,
+    (mut terrain_mask, mut terrain, mut grid): (ResMut<InGameTerrainMask>, ResMut<InGameTerrain>, ResMut<InGameLemmingGrid>),
     mut release_rate: ResMut<InGameReleaseRate>,
     mut skill_counts: ResMut<InGameSkillCounts>,
+    (mut lemming_counts, mut recording, mut in_game_level): (ResMut<InGameLemmingCounts>, ResMut<InGameReplayRecording>, ResMut<InGameLevel>),
 ) {
-	let Some(window) = windows.iter().next() else { return };
-    let Some(level) = game.level_named(&level_selection.level_name) else { return };
+    in_game_level.0 = None;
+    let Some(level) = game.level(level_selection.level_key) else { return };
+    let (ground, special) = match (game.ground_for(&level), game.special_for(&level)) {
+        (Ok(ground), Ok(special)) => (ground, special),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("Couldn't load the graphics for {}: {}", level.name, e);
+            return
+        }
+    };
 
+    in_game_level.0 = Some((level.clone(), ground.clone()));
+    *lemming_counts = InGameLemmingCounts { to_drop: level.globals.num_of_lemmings as i32, ..default() };
+    recording.0 = Replay { game_id: game.id.clone(), level_name: level_selection.level_name.clone(), level_key: Some(level_selection.level_key), actions: vec![] };
     release_rate.0 = level.globals.release_rate as isize;
     start_countdown.0 = FPS as i32;
     drop_countdown.0 = -1; // Not dropping yet.
@@ -615,7 +873,6 @@ fn enter(
     skill_counts.0.insert(SkillPanelSelection::DigVertical, level.globals.skills.diggers as isize);
 
     // Scale and bevy-ify the ground's objects.
-    let ground = &game.grounds[&(level.globals.normal_graphic_set as i32)];
     pub enum AnimationOrImageHandle {
         Animation(Handle<TextureAtlas>),
         Image(Handle<Image>),
@@ -628,23 +885,34 @@ fn enter(
             continue;
         } else if frame_count == 1 {
             // Static.
-            let image_handle = make_image_from_bitmap(&animation.frames[0], animation.width, animation.height, &mut images, true);
+            let image_handle = make_image_from_bitmap(&animation.frames[0], animation.width, animation.height, &mut images, true, settings.sprite_scaler);
             anim_or_image = AnimationOrImageHandle::Image(image_handle);
         } else {
             // Animation.
-            let atlas_handle = make_atlas_from_animation(animation, &mut images, &mut texture_atlases, true);
+            let atlas_handle = make_atlas_from_animation(animation, &mut images, &mut texture_atlases, true, settings.sprite_scaler);
             anim_or_image = AnimationOrImageHandle::Animation(atlas_handle);
         }
         object_handles.insert(index.clone(), anim_or_image);
     }
 
     // Spawn the level terrain.
-    let render = level_renderer::render(level, &game.grounds, &game.specials, false);
+    let render = level_renderer::render(&level, &ground, special.as_deref(), false);
+    terrain_mask.0 = Some(TerrainMask::from_rendered_level(&render));
+    grid.0 = LemmingGrid::new(render.size.min_x, render.image.width);
     let game_origin_offset_y: f32 = (render.image.height as f32) * POINT_SIZE / 2.; // Y to use for 0 in game coords.
-    let level_offset_y = window.height() / 2. - game_origin_offset_y;
-    let scaled = multi_scale(&render.image.bitmap, render.image.width, render.image.height, false);
-    let slices_raw = slice(&scaled, render.image.width * SCALE, render.image.height * SCALE, render.size.min_x * SCALE as isize);
+    let level_offset_y = CANVAS_H / 2. - game_origin_offset_y;
+    let scaled = multi_scale_cached(&render.image.bitmap, render.image.width, render.image.height, false, settings.terrain_scaler);
+    let slices_raw = slice(&scaled, render.image.width * scale(), render.image.height * scale(), render.size.min_x * scale() as isize);
     let slices = convert_slices_to_bevy(slices_raw, &mut images);
+    let slice_textures: Vec<(Handle<Image>, isize, usize)> = slices.iter().map(|s| (s.texture.clone(), s.x, s.width)).collect();
+    *terrain = InGameTerrain {
+        width: render.image.width,
+        height: render.image.height,
+        min_x: render.size.min_x,
+        bitmap: render.image.bitmap,
+        slices,
+        ..default()
+    };
     commands
         .spawn(SpatialBundle{
             // TODO for the start X, do we need to account for the current screen width?
@@ -654,14 +922,14 @@ fn enter(
         }).with_children(|parent| {
             // Terrain slices.
             parent.spawn(SpatialBundle::default()).with_children(|parent| {
-                for slice in &slices.slices {
+                for (texture, x, width) in slice_textures {
                     parent.spawn(SpriteBundle{
                         transform: Transform{
-                            translation: Vec3::new((slice.x as f32 + (slice.width as f32 / 2.)) * TEXTURE_SCALE, 0., 2.),
-                            scale: Vec3::new(TEXTURE_SCALE, TEXTURE_SCALE, 1.),
+                            translation: Vec3::new((x as f32 + (width as f32 / 2.)) * texture_scale(), 0., 2.),
+                            scale: Vec3::new(texture_scale(), texture_scale(), 1.),
                             ..default()
                         },
-                        texture: slice.texture.clone(),
+                        texture,
                         ..default()
                     });
                 }
@@ -673,7 +941,7 @@ fn enter(
                 let object_info = &ground.ground.object_info[object.obj_id];
                 if let Some(handle) = object_handles.get(&(object.obj_id as i32)) {
                     let transform = Transform{
-                        scale: Vec3::new(TEXTURE_SCALE, TEXTURE_SCALE, 1.),
+                        scale: Vec3::new(texture_scale(), texture_scale(), 1.),
                         translation: Vec3::new((object.x as f32 + object_info.width as f32 / 2.) * POINT_SIZE,
                         game_origin_offset_y - (object.y as f32 + object_info.height as f32 / 2.) * POINT_SIZE, 
                         z_index),
@@ -681,11 +949,20 @@ fn enter(
                     };
                     let object_component = ObjectComponent{
                         info: object_info.clone(),
+                        obj_id: object.obj_id,
+                        x: object.x,
+                        y: object.y,
+                        modifier: object.modifier.clone(),
+                        is_upside_down: object.is_upside_down,
                     };
                     match handle {
                         AnimationOrImageHandle::Animation(anim) => {
                             parent.spawn(SpriteSheetBundle{
-                                sprite: TextureAtlasSprite { index: object_info.start_animation_frame_index as usize % object_info.frame_count as usize, ..default() },
+                                sprite: TextureAtlasSprite {
+                                    index: object_info.start_animation_frame_index as usize % object_info.frame_count as usize,
+                                    flip_y: object.is_upside_down,
+                                    ..default()
+                                },
                                 texture_atlas: anim.clone(),
                                 transform, 
                                 ..default()
@@ -694,6 +971,7 @@ fn enter(
                         },
                         AnimationOrImageHandle::Image(image) => {
                             parent.spawn(SpriteBundle{
+                                sprite: Sprite { flip_y: object.is_upside_down, ..default() },
                                 texture: image.clone(),
                                 transform, 
                                 ..default()
@@ -717,14 +995,12 @@ fn enter(
         });
 
     // Keep the slices around.
-    slices_resource.0 = Some(slices);
 }
 
 struct UpdatePanelDigitsEvent; // No params, update them all.
 fn update_panel_digits_system(
     mut events: EventReader<UpdatePanelDigitsEvent>,
-    level_selection: Res<LevelSelectionResource>,
-	game: Res<Game>,
+    in_game_level: Res<InGameLevel>,
     game_textures: Res<GameTextures>,
     panel_digits: Res<InGamePanelDigits>,
     release_rate: Res<InGameReleaseRate>,
@@ -732,7 +1008,7 @@ fn update_panel_digits_system(
     mut digits_query: Query<&mut Handle<Image>, With<InGamePanelDigitComponent>>,
 ) {
     let Some(_ev) = events.iter().next() else { return }; // Quit early if no event.
-    let Some(level) = game.level_named(&level_selection.level_name) else { return };
+    let Some((level, _)) = &in_game_level.0 else { return };
     for (index, pair) in panel_digits.0.iter().enumerate() {
         let Some(button) = SkillPanelSelection::from_index(index as isize) else { continue };
         let value: isize = match button {
@@ -761,7 +1037,6 @@ fn update_panel_digits_system(
 fn enter_and_spawn_bottom_skill_panel(
     mut commands: Commands,
     game_textures: Res<GameTextures>,
-    windows: Query<&Window>,
     mut bottom_panel_id: ResMut<InGameBottomPanelId>,
     mut skill_selection_indicator_id: ResMut<InGameSkillSelectionIndicatorId>,
     mut speed_selection_indicator_id: ResMut<InGameSpeedSelectionIndicatorId>,
@@ -770,16 +1045,14 @@ fn enter_and_spawn_bottom_skill_panel(
     mut panel_digits: ResMut<InGamePanelDigits>,
     mut update_panel_digits_events: EventWriter<UpdatePanelDigitsEvent>,
 ) {
-    let Some(window) = windows.iter().next() else { return };
-
     // Skill panel.
     bottom_panel_id.0 = commands
         .spawn(SpriteBundle{
             sprite: Sprite { anchor: Anchor::BottomCenter, ..default() },
             texture: game_textures.skill_panel.clone(),
             transform: Transform{
-                translation: Vec3::new(0., -window.height() / 2., 10.),
-                scale: Vec3::new(TEXTURE_SCALE, TEXTURE_SCALE, 1.),
+                translation: Vec3::new(0., -CANVAS_H / 2., 10.),
+                scale: Vec3::new(texture_scale(), texture_scale(), 1.),
                 ..default()
             },        
             ..default()
@@ -789,7 +1062,7 @@ fn enter_and_spawn_bottom_skill_panel(
                 texture: game_textures.skill_selection.clone(),
                 sprite: Sprite { anchor: Anchor::BottomCenter, ..default() },
                 transform: Transform{
-                    translation: Vec3::new(-99999. * POINT_SIZE / TEXTURE_SCALE, 0., 11.), // Just on top of skill panel.
+                    translation: Vec3::new(-99999. * POINT_SIZE / texture_scale(), 0., 11.), // Just on top of skill panel.
                     ..default()
                 },
                 ..default()
@@ -800,7 +1073,7 @@ fn enter_and_spawn_bottom_skill_panel(
                 texture: game_textures.speed_selection.clone(),
                 sprite: Sprite { anchor: Anchor::BottomCenter, ..default() },
                 transform: Transform{
-                    translation: Vec3::new(-99999. * POINT_SIZE / TEXTURE_SCALE, 0., 11.),
+                    translation: Vec3::new(-99999. * POINT_SIZE / texture_scale(), 0., 11.),
                     ..default()
                 },
                 ..default()
@@ -811,7 +1084,7 @@ fn enter_and_spawn_bottom_skill_panel(
                 texture: game_textures.pause_selection.clone(),
                 sprite: Sprite { anchor: Anchor::BottomCenter, ..default() },
                 transform: Transform{
-                    translation: Vec3::new(-99999. * POINT_SIZE / TEXTURE_SCALE, 0., 11.),
+                    translation: Vec3::new(-99999. * POINT_SIZE / texture_scale(), 0., 11.),
                     ..default()
                 },
                 ..default()
@@ -822,7 +1095,7 @@ fn enter_and_spawn_bottom_skill_panel(
                 texture: game_textures.nuke_selection.clone(),
                 sprite: Sprite { anchor: Anchor::BottomCenter, ..default() },
                 transform: Transform{
-                    translation: Vec3::new(-99999. * POINT_SIZE / TEXTURE_SCALE, 0., 11.),
+                    translation: Vec3::new(-99999. * POINT_SIZE / texture_scale(), 0., 11.),
                     ..default()
                 },
                 ..default()
@@ -833,7 +1106,7 @@ fn enter_and_spawn_bottom_skill_panel(
             parent.spawn(SpatialBundle{
                 ..default()
             }).with_children(|parent| {
-                let point = POINT_SIZE / TEXTURE_SCALE; // Makes it simpler below.
+                let point = POINT_SIZE / texture_scale(); // Makes it simpler below.
                 let y = 18.5 * point;
                 let leftmost_skill: f32 = -9. * sizes::SKILL_PANEL_BUTTON_WIDTH as f32 - 8.;
                 let mut ids: Vec<LeftRightEntityPair> = Vec::new();
@@ -865,30 +1138,61 @@ struct LeftRightEntityPair {
 }
 
 fn update_lemmings(
+    mut commands: Commands,
     mut query: Query<(
+        Entity,
         &mut Transform,
         &mut TextureAtlasSprite,
         &mut Handle<TextureAtlas>,
         &mut LemmingComponent,
     )>,
+    objects: Query<&ObjectComponent>,
     timer: Res<GameTimer>,
-    slices: Res<InGameSlices>,
+    (mut terrain, mut terrain_mask): (ResMut<InGameTerrain>, ResMut<InGameTerrainMask>),
+    grid: Res<InGameLemmingGrid>,
     game_textures: Res<GameTextures>,
     is_paused: Res<InGameIsPaused>,
+    mut counts: ResMut<InGameLemmingCounts>,
 ) {
     if is_paused.0 { return }
     if !timer.0.just_finished() { return }
+    counts.frames += 1;
 
-    for (mut t, mut tas, mut ta, mut l) in query.iter_mut() {
+    for (entity, mut t, mut tas, mut ta, mut l) in query.iter_mut() {
         // let mut t: Mut<Transform> = t; // Uncomment these for IDE suggestions, but recomment them to remove compile warnings.
         // let mut tas: Mut<TextureAtlasSprite> = tas;
         // let mut l: Mut<LemmingComponent> = l;
         
         let (game_x, game_y) = game_xy_from_translation(&t.translation);
         let bottom_y = game_y + LEMMING_NOMINAL_HEIGHT_HALF;
+
+        // Walking into the exit, once the animation is done they're saved.
+        if l.is_exiting {
+            if tas.index + 1 >= game_textures.exiting_count {
+                commands.entity(entity).despawn_recursive();
+                counts.saved += 1;
+            } else {
+                tas.index += 1;
+            }
+            continue;
+        }
+        if objects.iter().any(|o| o.info.trigger_effect_id == TRIGGER_EFFECT_EXIT && o.is_in_trigger_area(game_x, bottom_y)) {
+            l.is_exiting = true;
+            *ta = game_textures.exiting.clone();
+            tas.index = 0;
+            continue;
+        }
+
+        // Fell out the bottom of the level.
+        if game_y > level_renderer::LEVEL_HEIGHT as i32 {
+            commands.entity(entity).despawn_recursive();
+            counts.died += 1;
+            continue;
+        }
+
         let mut texture_frame_count: Option<usize> = None; // Set if you want it to animate, don't set if you jump to 0 on new anim.
         // Check if there's any ground under this lemming.
-        let is_ground_under = is_there_ground_at_xy(game_x, bottom_y, slices.0.as_ref());
+        let is_ground_under = is_there_ground_at_xy(game_x, bottom_y, terrain_mask.0.as_ref());
         if let Some(skill) = l.skill_in_use {
             match skill {
                 SkillPanelSelection::DigVertical => {
@@ -897,9 +1201,26 @@ fn update_lemmings(
                         tas.index = 0;
                     } else {
                         texture_frame_count = Some(game_textures.digging_count);
+                        if tas.index % DIG_FRAMES_PER_ROW == 0 {
+                            if let Some(mask) = terrain_mask.0.as_mut() {
+                                if dig_row(&mut terrain, mask, game_x, bottom_y) {
+                                    t.translation.y = round_to_nearest_point(t.translation.y - POINT_SIZE);
+                                } else {
+                                    l.skill_in_use = None; // Dug right through, so it falls.
+                                }
+                            }
+                        }
+                    }
+                },
+                SkillPanelSelection::Block => {
+                    if ta.id() != game_textures.blocking.id() {
+                        *ta = game_textures.blocking.clone();
+                        tas.index = 0;
+                    } else {
+                        texture_frame_count = Some(game_textures.blocking_count);
                     }
                 },
-                _ => todo!(),
+                _ => l.skill_in_use = None, // assign_skill doesn't give these out, but if one got here it'd just walk on.
             }
         } else {
             // No skill being used, just walking.
@@ -908,21 +1229,21 @@ fn update_lemmings(
                 let game_x_in_direction = game_x + facing_direction_x_delta;
                 // These keep track of 'is there ground where i'm walking'.
                 // TODO optimise away the fact that these all use the same x, thus same slice.
-                let is_ground_3down = is_there_ground_at_xy(game_x_in_direction, bottom_y + 3, slices.0.as_ref());
-                let is_ground_2down = is_there_ground_at_xy(game_x_in_direction, bottom_y + 2, slices.0.as_ref());
-                let is_ground_1down = is_there_ground_at_xy(game_x_in_direction, bottom_y + 1, slices.0.as_ref());
-                let is_ground_on_same_level = is_there_ground_at_xy(game_x_in_direction, bottom_y, slices.0.as_ref());
-                let is_ground_1up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 1, slices.0.as_ref());
-                let is_ground_2up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 2, slices.0.as_ref());
-                let is_ground_3up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 3, slices.0.as_ref()); // Jump.
-                let is_ground_4up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 4, slices.0.as_ref());
-                let is_ground_5up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 5, slices.0.as_ref());
-                let is_ground_6up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 6, slices.0.as_ref());
-                let is_ground_7up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 7, slices.0.as_ref()); // Blocked.
-                let is_ground_8up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 8, slices.0.as_ref());
-                let is_ground_9up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 9, slices.0.as_ref());
+                let is_ground_3down = is_there_ground_at_xy(game_x_in_direction, bottom_y + 3, terrain_mask.0.as_ref());
+                let is_ground_2down = is_there_ground_at_xy(game_x_in_direction, bottom_y + 2, terrain_mask.0.as_ref());
+                let is_ground_1down = is_there_ground_at_xy(game_x_in_direction, bottom_y + 1, terrain_mask.0.as_ref());
+                let is_ground_on_same_level = is_there_ground_at_xy(game_x_in_direction, bottom_y, terrain_mask.0.as_ref());
+                let is_ground_1up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 1, terrain_mask.0.as_ref());
+                let is_ground_2up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 2, terrain_mask.0.as_ref());
+                let is_ground_3up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 3, terrain_mask.0.as_ref()); // Jump.
+                let is_ground_4up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 4, terrain_mask.0.as_ref());
+                let is_ground_5up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 5, terrain_mask.0.as_ref());
+                let is_ground_6up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 6, terrain_mask.0.as_ref());
+                let is_ground_7up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 7, terrain_mask.0.as_ref()); // Blocked.
+                let is_ground_8up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 8, terrain_mask.0.as_ref());
+                let is_ground_9up = is_there_ground_at_xy(game_x_in_direction, bottom_y - 9, terrain_mask.0.as_ref());
                 // Jumping is if you walk 3-6 pixels up.
-                let is_blocked = is_ground_7up || is_ground_8up || is_ground_9up;
+                let is_blocked = is_ground_7up || is_ground_8up || is_ground_9up || is_blocker_ahead(&grid.0, entity, game_x, game_y, facing_direction_x_delta);
                 if is_blocked { // Turn around.
                     l.is_facing_right ^= true; // Toggle.
                     *ta = if l.is_facing_right { game_textures.walking_right.clone() } else { game_textures.walking_left.clone() };
@@ -995,6 +1316,136 @@ fn update_lemmings(
     }
 }
 
+// Which of main.dat's animations a lemming's atlas was made from.
+fn lemming_animation<'a>(atlas: &Handle<TextureAtlas>, textures: &GameTextures, animations: &'a LemmingAnimations) -> Option<&'a Animation> {
+    let pairs = [
+        (&textures.walking_right, &animations.walking_right),
+        (&textures.jumping_right, &animations.jumping_right),
+        (&textures.walking_left, &animations.walking_left),
+        (&textures.jumping_left, &animations.jumping_left),
+        (&textures.digging, &animations.digging),
+        (&textures.climbing_right, &animations.climbing_right),
+        (&textures.climbing_left, &animations.climbing_left),
+        (&textures.drowning, &animations.drowning),
+        (&textures.post_climb_right, &animations.post_climb_right),
+        (&textures.post_climb_left, &animations.post_climb_left),
+        (&textures.brick_laying_right, &animations.brick_laying_right),
+        (&textures.brick_laying_left, &animations.brick_laying_left),
+        (&textures.bashing_right, &animations.bashing_right),
+        (&textures.bashing_left, &animations.bashing_left),
+        (&textures.mining_right, &animations.mining_right),
+        (&textures.mining_left, &animations.mining_left),
+        (&textures.falling_right, &animations.falling_right),
+        (&textures.falling_left, &animations.falling_left),
+        (&textures.pre_umbrella_right, &animations.pre_umbrella_right),
+        (&textures.umbrella_right, &animations.umbrella_right),
+        (&textures.pre_umbrella_left, &animations.pre_umbrella_left),
+        (&textures.umbrella_left, &animations.umbrella_left),
+        (&textures.splatting, &animations.splatting),
+        (&textures.exiting, &animations.exiting),
+        (&textures.fried, &animations.fried),
+        (&textures.blocking, &animations.blocking),
+        (&textures.shrugging_right, &animations.shrugging_right),
+        (&textures.shrugging_left, &animations.shrugging_left),
+        (&textures.oh_no_ing, &animations.oh_no_ing),
+        (&textures.explosion, &animations.explosion),
+    ];
+    pairs.into_iter().find(|(handle, _)| handle.id() == atlas.id()).map(|(_, animation)| animation)
+}
+
+// Draws the game frame on the CPU from where the sprites are, if InGameCpuFrame asks for it.
+fn compose_cpu_frame(
+    cpu_frame: Option<ResMut<InGameCpuFrame>>,
+    (timer, in_game_level, game, game_textures): (Res<GameTimer>, Res<InGameLevel>, Res<Game>, Res<GameTextures>),
+    terrain: Res<InGameTerrain>,
+    objects: Query<(&ObjectComponent, Option<&TextureAtlasSprite>)>,
+    lemmings: Query<(&Transform, &TextureAtlasSprite, &Handle<TextureAtlas>), With<LemmingComponent>>,
+    map_container: Query<&Transform, With<MapContainerComponent>>,
+    (release_rate, skill_counts, skill_selection, is_paused): (Res<InGameReleaseRate>, Res<InGameSkillCounts>, Res<InGameSkillSelection>, Res<InGameIsPaused>),
+) {
+    let Some(mut cpu_frame) = cpu_frame else { return };
+    if !timer.0.just_finished() { return }
+    let Some((level, ground)) = &in_game_level.0 else { return };
+    let (view_x, view_width) = if cpu_frame.is_full_width {
+        (terrain.min_x, terrain.width)
+    } else {
+        // The container's moved left by the scroll, plus half a screen to centre it, see enter.
+        let scroll_x = map_container.get_single().map_or(level.globals.start_screen_xpos as f32, |t| -t.translation.x / POINT_SIZE - ORIGINAL_GAME_W as f32 / 2.);
+        (scroll_x.round() as isize, ORIGINAL_GAME_W)
+    };
+    let mut values = [0isize; 10];
+    for (index, value) in values.iter_mut().enumerate() {
+        let Some(button) = SkillPanelSelection::from_index(index as isize) else { continue };
+        *value = match button {
+            SkillPanelSelection::SpeedMinus => level.globals.release_rate as isize,
+            SkillPanelSelection::SpeedPlus => release_rate.0,
+            _ => *skill_counts.0.get(&button).unwrap_or(&0),
+        };
+    }
+    let scene = Scene {
+        terrain: &terrain.bitmap,
+        terrain_width: terrain.width,
+        terrain_min_x: terrain.min_x,
+        view_x,
+        view_width,
+        objects: objects.iter().map(|(object, atlas_sprite)| SceneObject {
+            obj_id: object.obj_id,
+            x: object.x,
+            y: object.y,
+            frame: atlas_sprite.map_or(0, |s| s.index),
+            modifier: object.modifier.clone(),
+            is_upside_down: object.is_upside_down,
+        }).collect(),
+        lemmings: lemmings.iter().filter_map(|(transform, atlas_sprite, atlas)| {
+            let animation = lemming_animation(atlas, &game_textures, &game.main.lemming_animations)?;
+            let (x, y) = game_xy_from_translation(&transform.translation);
+            Some(SceneLemming { animation, frame: atlas_sprite.index, x, y })
+        }).collect(),
+        panel: Some(ScenePanel {
+            values,
+            selected_skill: skill_selection.0.map(|s| s as usize),
+            is_paused: is_paused.0,
+        }),
+    };
+    cpu_frame.image = Some(compositor::compose(&scene, ground, &game.main));
+}
+
+// Once every lemming is out and accounted for, head to the results screen.
+fn check_level_is_over(
+    mut fadeout: Fadeout,
+    counts: Res<InGameLemmingCounts>,
+    lemmings: Query<(), With<LemmingComponent>>,
+    in_game_level: Res<InGameLevel>,
+    mut result: ResMut<LevelResultResource>,
+    recording: Res<InGameReplayRecording>,
+) {
+    if counts.to_drop == 0 || counts.dropped < counts.to_drop { return }
+    if !lemmings.is_empty() { return }
+    let Some((level, _)) = &in_game_level.0 else { return };
+    *result = LevelResultResource {
+        saved: counts.saved as usize,
+        total: counts.to_drop as usize,
+        to_rescue: level.globals.num_to_rescue as usize,
+        skills_used: counts.skills_used as usize,
+        seconds: (counts.frames as f32 / FPS).round() as usize,
+    };
+    if let Err(e) = recording.0.save_as_last() {
+        println!("Couldn't save replay: {}", e);
+    }
+    fadeout.start(GameState::Postview);
+}
+
+// Whether a blocker is standing just in front, in the direction it's walking.
+fn is_blocker_ahead(grid: &LemmingGrid<GridLemming>, entity: Entity, x: i32, y: i32, direction: i32) -> bool {
+    let reach = Rect {
+        x: if direction > 0 { x + 1 } else { x - BLOCKER_REACH } as isize,
+        y: (y - LEMMING_NOMINAL_HEIGHT_HALF * 2) as isize,
+        width: BLOCKER_REACH as isize,
+        height: (LEMMING_NOMINAL_HEIGHT_HALF * 4 + 1) as isize,
+    };
+    grid.within(&reach).iter().any(|e| e.lemming.entity != entity && e.lemming.is_blocker())
+}
+
 fn round_to_nearest_point(a: f32) -> f32 {
     (a / POINT_SIZE).round() * POINT_SIZE
 }
@@ -1007,33 +1458,75 @@ fn game_xy_from_translation(translation: &Vec3) -> (i32, i32) {
 }
 
 // xy are game points, eg y=0=top.
-fn is_there_ground_at_xy(x: i32, y: i32, slices_o: Option<&Slices>) -> bool {
-    if let Some(slices) = slices_o {
-        if let Some(index) = slices.x_to_slice_index_lookup.get(&x) {
-            let slice = &slices.slices[*index as usize];
-            let game_points_x_offset = x as isize - slice.game_points_x;
-            let scaled_x_offset = game_points_x_offset as usize * SCALE;
-            let scaled_y_offset = y as usize * SCALE;
-            
-            // Check all scaled pixels until ground is found.
-            // Exit early if ground is found. This is an optimisation because lemmings will more
-            // often than not be on the ground.
-            for scale_search_y in 0..SCALE {
-                let offset = (scaled_y_offset + scale_search_y) * slice.width + scaled_x_offset;
-                for scale_search_x in 0..SCALE {
-                    if let Some(rgba) = slice.bitmap.get(offset + scale_search_x) {
-                        let a = *rgba as u8;
-                        if a > 0 {
-                            return true
-                        }
-                    }
-                }
+fn is_there_ground_at_xy(x: i32, y: i32, terrain_mask: Option<&TerrainMask>) -> bool {
+    terrain_mask.is_some_and(|mask| mask.is_solid(x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terrain(width: usize, height: usize, min_x: isize) -> InGameTerrain {
+        InGameTerrain { bitmap: vec![0xff00ff00; width * height], width, height, min_x, ..Default::default() }
+    }
+
+    #[test]
+    fn changing_terrain_marks_only_that_point() {
+        let mut terrain = terrain(20, 10, -4);
+        let mut mask = TerrainMask::new(-4, 20, 10);
+        mask.set_solid(3, 5, true);
+        change_terrain(&mut terrain, &mut mask, 3, 5, None);
+        assert!(!mask.is_solid(3, 5));
+        assert_eq!(terrain.bitmap[5 * 20 + 7], level_renderer::LEVEL_BACKGROUND);
+        assert_eq!(terrain.dirty.pop(), Some(Rect::point(3, 5)));
+        assert_eq!(terrain.dirty.pop(), None);
+    }
+
+    #[test]
+    fn patching_only_rewrites_the_dirty_rect() {
+        let scale = 2;
+        let (slice_x, slice_width, height) = (4, 8, 6); // Scaled pixels.
+        let mut data = vec![0u8; slice_width * height * 4];
+        let area = Rect::point(3, 1); // Game points, so scaled pixels 6-7 across and 2-3 down.
+        let scaled_area = area.expanded(1);
+        let scaled = vec![0xffffffff; (scaled_area.width * scaled_area.height * scale * scale) as usize];
+        patch_slice_data(&mut data, slice_x, slice_width, &scaled, area, scaled_area, scale).unwrap();
+        for y in 0..height {
+            for x in 0..slice_width {
+                let is_in_area = (2..4).contains(&y) && (2..4).contains(&x);
+                let pixel = &data[(y * slice_width + x) * 4..(y * slice_width + x) * 4 + 4];
+                assert_eq!(pixel.iter().all(|c| *c == 0xff), is_in_area, "{},{}", x, y);
+                assert_eq!(pixel.iter().all(|c| *c == 0), !is_in_area, "{},{}", x, y);
             }
-            return false
-        } else {
-            false
         }
-    } else {
-        false
+    }
+
+    #[test]
+    fn patching_with_sizes_that_dont_add_up_is_an_error() {
+        let scale = 2;
+        let (slice_x, slice_width, height) = (4, 8, 6);
+        let area = Rect::point(3, 1);
+        let scaled_area = area.expanded(1);
+        let scaled = vec![0xffffffff; (scaled_area.width * scaled_area.height * scale * scale) as usize];
+        let mut data = vec![0u8; slice_width * height * 4];
+        assert!(patch_slice_data(&mut data, slice_x, slice_width, &scaled[1..], area, scaled_area, scale).is_err());
+        assert!(patch_slice_data(&mut data, slice_x, slice_width, &scaled, area.expanded(2), scaled_area, scale).is_err());
+        assert!(patch_slice_data(&mut data[4..], slice_x, slice_width, &scaled, area, scaled_area, scale).is_err());
+        assert!(patch_slice_data(&mut data[..slice_width * 4], slice_x, slice_width, &scaled, area, scaled_area, scale).is_err());
+        assert!(data.iter().all(|c| *c == 0));
+    }
+
+    #[test]
+    fn digging_removes_a_row_until_there_is_none() {
+        let mut terrain = terrain(20, 10, 0);
+        let mut mask = TerrainMask::new(0, 20, 10);
+        for x in 0..20 {
+            mask.set_solid(x, 8, true);
+        }
+        assert!(dig_row(&mut terrain, &mut mask, 10, 8));
+        assert!((6..=14).all(|x| !mask.is_solid(x, 8)));
+        assert!(mask.is_solid(5, 8) && mask.is_solid(15, 8));
+        assert_eq!(terrain.dirty.pop(), Some(Rect { x: 6, y: 8, width: 9, height: 1 }));
+        assert!(!dig_row(&mut terrain, &mut mask, 10, 9));
     }
 }
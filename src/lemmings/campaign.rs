@@ -0,0 +1,129 @@
+// This is the order you play the levels in: each game has a few ratings (Fun, Tricky...), each played straight through.
+// Levels are keyed by their index key rather than name, as some names are used by more than one level.
+// Level codes count the game's fixed number of levels per rating, so a level only has a number, and so a code, if its
+// rating's list is complete. Otherwise its place in the list isn't its place in the original game.
+
+use crate::lemmings::levels_per_game_and_skill::{level_keys_per_game_and_skill, levels_per_rating, ratings_per_game};
+use crate::lemmings::models::Game;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CampaignPosition {
+    pub rating: usize,
+    pub index: usize, // Within the rating, 0-based.
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CampaignLevel {
+    pub key: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AfterLevel {
+    NextLevel(CampaignPosition),
+    RatingComplete { next_rating: Option<usize> }, // None once the last rating is done.
+}
+
+pub struct Campaign {
+    pub ratings: Vec<Vec<CampaignLevel>>,
+    levels_per_rating: usize, // In the original game.
+}
+
+impl Campaign {
+    pub fn new(ratings: Vec<Vec<CampaignLevel>>, levels_per_rating: usize) -> Campaign {
+        Campaign { ratings, levels_per_rating }
+    }
+
+    pub fn for_game(game: &Game) -> Campaign {
+        let ratings = (0..ratings_per_game(&game.id)).map(|rating| {
+            level_keys_per_game_and_skill(game, rating as isize).into_iter()
+                .filter_map(|key| Some(CampaignLevel { key, name: game.index.level_name(key)? }))
+                .collect()
+        }).collect();
+        Campaign::new(ratings, levels_per_rating(&game.id))
+    }
+
+    pub fn level(&self, position: CampaignPosition) -> Option<&CampaignLevel> {
+        self.ratings.get(position.rating)?.get(position.index)
+    }
+
+    pub fn position_of(&self, rating: usize, key: i32) -> Option<CampaignPosition> {
+        let index = self.ratings.get(rating)?.iter().position(|l| l.key == key)?;
+        Some(CampaignPosition { rating, index })
+    }
+
+    pub fn first_in_rating(&self, rating: usize) -> Option<CampaignPosition> {
+        if self.ratings.get(rating)?.is_empty() { return None }
+        Some(CampaignPosition { rating, index: 0 })
+    }
+
+    fn is_rating_complete(&self, rating: usize) -> bool {
+        self.levels_per_rating > 0 && self.ratings.get(rating).is_some_and(|r| r.len() == self.levels_per_rating)
+    }
+
+    // Counting across all ratings, as the level codes do.
+    pub fn level_number(&self, position: CampaignPosition) -> Option<usize> {
+        if !self.is_rating_complete(position.rating) || position.index >= self.levels_per_rating { return None }
+        Some(position.rating * self.levels_per_rating + position.index)
+    }
+
+    // The reverse of level_number.
+    pub fn position_of_level_number(&self, level_number: usize) -> Option<CampaignPosition> {
+        if self.levels_per_rating == 0 { return None }
+        let position = CampaignPosition { rating: level_number / self.levels_per_rating, index: level_number % self.levels_per_rating };
+        self.level_number(position).map(|_| position)
+    }
+
+    // Where to go after winning the level at the given position.
+    pub fn after(&self, position: CampaignPosition) -> AfterLevel {
+        let Some(levels) = self.ratings.get(position.rating) else {
+            return AfterLevel::RatingComplete { next_rating: None }
+        };
+        if position.index + 1 < levels.len() {
+            return AfterLevel::NextLevel(CampaignPosition { rating: position.rating, index: position.index + 1 })
+        }
+        let next_rating = (position.rating + 1..self.ratings.len()).find(|r| self.first_in_rating(*r).is_some());
+        AfterLevel::RatingComplete { next_rating }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lemmings 1's shape: 4 ratings of 30, keyed like the game's index keys.
+    fn campaign(levels_in_ratings: &[usize]) -> Campaign {
+        let ratings = levels_in_ratings.iter().enumerate().map(|(rating, &count)| {
+            (0..count).map(|i| CampaignLevel { key: (rating * 100 + i) as i32, name: format!("Level {}", i + 1) }).collect()
+        }).collect();
+        Campaign::new(ratings, 30)
+    }
+
+    #[test]
+    fn level_numbers_round_trip_across_every_slot() {
+        let campaign = campaign(&[30, 30, 30, 30]);
+        for number in 0..120 {
+            let position = campaign.position_of_level_number(number).unwrap();
+            assert_eq!(position, CampaignPosition { rating: number / 30, index: number % 30 });
+            assert_eq!(campaign.level_number(position), Some(number));
+        }
+        assert_eq!(campaign.position_of_level_number(120), None);
+    }
+
+    #[test]
+    fn an_incomplete_rating_has_no_level_numbers() {
+        let campaign = campaign(&[30, 9, 30, 30]);
+        assert_eq!(campaign.level_number(CampaignPosition { rating: 0, index: 29 }), Some(29));
+        assert_eq!(campaign.level_number(CampaignPosition { rating: 1, index: 0 }), None);
+        assert_eq!(campaign.position_of_level_number(30), None);
+        assert_eq!(campaign.position_of_level_number(60), Some(CampaignPosition { rating: 2, index: 0 }));
+    }
+
+    #[test]
+    fn after_moves_through_ratings() {
+        let campaign = campaign(&[2, 0, 1]);
+        assert_eq!(campaign.after(CampaignPosition { rating: 0, index: 0 }), AfterLevel::NextLevel(CampaignPosition { rating: 0, index: 1 }));
+        assert_eq!(campaign.after(CampaignPosition { rating: 0, index: 1 }), AfterLevel::RatingComplete { next_rating: Some(2) });
+        assert_eq!(campaign.after(CampaignPosition { rating: 2, index: 0 }), AfterLevel::RatingComplete { next_rating: None });
+    }
+}
@@ -0,0 +1,141 @@
+// This draws what the player sees in a level on the CPU, for when there's no GPU, eg screenshots and replay videos on
+// a headless CI box. ingame.rs fills in a Scene from its sprites, so this only knows about game points and bitmaps.
+// Things are layered as the sprites' z's have them in ingame.rs: objects that don't overwrite terrain go behind it, the
+// other objects in front, then the lemmings, then the skill panel along the bottom. Objects follow their modifier and
+// upside down flag the same way level_renderer.rs draws them, but against the terrain as it is now.
+
+use crate::lemmings::level_renderer::LEVEL_HEIGHT;
+use crate::lemmings::models::*;
+use crate::lemmings::sizes;
+
+const SCREEN_BACKGROUND: u32 = 0x000000ff; // Opaque black, as the terrain's background is see-through.
+const PANEL_BUTTONS_WITH_DIGITS: usize = 10; // The two release rates, then the 8 skills.
+const PANEL_DIGITS_TOP: isize = 17;
+const PANEL_SELECTION_TOP: isize = 15; // The selection indicators sit at the bottom of the panel.
+const PAUSE_BUTTON_INDEX: isize = 10;
+
+pub struct SceneObject {
+    pub obj_id: usize,
+    pub x: i32, // Top-left in game points.
+    pub y: i32,
+    pub frame: usize,
+    pub modifier: ObjectModifier,
+    pub is_upside_down: bool,
+}
+
+pub struct SceneLemming<'a> {
+    pub animation: &'a Animation,
+    pub frame: usize,
+    pub x: i32, // The middle of the sprite in game points, as ingame.rs positions them.
+    pub y: i32,
+}
+
+pub struct ScenePanel {
+    pub values: [isize; PANEL_BUTTONS_WITH_DIGITS], // What each button's digits show, as in update_panel_digits_system.
+    pub selected_skill: Option<usize>, // The button index.
+    pub is_paused: bool,
+}
+
+pub struct Scene<'a> {
+    pub terrain: &'a [u32], // The level as it is now, with whatever's been dug out. It's LEVEL_HEIGHT tall.
+    pub terrain_width: usize,
+    pub terrain_min_x: isize, // Game point x of the terrain's first column.
+    pub view_x: isize, // Game point x of the left of the screen.
+    pub view_width: usize,
+    pub objects: Vec<SceneObject>,
+    pub lemmings: Vec<SceneLemming<'a>>,
+    pub panel: Option<ScenePanel>,
+}
+
+// Draws the sprite's non see-through pixels with its top-left at x, y.
+fn blit(canvas: &mut Image, sprite: &[u32], sprite_width: usize, sprite_height: usize, x: isize, y: isize) {
+    for sprite_y in 0..sprite_height {
+        let canvas_y = y + sprite_y as isize;
+        if canvas_y < 0 || canvas_y >= canvas.height as isize { continue }
+        for sprite_x in 0..sprite_width {
+            let canvas_x = x + sprite_x as isize;
+            if canvas_x < 0 || canvas_x >= canvas.width as isize { continue }
+            let pixel = sprite[sprite_y * sprite_width + sprite_x];
+            if pixel & 0xff == 0 { continue }
+            canvas.bitmap[canvas_y as usize * canvas.width + canvas_x as usize] = pixel;
+        }
+    }
+}
+
+fn blit_image(canvas: &mut Image, image: &Image, x: isize, y: isize) {
+    blit(canvas, &image.bitmap, image.width, image.height, x, y);
+}
+
+// Whether there's terrain at the game point, for objects that only show over it.
+fn is_terrain_at(scene: &Scene, x: isize, y: isize) -> bool {
+    let x = x - scene.terrain_min_x;
+    if x < 0 || x >= scene.terrain_width as isize || y < 0 { return false }
+    scene.terrain.get(y as usize * scene.terrain_width + x as usize).is_some_and(|&pixel| pixel & 0xff != 0)
+}
+
+fn draw_objects(canvas: &mut Image, scene: &Scene, ground: &GroundCombined, is_behind_terrain: bool) {
+    for object in scene.objects.iter().filter(|o| o.modifier.is_do_not_overwrite_existing_terrain() == is_behind_terrain) {
+        let Some(sprite) = ground.object_sprites.get(&(object.obj_id as i32)) else { continue };
+        let Some(frame) = sprite.frames.get(object.frame) else { continue };
+        let needs_terrain = object.modifier.is_must_have_terrain_underneath_to_be_visible();
+        for sprite_y in 0..sprite.height {
+            let y = object.y as isize + sprite_y as isize;
+            if y < 0 || y >= canvas.height as isize { continue }
+            let row = if object.is_upside_down { sprite.height - 1 - sprite_y } else { sprite_y };
+            for sprite_x in 0..sprite.width {
+                let x = object.x as isize + sprite_x as isize;
+                let canvas_x = x - scene.view_x;
+                if canvas_x < 0 || canvas_x >= canvas.width as isize { continue }
+                let pixel = frame[row * sprite.width + sprite_x];
+                if pixel & 0xff == 0 { continue }
+                if needs_terrain && !is_terrain_at(scene, x, y) { continue }
+                canvas.bitmap[y as usize * canvas.width + canvas_x as usize] = pixel;
+            }
+        }
+    }
+}
+
+// As laid out in enter_and_spawn_bottom_skill_panel, with the panel's top-left at x, y.
+fn draw_panel(canvas: &mut Image, panel: &ScenePanel, main: &MainDat, x: isize, y: isize) {
+    let button_width = sizes::SKILL_PANEL_BUTTON_WIDTH as isize;
+    blit_image(canvas, &main.skill_panel, x, y);
+    for (index, &value) in panel.values.iter().enumerate() {
+        let digits_x = x + index as isize * button_width + 4;
+        let digits = &main.skill_number_digits;
+        if value / 10 != 0 { // No leading zeros.
+            blit_image(canvas, &digits.left[(value / 10).clamp(0, 9) as usize], digits_x, y + PANEL_DIGITS_TOP);
+        }
+        if value != 0 { // Nothing at all if they're both 0.
+            blit_image(canvas, &digits.right[(value % 10).clamp(0, 9) as usize], digits_x, y + PANEL_DIGITS_TOP);
+        }
+    }
+    if let Some(index) = panel.selected_skill {
+        blit_image(canvas, &main.skill_selection, x + index as isize * button_width, y + PANEL_SELECTION_TOP);
+    }
+    if panel.is_paused {
+        blit_image(canvas, &main.pause_selection, x + PAUSE_BUTTON_INDEX * button_width, y + PANEL_SELECTION_TOP);
+    }
+}
+
+// At the game's original resolution: the view's width, by the level's height plus the panel's if there is one. If the
+// view's narrower than the panel, the panel's cut off on the right.
+pub fn compose(scene: &Scene, ground: &GroundCombined, main: &MainDat) -> Image {
+    let panel_height = if scene.panel.is_some() { sizes::SKILL_PANEL_HEIGHT } else { 0 };
+    let height = LEVEL_HEIGHT as usize + panel_height;
+    let mut canvas = Image { bitmap: vec![SCREEN_BACKGROUND; scene.view_width * height], width: scene.view_width, height };
+    draw_objects(&mut canvas, scene, ground, true);
+    let terrain_height = scene.terrain.len() / scene.terrain_width.max(1);
+    blit(&mut canvas, scene.terrain, scene.terrain_width, terrain_height, scene.terrain_min_x - scene.view_x, 0);
+    draw_objects(&mut canvas, scene, ground, false);
+    for lemming in scene.lemmings.iter() {
+        let animation = lemming.animation;
+        let Some(frame) = animation.frames.get(lemming.frame) else { continue };
+        let left = lemming.x as isize - scene.view_x - animation.width as isize / 2;
+        let top = lemming.y as isize - animation.height as isize / 2;
+        blit(&mut canvas, frame, animation.width, animation.height, left, top);
+    }
+    if let Some(panel) = &scene.panel {
+        draw_panel(&mut canvas, panel, main, 0, LEVEL_HEIGHT);
+    }
+    canvas
+}
@@ -0,0 +1,534 @@
+// This is an RFC1951 DEFLATE compressor, for png.rs, small enough to not be worth a dependency.
+// It finds repeats with LZ77 (hash chains over the 32K window, with one step of lazy matching), then writes each block
+// whichever way is smallest: stored, with the fixed Huffman codes, or with codes made for the block.
+// See: https://datatracker.ietf.org/doc/html/rfc1951
+// Test output with: ruby -rzlib -e 'print Zlib::Inflate.new(-15).inflate(STDIN.read)' < foo.deflateStream
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+pub const WINDOW_SIZE: usize = 32768;
+pub const MIN_MATCH: usize = 3;
+pub const MAX_MATCH: usize = 258;
+pub const END_OF_BLOCK: usize = 256;
+pub const LITERAL_LENGTH_CODES: usize = 286;
+pub const DISTANCE_CODES: usize = 30;
+pub const MAX_CODE_BITS: usize = 15;
+pub const MAX_CODE_LENGTH_BITS: usize = 7;
+
+// Length codes 257-285 and distance codes 0-29: the smallest value each stands for, and how many extra bits follow.
+pub const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+pub const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+pub const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+pub const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// The order a dynamic block's header gives the code length codes' lengths in, rarest last so they can be left off.
+pub const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 128; // How many earlier places to try for each match. More is smaller but slower.
+const LAZY_LIMIT: usize = 32; // Matches at least this long are taken straight away rather than checking the next byte.
+const BLOCK_TOKENS: usize = 16384; // Each block gets its own codes, so they can follow changes in the data.
+
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+// The fixed Huffman codes' lengths, from RFC1951 3.2.6. There are 288 literal/length codes but 286 and 287 never occur.
+pub fn fixed_literal_lengths() -> Vec<u8> {
+    (0..288).map(|symbol| match symbol {
+        0..=143 => 8,
+        144..=255 => 9,
+        256..=279 => 7,
+        _ => 8,
+    }).collect()
+}
+
+pub fn fixed_distance_lengths() -> Vec<u8> {
+    vec![5; 32]
+}
+
+// Which of the length or distance codes a value falls in.
+pub fn length_code(length: usize) -> usize {
+    LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1
+}
+
+pub fn distance_code(distance: usize) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1
+}
+
+// Bits go in from the least significant end of each byte.
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter { bytes: Vec::new(), buffer: 0, count: 0 }
+    }
+
+    fn write(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go in most significant bit first, unlike everything else.
+    fn write_code(&mut self, code: u16, length: u8) {
+        self.write(reverse_bits(code, length) as u32, length as u32);
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer = 0;
+            self.count = 0;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.align_to_byte();
+        self.bytes
+    }
+}
+
+pub fn reverse_bits(code: u16, length: u8) -> u16 {
+    if length == 0 { return 0 }
+    code.reverse_bits() >> (16 - length as u32)
+}
+
+// The codes for a set of lengths, as in RFC1951 3.2.2. Symbols with length 0 aren't used and get 0.
+pub fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut length_counts = [0u16; MAX_CODE_BITS + 1];
+    for &length in lengths {
+        length_counts[length as usize] += 1;
+    }
+    length_counts[0] = 0;
+    let mut next_code = [0u16; MAX_CODE_BITS + 1];
+    let mut code = 0u16;
+    for bits in 1..=MAX_CODE_BITS {
+        code = (code + length_counts[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+    lengths.iter().map(|&length| {
+        if length == 0 { return 0 }
+        let code = next_code[length as usize];
+        next_code[length as usize] += 1;
+        code
+    }).collect()
+}
+
+// Huffman code lengths for the frequencies, none longer than max_bits. Symbols that don't occur get 0.
+pub fn code_lengths(frequencies: &[u32], max_bits: usize) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+    let used: Vec<usize> = (0..frequencies.len()).filter(|&s| frequencies[s] > 0).collect();
+    match used.len() {
+        0 => return lengths,
+        1 => {
+            lengths[used[0]] = 1; // A code needs at least a bit, even when there's only the one thing to say.
+            return lengths
+        },
+        _ => {},
+    }
+
+    // Build the tree bottom up: leaves are 0..used.len(), each joining makes a new node after them.
+    let mut parents = vec![0usize; used.len() * 2 - 1];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = used.iter().enumerate().map(|(node, &s)| Reverse((frequencies[s] as u64, node))).collect();
+    let mut next_node = used.len();
+    while heap.len() > 1 {
+        let Reverse((a_weight, a)) = heap.pop().unwrap();
+        let Reverse((b_weight, b)) = heap.pop().unwrap();
+        parents[a] = next_node;
+        parents[b] = next_node;
+        heap.push(Reverse((a_weight + b_weight, next_node)));
+        next_node += 1;
+    }
+    let root = next_node - 1;
+    let mut depths = vec![0usize; parents.len()];
+    for node in (0..root).rev() {
+        depths[node] = depths[parents[node]] + 1; // Parents always come after their children, so are done first.
+    }
+
+    // Too-long codes are cut to max_bits, which over-fills the code space, then shorter codes are moved down a level
+    // until it fits exactly again. This is the approach zlib and miniz take.
+    let mut length_counts = vec![0usize; max_bits + 1];
+    for &depth in &depths[..used.len()] {
+        length_counts[depth.min(max_bits)] += 1;
+    }
+    let mut total: usize = (1..=max_bits).map(|bits| length_counts[bits] << (max_bits - bits)).sum();
+    while total > 1 << max_bits {
+        length_counts[max_bits] -= 1;
+        if let Some(bits) = (1..max_bits).rev().find(|&bits| length_counts[bits] != 0) {
+            length_counts[bits] -= 1;
+            length_counts[bits + 1] += 2;
+        }
+        total -= 1;
+    }
+
+    // The most frequent symbols get the shortest codes.
+    let mut by_frequency = used;
+    by_frequency.sort_by_key(|&s| (Reverse(frequencies[s]), s));
+    let mut symbols = by_frequency.into_iter();
+    for (bits, &count) in length_counts.iter().enumerate().skip(1) {
+        for _ in 0..count {
+            lengths[symbols.next().unwrap()] = bits as u8;
+        }
+    }
+    lengths
+}
+
+struct Matcher<'a> {
+    data: &'a [u8],
+    head: Vec<i32>,
+    previous: Vec<i32>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data: &'a [u8]) -> Matcher<'a> {
+        Matcher { data, head: vec![-1; 1 << HASH_BITS], previous: vec![-1; WINDOW_SIZE] }
+    }
+
+    fn hash(&self, position: usize) -> usize {
+        let d = self.data;
+        let key = (d[position] as u32) << 16 | (d[position + 1] as u32) << 8 | d[position + 2] as u32;
+        (key.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, position: usize) {
+        if position + MIN_MATCH > self.data.len() { return }
+        let hash = self.hash(position);
+        self.previous[position % WINDOW_SIZE] = self.head[hash];
+        self.head[hash] = position as i32;
+    }
+
+    // The longest earlier repeat of what's at the position, as (length, distance). Length is 0 if there's none.
+    fn longest_match(&self, position: usize) -> (usize, usize) {
+        let data = self.data;
+        if position + MIN_MATCH > data.len() { return (0, 0) }
+        let max_length = (data.len() - position).min(MAX_MATCH);
+        let mut best = (0, 0);
+        let mut candidate = self.head[self.hash(position)];
+        for _ in 0..MAX_CHAIN {
+            if candidate < 0 { break }
+            let start = candidate as usize;
+            let distance = position - start;
+            if distance > WINDOW_SIZE { break }
+            // Only worth comparing fully if it could beat the best so far.
+            if data[start + best.0.min(max_length - 1)] == data[position + best.0.min(max_length - 1)] {
+                let length = data[start..start + max_length].iter().zip(&data[position..position + max_length]).take_while(|(a, b)| a == b).count();
+                if length > best.0 {
+                    best = (length, distance);
+                    if length == max_length { break }
+                }
+            }
+            let next = self.previous[start % WINDOW_SIZE];
+            if next >= candidate { break } // The slot's been reused by something newer, so the chain ends here.
+            candidate = next;
+        }
+        if best.0 < MIN_MATCH { (0, 0) } else { best }
+    }
+}
+
+fn tokenise(data: &[u8]) -> Vec<Token> {
+    let mut matcher = Matcher::new(data);
+    let mut tokens = Vec::<Token>::with_capacity(data.len() / 4);
+    let mut position = 0;
+    while position < data.len() {
+        let (length, distance) = matcher.longest_match(position);
+        if length == 0 {
+            matcher.insert(position);
+            tokens.push(Token::Literal(data[position]));
+            position += 1;
+            continue
+        }
+        matcher.insert(position);
+        if length < LAZY_LIMIT && matcher.longest_match(position + 1).0 > length {
+            // Better to leave this byte as it is and take the longer match that starts after it.
+            tokens.push(Token::Literal(data[position]));
+            position += 1;
+            continue
+        }
+        for skipped in position + 1..position + length {
+            matcher.insert(skipped);
+        }
+        tokens.push(Token::Match { length: length as u16, distance: distance as u16 });
+        position += length;
+    }
+    tokens
+}
+
+// The lengths for a dynamic block's header, run-length encoded as (code length code, extra bits value).
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut encoded = Vec::<(u8, u8)>::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let length = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == length).count();
+        if length == 0 && run >= 11 {
+            let run = run.min(138);
+            encoded.push((18, (run - 11) as u8));
+            i += run;
+        } else if length == 0 && run >= 3 {
+            encoded.push((17, (run - 3) as u8));
+            i += run;
+        } else if length != 0 && run >= 4 {
+            // The length itself, then repeats of it.
+            encoded.push((length, 0));
+            let repeats = (run - 1).min(6);
+            encoded.push((16, (repeats - 3) as u8));
+            i += 1 + repeats;
+        } else {
+            encoded.push((length, 0));
+            i += 1;
+        }
+    }
+    encoded
+}
+
+fn code_length_extra_bits(code: u8) -> u32 {
+    match code {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+struct Codes {
+    literal_lengths: Vec<u8>,
+    literal_codes: Vec<u16>,
+    distance_lengths: Vec<u8>,
+    distance_codes: Vec<u16>,
+}
+
+impl Codes {
+    fn new(literal_lengths: Vec<u8>, distance_lengths: Vec<u8>) -> Codes {
+        let literal_codes = canonical_codes(&literal_lengths);
+        let distance_codes = canonical_codes(&distance_lengths);
+        Codes { literal_lengths, literal_codes, distance_lengths, distance_codes }
+    }
+
+    // How many bits the tokens and the end of block take with these codes.
+    fn data_bits(&self, tokens: &[Token]) -> usize {
+        let mut bits = self.literal_lengths[END_OF_BLOCK] as usize;
+        for token in tokens {
+            bits += match *token {
+                Token::Literal(byte) => self.literal_lengths[byte as usize] as usize,
+                Token::Match { length, distance } => {
+                    let (l, d) = (length_code(length as usize), distance_code(distance as usize));
+                    self.literal_lengths[257 + l] as usize + LENGTH_EXTRA[l] as usize
+                        + self.distance_lengths[d] as usize + DISTANCE_EXTRA[d] as usize
+                },
+            };
+        }
+        bits
+    }
+
+    fn write_data(&self, writer: &mut BitWriter, tokens: &[Token]) {
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => writer.write_code(self.literal_codes[byte as usize], self.literal_lengths[byte as usize]),
+                Token::Match { length, distance } => {
+                    let (length, distance) = (length as usize, distance as usize);
+                    let (l, d) = (length_code(length), distance_code(distance));
+                    writer.write_code(self.literal_codes[257 + l], self.literal_lengths[257 + l]);
+                    writer.write((length - LENGTH_BASE[l] as usize) as u32, LENGTH_EXTRA[l] as u32);
+                    writer.write_code(self.distance_codes[d], self.distance_lengths[d]);
+                    writer.write((distance - DISTANCE_BASE[d] as usize) as u32, DISTANCE_EXTRA[d] as u32);
+                },
+            }
+        }
+        writer.write_code(self.literal_codes[END_OF_BLOCK], self.literal_lengths[END_OF_BLOCK]);
+    }
+}
+
+// A dynamic block's codes and the header that describes them.
+struct DynamicHeader {
+    codes: Codes,
+    literal_count: usize,
+    distance_count: usize,
+    code_length_lengths: Vec<u8>,
+    code_length_count: usize,
+    encoded_lengths: Vec<(u8, u8)>,
+}
+
+impl DynamicHeader {
+    fn new(tokens: &[Token]) -> DynamicHeader {
+        let mut literal_frequencies = vec![0u32; LITERAL_LENGTH_CODES];
+        let mut distance_frequencies = vec![0u32; DISTANCE_CODES];
+        literal_frequencies[END_OF_BLOCK] = 1;
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => literal_frequencies[byte as usize] += 1,
+                Token::Match { length, distance } => {
+                    literal_frequencies[257 + length_code(length as usize)] += 1;
+                    distance_frequencies[distance_code(distance as usize)] += 1;
+                },
+            }
+        }
+        let literal_lengths = code_lengths(&literal_frequencies, MAX_CODE_BITS);
+        let mut distance_lengths = code_lengths(&distance_frequencies, MAX_CODE_BITS);
+        if distance_lengths.iter().all(|&l| l == 0) {
+            distance_lengths[0] = 1; // There must be at least one distance code, even if it's never used.
+        }
+        let literal_count = (257..=LITERAL_LENGTH_CODES).rev().find(|&n| literal_lengths[n - 1] != 0).unwrap_or(257);
+        let distance_count = (1..=DISTANCE_CODES).rev().find(|&n| distance_lengths[n - 1] != 0).unwrap_or(1);
+
+        let mut all_lengths = literal_lengths[..literal_count].to_vec();
+        all_lengths.extend_from_slice(&distance_lengths[..distance_count]);
+        let encoded_lengths = run_length_encode(&all_lengths);
+        let mut code_length_frequencies = vec![0u32; 19];
+        for &(code, _) in &encoded_lengths {
+            code_length_frequencies[code as usize] += 1;
+        }
+        let code_length_lengths = code_lengths(&code_length_frequencies, MAX_CODE_LENGTH_BITS);
+        let code_length_count = (4..=19).rev().find(|&n| code_length_lengths[CODE_LENGTH_ORDER[n - 1]] != 0).unwrap_or(4);
+
+        DynamicHeader {
+            codes: Codes::new(literal_lengths, distance_lengths),
+            literal_count,
+            distance_count,
+            code_length_lengths,
+            code_length_count,
+            encoded_lengths,
+        }
+    }
+
+    fn bits(&self) -> usize {
+        let lengths: usize = self.encoded_lengths.iter()
+            .map(|&(code, _)| self.code_length_lengths[code as usize] as usize + code_length_extra_bits(code) as usize)
+            .sum();
+        5 + 5 + 4 + 3 * self.code_length_count + lengths
+    }
+
+    fn write(&self, writer: &mut BitWriter) {
+        writer.write((self.literal_count - 257) as u32, 5);
+        writer.write((self.distance_count - 1) as u32, 5);
+        writer.write((self.code_length_count - 4) as u32, 4);
+        for &code in &CODE_LENGTH_ORDER[..self.code_length_count] {
+            writer.write(self.code_length_lengths[code] as u32, 3);
+        }
+        let code_length_codes = canonical_codes(&self.code_length_lengths);
+        for &(code, extra) in &self.encoded_lengths {
+            writer.write_code(code_length_codes[code as usize], self.code_length_lengths[code as usize]);
+            writer.write(extra as u32, code_length_extra_bits(code));
+        }
+    }
+}
+
+fn write_stored(writer: &mut BitWriter, raw: &[u8], is_final: bool) {
+    let chunks: Vec<&[u8]> = if raw.is_empty() { vec![raw] } else { raw.chunks(0xffff).collect() };
+    let last = chunks.len() - 1;
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        writer.write((is_final && index == last) as u32, 1);
+        writer.write(0, 2);
+        writer.align_to_byte();
+        let length = chunk.len() as u16;
+        writer.write(length as u32, 16);
+        writer.write(!length as u32, 16);
+        writer.bytes.extend_from_slice(chunk);
+    }
+}
+
+fn write_block(writer: &mut BitWriter, tokens: &[Token], raw: &[u8], is_final: bool) {
+    let fixed = Codes::new(fixed_literal_lengths(), fixed_distance_lengths());
+    let dynamic = DynamicHeader::new(tokens);
+    let fixed_bits = fixed.data_bits(tokens);
+    let dynamic_bits = dynamic.bits() + dynamic.codes.data_bits(tokens);
+    let stored_bits = (raw.len() + 5 * (raw.len() / 0xffff + 1)) * 8 + 7;
+    if stored_bits < fixed_bits.min(dynamic_bits) {
+        write_stored(writer, raw, is_final);
+    } else if fixed_bits <= dynamic_bits {
+        writer.write(is_final as u32, 1);
+        writer.write(1, 2);
+        fixed.write_data(writer, tokens);
+    } else {
+        writer.write(is_final as u32, 1);
+        writer.write(2, 2);
+        dynamic.write(writer);
+        dynamic.codes.write_data(writer, tokens);
+    }
+}
+
+// The input as a raw deflate stream, with no zlib header or checksum.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let tokens = tokenise(input);
+    let mut writer = BitWriter::new();
+    if tokens.is_empty() {
+        write_block(&mut writer, &[], &[], true);
+        return writer.finish()
+    }
+    let blocks: Vec<&[Token]> = tokens.chunks(BLOCK_TOKENS).collect();
+    let last = blocks.len() - 1;
+    let mut raw_start = 0;
+    for (index, block) in blocks.into_iter().enumerate() {
+        let raw_length: usize = block.iter().map(|token| match *token {
+            Token::Literal(_) => 1,
+            Token::Match { length, .. } => length as usize,
+        }).sum();
+        write_block(&mut writer, block, &input[raw_start..raw_start + raw_length], index == last);
+        raw_start += raw_length;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lemmings::inflate;
+
+    fn made_up_bytes(count: usize, alphabet: u32) -> Vec<u8> {
+        let mut state: u32 = 1;
+        (0..count).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state % alphabet) as u8
+        }).collect()
+    }
+
+    // The first block's type, from the 2 bits after its final flag.
+    fn first_block_type(compressed: &[u8]) -> u8 {
+        (compressed[0] >> 1) & 3
+    }
+
+    fn assert_round_trips(input: &[u8]) -> Vec<u8> {
+        let compressed = compress(input);
+        let (output, used) = inflate::decompress(&compressed).unwrap();
+        assert_eq!(output, input);
+        assert_eq!(used, compressed.len());
+        compressed
+    }
+
+    #[test]
+    fn empty_input_round_trips() {
+        assert_round_trips(&[]);
+    }
+
+    #[test]
+    fn noise_round_trips_as_stored_blocks() {
+        assert_eq!(first_block_type(&assert_round_trips(&made_up_bytes(1000, 256))), 0);
+        assert_eq!(first_block_type(&assert_round_trips(&made_up_bytes(200_000, 256))), 0); // Over 0xffff, so split up.
+    }
+
+    #[test]
+    fn a_little_text_round_trips_with_the_fixed_codes() {
+        assert_eq!(first_block_type(&assert_round_trips(b"Let's go! Let's go! Oh no! Oh no!")), 1);
+    }
+
+    #[test]
+    fn skewed_data_round_trips_with_its_own_codes() {
+        assert_eq!(first_block_type(&assert_round_trips(&made_up_bytes(10_000, 4))), 2);
+        let mut repeats = made_up_bytes(50_000, 16);
+        repeats.extend_from_within(1000..40_000); // Long matches, over several blocks.
+        assert_eq!(first_block_type(&assert_round_trips(&repeats)), 2);
+    }
+}
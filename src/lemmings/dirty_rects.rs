@@ -0,0 +1,30 @@
+// This keeps track of which areas of a level have changed, eg by digging or building, so only they need redrawing.
+// Everything is in game points, the same as the terrain mask.
+
+use crate::lemmings::geometry::Rect;
+
+#[derive(Debug, Default)]
+pub struct DirtyRects {
+    rects: Vec<Rect>,
+}
+
+impl DirtyRects {
+    // Merges with any it touches, so a lemming digging a tunnel makes one long rect rather than hundreds.
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() { return }
+        let mut merged = rect;
+        while let Some(index) = self.rects.iter().position(|r| r.touches(&merged)) {
+            merged = merged.union(&self.rects.swap_remove(index));
+        }
+        self.rects.push(merged);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    // Takes the next one to deal with.
+    pub fn pop(&mut self) -> Option<Rect> {
+        if self.rects.is_empty() { None } else { Some(self.rects.remove(0)) }
+    }
+}
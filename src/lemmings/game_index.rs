@@ -0,0 +1,257 @@
+// This is what's known about a game's files without decoding them: which levels, grounds and specials there are, and
+// the levels' names so they can be looked up. Building it only needs the level files decompressed to read the names.
+// Everything else is decoded the first time it's asked for, then kept in an LRU cache and shared through Arc, so
+// entering a level or going back to the menus doesn't redo it, but a game's worth of sprites isn't all held at once.
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::lemmings::loader::{numbered_files, parallel_map};
+use crate::lemmings::lru_cache::LruCache;
+use crate::lemmings::models::*;
+use crate::lemmings::parsers::*;
+
+const LEVEL_CACHE_SIZE: usize = 16;
+const GROUND_CACHE_SIZE: usize = 4; // Each has all its terrain and object sprites.
+const SPECIAL_CACHE_SIZE: usize = 2; // Each is a whole level's worth of pixels.
+
+// Where to find a level.
+struct LevelEntry {
+    key: i32, // As in LevelMap, see models.rs.
+    name: String,
+    file_name: String,
+    section: usize,
+    odd_table_entry: Option<OddTableEntry>, // For the renamed re-uses from ODDTABLE.DAT, applied to the section's level.
+}
+
+pub struct GameIndex {
+    dir: String,
+    levels: Vec<LevelEntry>, // In key order.
+    grounds: Vec<i32>, // The numbers of the vgagrN.dat/groundNo.dat pairs.
+    specials: Vec<(i32, String)>, // Number and file name.
+    level_cache: Mutex<LruCache<i32, Level>>,
+    ground_cache: Mutex<LruCache<i32, GroundCombined>>,
+    special_cache: Mutex<LruCache<i32, Image>>,
+}
+
+fn not_found(what: String) -> Error {
+    Error::new(ErrorKind::NotFound, what)
+}
+
+// Returns the lock even if another thread panicked while holding it, as the caches are always left consistent.
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn decompress_file(dir: &str, file_name: &str) -> Result<Vec<Vec<u8>>> {
+    let raw: Vec<u8> = fs::read(format!("{}/{}", dir, file_name))?;
+    decompressor::decompress(&raw)
+}
+
+// Load a ground file and its associated vga graphics.
+fn load_ground_and_sprites(dir: &str, index: i32) -> Result<GroundCombined> {
+    let vga_file: Vec<u8> = fs::read(format!("{}/vgagr{}.dat", dir, index))?;
+    let vga_sections = decompressor::decompress(&vga_file)?;
+
+    let ground_file: Vec<u8> = fs::read(format!("{}/ground{}o.dat", dir, index))?;
+    let ground = ground::parse(&ground_file)?;
+    let palette = ground.palettes.as_rgba();
+
+    let mut terrain_sprites: ImageMap = ImageMap::new();
+    for (i, terrain) in ground.terrain_info.iter().enumerate() {
+        if terrain.is_valid() {
+            let sprite = sprites::extract_image(&vga_sections[0], terrain.width, terrain.height, terrain.image_loc, terrain.mask_loc, &palette);
+            terrain_sprites.insert(i as i32, sprite);
+        }
+    }
+
+    let mut object_sprites: AnimationMap = AnimationMap::new();
+    for (i, object) in ground.object_info.iter().enumerate() {
+        if object.is_valid() {
+            let sprite = sprites::extract_animation(&vga_sections[1], object.width, object.height, object.animation_frames_base_loc as usize, object.animation_frames_base_loc as usize + object.mask_offset_from_image as usize, &palette, object.animation_frame_data_size as usize, object.frame_count as usize);
+            object_sprites.insert(i as i32, sprite);
+        }
+    }
+
+    Ok(GroundCombined {
+        ground,
+        terrain_sprites,
+        object_sprites,
+    })
+}
+
+// Finds every level and its name. The level files are read on a thread each.
+fn scan_levels(dir: &str) -> Result<Vec<LevelEntry>> {
+    let mut files = numbered_files(dir, "level", 5..8)?;
+    files.extend(numbered_files(dir, "dlvel", 5..8)?);
+    let names_per_file = parallel_map(&files, |(file_name, _)| {
+        decompress_file(dir, file_name)?.iter().map(|section| level::parse_name(section)).collect::<Result<Vec<String>>>()
+    })?;
+    let mut levels = Vec::<LevelEntry>::new();
+    for ((file_name, file_number), names) in files.iter().zip(names_per_file) {
+        for (section, name) in names.into_iter().enumerate() {
+            levels.push(LevelEntry { key: file_number * 100 + section as i32, name, file_name: file_name.clone(), section, odd_table_entry: None });
+        }
+    }
+    add_odd_table_levels(dir, &mut levels)?;
+    levels.sort_by_key(|l| l.key);
+    Ok(levels)
+}
+
+// Adds the renamed re-uses of levels from ODDTABLE.DAT, if the game has one, so they can be found by name like any other.
+fn add_odd_table_levels(dir: &str, levels: &mut Vec<LevelEntry>) -> Result<()> {
+    let path = format!("{}/oddtable.dat", dir);
+    if !Path::new(&path).exists() {
+        return Ok(());
+    }
+    let entries = oddtable::parse(&fs::read(path)?)?;
+    for (index, entry) in entries.into_iter().enumerate() {
+        let original_key = oddtable::level_key_for_entry(index);
+        let Some(original) = levels.iter().find(|l| l.key == original_key) else { continue };
+        if entry.name.is_empty() || entry.name == original.name { continue } // Unused entry.
+        let odd = LevelEntry {
+            key: ODD_TABLE_LEVEL_KEY + index as i32,
+            name: entry.name.clone(),
+            file_name: original.file_name.clone(),
+            section: original.section,
+            odd_table_entry: Some(entry),
+        };
+        levels.push(odd);
+    }
+    Ok(())
+}
+
+impl GameIndex {
+    pub fn scan(dir: &str) -> Result<GameIndex> {
+        let levels = scan_levels(dir)?;
+        let grounds = numbered_files(dir, "vgagr", 5..6)?.into_iter().map(|(_, n)| n).collect();
+        let specials = numbered_files(dir, "vgaspec", 7..8)?.into_iter().map(|(file_name, n)| (n, file_name)).collect();
+        Ok(GameIndex {
+            dir: dir.to_string(),
+            levels,
+            grounds,
+            specials,
+            level_cache: Mutex::new(LruCache::new(LEVEL_CACHE_SIZE)),
+            ground_cache: Mutex::new(LruCache::new(GROUND_CACHE_SIZE)),
+            special_cache: Mutex::new(LruCache::new(SPECIAL_CACHE_SIZE)),
+        })
+    }
+
+    pub fn level_keys(&self) -> Vec<i32> {
+        self.levels.iter().map(|l| l.key).collect()
+    }
+
+    // If two levels have the same name, it's the one with the lowest key.
+    pub fn level_key_named(&self, name: &str) -> Option<i32> {
+        self.levels.iter().find(|l| l.name == name).map(|l| l.key)
+    }
+
+    pub fn level_name(&self, key: i32) -> Option<String> {
+        self.levels.iter().find(|l| l.key == key).map(|l| l.name.clone())
+    }
+
+    // Every level with the name, in key order.
+    pub fn level_keys_named(&self, name: &str) -> Vec<i32> {
+        self.levels.iter().filter(|l| l.name == name).map(|l| l.key).collect()
+    }
+
+    pub fn level(&self, key: i32) -> Result<Arc<Level>> {
+        let Some(entry) = self.levels.iter().find(|l| l.key == key) else { return Err(not_found(format!("No level {}", key))) };
+        lock(&self.level_cache).get_or_load(key, || {
+            let sections = decompress_file(&self.dir, &entry.file_name)?;
+            let Some(section) = sections.get(entry.section) else { return Err(not_found(format!("No section {} in {}", entry.section, entry.file_name))) };
+            let level = level::parse(section)?;
+            Ok(match &entry.odd_table_entry {
+                Some(odd) => odd.apply_to(&level),
+                None => level,
+            })
+        })
+    }
+
+    pub fn ground_ids(&self) -> Vec<i32> {
+        self.grounds.clone()
+    }
+
+    pub fn ground(&self, id: i32) -> Result<Arc<GroundCombined>> {
+        if !self.grounds.contains(&id) { return Err(not_found(format!("No ground {}", id))) }
+        lock(&self.ground_cache).get_or_load(id, || load_ground_and_sprites(&self.dir, id))
+    }
+
+    pub fn special_ids(&self) -> Vec<i32> {
+        self.specials.iter().map(|(n, _)| *n).collect()
+    }
+
+    pub fn special(&self, id: i32) -> Result<Arc<Image>> {
+        let Some((_, file_name)) = self.specials.iter().find(|(n, _)| *n == id) else { return Err(not_found(format!("No special {}", id))) };
+        lock(&self.special_cache).get_or_load(id, || {
+            let sections = decompress_file(&self.dir, file_name)?;
+            let Some(section) = sections.first() else { return Err(not_found(format!("Nothing in {}", file_name))) };
+            special::parse(section)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    // A level file section with just a name and how many lemmings, which is all the index needs.
+    fn level_section(name: &str, num_of_lemmings: u16) -> Vec<u8> {
+        let mut level = Level { name: name.to_string(), ..Default::default() };
+        level.globals.num_of_lemmings = num_of_lemmings;
+        level::write(&level).unwrap()
+    }
+
+    // Levels in level000.dat and level002.dat, an odd table renaming level 1, and files the index should skip.
+    fn game_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rusty-lemmings-game-index-test-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("level000.dat"), decompressor::store(&[level_section("Zero", 10), level_section("One", 11)])).unwrap();
+        fs::write(dir.join("LEVEL002.DAT"), decompressor::store(&[level_section("Two hundred", 12), level_section("Zero", 13)])).unwrap();
+        fs::write(dir.join("levelx.dat"), []).unwrap();
+        fs::write(dir.join("vgagr3.dat"), []).unwrap();
+        let mut odd_table = vec![0u8; 56 * 2];
+        odd_table[56 + 2..56 + 4].copy_from_slice(&50u16.to_be_bytes()); // Entry 1, level 1, gets 50 lemmings...
+        odd_table[56 + 24..56 + 56].copy_from_slice(&[b' '; 32]); // ...and a new name.
+        odd_table[56 + 24..56 + 24 + 7].copy_from_slice(b"Renamed");
+        odd_table[24..56].copy_from_slice(b"Zero                            "); // The same as the original, so unused.
+        fs::write(dir.join("oddtable.dat"), odd_table).unwrap();
+        dir
+    }
+
+    #[test]
+    fn scan_finds_the_levels_by_key_and_name() {
+        let dir = game_dir("scan");
+        let index = GameIndex::scan(dir.to_str().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+        let index = index.unwrap();
+        assert_eq!(index.level_keys(), vec![0, 1, 200, 201, ODD_TABLE_LEVEL_KEY + 1]);
+        assert_eq!(index.level_name(200).as_deref(), Some("Two hundred"));
+        assert_eq!(index.level_key_named("Zero"), Some(0));
+        assert_eq!(index.level_keys_named("Zero"), vec![0, 201]);
+        assert_eq!(index.level_key_named("Renamed"), Some(ODD_TABLE_LEVEL_KEY + 1));
+        assert_eq!(index.ground_ids(), vec![3]);
+        assert!(index.special_ids().is_empty());
+    }
+
+    #[test]
+    fn levels_load_once_and_odd_table_ones_are_renamed() {
+        let dir = game_dir("load");
+        let index = GameIndex::scan(dir.to_str().unwrap()).unwrap();
+        let zero = index.level(0);
+        let again = index.level(0);
+        let other = index.level(201);
+        let renamed = index.level(ODD_TABLE_LEVEL_KEY + 1);
+        let missing = index.level(2);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(Arc::ptr_eq(&zero.unwrap(), &again.unwrap()));
+        assert_eq!(other.unwrap().globals.num_of_lemmings, 13);
+        let renamed = renamed.unwrap();
+        assert_eq!((renamed.name.as_str(), renamed.globals.num_of_lemmings), ("Renamed", 50));
+        assert_eq!(missing.unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
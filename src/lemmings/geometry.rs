@@ -0,0 +1,43 @@
+// Rectangles, in game points unless said otherwise, for dirty areas, hit boxes, steel and the like.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: isize,
+    pub y: isize,
+    pub width: isize,
+    pub height: isize,
+}
+
+impl Rect {
+    pub fn point(x: isize, y: isize) -> Rect {
+        Rect { x, y, width: 1, height: 1 }
+    }
+
+    pub fn right(&self) -> isize { self.x + self.width }
+    pub fn bottom(&self) -> isize { self.y + self.height }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect { x, y, width: self.right().max(other.right()) - x, height: self.bottom().max(other.bottom()) - y }
+    }
+
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Rect { x, y, width: self.right().min(other.right()) - x, height: self.bottom().min(other.bottom()) - y }
+    }
+
+    // Touching counts, so neighbouring changes get merged.
+    pub fn touches(&self, other: &Rect) -> bool {
+        self.x <= other.right() && other.x <= self.right() && self.y <= other.bottom() && other.y <= self.bottom()
+    }
+
+    pub fn expanded(&self, margin: isize) -> Rect {
+        Rect { x: self.x - margin, y: self.y - margin, width: self.width + margin * 2, height: self.height + margin * 2 }
+    }
+}
@@ -0,0 +1,185 @@
+// This is an RFC1951 DEFLATE decompressor, the other half of deflate.rs, for reading PNGs.
+// Huffman codes are decoded with a table indexed by the next bits of input, as many as the longest code, so each
+// symbol is one lookup.
+// See: https://datatracker.ietf.org/doc/html/rfc1951
+
+use std::io::{Error, ErrorKind, Result};
+use super::deflate::*;
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+// Bits come out from the least significant end of each byte.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize, // The next byte to go into the buffer.
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, position: 0, buffer: 0, count: 0 }
+    }
+
+    // Past the end reads as zeros, so a code near the end can be peeked at in full. Taking them fails though.
+    fn refill(&mut self) {
+        while self.count <= 56 {
+            let byte = self.data.get(self.position).copied().unwrap_or(0);
+            self.buffer |= (byte as u64) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+    }
+
+    fn peek(&mut self, bits: u32) -> u32 {
+        if self.count < bits { self.refill() }
+        (self.buffer & ((1u64 << bits) - 1)) as u32
+    }
+
+    fn consume(&mut self, bits: u32) -> Result<()> {
+        if self.count < bits { self.refill() }
+        self.buffer >>= bits;
+        self.count -= bits;
+        if self.bytes_used() > self.data.len() { return Err(Error::new(ErrorKind::UnexpectedEof, "Deflate stream ended early")) }
+        Ok(())
+    }
+
+    fn read(&mut self, bits: u32) -> Result<u32> {
+        if bits == 0 { return Ok(0) }
+        let value = self.peek(bits);
+        self.consume(bits)?;
+        Ok(value)
+    }
+
+    fn bytes_used(&self) -> usize {
+        self.position - (self.count / 8) as usize
+    }
+
+    // Skips to the next whole byte, for stored blocks.
+    fn align_to_byte(&mut self) -> Result<()> {
+        let partial = self.count % 8;
+        self.consume(partial)
+    }
+}
+
+struct Decoder {
+    table: Vec<(u16, u8)>, // Symbol and code length, for each possible value of the next max_bits bits.
+    max_bits: u32,
+}
+
+impl Decoder {
+    fn new(lengths: &[u8]) -> Result<Decoder> {
+        let max_bits = lengths.iter().copied().max().unwrap_or(0) as u32;
+        if max_bits == 0 { return Err(invalid("Empty Huffman code")) }
+        // Over-subscribed codes can't be decoded. Incomplete ones are allowed, eg a single distance code.
+        let space: u64 = lengths.iter().filter(|&&l| l > 0).map(|&l| 1u64 << (max_bits - l as u32)).sum();
+        if space > 1u64 << max_bits { return Err(invalid("Over-subscribed Huffman code")) }
+        let mut table = vec![(0u16, 0u8); 1 << max_bits];
+        for (symbol, (&length, &code)) in lengths.iter().zip(&canonical_codes(lengths)).enumerate() {
+            if length == 0 { continue }
+            let reversed = reverse_bits(code, length) as usize;
+            let mut index = reversed;
+            while index < table.len() {
+                table[index] = (symbol as u16, length);
+                index += 1 << length;
+            }
+        }
+        Ok(Decoder { table, max_bits })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<usize> {
+        let (symbol, length) = self.table[reader.peek(self.max_bits) as usize];
+        if length == 0 { return Err(invalid("Bad Huffman code")) }
+        reader.consume(length as u32)?;
+        Ok(symbol as usize)
+    }
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(Decoder, Decoder)> {
+    let literal_count = reader.read(5)? as usize + 257;
+    let distance_count = reader.read(5)? as usize + 1;
+    let code_length_count = reader.read(4)? as usize + 4;
+    if literal_count > LITERAL_LENGTH_CODES || distance_count > DISTANCE_CODES { return Err(invalid("Too many codes")) }
+    let mut code_length_lengths = [0u8; 19];
+    for &code in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[code] = reader.read(3)? as u8;
+    }
+    let code_length_decoder = Decoder::new(&code_length_lengths)?;
+
+    let mut lengths = Vec::<u8>::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeats) = match code_length_decoder.decode(reader)? {
+            length @ 0..=15 => (length as u8, 1),
+            16 => {
+                let Some(&previous) = lengths.last() else { return Err(invalid("Repeat with nothing before it")) };
+                (previous, 3 + reader.read(2)? as usize)
+            },
+            17 => (0, 3 + reader.read(3)? as usize),
+            _ => (0, 11 + reader.read(7)? as usize),
+        };
+        if lengths.len() + repeats > literal_count + distance_count { return Err(invalid("Code lengths run over")) }
+        lengths.extend(std::iter::repeat_n(value, repeats));
+    }
+    if lengths[END_OF_BLOCK] == 0 { return Err(invalid("No end of block code")) }
+    let distance_lengths = &lengths[literal_count..];
+    let distances = if distance_lengths.iter().all(|&l| l == 0) {
+        Decoder { table: vec![(0, 0)], max_bits: 0 } // Only literals, so any distance is an error.
+    } else {
+        Decoder::new(distance_lengths)?
+    };
+    Ok((Decoder::new(&lengths[..literal_count])?, distances))
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literals: &Decoder, distances: &Decoder) -> Result<()> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            output.push(symbol as u8);
+            continue
+        }
+        if symbol == END_OF_BLOCK { return Ok(()) }
+        let l = symbol - 257;
+        if l >= LENGTH_BASE.len() { return Err(invalid("Bad length code")) }
+        let length = LENGTH_BASE[l] as usize + reader.read(LENGTH_EXTRA[l] as u32)? as usize;
+        let d = distances.decode(reader)?;
+        if d >= DISTANCE_BASE.len() { return Err(invalid("Bad distance code")) }
+        let distance = DISTANCE_BASE[d] as usize + reader.read(DISTANCE_EXTRA[d] as u32)? as usize;
+        if distance > output.len() { return Err(invalid("Distance before the start")) }
+        let start = output.len() - distance;
+        for i in 0..length {
+            output.push(output[start + i]); // Byte by byte, as the copy can overlap what it's writing.
+        }
+    }
+}
+
+// A raw deflate stream, with no zlib header or checksum. Also returns how many bytes of input it took up.
+pub fn decompress(input: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let mut reader = BitReader::new(input);
+    let mut output = Vec::<u8>::new();
+    let fixed = (Decoder::new(&fixed_literal_lengths())?, Decoder::new(&fixed_distance_lengths())?);
+    loop {
+        let is_final = reader.read(1)? == 1;
+        match reader.read(2)? {
+            0 => {
+                reader.align_to_byte()?;
+                let length = reader.read(16)? as usize;
+                let complement = reader.read(16)? as usize;
+                if length != !complement & 0xffff { return Err(invalid("Stored block length doesn't match")) }
+                for _ in 0..length {
+                    output.push(reader.read(8)? as u8);
+                }
+            },
+            1 => inflate_block(&mut reader, &mut output, &fixed.0, &fixed.1)?,
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            },
+            _ => return Err(invalid("Bad block type")),
+        }
+        if is_final { break }
+    }
+    reader.align_to_byte()?;
+    Ok((output, reader.bytes_used()))
+}
@@ -0,0 +1,135 @@
+// This is a uniform grid of where the lemmings are, so finding the ones near a point doesn't mean checking them all.
+// Everything is in game points, the same as the terrain mask. Positions are a lemming's middle.
+// The grid covers the level; anything outside it goes in the nearest edge cell, so it can still be found.
+
+use std::cmp::Reverse;
+use crate::lemmings::geometry::Rect;
+use crate::lemmings::level_renderer::LEVEL_HEIGHT;
+
+const CELL_SIZE: i32 = 16; // The same as the selection box, so picking only ever looks at 4 cells.
+
+// The cursor's selection box, in points. The crosshair is 16x16 and anything whose middle is inside it can be picked.
+pub const SELECTION_LEFT: i32 = 8; // How far left and up of the cursor it goes.
+pub const SELECTION_SIZE: i32 = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GridEntry<T> {
+    pub x: i32,
+    pub y: i32,
+    pub lemming: T, // Whatever the caller wants to know about it.
+}
+
+pub struct LemmingGrid<T> {
+    min_x: i32,
+    columns: i32,
+    rows: i32,
+    cells: Vec<Vec<GridEntry<T>>>,
+}
+
+impl<T: Copy> Default for LemmingGrid<T> {
+    fn default() -> Self {
+        LemmingGrid::new(0, 0)
+    }
+}
+
+impl<T: Copy> LemmingGrid<T> {
+    // Width and min_x are the level's, as in RenderedLevel.
+    pub fn new(min_x: isize, width: usize) -> LemmingGrid<T> {
+        let columns = (width as i32 + CELL_SIZE - 1) / CELL_SIZE;
+        let rows = (LEVEL_HEIGHT as i32 + CELL_SIZE - 1) / CELL_SIZE;
+        let columns = columns.max(1);
+        let mut cells = Vec::with_capacity((columns * rows) as usize);
+        cells.resize_with((columns * rows) as usize, Vec::new);
+        LemmingGrid { min_x: min_x as i32, columns, rows, cells }
+    }
+
+    // Empties it without freeing the cells, for rebuilding each frame.
+    pub fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            cell.clear();
+        }
+    }
+
+    pub fn insert(&mut self, x: i32, y: i32, lemming: T) {
+        let index = self.cell_index(self.column(x), self.row(y));
+        self.cells[index].push(GridEntry { x, y, lemming });
+    }
+
+    fn column(&self, x: i32) -> i32 {
+        ((x - self.min_x).div_euclid(CELL_SIZE)).clamp(0, self.columns - 1)
+    }
+
+    fn row(&self, y: i32) -> i32 {
+        y.div_euclid(CELL_SIZE).clamp(0, self.rows - 1)
+    }
+
+    fn cell_index(&self, column: i32, row: i32) -> usize {
+        (row * self.columns + column) as usize
+    }
+
+    // Everything whose position is inside the rect, in no particular order.
+    pub fn within(&self, rect: &Rect) -> Vec<GridEntry<T>> {
+        let mut found = Vec::<GridEntry<T>>::new();
+        if rect.is_empty() { return found }
+        let (left, top) = (rect.x as i32, rect.y as i32);
+        let (right, bottom) = (rect.right() as i32, rect.bottom() as i32);
+        for row in self.row(top)..=self.row(bottom - 1) {
+            for column in self.column(left)..=self.column(right - 1) {
+                let cell = &self.cells[self.cell_index(column, row)];
+                found.extend(cell.iter().filter(|e| left <= e.x && e.x < right && top <= e.y && e.y < bottom));
+            }
+        }
+        found
+    }
+
+    // Which lemming a click at this point would pick: of those in the selection box, the one with the highest priority,
+    // and of those the one closest to the point. Priority returns None for lemmings that can't be picked at all.
+    pub fn pickable_at<K: Ord, F: Fn(&GridEntry<T>) -> Option<K>>(&self, x: i32, y: i32, priority: F) -> Option<T> {
+        let selection = Rect {
+            x: (x - SELECTION_LEFT) as isize,
+            y: (y - SELECTION_LEFT) as isize,
+            width: SELECTION_SIZE as isize,
+            height: SELECTION_SIZE as isize,
+        };
+        self.within(&selection).iter()
+            .filter_map(|e| {
+                let distance = (e.x - x).pow(2) + (e.y - y).pow(2);
+                priority(e).map(|p| ((p, Reverse(distance)), e.lemming))
+            })
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, lemming)| lemming)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.iter().map(|c| c.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.iter().all(|c| c.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_closest_of_equal_priority() {
+        let mut grid = LemmingGrid::<i32>::new(0, 320);
+        grid.insert(100, 50, 1);
+        grid.insert(104, 52, 2);
+        grid.insert(97, 49, 3);
+        assert_eq!(grid.pickable_at(103, 51, |_| Some(())), Some(2));
+        assert_eq!(grid.pickable_at(98, 50, |_| Some(())), Some(3));
+    }
+
+    #[test]
+    fn priority_comes_before_distance() {
+        let mut grid = LemmingGrid::<i32>::new(0, 320);
+        grid.insert(100, 50, 1);
+        grid.insert(106, 50, 2);
+        assert_eq!(grid.pickable_at(100, 50, |e| Some(e.lemming == 2)), Some(2));
+        assert_eq!(grid.pickable_at(100, 50, |e| (e.lemming != 1).then_some(())), Some(2));
+        assert_eq!(grid.pickable_at(130, 50, |_| Some(())), None);
+    }
+}
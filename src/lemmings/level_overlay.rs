@@ -0,0 +1,196 @@
+// This draws review markings over a rendered level: steel, object trigger areas, entrances and exits, where the screen
+// starts, and a header with the level's numbers. It's for the 'render-levels' command's sheets, not the game itself.
+// Colours are 0xRRGGBBAA like everything else.
+
+use crate::lemmings::geometry::Rect;
+use crate::lemmings::level_renderer::RenderedLevel;
+use crate::lemmings::models::*;
+use crate::ORIGINAL_GAME_W;
+
+const STEEL_COLOUR: u32 = 0x80c0ffff;
+const ENTRANCE_COLOUR: u32 = 0x00ffffff;
+const EXIT_COLOUR: u32 = 0x00ff00ff;
+const START_COLOUR: u32 = 0xffffffff;
+const HEADER_BACKGROUND: u32 = 0x000000ff;
+const FILL_ALPHA: u32 = 0x60; // How much of a fill's colour shows over the level.
+const GLYPH_WIDTH: usize = 8; // The game font's.
+const GLYPH_HEIGHT: usize = 16;
+const HEADER_PADDING: usize = 2;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Overlays {
+    pub steel: bool,
+    pub triggers: bool,
+    pub entrances_and_exits: bool,
+    pub start: bool,
+    pub header: bool,
+}
+
+impl Overlays {
+    pub fn all() -> Overlays {
+        Overlays { steel: true, triggers: true, entrances_and_exits: true, start: true, header: true }
+    }
+}
+
+// Eg exits are green, traps red, water blue. See ObjectInfo::trigger_effect_id for the ids.
+fn trigger_colour(effect_id: u8) -> u32 {
+    match effect_id {
+        1 => EXIT_COLOUR,
+        4 => 0xff2020ff, // Trap.
+        5 => 0x2060ffff, // Drown.
+        6 => 0xff9000ff, // Disintegrate.
+        7 | 8 => 0xffff00ff, // One way walls.
+        9 => STEEL_COLOUR,
+        _ => 0xff00ffff,
+    }
+}
+
+// The level with the overlays drawn on, plus the header above it if wanted.
+pub fn draw(level: &Level, ground: &GroundCombined, rendered: &RenderedLevel, font: &GameFont, overlays: Overlays) -> Image {
+    let mut image = rendered.image.clone();
+    let min_x = rendered.size.min_x;
+    if overlays.steel {
+        for steel in level.steel.iter() {
+            let rect = steel.rect();
+            let rect = Rect { x: rect.x - min_x, ..rect };
+            fill(&mut image, &rect, STEEL_COLOUR);
+            outline(&mut image, &rect, STEEL_COLOUR);
+        }
+    }
+    for object in level.objects.iter() {
+        let Some(info) = ground.ground.object_info.get(object.obj_id) else { continue };
+        let (x, y) = (object.x as isize - min_x, object.y as isize);
+        if overlays.triggers && info.trigger_effect_id != 0 {
+            let area = info.trigger_rect();
+            let rect = Rect { x: x + area.x, y: y + area.y, ..area };
+            fill(&mut image, &rect, trigger_colour(info.trigger_effect_id));
+            outline(&mut image, &rect, trigger_colour(info.trigger_effect_id));
+        }
+        if overlays.entrances_and_exits && (info.is_entrance || info.is_exit) {
+            let colour = if info.is_exit { EXIT_COLOUR } else { ENTRANCE_COLOUR };
+            let rect = Rect { x, y, width: info.width as isize, height: info.height as isize };
+            outline(&mut image, &rect, colour);
+            outline(&mut image, &Rect { x: x + 1, y: y + 1, width: rect.width - 2, height: rect.height - 2 }, colour);
+        }
+    }
+    if overlays.start {
+        let rect = Rect { x: level.globals.start_screen_xpos as isize - min_x, y: 0, width: ORIGINAL_GAME_W as isize, height: image.height as isize };
+        outline(&mut image, &rect, START_COLOUR);
+    }
+    if overlays.header {
+        image = with_header(image, &header_lines(level), font);
+    }
+    image
+}
+
+fn header_lines(level: &Level) -> Vec<String> {
+    let globals = &level.globals;
+    let skills = &globals.skills;
+    let percent = if globals.num_of_lemmings == 0 { 0 } else { globals.num_to_rescue as usize * 100 / globals.num_of_lemmings as usize };
+    let mut numbers = format!("LEMMINGS {}  SAVE {} {}%  RATE {}  TIME {}  GROUND {}",
+        globals.num_of_lemmings, globals.num_to_rescue, percent, globals.release_rate, globals.time_limit, globals.normal_graphic_set);
+    if globals.extended_graphic_set != 0 {
+        numbers.push_str(&format!("  SPECIAL {}", globals.extended_graphic_set));
+    }
+    let skill_counts = format!("CLIMB {}  FLOAT {}  BOMB {}  BLOCK {}  BUILD {}  BASH {}  MINE {}  DIG {}",
+        skills.climbers, skills.floaters, skills.bombers, skills.blockers, skills.builders, skills.bashers, skills.miners, skills.diggers);
+    vec![level.name.trim().to_uppercase(), numbers, skill_counts]
+}
+
+// Wraps at spaces to fit the width, as special levels can be narrower than a long name.
+fn wrap(lines: &[String], columns: usize) -> Vec<String> {
+    let mut wrapped = Vec::<String>::new();
+    for line in lines {
+        let mut current = String::new();
+        for word in line.split(' ') {
+            if !current.is_empty() && current.len() + 1 + word.len() > columns {
+                wrapped.push(current.trim_end().to_string());
+                current = String::new();
+            }
+            if !current.is_empty() { current.push(' ') }
+            current.push_str(word);
+        }
+        wrapped.push(current.trim_end().to_string());
+    }
+    wrapped
+}
+
+fn with_header(image: Image, lines: &[String], font: &GameFont) -> Image {
+    let lines = wrap(lines, (image.width.saturating_sub(HEADER_PADDING * 2) / GLYPH_WIDTH).max(1));
+    let header_height = lines.len() * GLYPH_HEIGHT + HEADER_PADDING * 2;
+    let mut bitmap = vec![HEADER_BACKGROUND; image.width * header_height];
+    bitmap.extend_from_slice(&image.bitmap);
+    let mut out = Image { bitmap, width: image.width, height: image.height + header_height };
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let Some(glyph) = glyph(font, c) else { continue };
+            draw_sprite(&mut out, glyph, HEADER_PADDING + column * GLYPH_WIDTH, HEADER_PADDING + row * GLYPH_HEIGHT);
+        }
+    }
+    out
+}
+
+// The game font only has capitals, digits, dashes and percent signs. Anything else is left as a gap.
+fn glyph(font: &GameFont, c: char) -> Option<&Image> {
+    match c.to_ascii_uppercase() {
+        'A'..='Z' => font.letters.get(c.to_ascii_uppercase() as usize - 'A' as usize),
+        '0'..='9' => font.digits.get(c as usize - '0' as usize),
+        '-' => Some(&font.dash),
+        '%' => Some(&font.percent),
+        _ => None,
+    }
+}
+
+fn draw_sprite(canvas: &mut Image, sprite: &Image, x: usize, y: usize) {
+    for sprite_y in 0..sprite.height {
+        for sprite_x in 0..sprite.width {
+            let (canvas_x, canvas_y) = (x + sprite_x, y + sprite_y);
+            if canvas_x >= canvas.width || canvas_y >= canvas.height { continue }
+            let pixel = sprite.bitmap[sprite_y * sprite.width + sprite_x];
+            if pixel != 0 {
+                canvas.bitmap[canvas_y * canvas.width + canvas_x] = pixel;
+            }
+        }
+    }
+}
+
+fn clipped(image: &Image, rect: &Rect) -> Rect {
+    rect.intersection(&Rect { x: 0, y: 0, width: image.width as isize, height: image.height as isize })
+}
+
+// Tints what's there, or where there's nothing, puts down a see-through colour.
+fn fill(image: &mut Image, rect: &Rect, colour: u32) {
+    let rect = clipped(image, rect);
+    if rect.is_empty() { return }
+    for y in rect.y..rect.bottom() {
+        for x in rect.x..rect.right() {
+            let pixel = &mut image.bitmap[y as usize * image.width + x as usize];
+            *pixel = blend(*pixel, colour);
+        }
+    }
+}
+
+fn blend(under: u32, colour: u32) -> u32 {
+    if under & 0xff == 0 { return (colour & 0xffffff00) | FILL_ALPHA }
+    let channel = |shift: u32| {
+        let (a, b) = ((under >> shift) & 0xff, (colour >> shift) & 0xff);
+        ((a * (0xff - FILL_ALPHA) + b * FILL_ALPHA) / 0xff) << shift
+    };
+    channel(24) | channel(16) | channel(8) | 0xff
+}
+
+fn outline(image: &mut Image, rect: &Rect, colour: u32) {
+    if rect.is_empty() { return }
+    let mut put = |x: isize, y: isize| {
+        if x < 0 || y < 0 || x >= image.width as isize || y >= image.height as isize { return }
+        image.bitmap[y as usize * image.width + x as usize] = colour;
+    };
+    for x in rect.x..rect.right() {
+        put(x, rect.y);
+        put(x, rect.bottom() - 1);
+    }
+    for y in rect.y..rect.bottom() {
+        put(rect.x, y);
+        put(rect.right() - 1, y);
+    }
+}
@@ -1,7 +1,7 @@
 // This renders a level to a bitmap.
 
 use std::cmp;
-//use std::io::Result;
+use std::io::Result;
 use crate::lemmings::models::*;
 use crate::lemmings::models::Image;
 use crate::lemmings::parsers::special;
@@ -20,7 +20,8 @@ impl LevelSize {
     }
 }
 
-fn size_of_level(level: &Level, grounds: &GroundMap) -> LevelSize {
+// The span of game point x's the level's terrain covers, which is what gets rendered.
+pub fn size_of_level(level: &Level, ground: &GroundCombined) -> LevelSize {
     if level.globals.extended_graphic_set != 0 {
         return LevelSize {
             min_x: SPECIAL_LEFT_X,
@@ -32,7 +33,6 @@ fn size_of_level(level: &Level, grounds: &GroundMap) -> LevelSize {
         min_x: std::isize::MAX,
         max_x: std::isize::MIN,
     };
-    let ground = &grounds[&(level.globals.normal_graphic_set as i32)];
     for terrain in level.terrain.iter() {
         let width = ground.ground.terrain_info[terrain.terrain_id as usize].width as isize;
         size.min_x = cmp::min(size.min_x, terrain.x);
@@ -109,14 +109,81 @@ fn draw(sprite: &[u32],
     }
 }
 
-pub fn render(level: &Level, grounds: &GroundMap, specials: &SpecialMap, show_objects: bool) -> RenderedLevel {
-    let size = size_of_level(level, grounds);
+// Objects go on after the terrain, as some only show over it. With a tick, each is on its frame for then, see
+// object_frame_index, otherwise they're all on their first.
+fn draw_objects(level: &Level, ground: &GroundCombined, size: LevelSize, bitmap: &mut Vec<u32>, tick: Option<usize>) {
+    for object in level.objects.iter() {
+        let sprite = &ground.object_sprites[&(object.obj_id as i32)];
+        let frame = match tick {
+            Some(tick) => object_frame_index(&ground.ground.object_info[object.obj_id], sprite, tick),
+            None => 0, // info.preview_image_index seems correct but is too large and crashes.
+        };
+        draw(&sprite.frames[frame],
+            sprite.width as isize, sprite.height as isize,
+            object.x as isize - size.min_x, object.y as isize,
+            bitmap,
+            size.width(), LEVEL_HEIGHT,
+            object.modifier.is_do_not_overwrite_existing_terrain(),
+            object.is_upside_down,
+            false,
+            object.modifier.is_must_have_terrain_underneath_to_be_visible());
+    }
+}
+
+// As in ingame.rs, every object loops through its frames a tick at a time, starting from start_animation_frame_index.
+pub fn object_frame_index(info: &ObjectInfo, sprite: &Animation, tick: usize) -> usize {
+    let frame_count = (info.frame_count as usize).min(sprite.frames.len()).max(1);
+    (info.start_animation_frame_index as usize + tick) % frame_count
+}
+
+fn greatest_common_divisor(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { greatest_common_divisor(b, a % b) }
+}
+
+// How many ticks until every object is back on its starting frame, ie the lowest common multiple of their frame counts,
+// but no more than max_ticks, as a few odd counts together could take minutes.
+pub fn animation_cycle_ticks(level: &Level, ground: &GroundCombined, max_ticks: usize) -> usize {
+    let mut ticks = 1;
+    for object in level.objects.iter() {
+        let (Some(info), Some(sprite)) = (ground.ground.object_info.get(object.obj_id), ground.object_sprites.get(&(object.obj_id as i32))) else { continue };
+        let frame_count = (info.frame_count as usize).min(sprite.frames.len()).max(1);
+        ticks = ticks / greatest_common_divisor(ticks, frame_count) * frame_count;
+        if ticks >= max_ticks { return max_ticks }
+    }
+    ticks
+}
+
+// The level with its objects moving, a rendering per tick for a full cycle of their animations, up to max_ticks. The
+// terrain's only drawn once.
+pub fn render_animated(level: &Level, ground: &GroundCombined, special: Option<&Image>, max_ticks: usize) -> Vec<RenderedLevel> {
+    let terrain = render(level, ground, special, false);
+    (0..animation_cycle_ticks(level, ground, max_ticks)).map(|tick| {
+        let mut bitmap = terrain.image.bitmap.clone();
+        draw_objects(level, ground, terrain.size, &mut bitmap, Some(tick));
+        RenderedLevel {
+            image: Image { bitmap, width: terrain.image.width, height: terrain.image.height },
+            size: terrain.size,
+        }
+    }).collect()
+}
+
+// Loads what the level needs from the game first.
+pub fn render_from_game(level: &Level, game: &Game, show_objects: bool) -> Result<RenderedLevel> {
+    let ground = game.ground_for(level)?;
+    let special = game.special_for(level)?;
+    Ok(render(level, &ground, special.as_deref(), show_objects))
+}
+
+// The special is only needed if it's a special level, ie extended_graphic_set isn't 0.
+pub fn render(level: &Level, ground: &GroundCombined, special: Option<&Image>, show_objects: bool) -> RenderedLevel {
+    let size = size_of_level(level, ground);
     let width = size.width();
     let height = LEVEL_HEIGHT;
     let pixels = width * height;
     let mut bitmap = vec![LEVEL_BACKGROUND; pixels as usize];
-    let ground = &grounds[&(level.globals.normal_graphic_set as i32)];
-    if level.globals.extended_graphic_set == 0 {
+    if let Some(special) = special.filter(|_| level.globals.extended_graphic_set != 0) {
+        bitmap.copy_from_slice(&special.bitmap);
+    } else {
         for terrain in level.terrain.iter() {
             let sprite = &ground.terrain_sprites[&(terrain.terrain_id as i32)];
             draw(&sprite.bitmap,
@@ -129,24 +196,9 @@ pub fn render(level: &Level, grounds: &GroundMap, specials: &SpecialMap, show_ob
                 terrain.remove_terrain,
                 false);
         }
-    } else {
-        let special = &specials[&(level.globals.extended_graphic_set as i32 - 1)];
-        bitmap.copy_from_slice(&special.bitmap);
     }
     if show_objects {
-        for object in level.objects.iter() {
-            let sprite = &ground.object_sprites[&(object.obj_id as i32)];
-            // let info: &ObjectInfo = &ground.ground.object_info[object.obj_id];
-            draw(&sprite.frames[0], // info.preview_image_index seems correct but is too large and crashes.
-                sprite.width as isize, sprite.height as isize,
-                object.x as isize - size.min_x, object.y as isize,
-                &mut bitmap,
-                width as isize, height as isize,
-                object.modifier.is_do_not_overwrite_existing_terrain(),
-                object.is_upside_down,
-                false,
-                object.modifier.is_must_have_terrain_underneath_to_be_visible());
-        }
+        draw_objects(level, ground, size, &mut bitmap, None);
     }
     let image = Image {
         bitmap,
@@ -0,0 +1,94 @@
+// This checks a level against what the LVL format can hold and the game can play, for levels made outside the game,
+// eg imported from Tiled. The limits are from lemmings_lvl_file_format.txt, see the comments in models.rs.
+
+use std::ops::RangeInclusive;
+
+use crate::lemmings::models::*;
+
+// What an LVL file can hold. parsers/level.rs reads and writes with these too, so they're only defined here.
+pub const MAX_OBJECTS: usize = 32;
+pub const MAX_TERRAIN: usize = 400;
+pub const MAX_STEEL: usize = 32;
+pub const MAX_NAME_BYTES: usize = 32;
+pub const OBJECT_IDS: usize = 16;
+pub const TERRAIN_IDS: usize = 64;
+pub const TERRAIN_X: RangeInclusive<isize> = 0..=0xfff;
+pub const TERRAIN_FILE_Y: RangeInclusive<isize> = -256..=255; // 4 lower than terrain.y, as the file has it.
+pub const STEEL_X: RangeInclusive<isize> = 0..=0x1ff; // In 4 point units.
+pub const STEEL_Y: RangeInclusive<isize> = 0..=0x7f * 4;
+pub const MAX_STEEL_SIZE: u8 = 15; // Width and height in 4 point units, less 1.
+
+const MAX_RELEASE_RATE: u16 = 0xfa;
+const MAX_LEMMINGS: u16 = 0x72;
+const MAX_TIME_LIMIT: u16 = 0xff;
+const MAX_SKILL: u16 = 0xfa;
+const MAX_START_X: u16 = 0x4f0;
+const EXIT_ID: usize = 0; // The first two objects in every ground.
+const ENTRANCE_ID: usize = 1;
+
+// Everything wrong with the level, or nothing if it's fine. With the ground it uses, the pieces are checked to exist.
+pub fn problems(level: &Level, ground: Option<&GroundCombined>) -> Vec<String> {
+    let mut problems = Vec::<String>::new();
+    let globals = &level.globals;
+    let skills = &globals.skills;
+
+    if level.name.len() > MAX_NAME_BYTES { problems.push(format!("The name is over {} bytes", MAX_NAME_BYTES)) }
+    if !level.name.is_ascii() { problems.push("The name has characters the game can't show".to_string()) }
+    if globals.release_rate > MAX_RELEASE_RATE { problems.push(format!("Release rate {} is over {}", globals.release_rate, MAX_RELEASE_RATE)) }
+    if globals.num_of_lemmings > MAX_LEMMINGS { problems.push(format!("{} lemmings is over {}", globals.num_of_lemmings, MAX_LEMMINGS)) }
+    if globals.num_to_rescue > globals.num_of_lemmings { problems.push(format!("{} to rescue is more than the {} lemmings", globals.num_to_rescue, globals.num_of_lemmings)) }
+    if globals.time_limit > MAX_TIME_LIMIT { problems.push(format!("Time limit {} is over {}", globals.time_limit, MAX_TIME_LIMIT)) }
+    if globals.start_screen_xpos > MAX_START_X { problems.push(format!("Start x {} is over {}", globals.start_screen_xpos, MAX_START_X)) }
+    for (name, count) in [
+        ("climbers", skills.climbers), ("floaters", skills.floaters), ("bombers", skills.bombers), ("blockers", skills.blockers),
+        ("builders", skills.builders), ("bashers", skills.bashers), ("miners", skills.miners), ("diggers", skills.diggers),
+    ] {
+        if count > MAX_SKILL { problems.push(format!("{} {} is over {}", count, name, MAX_SKILL)) }
+    }
+
+    if level.objects.len() > MAX_OBJECTS { problems.push(format!("{} objects is over {}", level.objects.len(), MAX_OBJECTS)) }
+    if level.terrain.len() > MAX_TERRAIN { problems.push(format!("{} terrain pieces is over {}", level.terrain.len(), MAX_TERRAIN)) }
+    if level.steel.len() > MAX_STEEL { problems.push(format!("{} steel areas is over {}", level.steel.len(), MAX_STEEL)) }
+    if !level.objects.iter().any(|o| o.obj_id == ENTRANCE_ID) { problems.push("There's no entrance".to_string()) }
+    if !level.objects.iter().any(|o| o.obj_id == EXIT_ID) { problems.push("There's no exit".to_string()) }
+
+    for (i, object) in level.objects.iter().enumerate() {
+        let what = format!("Object {} (id {} at {},{})", i + 1, object.obj_id, object.x, object.y);
+        if object.obj_id >= OBJECT_IDS { problems.push(format!("{}: the id is over {}", what, OBJECT_IDS - 1)) }
+        if i16::try_from(object.x).is_err() || i16::try_from(object.y).is_err() { problems.push(format!("{}: too far out", what)) }
+        if object.x == 0 && object.y == 0 && object.obj_id == 0 { problems.push(format!("{}: an exit at 0,0 reads as no object", what)) }
+        if let Some(ground) = ground {
+            if !ground.ground.object_info.get(object.obj_id).is_some_and(|info| info.is_valid()) {
+                problems.push(format!("{}: the ground has no such object", what))
+            }
+        }
+    }
+
+    for (i, terrain) in level.terrain.iter().enumerate() {
+        let what = format!("Terrain {} (id {} at {},{})", i + 1, terrain.terrain_id, terrain.x, terrain.y);
+        if terrain.terrain_id >= TERRAIN_IDS { problems.push(format!("{}: the id is over {}", what, TERRAIN_IDS - 1)) }
+        if !TERRAIN_X.contains(&terrain.x) { problems.push(format!("{}: x is outside {} to {}", what, TERRAIN_X.start(), TERRAIN_X.end())) }
+        if !TERRAIN_FILE_Y.contains(&(terrain.y + 4)) {
+            problems.push(format!("{}: y is outside {} to {}", what, TERRAIN_FILE_Y.start() - 4, TERRAIN_FILE_Y.end() - 4))
+        }
+        if terrain.remove_terrain && terrain.do_not_overwrite_existing_terrain {
+            problems.push(format!("{}: it can't both remove terrain and not overwrite it", what))
+        }
+        if let Some(ground) = ground {
+            if !ground.ground.terrain_info.get(terrain.terrain_id).is_some_and(|info| info.is_valid()) {
+                problems.push(format!("{}: the ground has no such piece", what))
+            }
+        }
+    }
+
+    for (i, steel) in level.steel.iter().enumerate() {
+        let what = format!("Steel {} (at {},{})", i + 1, steel.x, steel.y);
+        if !STEEL_X.contains(&steel.x) { problems.push(format!("{}: x is outside {} to {}", what, STEEL_X.start(), STEEL_X.end())) }
+        if steel.y % 4 != 0 || !STEEL_Y.contains(&steel.y) {
+            problems.push(format!("{}: y isn't a multiple of 4 from {} to {}", what, STEEL_Y.start(), STEEL_Y.end()))
+        }
+        if steel.width > MAX_STEEL_SIZE || steel.height > MAX_STEEL_SIZE { problems.push(format!("{}: the size is over {}", what, MAX_STEEL_SIZE)) }
+        if steel.x == 0 && steel.y == 0 && steel.width == 0 && steel.height == 0 { problems.push(format!("{}: it reads as no steel", what)) }
+    }
+    problems
+}
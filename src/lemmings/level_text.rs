@@ -0,0 +1,253 @@
+// This converts levels to and from a text format, INI-like in the style of Lemmix's, so level packs can live in git and
+// changes to them can be read in a diff. Everything in models::Level is kept, so from_text(&to_text(&level)) is the
+// same level. The numbers are exactly as they are in Level, so the comments there say what they mean.
+//
+//   # Comments start with a hash.
+//   name = "Just dig!"
+//   release_rate = 50
+//   ...the rest of the globals and skills, one per line...
+//
+//   [object]
+//   x = 160
+//   y = 40
+//   id = 1
+//   modifier = normal          (or must_have_terrain_underneath, or do_not_overwrite_existing_terrain)
+//   upside_down = false
+//
+//   [terrain]
+//   x = 368
+//   y = 64
+//   id = 4
+//   do_not_overwrite_existing_terrain = false
+//   upside_down = false
+//   remove_terrain = false
+//
+//   [steel]
+//   x = 100
+//   y = 36
+//   width = 3                  (in the file's units, 0 meaning 4 points)
+//   height = 1
+//
+// Each section header starts another of that thing. Within a section, keys can be in any order and left out for their
+// default, which is 0, false or normal.
+
+use std::fmt::Write;
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+use crate::lemmings::models::*;
+
+const NAME_SIZE: usize = 32; // As in the LVL file.
+
+pub fn modifier_name(modifier: &ObjectModifier) -> &'static str {
+    match modifier {
+        ObjectModifier::Normal => "normal",
+        ObjectModifier::MustHaveTerrainUnderneathToBeVisible => "must_have_terrain_underneath",
+        ObjectModifier::DoNotOverwriteExistingTerrain => "do_not_overwrite_existing_terrain",
+    }
+}
+
+pub fn modifier_named(name: &str) -> Option<ObjectModifier> {
+    [ObjectModifier::Normal, ObjectModifier::MustHaveTerrainUnderneathToBeVisible, ObjectModifier::DoNotOverwriteExistingTerrain]
+        .into_iter().find(|m| modifier_name(m) == name)
+}
+
+// The level's numbers, named as they are in the text and as Tiled map properties.
+pub fn global_values(level: &Level) -> Vec<(&'static str, u16)> {
+    let globals = &level.globals;
+    let skills = &globals.skills;
+    vec![
+        ("release_rate", globals.release_rate),
+        ("lemmings", globals.num_of_lemmings),
+        ("to_rescue", globals.num_to_rescue),
+        ("time_limit", globals.time_limit),
+        ("climbers", skills.climbers),
+        ("floaters", skills.floaters),
+        ("bombers", skills.bombers),
+        ("blockers", skills.blockers),
+        ("builders", skills.builders),
+        ("bashers", skills.bashers),
+        ("miners", skills.miners),
+        ("diggers", skills.diggers),
+        ("start_x", globals.start_screen_xpos),
+        ("graphic_set", globals.normal_graphic_set),
+        ("extended_graphic_set", globals.extended_graphic_set),
+    ]
+}
+
+pub fn global_value_mut<'a>(level: &'a mut Level, name: &str) -> Option<&'a mut u16> {
+    let globals = &mut level.globals;
+    let skills = &mut globals.skills;
+    Some(match name {
+        "release_rate" => &mut globals.release_rate,
+        "lemmings" => &mut globals.num_of_lemmings,
+        "to_rescue" => &mut globals.num_to_rescue,
+        "time_limit" => &mut globals.time_limit,
+        "climbers" => &mut skills.climbers,
+        "floaters" => &mut skills.floaters,
+        "bombers" => &mut skills.bombers,
+        "blockers" => &mut skills.blockers,
+        "builders" => &mut skills.builders,
+        "bashers" => &mut skills.bashers,
+        "miners" => &mut skills.miners,
+        "diggers" => &mut skills.diggers,
+        "start_x" => &mut globals.start_screen_xpos,
+        "graphic_set" => &mut globals.normal_graphic_set,
+        "extended_graphic_set" => &mut globals.extended_graphic_set,
+        _ => return None,
+    })
+}
+
+fn quoted(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        if c == '"' || c == '\\' { out.push('\\') }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+pub fn to_text(level: &Level) -> String {
+    let mut text = String::new();
+    // Writing to a String can't fail, hence the unwraps.
+    writeln!(text, "# Lemmings level").unwrap();
+    writeln!(text, "name = {}", quoted(&level.name)).unwrap();
+    for (key, value) in global_values(level) {
+        writeln!(text, "{} = {}", key, value).unwrap();
+    }
+    for object in level.objects.iter() {
+        writeln!(text, "\n[object]").unwrap();
+        writeln!(text, "x = {}\ny = {}\nid = {}", object.x, object.y, object.obj_id).unwrap();
+        writeln!(text, "modifier = {}", modifier_name(&object.modifier)).unwrap();
+        writeln!(text, "upside_down = {}", object.is_upside_down).unwrap();
+    }
+    for terrain in level.terrain.iter() {
+        writeln!(text, "\n[terrain]").unwrap();
+        writeln!(text, "x = {}\ny = {}\nid = {}", terrain.x, terrain.y, terrain.terrain_id).unwrap();
+        writeln!(text, "do_not_overwrite_existing_terrain = {}", terrain.do_not_overwrite_existing_terrain).unwrap();
+        writeln!(text, "upside_down = {}", terrain.is_upside_down).unwrap();
+        writeln!(text, "remove_terrain = {}", terrain.remove_terrain).unwrap();
+    }
+    for steel in level.steel.iter() {
+        writeln!(text, "\n[steel]").unwrap();
+        writeln!(text, "x = {}\ny = {}\nwidth = {}\nheight = {}", steel.x, steel.y, steel.width, steel.height).unwrap();
+    }
+    text
+}
+
+fn error_at(line_number: usize, message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Line {}: {}", line_number, message))
+}
+
+fn number<T: FromStr>(value: &str, line_number: usize) -> Result<T> {
+    value.parse::<T>().map_err(|_| error_at(line_number, format!("'{}' isn't a number in range", value)))
+}
+
+fn boolean(value: &str, line_number: usize) -> Result<bool> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(error_at(line_number, format!("'{}' isn't true or false", value))),
+    }
+}
+
+fn unquoted(value: &str, line_number: usize) -> Result<String> {
+    let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).filter(|_| value.len() >= 2) else {
+        return Err(error_at(line_number, "The name needs to be in quotes".to_string()))
+    };
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) => out.push(escaped),
+                None => return Err(error_at(line_number, "The name ends with a lone \\".to_string())),
+            },
+            '"' => return Err(error_at(line_number, "Quotes in the name need a \\ before them".to_string())),
+            c => out.push(c),
+        }
+    }
+    if out.len() > NAME_SIZE { return Err(error_at(line_number, format!("The name is over {} bytes", NAME_SIZE))) }
+    Ok(out)
+}
+
+enum Section {
+    Globals,
+    Object,
+    Terrain,
+    Steel,
+}
+
+pub fn from_text(text: &str) -> Result<Level> {
+    let mut level = Level::default();
+    let mut section = Section::Globals;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue }
+        if line.starts_with('[') {
+            section = match line {
+                "[object]" => {
+                    level.objects.push(Object { x: 0, y: 0, obj_id: 0, modifier: ObjectModifier::Normal, is_upside_down: false });
+                    Section::Object
+                },
+                "[terrain]" => {
+                    level.terrain.push(Terrain { do_not_overwrite_existing_terrain: false, is_upside_down: false, remove_terrain: false, x: 0, y: 0, terrain_id: 0 });
+                    Section::Terrain
+                },
+                "[steel]" => {
+                    level.steel.push(SteelArea { x: 0, y: 0, width: 0, height: 0 });
+                    Section::Steel
+                },
+                _ => return Err(error_at(line_number, format!("Unknown section {}", line))),
+            };
+            continue
+        }
+        let Some((key, value)) = line.split_once('=') else { return Err(error_at(line_number, "Expected 'key = value'".to_string())) };
+        let (key, value) = (key.trim(), value.trim());
+        let n = line_number;
+        match section {
+            Section::Globals => match key {
+                "name" => level.name = unquoted(value, n)?,
+                _ => match global_value_mut(&mut level, key) {
+                    Some(global) => *global = number(value, n)?,
+                    None => return Err(error_at(n, format!("Unknown key {}", key))),
+                },
+            },
+            Section::Object => {
+                let object = level.objects.last_mut().unwrap();
+                match key {
+                    "x" => object.x = number(value, n)?,
+                    "y" => object.y = number(value, n)?,
+                    "id" => object.obj_id = number(value, n)?,
+                    "modifier" => object.modifier = modifier_named(value).ok_or_else(|| error_at(n, format!("Unknown modifier {}", value)))?,
+                    "upside_down" => object.is_upside_down = boolean(value, n)?,
+                    _ => return Err(error_at(n, format!("Unknown object key {}", key))),
+                }
+            },
+            Section::Terrain => {
+                let terrain = level.terrain.last_mut().unwrap();
+                match key {
+                    "x" => terrain.x = number(value, n)?,
+                    "y" => terrain.y = number(value, n)?,
+                    "id" => terrain.terrain_id = number(value, n)?,
+                    "do_not_overwrite_existing_terrain" => terrain.do_not_overwrite_existing_terrain = boolean(value, n)?,
+                    "upside_down" => terrain.is_upside_down = boolean(value, n)?,
+                    "remove_terrain" => terrain.remove_terrain = boolean(value, n)?,
+                    _ => return Err(error_at(n, format!("Unknown terrain key {}", key))),
+                }
+            },
+            Section::Steel => {
+                let steel = level.steel.last_mut().unwrap();
+                match key {
+                    "x" => steel.x = number(value, n)?,
+                    "y" => steel.y = number(value, n)?,
+                    "width" => steel.width = number(value, n)?,
+                    "height" => steel.height = number(value, n)?,
+                    _ => return Err(error_at(n, format!("Unknown steel key {}", key))),
+                }
+            },
+        }
+    }
+    Ok(level)
+}
@@ -2,11 +2,15 @@
 // This is a big fat workaround for the fact that the levels' relationship to the skill was hardcoded in the original game.
 
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::lemmings::models::*;
 
 // Lemmings 1 is a bit lousy: there are 30 levels per each of 4 difficulties (120) but only 80 level files - many are re-used with different skill numbers and names.
-// Here i'm just matching them by the names in the level files, so it'll skip some, but it's only skipping the renamed duplicate ones so no big loss.
+// The re-uses come from ODDTABLE.DAT, which the loader turns into levels of their own, so they can be matched by name here too.
+// These lists hold the 80 level files' levels in the order they're played. Which of the 120 slots each of the 40 renamed
+// re-uses goes in is hardcoded in the original executable rather than any data file, so they aren't in the lists: each
+// Lemmings 1 rating's list is shorter than the game's 30, and is played straight through.
 const LEMMINGS_1_FUN: &str = "
 Just dig!
 Only floaters can survive this
@@ -231,22 +235,92 @@ fn unsplit_names_per_game_and_skill(game_id: &str, skill: isize) -> &'static str
     }
 }
 
+// How many levels the original game has in each rating, eg 30 for Lemmings 1's Fun.
+// This is what level codes are counted against, even where the name lists above are incomplete.
+pub fn levels_per_rating(game_id: &str) -> usize {
+    match game_id {
+        "lemmings" => 30,
+        "ohnomore" => 20,
+        _ => 0,
+    }
+}
+
+pub fn ratings_per_game(game_id: &str) -> usize {
+    match game_id {
+        "lemmings" => 4,
+        "ohnomore" => 5,
+        _ => 0,
+    }
+}
+
+// Eg 'Fun' for Lemmings 1's first rating.
+pub fn rating_name(game_id: &str, rating: usize) -> &'static str {
+    match (game_id, rating) {
+        ("lemmings", 0) => "Fun",
+        ("lemmings", 1) => "Tricky",
+        ("lemmings", 2) => "Taxing",
+        ("lemmings", 3) => "Mayhem",
+        ("ohnomore", 0) => "Tame",
+        ("ohnomore", 1) => "Crazy",
+        ("ohnomore", 2) => "Wild",
+        ("ohnomore", 3) => "Wicked",
+        ("ohnomore", 4) => "Havoc",
+        _ => "",
+    }
+}
+
 pub fn names_per_game_and_skill(game_id: &str, skill: isize) -> Vec<String> {
     let names = unsplit_names_per_game_and_skill(game_id, skill);
     names.split("\n").filter(|s|!s.is_empty()).map(|s|{s.to_owned()}).collect()
 }
         
-pub fn levels_per_game_and_skill(game_id: &str, skill: isize, level_map: &HashMap<i32, Level>) -> Vec<Level> {
-    let mut levels: Vec<Level> = Vec::new();
-    let names = names_per_game_and_skill(game_id, skill);
-    for level_name in names {
-        for (_, level) in level_map {
-            if level.name == level_name {
-                levels.push(level.clone());
-                break;
-            }
+// The index keys of a rating's levels, in order. A name that's listed more than once, eg 'We all fall down' in Fun and
+// Tricky, is the next level of that name each time, in key order, if the game has more than one.
+pub fn level_keys_per_game_and_skill(game: &Game, skill: isize) -> Vec<i32> {
+    let mut times_listed = HashMap::<String, usize>::new();
+    for earlier in 0..skill.max(0) {
+        for name in names_per_game_and_skill(&game.id, earlier) {
+            *times_listed.entry(name).or_default() += 1;
         }
     }
-    return levels;
+    names_per_game_and_skill(&game.id, skill).into_iter().filter_map(|name| {
+        let keys = game.index.level_keys_named(&name);
+        let times = times_listed.entry(name).or_default();
+        let key = keys.get(*times).or(keys.last()).copied();
+        *times += 1;
+        key
+    }).collect()
+}
+
+pub fn levels_per_game_and_skill(game: &Game, skill: isize) -> Vec<Arc<Level>> {
+    level_keys_per_game_and_skill(game, skill).into_iter().filter_map(|key| game.index.level(key).ok()).collect()
+}
+ 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::lemmings::loader;
+
+    fn assert_every_slot_is_listed(game_id: &str) {
+        for rating in 0..ratings_per_game(game_id) {
+            assert_eq!(names_per_game_and_skill(game_id, rating as isize).len(), levels_per_rating(game_id), "{} {}", game_id, rating_name(game_id, rating));
+        }
+    }
+
+    #[test]
+    fn ohnomore_lists_every_slot() {
+        assert_every_slot_is_listed("ohnomore");
+    }
+
+    // Also checks the slots are all different levels, if the game data's there.
+    #[test]
+    #[ignore = "Which of Lemmings 1's slots the 40 odd-table levels go in is hardcoded in the DOS executable, and isn't in the lists yet"]
+    fn lemmings_lists_every_slot() {
+        assert_every_slot_is_listed("lemmings");
+        let Some(game) = loader::load_lemmings() else { return };
+        let keys: Vec<i32> = (0..ratings_per_game(&game.id)).flat_map(|rating| level_keys_per_game_and_skill(&game, rating as isize)).collect();
+        assert_eq!(keys.len(), 120);
+        assert_eq!(keys.iter().collect::<HashSet<_>>().len(), 120);
+    }
 }
- 
\ No newline at end of file
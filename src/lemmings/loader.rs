@@ -1,102 +1,40 @@
-// This loads the games from disk into memory.
+// This finds the games on disk. MAIN.DAT is loaded into memory straight away, as the menus and lemmings all need it,
+// but the levels and graphics sets are only indexed, and loaded when first needed, see game_index.rs.
+// Files are read on a thread each, since there are dozens and they don't depend on each other.
 
 use std::env;
 use std::fs;
-use std::io::Result;
+use std::io::{Error, Result};
 use std::path::Path;
+use std::sync::Arc;
+use std::thread;
 
+use crate::lemmings::game_index::GameIndex;
+use crate::lemmings::level_renderer;
 use crate::lemmings::models::*;
 use crate::lemmings::parsers::*;
 
-// Load a ground file and its associated vga graphics.
-fn load_ground_and_sprites(dir: &str, index: i32) -> Result<GroundCombined> {
-    let vga_file: Vec<u8> = fs::read(format!("{}/vgagr{}.dat", dir, index))?;
-    let vga_sections = decompressor::decompress(&vga_file)?;
-
-    let ground_file: Vec<u8> = fs::read(format!("{}/ground{}o.dat", dir, index))?;
-    let ground = ground::parse(&ground_file)?;
-    let palette = ground.palettes.as_rgba();
-
-    let mut terrain_sprites: ImageMap = ImageMap::new();
-    for (i, terrain) in ground.terrain_info.iter().enumerate() {
-        if terrain.is_valid() {
-            let sprite = sprites::extract_image(&vga_sections[0], terrain.width, terrain.height, terrain.image_loc, terrain.mask_loc, &palette);
-            terrain_sprites.insert(i as i32, sprite);
-        }
-    }
-
-    let mut object_sprites: AnimationMap = AnimationMap::new();
-    for (i, object) in ground.object_info.iter().enumerate() {
-        if object.is_valid() {
-            let sprite = sprites::extract_animation(&vga_sections[1], object.width, object.height, object.animation_frames_base_loc as usize, object.animation_frames_base_loc as usize + object.mask_offset_from_image as usize, &palette, object.animation_frame_data_size as usize, object.frame_count as usize);
-            object_sprites.insert(i as i32, sprite);
-        }
-    }
-
-    Ok(GroundCombined {
-        ground: ground,
-        terrain_sprites: terrain_sprites,
-        object_sprites: object_sprites,
+// Runs the closure on a thread per item, returning the results in the same order.
+pub fn parallel_map<T: Sync, R: Send, F: Fn(&T) -> Result<R> + Sync>(items: &[T], f: F) -> Result<Vec<R>> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.iter().map(|item| scope.spawn(|| f(item))).collect();
+        handles.into_iter().map(join).collect()
     })
 }
 
-fn load_all_grounds(dir: &str) -> Result<GroundMap> {
-    let mut all: GroundMap = GroundMap::new();
-    for entry in fs::read_dir(dir)? {
-        if let Ok(entry) = entry {
-            let raw_name = entry.file_name().into_string().unwrap();
-            let file_name = raw_name.to_lowercase();
-            if file_name.starts_with("vgagr") && file_name.ends_with(".dat") {
-                let file_number: i32 = file_name[5..6].parse().unwrap();
-                let ground = load_ground_and_sprites(dir, file_number)?;
-                all.insert(file_number, ground);
-            }
+// The names of the files in the dir that match, and the number in their name. Files that aren't named like the game's,
+// eg levelx.dat or a name that isn't UTF-8, are skipped rather than being an error, as they can't be the game's.
+pub fn numbered_files(dir: &str, prefix: &str, number_range: std::ops::Range<usize>) -> Result<Vec<(String, i32)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let Ok(raw_name) = entry.file_name().into_string() else { continue };
+        let file_name = raw_name.to_lowercase();
+        if file_name.starts_with(prefix) && file_name.ends_with(".dat") {
+            let Some(Ok(file_number)) = file_name.get(number_range.clone()).map(|number| number.parse::<i32>()) else { continue };
+            files.push((raw_name, file_number));
         }
     }
-    Ok(all)
-}
-
-fn load_all_specials(dir: &str) -> Result<SpecialMap> {
-    let mut all: SpecialMap = SpecialMap::new();
-    for entry in fs::read_dir(dir)? {
-        if let Ok(entry) = entry {
-            let raw_name = entry.file_name().into_string().unwrap();
-            let file_name = raw_name.to_lowercase();
-            if file_name.starts_with("vgaspec") && file_name.ends_with(".dat") {
-                let file_number: i32 = file_name[7..8].parse().unwrap();
-                let filename = format!("{}/{}", dir, raw_name);
-                let raw: Vec<u8> = fs::read(filename)?;
-                let sections = decompressor::decompress(&raw)?;
-                let spec = special::parse(&sections[0])?;
-                all.insert(file_number, spec);
-            }
-        }
-    }
-    Ok(all)
-}
-
-// Load all the levels from all the sections in all the files into memory.
-// Might as well load into ram, only takes <30ms on my laptop in release mode.
-fn load_all_levels(dir: &str) -> Result<LevelMap> {
-    let mut all: LevelMap = LevelMap::new();
-    for entry in fs::read_dir(dir)? {
-        if let Ok(entry) = entry {
-            let raw_name = entry.file_name().into_string().unwrap();
-            let file_name = raw_name.to_lowercase();
-            if (file_name.starts_with("level") || file_name.starts_with("dlvel")) && file_name.ends_with(".dat") {
-                let file_number: i32 = file_name[5..8].parse().unwrap();
-                let filename = format!("{}/{}", dir, raw_name);
-                let raw: Vec<u8> = fs::read(filename)?;
-                let sections = decompressor::decompress(&raw)?;
-                for (section_index, section) in sections.iter().enumerate() {
-                    let level = level::parse(section)?;
-                    let key = file_number*100 + (section_index as i32);
-                    all.insert(key, level);
-                }
-            }
-        }
-    }
-    Ok(all)
+    Ok(files)
 }
 
 fn load_main_dat(dir: &str) -> Result<MainDat> {
@@ -110,17 +48,25 @@ fn load_game(dir: &str, sub_dir: &str, name: &str) -> Result<Option<Game>> {
     if !Path::new(&sub_path).exists() {
         return Ok(None);
     }
+    // Indexing reads the level files, so it happens alongside loading MAIN.DAT.
+    let (index, main) = thread::scope(|scope| {
+        let index = scope.spawn(|| GameIndex::scan(&sub_path));
+        let main = load_main_dat(&sub_path);
+        (join(index), main)
+    });
     Ok(Some(Game {
         name: name.to_string(),
         id: sub_dir.to_string(),
         path: sub_path.to_string(),
-        levels: load_all_levels(&sub_path)?,
-        specials: load_all_specials(&sub_path)?,
-        grounds: load_all_grounds(&sub_path)?,
-        main: load_main_dat(&sub_path)?,
+        index: Arc::new(index?),
+        main: Arc::new(main?),
     }))
 }
 
+fn join<T>(handle: thread::ScopedJoinHandle<Result<T>>) -> Result<T> {
+    handle.join().unwrap_or_else(|_| Err(Error::other("Loader thread panicked")))
+}
+
 pub fn load() -> Result<Games> {
     let home = env::var("HOME").unwrap_or("~".to_string());
     let data_root = format!("{}/Lemmings", home);
@@ -133,3 +79,86 @@ pub fn load() -> Result<Games> {
         holiday_94: None, // load_game(&data_root, "holiday1994", "Holiday Lemmings '94")?,
     })
 }
+
+// The game data as a pile of bitmaps, for the commands that check or export every image in it.
+pub struct Bitmap {
+    pub name: String,
+    pub pixels: Vec<u32>,
+    pub width: usize,
+    pub height: usize,
+}
+
+// The Lemmings game, saying why not if it can't be loaded.
+pub fn load_lemmings() -> Option<Game> {
+    match load() {
+        Ok(games) => {
+            if games.lemmings.is_none() {
+                println!("Couldn't find the Lemmings game data");
+            }
+            games.lemmings
+        },
+        Err(e) => {
+            println!("Couldn't load the game data: {}", e);
+            None
+        },
+    }
+}
+
+// Every sprite, animation frame and level in the game.
+pub fn all_bitmaps(game: &Game) -> Result<Vec<Bitmap>> {
+    let mut bitmaps = Vec::<Bitmap>::new();
+    for asset in game.all_assets() {
+        match asset.content {
+            AnimationOrImage::Image(image) => bitmaps.push(Bitmap { name: asset.name, pixels: image.bitmap.clone(), width: image.width, height: image.height }),
+            AnimationOrImage::Animation(animation) => for (i, frame) in animation.frames.iter().enumerate() {
+                bitmaps.push(Bitmap { name: format!("{} frame {}", asset.name, i), pixels: frame.clone(), width: animation.width, height: animation.height });
+            },
+        }
+    }
+    for ground_id in game.index.ground_ids() {
+        let ground = game.index.ground(ground_id)?;
+        let mut terrain_ids: Vec<&i32> = ground.terrain_sprites.keys().collect();
+        terrain_ids.sort();
+        for terrain_id in terrain_ids {
+            let image = &ground.terrain_sprites[terrain_id];
+            bitmaps.push(Bitmap { name: format!("ground {} terrain {}", ground_id, terrain_id), pixels: image.bitmap.clone(), width: image.width, height: image.height });
+        }
+        let mut object_ids: Vec<&i32> = ground.object_sprites.keys().collect();
+        object_ids.sort();
+        for object_id in object_ids {
+            let animation = &ground.object_sprites[object_id];
+            for (i, frame) in animation.frames.iter().enumerate() {
+                bitmaps.push(Bitmap { name: format!("ground {} object {} frame {}", ground_id, object_id, i), pixels: frame.clone(), width: animation.width, height: animation.height });
+            }
+        }
+    }
+    for level_key in game.index.level_keys() {
+        let level = game.index.level(level_key)?;
+        let rendered = level_renderer::render_from_game(&level, game, true)?;
+        bitmaps.push(Bitmap { name: format!("level '{}'", level.name.trim()), pixels: rendered.image.bitmap, width: rendered.image.width, height: rendered.image.height });
+    }
+    Ok(bitmaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_files_skips_names_that_arent_the_games() {
+        let dir = env::temp_dir().join(format!("rusty-lemmings-numbered-files-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["LEVEL003.DAT", "level010.dat", "levelx.dat", "level0.dat", "level001.txt", "vgagr1.dat"] {
+            fs::write(dir.join(name), []).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            fs::write(dir.join(std::ffi::OsStr::from_bytes(b"level\xff\xfe1.dat")), []).unwrap();
+        }
+        let mut files = numbered_files(dir.to_str().unwrap(), "level", 5..8);
+        fs::remove_dir_all(&dir).unwrap();
+        files.as_mut().unwrap().sort();
+        assert_eq!(files.unwrap(), vec![("LEVEL003.DAT".to_string(), 3), ("level010.dat".to_string(), 10)]);
+    }
+}
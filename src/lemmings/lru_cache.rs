@@ -0,0 +1,82 @@
+// A small least-recently-used cache, for decoded game data that's too big to keep all of in memory.
+// Values are shared through Arc, so anything still using one keeps it alive even after it's been evicted.
+// It's a Vec rather than a map and a linked list, as the capacities are small enough that scanning it is quicker.
+
+use std::io::Result;
+use std::sync::Arc;
+
+pub struct LruCache<K, V> {
+    capacity: usize,
+    entries: Vec<(K, Arc<V>)>, // Least recently used first.
+}
+
+impl<K: PartialEq, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache { capacity: capacity.max(1), entries: Vec::with_capacity(capacity) }
+    }
+
+    // Returns the cached value if there is one, otherwise loads and caches it, evicting the least recently used.
+    // If loading fails, nothing is cached, so it'll be tried again next time.
+    pub fn get_or_load<F: FnOnce() -> Result<V>>(&mut self, key: K, load: F) -> Result<Arc<V>> {
+        if let Some(index) = self.entries.iter().position(|(k, _)| *k == key) {
+            let entry = self.entries.remove(index);
+            let value = entry.1.clone();
+            self.entries.push(entry);
+            return Ok(value)
+        }
+        let value = Arc::new(load()?);
+        if self.entries.len() >= self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push((key, value.clone()));
+        Ok(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let mut cache = LruCache::<i32, String>::new(2);
+        let mut loads = Vec::<i32>::new();
+        let mut get = |cache: &mut LruCache<i32, String>, key: i32| {
+            cache.get_or_load(key, || { loads.push(key); Ok(format!("value {}", key)) }).unwrap()
+        };
+        assert_eq!(*get(&mut cache, 1), "value 1");
+        get(&mut cache, 2);
+        get(&mut cache, 1); // Now 2 is the least recently used.
+        get(&mut cache, 3);
+        get(&mut cache, 1);
+        get(&mut cache, 2);
+        assert_eq!(loads, vec![1, 2, 3, 2]);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn a_cached_value_is_shared_and_outlives_eviction() {
+        let mut cache = LruCache::<i32, String>::new(1);
+        let first = cache.get_or_load(1, || Ok("one".to_string())).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.get_or_load(1, || panic!("Loaded again")).unwrap()));
+        cache.get_or_load(2, || Ok("two".to_string())).unwrap();
+        assert_eq!(*first, "one");
+    }
+
+    #[test]
+    fn a_failed_load_isnt_cached() {
+        let mut cache = LruCache::<i32, String>::new(2);
+        assert!(cache.get_or_load(1, || Err(Error::new(ErrorKind::NotFound, "Missing"))).is_err());
+        assert!(cache.is_empty());
+        assert_eq!(*cache.get_or_load(1, || Ok("one".to_string())).unwrap(), "one");
+    }
+}
@@ -1,7 +1,24 @@
 pub mod models;
 pub mod parsers;
 pub mod loader;
+pub mod lru_cache;
+pub mod game_index;
 pub mod levels_per_game_and_skill;
 pub mod level_renderer;
 pub mod png;
+pub mod deflate;
+pub mod inflate;
+pub mod png_reader;
 pub mod sizes;
+pub mod password;
+pub mod campaign;
+pub mod terrain_mask;
+pub mod geometry;
+pub mod dirty_rects;
+pub mod lemming_grid;
+pub mod level_text;
+pub mod level_rules;
+pub mod xml;
+pub mod tmx;
+pub mod level_overlay;
+pub mod compositor;
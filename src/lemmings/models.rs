@@ -1,11 +1,15 @@
 use bevy::prelude::*;
 use std::collections::HashMap;
+use std::io::Result;
+use std::sync::Arc;
 use std::vec::IntoIter;
+use crate::lemmings::geometry::Rect;
+use crate::lemmings::game_index::GameIndex;
 
 ////////////////////////////////////////////////////////////////////////////////
 /// Levels
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Skills {
     pub climbers: u16, // 2 bytes each, only lower byte is used, max 0x00FA
     pub floaters: u16,
@@ -17,7 +21,7 @@ pub struct Skills {
     pub diggers: u16,
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Globals {
     pub release_rate: u16, // 0x0000 is slowest, 0x00FA is fastest
     pub num_of_lemmings: u16, // maximum 0x0072
@@ -29,7 +33,7 @@ pub struct Globals {
     pub extended_graphic_set: u16, // Apparently ignored in windows version.
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ObjectModifier {
     Normal, // Draw full graphic, 0
     MustHaveTerrainUnderneathToBeVisible, // 40
@@ -48,7 +52,7 @@ impl ObjectModifier {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Object {
     pub x: i32, // Normalised to 0.
         // In file:
@@ -72,7 +76,7 @@ pub struct Object {
     pub is_upside_down: bool, // can be 8F (display graphic upside-down) or 0F (display graphic normally)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Terrain {
     pub do_not_overwrite_existing_terrain: bool,
     pub is_upside_down: bool,
@@ -85,20 +89,33 @@ pub struct Terrain {
     pub terrain_id: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SteelArea {
-    pub x: isize, // Normalised.
+    pub x: isize, // As in the file, in 4 point units.
         // In file: min 0x000, max 0xC78.  0x000 = -16, 0x008 = -12,
         // 0x010 = -8, 0x018 = -4, ... , 0xC78 = 1580.
         // note: each hex value represents 4 pixels.
-    pub y: isize, // Normalised.
+    pub y: isize, // In points.
         // In file: min 0x00, max 0x27. 0x00 = 0, 0x01 = 4, 0x02 = 8, ... , 0x27 = 156
         // note: each hex value represents 4 pixels
     pub width: u8, // 0-F, each value represents 4 pixels, 0=4, 1=8, 7=32
     pub height: u8,
 }
 
-#[derive(Default, Debug, Clone)]
+impl SteelArea {
+    // Where it is in the level, in game points. The parser leaves x in the file's 4 point units, so that's done here.
+    // Like terrain and objects, x isn't shifted by the level's min_x yet.
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x * 4,
+            y: self.y,
+            width: (self.width as isize + 1) * 4,
+            height: (self.height as isize + 1) * 4,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Level {
     pub globals: Globals,
     pub objects: Vec<Object>, // Up to 32
@@ -107,6 +124,31 @@ pub struct Level {
     pub name: String,
 }
 
+// Lemmings 1 re-uses some level files under a different name with different numbers, this is the replacement.
+#[derive(Default, Debug, Clone)]
+pub struct OddTableEntry {
+    pub release_rate: u16,
+    pub num_of_lemmings: u16,
+    pub num_to_rescue: u16,
+    pub time_limit: u16,
+    pub skills: Skills,
+    pub name: String,
+}
+
+impl OddTableEntry {
+    // Makes the re-used version of a level: same terrain, different name and numbers.
+    pub fn apply_to(&self, level: &Level) -> Level {
+        let mut odd = level.clone();
+        odd.globals.release_rate = self.release_rate;
+        odd.globals.num_of_lemmings = self.num_of_lemmings;
+        odd.globals.num_to_rescue = self.num_to_rescue;
+        odd.globals.time_limit = self.time_limit;
+        odd.globals.skills = self.skills.clone();
+        odd.name = self.name.clone();
+        odd
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 /// Ground
 
@@ -135,6 +177,19 @@ impl ObjectInfo {
     pub fn is_valid(&self) -> bool {
         return self.width>0 && self.height>0;
     }
+
+    // The area a lemming's feet must be in to trigger the object, eg to exit, in game points from its top-left.
+    // See lemmings_vgagrx_dat_groundxo_dat_file_format.txt for the encoding.
+    pub fn trigger_rect(&self) -> Rect {
+        let width = if self.trigger_width == 0 { 256 } else { self.trigger_width as isize };
+        let height = if self.trigger_height == 0 { 256 } else { self.trigger_height as isize };
+        Rect {
+            x: self.trigger_left as isize * 4,
+            y: self.trigger_top as isize * 4 - 4,
+            width: width * 4,
+            height: height * 4,
+        }
+    }
 }
 
 #[derive(Default, Copy, Clone, Debug)]
@@ -363,19 +418,17 @@ pub struct GroundCombined {
     pub object_sprites: AnimationMap,
 }
 
-pub type GroundMap = HashMap<i32, GroundCombined>;
-pub type LevelMap = HashMap<i32, Level>; // Key is file# * 100 + section. Eg 203 = LEVEL002.DAT section 3.
-pub type SpecialMap = HashMap<i32, Image>;
+// Levels are keyed by file# * 100 + section. Eg 203 = LEVEL002.DAT section 3. ODD_TABLE_LEVEL_KEY + n is ODDTABLE.DAT entry n.
+pub const ODD_TABLE_LEVEL_KEY: i32 = 100_000;
 
+// Cheap to clone: the levels, grounds and specials are loaded on demand through the shared index, see game_index.rs.
 #[derive(Clone, Resource)]
 pub struct Game {
     pub name: String, // Eg 'Oh No More Lemmings'
     pub id: String, // Eg 'ohnomore'
     pub path: String, // Eg '/Users/foo/Lemmings/ohnomore'
-    pub levels: LevelMap,
-    pub specials: SpecialMap,
-    pub grounds: GroundMap,
-    pub main: MainDat,
+    pub index: Arc<GameIndex>,
+    pub main: Arc<MainDat>,
 }
 
 pub struct Games {
@@ -416,97 +469,199 @@ impl Games {
 }
 
 impl Game {
-    pub fn level_named<'a>(&'a self, name: &str) -> Option<&'a Level> {
-        for (_, l) in &self.levels {
-            if l.name == name {
-                return Some(l)
+    pub fn level(&self, key: i32) -> Option<Arc<Level>> {
+        match self.index.level(key) {
+            Ok(level) => Some(level),
+            Err(e) => {
+                println!("Couldn't load level {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    pub fn level_named(&self, name: &str) -> Option<Arc<Level>> {
+        let key = self.index.level_key_named(name)?;
+        match self.index.level(key) {
+            Ok(level) => Some(level),
+            Err(e) => {
+                println!("Couldn't load level {}: {}", name, e);
+                None
             }
         }
-        None
+    }
+
+    // The terrain and objects the level is built from.
+    pub fn ground_for(&self, level: &Level) -> Result<Arc<GroundCombined>> {
+        self.index.ground(level.globals.normal_graphic_set as i32)
+    }
+
+    // The pre-drawn terrain of a special level, eg a Sega crossover, or None for a normal level.
+    pub fn special_for(&self, level: &Level) -> Result<Option<Arc<Image>>> {
+        if level.globals.extended_graphic_set == 0 { return Ok(None) }
+        Ok(Some(self.index.special(level.globals.extended_graphic_set as i32 - 1)?))
     }
     
-    pub fn all_assets(&self) -> Vec<AssetToPreProcess> {
-        let mut all = Vec::<AssetToPreProcess>::new();        
-
-        all.push(AssetToPreProcess{name: "lemming.walking_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.walking_right),});
-        all.push(AssetToPreProcess{name: "lemming.jumping_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.jumping_right),});
-        all.push(AssetToPreProcess{name: "lemming.walking_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.walking_left),});
-        all.push(AssetToPreProcess{name: "lemming.jumping_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.jumping_left),});
-        all.push(AssetToPreProcess{name: "lemming.digging".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.digging),});
-        all.push(AssetToPreProcess{name: "lemming.climbing_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.climbing_right),});
-        all.push(AssetToPreProcess{name: "lemming.climbing_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.climbing_left),});
-        all.push(AssetToPreProcess{name: "lemming.drowning".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.drowning),});
-        all.push(AssetToPreProcess{name: "lemming.post_climb_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.post_climb_right),});
-        all.push(AssetToPreProcess{name: "lemming.post_climb_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.post_climb_left),});
-        all.push(AssetToPreProcess{name: "lemming.brick_laying_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.brick_laying_right),});
-        all.push(AssetToPreProcess{name: "lemming.brick_laying_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.brick_laying_left),});
-        all.push(AssetToPreProcess{name: "lemming.bashing_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.bashing_right),});
-        all.push(AssetToPreProcess{name: "lemming.bashing_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.bashing_left),});
-        all.push(AssetToPreProcess{name: "lemming.mining_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.mining_right),});
-        all.push(AssetToPreProcess{name: "lemming.mining_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.mining_left),});
-        all.push(AssetToPreProcess{name: "lemming.falling_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.falling_right),});
-        all.push(AssetToPreProcess{name: "lemming.falling_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.falling_left),});
-        all.push(AssetToPreProcess{name: "lemming.pre_umbrella_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.pre_umbrella_right),});
-        all.push(AssetToPreProcess{name: "lemming.umbrella_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.umbrella_right),});
-        all.push(AssetToPreProcess{name: "lemming.pre_umbrella_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.pre_umbrella_left),});
-        all.push(AssetToPreProcess{name: "lemming.umbrella_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.umbrella_left),});
-        all.push(AssetToPreProcess{name: "lemming.splatting".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.splatting),});
-        all.push(AssetToPreProcess{name: "lemming.exiting".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.exiting),});
-        all.push(AssetToPreProcess{name: "lemming.fried".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.fried),});
-        all.push(AssetToPreProcess{name: "lemming.blocking".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.blocking),});
-        all.push(AssetToPreProcess{name: "lemming.shrugging_right".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.shrugging_right),});
-        all.push(AssetToPreProcess{name: "lemming.shrugging_left".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.shrugging_left),});
-        all.push(AssetToPreProcess{name: "lemming.oh_no_ing".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.oh_no_ing),});
-        all.push(AssetToPreProcess{name: "lemming.explosion".to_string(), content: AnimationOrImage::Animation(&self.main.lemming_animations.explosion),});
-
-        all.push(AssetToPreProcess{name: "font.percent".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.percent),});
-        all.push(AssetToPreProcess{name: "font.dash".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.dash),});
-        all.push(AssetToPreProcess{name: "font.0".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.digits[0]),});
-        all.push(AssetToPreProcess{name: "font.1".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.digits[1]),});
-        all.push(AssetToPreProcess{name: "font.2".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.digits[2]),});
-        all.push(AssetToPreProcess{name: "font.3".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.digits[3]),});
-        all.push(AssetToPreProcess{name: "font.4".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.digits[4]),});
-        all.push(AssetToPreProcess{name: "font.5".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.digits[5]),});
-        all.push(AssetToPreProcess{name: "font.6".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.digits[6]),});
-        all.push(AssetToPreProcess{name: "font.7".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.digits[7]),});
-        all.push(AssetToPreProcess{name: "font.8".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.digits[8]),});
-        all.push(AssetToPreProcess{name: "font.9".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.digits[9]),});
-        all.push(AssetToPreProcess{name: "font.a".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[0]),});
-        all.push(AssetToPreProcess{name: "font.b".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[1]),});
-        all.push(AssetToPreProcess{name: "font.c".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[2]),});
-        all.push(AssetToPreProcess{name: "font.d".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[3]),});
-        all.push(AssetToPreProcess{name: "font.e".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[4]),});
-        all.push(AssetToPreProcess{name: "font.f".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[5]),});
-        all.push(AssetToPreProcess{name: "font.g".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[6]),});
-        all.push(AssetToPreProcess{name: "font.h".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[7]),});
-        all.push(AssetToPreProcess{name: "font.i".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[8]),});
-        all.push(AssetToPreProcess{name: "font.j".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[9]),});
-        all.push(AssetToPreProcess{name: "font.k".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[10]),});
-        all.push(AssetToPreProcess{name: "font.l".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[11]),});
-        all.push(AssetToPreProcess{name: "font.m".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[12]),});
-        all.push(AssetToPreProcess{name: "font.n".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[13]),});
-        all.push(AssetToPreProcess{name: "font.o".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[14]),});
-        all.push(AssetToPreProcess{name: "font.p".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[15]),});
-        all.push(AssetToPreProcess{name: "font.q".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[16]),});
-        all.push(AssetToPreProcess{name: "font.r".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[17]),});
-        all.push(AssetToPreProcess{name: "font.s".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[18]),});
-        all.push(AssetToPreProcess{name: "font.t".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[19]),});
-        all.push(AssetToPreProcess{name: "font.u".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[20]),});
-        all.push(AssetToPreProcess{name: "font.v".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[21]),});
-        all.push(AssetToPreProcess{name: "font.w".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[22]),});
-        all.push(AssetToPreProcess{name: "font.x".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[23]),});
-        all.push(AssetToPreProcess{name: "font.y".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[24]),});
-        all.push(AssetToPreProcess{name: "font.z".to_string(), content: AnimationOrImage::Image(&self.main.game_font_high_perf.letters[25]),});
+    // Every image in MAIN.DAT, named for files and debugging.
+    pub fn all_assets(&self) -> Vec<AssetToPreProcess<'_>> {
+        let main = &self.main;
+        let lemmings = &main.lemming_animations;
+        let menu = &main.main_menu;
+        let mut all = Vec::<AssetToPreProcess>::new();
+
+        let lemming_animations = [
+            ("walking_right", &lemmings.walking_right),
+            ("jumping_right", &lemmings.jumping_right),
+            ("walking_left", &lemmings.walking_left),
+            ("jumping_left", &lemmings.jumping_left),
+            ("digging", &lemmings.digging),
+            ("climbing_right", &lemmings.climbing_right),
+            ("climbing_left", &lemmings.climbing_left),
+            ("drowning", &lemmings.drowning),
+            ("post_climb_right", &lemmings.post_climb_right),
+            ("post_climb_left", &lemmings.post_climb_left),
+            ("brick_laying_right", &lemmings.brick_laying_right),
+            ("brick_laying_left", &lemmings.brick_laying_left),
+            ("bashing_right", &lemmings.bashing_right),
+            ("bashing_left", &lemmings.bashing_left),
+            ("mining_right", &lemmings.mining_right),
+            ("mining_left", &lemmings.mining_left),
+            ("falling_right", &lemmings.falling_right),
+            ("falling_left", &lemmings.falling_left),
+            ("pre_umbrella_right", &lemmings.pre_umbrella_right),
+            ("umbrella_right", &lemmings.umbrella_right),
+            ("pre_umbrella_left", &lemmings.pre_umbrella_left),
+            ("umbrella_left", &lemmings.umbrella_left),
+            ("splatting", &lemmings.splatting),
+            ("exiting", &lemmings.exiting),
+            ("fried", &lemmings.fried),
+            ("blocking", &lemmings.blocking),
+            ("shrugging_right", &lemmings.shrugging_right),
+            ("shrugging_left", &lemmings.shrugging_left),
+            ("oh_no_ing", &lemmings.oh_no_ing),
+            ("explosion", &lemmings.explosion),
+        ];
+        for (name, animation) in lemming_animations {
+            all.push(AssetToPreProcess::animation(format!("lemming.{}", name), PaletteSource::MainDatGame, animation));
+        }
+
+        all.extend(font_assets("font", &main.game_font_high_perf)); // The font for the high performance skill panel.
+        all.extend(font_assets("standard_font", &main.game_font));
+
+        let game_images = [
+            ("skill_panel", &main.skill_panel),
+            ("skill_panel_high_perf", &main.skill_panel_high_perf),
+            ("skill_selection", &main.skill_selection),
+            ("speed_selection", &main.speed_selection),
+            ("pause_selection", &main.pause_selection),
+            ("nuke_selection", &main.nuke_selection),
+            ("mouse_cursor", &main.mouse_cursor),
+            ("mouse_cursor_hovering", &main.mouse_cursor_hovering),
+        ];
+        for (name, image) in game_images {
+            all.push(AssetToPreProcess::image(name.to_string(), PaletteSource::MainDatGame, image));
+        }
+        for (i, digit) in main.countdown_numbers.iter().enumerate() {
+            all.push(AssetToPreProcess::image(format!("countdown.{}", i), PaletteSource::White, digit));
+        }
+        for (i, (left, right)) in main.skill_number_digits.left.iter().zip(&main.skill_number_digits.right).enumerate() {
+            all.push(AssetToPreProcess::image(format!("skill_number.left.{}", i), PaletteSource::White, left));
+            all.push(AssetToPreProcess::image(format!("skill_number.right.{}", i), PaletteSource::White, right));
+        }
+
+        let menu_images = [
+            ("background", &menu.background, PaletteSource::MainDatMenuOpaque),
+            ("logo", &menu.logo, PaletteSource::MainDatMenu),
+            ("f1", &menu.f1, PaletteSource::MainDatMenu),
+            ("f2", &menu.f2, PaletteSource::MainDatMenu),
+            ("f3", &menu.f3, PaletteSource::MainDatMenu),
+            ("f4", &menu.f4, PaletteSource::MainDatMenu),
+            ("level_rating", &menu.level_rating, PaletteSource::MainDatMenu),
+            ("exit_to_dos", &menu.exit_to_dos, PaletteSource::MainDatMenu),
+            ("music_note", &menu.music_note, PaletteSource::MainDatMenu),
+            ("fx", &menu.fx, PaletteSource::MainDatMenu),
+            ("reel", &menu.reel, PaletteSource::MainDatMenu),
+            ("mayhem", &menu.mayhem, PaletteSource::MainDatMenuOpaque),
+            ("taxing", &menu.taxing, PaletteSource::MainDatMenuOpaque),
+            ("tricky", &menu.tricky, PaletteSource::MainDatMenuOpaque),
+            ("fun", &menu.fun, PaletteSource::MainDatMenuOpaque),
+        ];
+        for (name, image, palette) in menu_images {
+            all.push(AssetToPreProcess::image(format!("main_menu.{}", name), palette, image));
+        }
+        let menu_animations = [
+            ("blink1", &menu.blink1),
+            ("blink2", &menu.blink2),
+            ("blink3", &menu.blink3),
+            ("blink4", &menu.blink4),
+            ("blink5", &menu.blink5),
+            ("blink6", &menu.blink6),
+            ("blink7", &menu.blink7),
+            ("left_scroller", &menu.left_scroller),
+            ("right_scroller", &menu.right_scroller),
+            ("menu_font", &menu.menu_font),
+        ];
+        for (name, animation) in menu_animations {
+            all.push(AssetToPreProcess::animation(format!("main_menu.{}", name), PaletteSource::MainDatMenu, animation));
+        }
 
         all
     }
 }
 
+fn font_assets<'a>(prefix: &str, font: &'a GameFont) -> Vec<AssetToPreProcess<'a>> {
+    let mut all = vec![
+        AssetToPreProcess::image(format!("{}.percent", prefix), PaletteSource::MainDatGame, &font.percent),
+        AssetToPreProcess::image(format!("{}.dash", prefix), PaletteSource::MainDatGame, &font.dash),
+    ];
+    for (i, digit) in font.digits.iter().enumerate() {
+        all.push(AssetToPreProcess::image(format!("{}.{}", prefix, i), PaletteSource::MainDatGame, digit));
+    }
+    for (letter, image) in ('a'..='z').zip(&font.letters) {
+        all.push(AssetToPreProcess::image(format!("{}.{}", prefix, letter), PaletteSource::MainDatGame, image));
+    }
+    all
+}
+
+// Which colours an image was decoded with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaletteSource {
+    MainDatGame, // The lemmings' and skill panel's colours.
+    MainDatMenu,
+    MainDatMenuOpaque, // With colour 0 black rather than see-through.
+    White, // 1 bit, white or see-through.
+    Ground(i32), // GROUNDxO.DAT.
+    Special(i32), // VGASPECx.DAT.
+}
+
+impl PaletteSource {
+    pub fn describe(&self) -> String {
+        match self {
+            PaletteSource::MainDatGame => "main.dat game".to_string(),
+            PaletteSource::MainDatMenu => "main.dat menu".to_string(),
+            PaletteSource::MainDatMenuOpaque => "main.dat menu, opaque".to_string(),
+            PaletteSource::White => "white".to_string(),
+            PaletteSource::Ground(n) => format!("ground{}o.dat", n),
+            PaletteSource::Special(n) => format!("vgaspec{}.dat", n),
+        }
+    }
+}
+
 pub struct AssetToPreProcess<'a> {
     pub name: String,
+    pub palette: PaletteSource,
     pub content: AnimationOrImage<'a>,
 }
 
+impl<'a> AssetToPreProcess<'a> {
+    pub fn image(name: String, palette: PaletteSource, image: &'a Image) -> AssetToPreProcess<'a> {
+        AssetToPreProcess { name, palette, content: AnimationOrImage::Image(image) }
+    }
+
+    pub fn animation(name: String, palette: PaletteSource, animation: &'a Animation) -> AssetToPreProcess<'a> {
+        AssetToPreProcess { name, palette, content: AnimationOrImage::Animation(animation) }
+    }
+}
+
 pub enum AnimationOrImage<'a> {
     Animation(&'a Animation),
     Image(&'a Image),
@@ -187,3 +187,62 @@ fn decompress_recursively(compressed: &[u8], sections: Vec<Vec<u8>>) -> io::Resu
 pub fn decompress(compressed: &[u8]) -> io::Result<Vec<Vec<u8>>> {
     decompress_recursively(&compressed, Vec::new())
 }
+
+// The reverse of decompress, for tests that need DAT files. It doesn't compress, it only stores the bytes raw, which
+// decompress reads just the same.
+#[cfg(test)]
+pub fn store(sections: &[Vec<u8>]) -> Vec<u8> {
+    fn push_byte(bits: &mut Vec<u8>, byte: u8) {
+        bits.extend((0..8).rev().map(|i| (byte >> i) & 1));
+    }
+    let mut file = Vec::<u8>::new();
+    for section in sections {
+        // The bits, in the order they're read. Bytes come out last first, as decompress reverses them at the end.
+        let mut bits = Vec::<u8>::new();
+        let reversed: Vec<u8> = section.iter().rev().cloned().collect();
+        for chunk in reversed.chunks(264) {
+            if chunk.len() >= 9 { // 6: many raw bytes.
+                bits.extend([1, 1, 1]);
+                push_byte(&mut bits, (chunk.len() - 9) as u8);
+                chunk.iter().for_each(|b| push_byte(&mut bits, *b));
+            } else { // 1: some raw bytes, 8 at a time.
+                for part in chunk.chunks(8) {
+                    let n = part.len() as u8 - 1;
+                    bits.extend([0, 0, (n >> 2) & 1, (n >> 1) & 1, n & 1]);
+                    part.iter().for_each(|b| push_byte(&mut bits, *b));
+                }
+            }
+        }
+        // The last byte in the file is read first, from its low bit up, and may only have some bits used.
+        let num_bits_in_first_byte = match bits.len() % 8 { 0 => 8, n => n };
+        let mut data = Vec::<u8>::new();
+        let (first, rest) = bits.split_at(num_bits_in_first_byte.min(bits.len()));
+        for byte_bits in std::iter::once(first).chain(rest.chunks(8)) {
+            data.push(byte_bits.iter().enumerate().fold(0, |byte, (i, bit)| byte | bit << i));
+        }
+        data.reverse();
+        let checksum = data.iter().fold(0, |sum, b| sum ^ b);
+        let end = 10 + data.len();
+        file.extend([num_bits_in_first_byte as u8, checksum, 0, 0]);
+        file.extend((section.len() as u16).to_be_bytes());
+        file.extend([0, 0]);
+        file.extend((end as u16).to_be_bytes());
+        file.extend(data);
+    }
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompresses_what_was_stored() {
+        let sections: Vec<Vec<u8>> = vec![
+            (0..2048).map(|i| (i * 7 % 256) as u8).collect(),
+            vec![1, 2, 3],
+            (0..20).collect(),
+        ];
+        assert_eq!(decompress(&store(&sections)).unwrap(), sections);
+    }
+}
@@ -1,9 +1,10 @@
-// This is for parsing lemmings LVL files.
+// This is for parsing lemmings LVL files, and writing them back out.
 // https://www.camanis.net/lemmings/files/docs/lemmings_lvl_file_format.txt
 
 use std::io::{Error, ErrorKind, Result};
 use std::slice::Iter;
 
+use crate::lemmings::level_rules::*;
 use crate::lemmings::models::*;
 
 impl ObjectModifier {
@@ -16,9 +17,17 @@ impl ObjectModifier {
             return ObjectModifier::Normal;
         }
     }
+
+    fn to_lvl(&self) -> u8 {
+        match self {
+            ObjectModifier::Normal => 0,
+            ObjectModifier::MustHaveTerrainUnderneathToBeVisible => 0x40,
+            ObjectModifier::DoNotOverwriteExistingTerrain => 0x80,
+        }
+    }
 }
 
-fn string_from_vec(vec: Vec<u8>) -> Result<String> {
+pub fn string_from_vec(vec: Vec<u8>) -> Result<String> {
     match String::from_utf8(vec).ok() {
         Some(t) => Ok(t),
         None => Err(Error::new(ErrorKind::InvalidData, "Bad string")),
@@ -26,7 +35,7 @@ fn string_from_vec(vec: Vec<u8>) -> Result<String> {
 }
 
 // Exposes the 'next' as a result so you can use '?'.
-fn read_u8(data: &mut Iter<u8>) -> Result<u8> {
+pub fn read_u8(data: &mut Iter<u8>) -> Result<u8> {
     match data.next() {
         Some(t) => Ok(*t),
         None => Err(Error::new(ErrorKind::UnexpectedEof, "No data remaining")),
@@ -34,16 +43,27 @@ fn read_u8(data: &mut Iter<u8>) -> Result<u8> {
 }
 
 // Unlike the GROUND file format, WORDs in LVL are stored big-endian (camanis.net).
-fn read_u16(data: &mut Iter<u8>) -> Result<u16> {
+pub fn read_u16(data: &mut Iter<u8>) -> Result<u16> {
     let big = read_u8(data)?;
     let little = read_u8(data)?;
     Ok(((big as u16) << 8) + (little as u16))
 }
 
+const LEVEL_SIZE: usize = 2048;
+const NAME_SIZE: usize = MAX_NAME_BYTES; // The name is the last thing in the level.
+
+// Just the name, without parsing the rest, for indexing the levels.
+pub fn parse_name(data: &[u8]) -> Result<String> {
+    if data.len() != LEVEL_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "Wrong length"))
+    }
+    Ok(string_from_vec(data[LEVEL_SIZE - NAME_SIZE..].to_vec())?.trim().to_string())
+}
+
 /// Decompresses all the sections from a compressed dat file.
 /// Returns a vec of sections. Each section is a vec of its data.
 pub fn parse(data: &[u8]) -> Result<Level> {
-    if data.len() != 2048 {
+    if data.len() != LEVEL_SIZE {
         return Err(Error::new(ErrorKind::InvalidData, "Wrong length"))
     }
     let mut level: Level = Default::default();
@@ -68,13 +88,13 @@ pub fn parse(data: &[u8]) -> Result<Level> {
     let _unused = read_u16(&mut data_iter)?;
 
     // Objects.
-    for _ in 0..32 {
+    for _ in 0..MAX_OBJECTS {
         let ix = read_u16(&mut data_iter)? as i16; // Will convert eg 0xfff8 to -24;
         let iy = read_u16(&mut data_iter)? as i16;
         let id = read_u16(&mut data_iter)?;
         let ma = read_u8(&mut data_iter)?;
         let mb = read_u8(&mut data_iter)?;
-        let is_bad = (ix==0 && iy==0 && id==0) || id>=16;
+        let is_bad = (ix==0 && iy==0 && id==0) || id as usize >= OBJECT_IDS;
         if !is_bad {
             level.objects.push(Object {
                 x: ix as i32,
@@ -87,13 +107,13 @@ pub fn parse(data: &[u8]) -> Result<Level> {
     }
 
     // Terrain.
-    for _ in 0..400 {
+    for _ in 0..MAX_TERRAIN {
         let a = read_u8(&mut data_iter)?; // significant nibble = flags, other = x.
         let b = read_u8(&mut data_iter)?; // x. 
         let c = read_u8(&mut data_iter)?; // First 8 of 9 bits of y.
         let d = read_u8(&mut data_iter)?; // Another bit of y, and terrain id.
         let terrain_id = d & 0x7f;
-        let is_bad = (a==0xff && b==0xff && c==0xff && d==0xff) || terrain_id>=64;
+        let is_bad = (a==0xff && b==0xff && c==0xff && d==0xff) || terrain_id as usize >= TERRAIN_IDS;
         if !is_bad {
             let x: u16 = (((a & 0xf) as u16) << 8) + (b as u16);
             let y_bits: u16 = ((c as u16) << 1) + ((d >> 7) as u16);
@@ -114,7 +134,7 @@ pub fn parse(data: &[u8]) -> Result<Level> {
     }
 
     // Steel.
-    for _ in 0..32 {
+    for _ in 0..MAX_STEEL {
         let a = read_u8(&mut data_iter)?; // First 8 of 9 bits of x.
         let b = read_u8(&mut data_iter)?; // Last x bit, y.
         let c = read_u8(&mut data_iter)?; // Area.
@@ -125,7 +145,7 @@ pub fn parse(data: &[u8]) -> Result<Level> {
             let y: u8 = b & 0x7f;
             level.steel.push(SteelArea {
                 x: (x as isize),
-                y: (y as isize) * 4 - 4, // TODO test this makes the steel in the correct place?
+                y: (y as isize) * 4,
                 width: c >> 4,
                 height: c & 0xf,
             });
@@ -143,3 +163,146 @@ pub fn parse(data: &[u8]) -> Result<Level> {
 
     Ok(level)
 }
+
+fn write_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+fn doesnt_fit(what: String) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{} doesn't fit in an LVL file", what))
+}
+
+// Whether the level can be written as it is, rather than having anything cut short or wrapped around, so it reads back
+// the same. level_rules.rs checks these too, along with what the game needs to play it.
+fn check_fits(level: &Level) -> Result<()> {
+    if level.objects.len() > MAX_OBJECTS { return Err(doesnt_fit(format!("{} objects", level.objects.len()))) }
+    if level.terrain.len() > MAX_TERRAIN { return Err(doesnt_fit(format!("{} terrain pieces", level.terrain.len()))) }
+    if level.steel.len() > MAX_STEEL { return Err(doesnt_fit(format!("{} steel areas", level.steel.len()))) }
+    if level.name.len() > NAME_SIZE { return Err(doesnt_fit(format!("The name '{}'", level.name))) }
+    for (i, object) in level.objects.iter().enumerate() {
+        if object.obj_id >= OBJECT_IDS || i16::try_from(object.x).is_err() || i16::try_from(object.y).is_err() {
+            return Err(doesnt_fit(format!("Object {} (id {} at {},{})", i + 1, object.obj_id, object.x, object.y)))
+        }
+    }
+    for (i, terrain) in level.terrain.iter().enumerate() {
+        if terrain.terrain_id >= TERRAIN_IDS || !TERRAIN_X.contains(&terrain.x) || !TERRAIN_FILE_Y.contains(&(terrain.y + 4)) {
+            return Err(doesnt_fit(format!("Terrain {} (id {} at {},{})", i + 1, terrain.terrain_id, terrain.x, terrain.y)))
+        }
+    }
+    for (i, steel) in level.steel.iter().enumerate() {
+        if !STEEL_X.contains(&steel.x) || steel.y % 4 != 0 || !STEEL_Y.contains(&steel.y) || steel.width > MAX_STEEL_SIZE || steel.height > MAX_STEEL_SIZE {
+            return Err(doesnt_fit(format!("Steel {} (at {},{})", i + 1, steel.x, steel.y)))
+        }
+    }
+    Ok(())
+}
+
+// The reverse of parse: parse(&write(&level)?) gives the same level back. Unused slots are filled the way the original
+// levels fill them, which parse skips over. Errors if anything doesn't fit, eg a 33rd object.
+pub fn write(level: &Level) -> Result<Vec<u8>> {
+    check_fits(level)?;
+    let mut data = Vec::<u8>::with_capacity(LEVEL_SIZE);
+
+    // Globals.
+    let globals = &level.globals;
+    let skills = &globals.skills;
+    for value in [
+        globals.release_rate, globals.num_of_lemmings, globals.num_to_rescue, globals.time_limit,
+        skills.climbers, skills.floaters, skills.bombers, skills.blockers,
+        skills.builders, skills.bashers, skills.miners, skills.diggers,
+        globals.start_screen_xpos, globals.normal_graphic_set, globals.extended_graphic_set, 0,
+    ] {
+        write_u16(&mut data, value);
+    }
+
+    // Objects.
+    for i in 0..MAX_OBJECTS {
+        let Some(object) = level.objects.get(i) else {
+            data.extend_from_slice(&[0; 8]);
+            continue
+        };
+        write_u16(&mut data, object.x as i16 as u16);
+        write_u16(&mut data, object.y as i16 as u16);
+        write_u16(&mut data, object.obj_id as u16);
+        data.push(object.modifier.to_lvl());
+        data.push(if object.is_upside_down { 0x8f } else { 0x0f });
+    }
+
+    // Terrain.
+    for i in 0..MAX_TERRAIN {
+        let Some(terrain) = level.terrain.get(i) else {
+            data.extend_from_slice(&[0xff; 4]);
+            continue
+        };
+        let flags: u8 = if terrain.do_not_overwrite_existing_terrain { 8 } else { 0 }
+            | if terrain.is_upside_down { 4 } else { 0 }
+            | if terrain.remove_terrain { 2 } else { 0 };
+        let x = terrain.x as u16 & 0xfff;
+        let y_bits = (terrain.y + 4) as u16 & 0x1ff;
+        data.push(flags << 4 | (x >> 8) as u8);
+        data.push(x as u8);
+        data.push((y_bits >> 1) as u8);
+        data.push(((y_bits & 1) as u8) << 7 | terrain.terrain_id as u8 & 0x7f);
+    }
+
+    // Steel.
+    for i in 0..MAX_STEEL {
+        let Some(steel) = level.steel.get(i) else {
+            data.extend_from_slice(&[0; 4]);
+            continue
+        };
+        let x = steel.x as u16 & 0x1ff;
+        let y = (steel.y / 4) as u8 & 0x7f;
+        data.push((x >> 1) as u8);
+        data.push(((x & 1) as u8) << 7 | y);
+        data.push(steel.width << 4 | steel.height & 0xf);
+        data.push(0);
+    }
+
+    // Name, padded with spaces.
+    let mut name: Vec<u8> = level.name.bytes().collect();
+    name.resize(NAME_SIZE, b' ');
+    data.extend_from_slice(&name);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lemmings::geometry::Rect;
+
+    // An LVL file with just a terrain piece at 64,36 and a 64x64 steel area over it, laid out by hand.
+    fn steel_over_terrain() -> Vec<u8> {
+        let mut data = vec![0u8; 32 + 32 * 8]; // Globals, all 0, and 32 unused objects.
+        data.extend_from_slice(&[
+            0x00, // No flags, and the top 4 bits of x.
+            64,   // The rest of x, so x is 64.
+            20,   // The top 8 of y's 9 bits, so y is 40 in the file's frame, 4 lower than the game's.
+            0,    // y's last bit, and terrain id 0.
+        ]);
+        data.extend_from_slice(&[0xff; 399 * 4]); // Unused terrain.
+        data.extend_from_slice(&[
+            8,    // The top 8 of x's 9 bits, so x is 16, in 4 point units, which is 64.
+            9,    // x's last bit, and y, 9 in 4 point units, which is 36.
+            0xff, // 16 units wide and 16 high, which is 64x64.
+            0,    // Unused.
+        ]);
+        data.extend_from_slice(&[0; 31 * 4]); // Unused steel.
+        data.extend_from_slice(&[b' '; 32]); // A blank name.
+        data
+    }
+
+    #[test]
+    fn steel_is_in_the_same_frame_as_terrain() {
+        let level = parse(&steel_over_terrain()).unwrap();
+        let terrain = &level.terrain[0];
+        assert_eq!((terrain.x, terrain.y), (64, 36));
+        assert_eq!(level.steel[0].rect(), Rect { x: terrain.x, y: terrain.y, width: 64, height: 64 });
+    }
+
+    #[test]
+    fn steel_writes_back_the_same() {
+        let data = steel_over_terrain();
+        assert_eq!(write(&parse(&data).unwrap()).unwrap(), data);
+    }
+}
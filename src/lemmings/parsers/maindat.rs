@@ -4,7 +4,7 @@
 use std::io;
 use std::io::Error;
 use std::io::ErrorKind;
-use super::helpers::BitsIterMS;
+use super::planar;
 use crate::lemmings::models::*;
 use crate::lemmings::sizes;
 use crate::lemmings::models::Image;
@@ -43,71 +43,27 @@ const MOUSE_CURSOR_HOVERING: [u8; 196] = [
     2, 2, 3, 3, 0, 0, 1, 1, 0, 0, 3, 3, 2, 2,
 ];
 
-// Creates a bit iterator from [u8].
-macro_rules! iterate_bits { ($data:expr) => { $data.iter().flat_map(BitsIterMS::new) } }
-
 impl Image {
     /// Parses where 0=transparent, 1=white.
     fn parse_1bpp(data: &[u8], width: usize, height: usize) -> Image {
-        let pixels = width * height;
-        let mut plane = iterate_bits!(data);
-        let mut bitmap: Vec<u32> = Vec::with_capacity(pixels);
-        for _ in 0..pixels {
-            let bit = plane.next().unwrap();
-            bitmap.push(if bit==0 { 0 } else { 0xffffffff } );
-        }
-        return Image { bitmap: bitmap, width: width, height: height };
+        Image::parse_planes(data, width, height, &[0, 0xffffffff], 1)
     }
 
     fn parse_2bpp(data: &[u8], width: usize, height: usize, palette: [u32; 16]) -> Image {
-        let pixels = width * height;
-        let mut plane_0 = iterate_bits!(data);
-        let mut plane_1 = iterate_bits!(data).skip(pixels);
-        let mut bitmap: Vec<u32> = Vec::with_capacity(pixels);
-        for _ in 0..pixels {
-            let colour_index =
-                plane_0.next().unwrap() +
-                (plane_1.next().unwrap() << 1);
-            let colour = palette[colour_index as usize];
-            bitmap.push(colour);
-        }
-        return Image { bitmap: bitmap, width: width, height: height };
+        Image::parse_planes(data, width, height, &palette, 2)
     }
 
     fn parse_3bpp(data: &[u8], width: usize, height: usize, palette: [u32; 16]) -> Image {
-        let pixels = width * height;
-        let mut plane_0 = iterate_bits!(data);
-        let mut plane_1 = iterate_bits!(data).skip(pixels);
-        let mut plane_2 = iterate_bits!(data).skip(pixels * 2);
-        let mut bitmap: Vec<u32> = Vec::with_capacity(pixels);
-        for _ in 0..pixels {
-            let colour_index =
-                plane_0.next().unwrap() +
-                (plane_1.next().unwrap() << 1) +
-                (plane_2.next().unwrap() << 2);
-            let colour = palette[colour_index as usize];
-            bitmap.push(colour);
-        }
-        return Image { bitmap: bitmap, width: width, height: height };
+        Image::parse_planes(data, width, height, &palette, 3)
     }
 
     fn parse_4bpp(data: &[u8], width: usize, height: usize, palette: [u32; 16]) -> Image {
-        let pixels = width * height;
-        let mut plane_0 = iterate_bits!(data);
-        let mut plane_1 = iterate_bits!(data).skip(pixels);
-        let mut plane_2 = iterate_bits!(data).skip(pixels * 2);
-        let mut plane_3 = iterate_bits!(data).skip(pixels * 3);
-        let mut bitmap: Vec<u32> = Vec::with_capacity(pixels);
-        for _ in 0..pixels {
-            let colour_index =
-                plane_0.next().unwrap() +
-                (plane_1.next().unwrap() << 1) +
-                (plane_2.next().unwrap() << 2) +
-                (plane_3.next().unwrap() << 3);
-            let colour = palette[colour_index as usize];
-            bitmap.push(colour);
-        }
-        return Image { bitmap: bitmap, width: width, height: height };
+        Image::parse_planes(data, width, height, &palette, 4)
+    }
+
+    fn parse_planes(data: &[u8], width: usize, height: usize, palette: &[u32], planes: usize) -> Image {
+        let bitmap = planar::decode(data, 0, width * height, planes, palette);
+        Image { bitmap, width, height }
     }
 
     fn parse_8bpp(data: &[u8], width: usize, height: usize, palette: [u32; 16]) -> Image {
@@ -122,72 +78,24 @@ impl Image {
 
 impl Animation {
     fn parse_2bpp(data: &[u8], frame_count: usize, width: usize, height: usize, palette: [u32; 16]) -> Animation {
-        const BPP: usize = 2;
-        let pixels = width * height;
-        let mut frames: Vec<Vec<u32>> = Vec::with_capacity(frame_count);
-        for frame_index in 0..frame_count {
-            let offset_bits = frame_index * pixels * BPP;
-            let mut plane_0 = iterate_bits!(data).skip(offset_bits);
-            let mut plane_1 = iterate_bits!(data).skip(offset_bits + pixels);
-            let mut bitmap: Vec<u32> = Vec::with_capacity(pixels);
-            for _ in 0..pixels {
-                let colour_index =
-                    plane_0.next().unwrap() +
-                    (plane_1.next().unwrap() << 1);
-                let colour = palette[colour_index as usize];
-                bitmap.push(colour);
-            }
-            frames.push(bitmap);
-        }
-        return Animation { frames: frames, width: width, height: height };
+        Animation::parse_planes(data, frame_count, width, height, palette, 2)
     }
 
     fn parse_3bpp(data: &[u8], frame_count: usize, width: usize, height: usize, palette: [u32; 16]) -> Animation {
-        const BPP: usize = 3;
-        let pixels = width * height;
-        let mut frames: Vec<Vec<u32>> = Vec::with_capacity(frame_count);
-        for frame_index in 0..frame_count {
-            let offset_bits = frame_index * pixels * BPP;
-            let mut plane_0 = iterate_bits!(data).skip(offset_bits);
-            let mut plane_1 = iterate_bits!(data).skip(offset_bits + pixels);
-            let mut plane_2 = iterate_bits!(data).skip(offset_bits + pixels * 2);
-            let mut bitmap: Vec<u32> = Vec::with_capacity(pixels);
-            for _ in 0..pixels {
-                let colour_index =
-                    plane_0.next().unwrap() +
-                    (plane_1.next().unwrap() << 1) +
-                    (plane_2.next().unwrap() << 2);
-                let colour = palette[colour_index as usize];
-                bitmap.push(colour);
-            }
-            frames.push(bitmap);
-        }
-        return Animation { frames: frames, width: width, height: height };
+        Animation::parse_planes(data, frame_count, width, height, palette, 3)
     }
 
     fn parse_4bpp(data: &[u8], frame_count: usize, width: usize, height: usize, palette: [u32; 16]) -> Animation {
-        const BPP: usize = 4;
+        Animation::parse_planes(data, frame_count, width, height, palette, 4)
+    }
+
+    // Each frame's planes follow on from the last frame's.
+    fn parse_planes(data: &[u8], frame_count: usize, width: usize, height: usize, palette: [u32; 16], planes: usize) -> Animation {
         let pixels = width * height;
-        let mut frames: Vec<Vec<u32>> = Vec::with_capacity(frame_count);
-        for frame_index in 0..frame_count {
-            let offset_bits = frame_index * pixels * BPP;
-            let mut plane_0 = iterate_bits!(data).skip(offset_bits);
-            let mut plane_1 = iterate_bits!(data).skip(offset_bits + pixels);
-            let mut plane_2 = iterate_bits!(data).skip(offset_bits + pixels * 2);
-            let mut plane_3 = iterate_bits!(data).skip(offset_bits + pixels * 3);
-            let mut bitmap: Vec<u32> = Vec::with_capacity(pixels);
-            for _ in 0..pixels {
-                let colour_index =
-                    plane_0.next().unwrap() +
-                    (plane_1.next().unwrap() << 1) +
-                    (plane_2.next().unwrap() << 2) +
-                    (plane_3.next().unwrap() << 3);
-                let colour = palette[colour_index as usize];
-                bitmap.push(colour);
-            }
-            frames.push(bitmap);
-        }
-        return Animation { frames: frames, width: width, height: height };
+        let frames: Vec<Vec<u32>> = (0..frame_count)
+            .map(|frame_index| planar::decode(data, frame_index * pixels * planes, pixels, planes, &palette))
+            .collect();
+        Animation { frames, width, height }
     }
 
     fn parse(data: &[u8], frames: usize, width: usize, height: usize, palette: [u32; 16], bpp: u8) -> Animation {
@@ -243,17 +151,9 @@ impl LemmingAnimations {
 impl Mask {
     fn parse(data: &[u8], frame_count: usize, width: isize, height: isize) -> Mask {
         let pixels = (width * height) as usize;
-        let mut frames: Vec<Vec<u8>> = Vec::with_capacity(frame_count);
-        for frame_index in 0..frame_count {
-            let offset_bits = frame_index * pixels;
-            let mut plane = iterate_bits!(data).skip(offset_bits);
-            let mut bitmap: Vec<u8> = Vec::with_capacity(pixels);
-            for _ in 0..pixels {
-                let bit = plane.next().unwrap();
-                bitmap.push(bit);
-            }
-            frames.push(bitmap);
-        }
+        let frames: Vec<Vec<u8>> = (0..frame_count)
+            .map(|frame_index| planar::indices(data, frame_index * pixels, pixels, 1))
+            .collect();
         return Mask { frames: frames, width: width, height: height };
     }
 }
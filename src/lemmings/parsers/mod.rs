@@ -1,8 +1,13 @@
 mod helpers;
 
+pub mod planar;
+#[cfg(any(test, feature = "reference"))]
+pub mod planar_reference;
+
 pub mod maindat;
 pub mod special;
 pub mod decompressor;
 pub mod ground;
 pub mod level;
 pub mod sprites;
+pub mod oddtable;
@@ -0,0 +1,54 @@
+// This is for parsing ODDTABLE.DAT, which Lemmings 1 uses to re-use a level file with a different name and skills.
+// It has one 56-byte entry per level file section (so entry 9 is LEVEL001.DAT section 1):
+// the same first 12 WORDs as a LVL file (release rate, lemmings, to rescue, time, 8 skills), then a 32-byte name.
+
+use std::io::{Error, ErrorKind, Result};
+
+use crate::lemmings::models::*;
+use crate::lemmings::parsers::level::{read_u16, read_u8, string_from_vec};
+
+const ENTRY_SIZE: usize = 56;
+pub const SECTIONS_PER_FILE: usize = 8;
+
+pub fn parse(data: &[u8]) -> Result<Vec<OddTableEntry>> {
+    if !data.len().is_multiple_of(ENTRY_SIZE) {
+        return Err(Error::new(ErrorKind::InvalidData, "Wrong length"))
+    }
+    let mut entries: Vec<OddTableEntry> = Vec::with_capacity(data.len() / ENTRY_SIZE);
+    for entry_data in data.chunks(ENTRY_SIZE) {
+        let mut data_iter = entry_data.iter();
+        let release_rate = read_u16(&mut data_iter)?;
+        let num_of_lemmings = read_u16(&mut data_iter)?;
+        let num_to_rescue = read_u16(&mut data_iter)?;
+        let time_limit = read_u16(&mut data_iter)?;
+        let skills = Skills {
+            climbers: read_u16(&mut data_iter)?,
+            floaters: read_u16(&mut data_iter)?,
+            bombers: read_u16(&mut data_iter)?,
+            blockers: read_u16(&mut data_iter)?,
+            builders: read_u16(&mut data_iter)?,
+            bashers: read_u16(&mut data_iter)?,
+            miners: read_u16(&mut data_iter)?,
+            diggers: read_u16(&mut data_iter)?,
+        };
+        let mut str_raw: Vec<u8> = Vec::with_capacity(32);
+        for _ in 0..32 {
+            str_raw.push(read_u8(&mut data_iter)?);
+        }
+        let entry = OddTableEntry {
+            release_rate,
+            num_of_lemmings,
+            num_to_rescue,
+            time_limit,
+            skills,
+            name: string_from_vec(str_raw)?.trim().to_string(),
+        };
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+// The level map key of the level that an entry overrides.
+pub fn level_key_for_entry(index: usize) -> i32 {
+    ((index / SECTIONS_PER_FILE) * 100 + index % SECTIONS_PER_FILE) as i32
+}
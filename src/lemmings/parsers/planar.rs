@@ -0,0 +1,108 @@
+// This converts planar graphics, as used everywhere in the DOS files, into a pixel per value.
+// Planar means all the 1 bits come first, then all the 2 bits, and so on, a bit per pixel, most significant first.
+// Rather than a bit at a time, 8 pixels are done at once: a lookup table spreads a plane's byte into a byte per pixel
+// of a u64, and the planes are shifted and or'd together.
+// The old bit-at-a-time way is kept in planar_reference.rs, which the tests check this against.
+
+// For each byte, its bits spread out a byte each, the most significant bit in the lowest byte so they come out in order.
+const SPREAD: [u64; 256] = spread_table();
+
+const fn spread_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut bit = 0;
+        while bit < 8 {
+            if byte & (0x80 >> bit) != 0 {
+                table[byte] |= 1 << (bit * 8);
+            }
+            bit += 1;
+        }
+        byte += 1;
+    }
+    table
+}
+
+// The 8 bits starting at any bit, not just on a byte boundary. Past the end is zeros, as only a last partial group
+// reaches there and those bits aren't used.
+#[inline]
+fn byte_at_bit(data: &[u8], bit: usize) -> u8 {
+    let index = bit / 8;
+    let shift = bit % 8;
+    if shift == 0 {
+        data[index]
+    } else {
+        let next = data.get(index + 1).copied().unwrap_or(0);
+        ((((data[index] as u16) << 8) | next as u16) >> (8 - shift)) as u8
+    }
+}
+
+// Calls back with each 8 pixels' values, as the bytes of a u64 from the lowest, and how many of them are real.
+#[inline]
+fn for_each_group<F: FnMut(u64, usize)>(data: &[u8], first_bit: usize, pixels: usize, planes: usize, mut f: F) {
+    let mut pixel = 0;
+    while pixel < pixels {
+        let mut group: u64 = 0;
+        for plane in 0..planes {
+            let byte = byte_at_bit(data, first_bit + plane * pixels + pixel);
+            group |= SPREAD[byte as usize] << plane;
+        }
+        f(group, (pixels - pixel).min(8));
+        pixel += 8;
+    }
+}
+
+// The value (eg palette index) of each pixel, from the planes starting at first_bit (not byte) and following on from
+// each other. Panics if the data is too short, like indexing would.
+pub fn indices(data: &[u8], first_bit: usize, pixels: usize, planes: usize) -> Vec<u8> {
+    let mut out = Vec::<u8>::with_capacity(pixels);
+    for_each_group(data, first_bit, pixels, planes, |group, count| {
+        out.extend_from_slice(&group.to_le_bytes()[..count]);
+    });
+    out
+}
+
+// The same, but looked up in the palette.
+pub fn decode(data: &[u8], first_bit: usize, pixels: usize, planes: usize, palette: &[u32]) -> Vec<u32> {
+    let mut out = Vec::<u32>::with_capacity(pixels);
+    for_each_group(data, first_bit, pixels, planes, |group, count| {
+        out.extend(group.to_le_bytes()[..count].iter().map(|&index| palette[index as usize]));
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::planar_reference;
+
+    const SPECIAL_SECTION_PIXELS: usize = 960 * 40; // A quarter of a special level, the biggest thing that's decoded.
+
+    // The same made-up data each run, so any difference can be reproduced.
+    fn made_up_bytes(count: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed.max(1);
+        (0..count).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }).collect()
+    }
+
+    // Every plane count, at odd offsets and sizes, so partial groups and unaligned starts are covered.
+    #[test]
+    fn matches_the_reference() {
+        let palette: Vec<u32> = (0..16).map(|i| 0x01020304 * i + 0xff).collect();
+        for planes in 1..=4 {
+            for pixels in (0..70).chain([160, 208, 1024, SPECIAL_SECTION_PIXELS]) {
+                for first_bit in [0, 1, 3, 7, 8, 13, 64] {
+                    let data = made_up_bytes((first_bit + planes * pixels).div_ceil(8), (planes * 1000 + pixels * 10 + first_bit) as u32);
+                    assert_eq!(indices(&data, first_bit, pixels, planes), planar_reference::indices(&data, first_bit, pixels, planes),
+                        "indices of {} planes of {} pixels from bit {}", planes, pixels, first_bit);
+                    assert_eq!(decode(&data, first_bit, pixels, planes, &palette), planar_reference::decode(&data, first_bit, pixels, planes, &palette),
+                        "decoding {} planes of {} pixels from bit {}", planes, pixels, first_bit);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,23 @@
+// The original bit-at-a-time planar decoding, as the parsers used to do it, kept to check planar.rs against.
+
+use super::helpers::BitsIterMS;
+
+// Creates a bit iterator from [u8].
+macro_rules! iterate_bits { ($data:expr) => { $data.iter().flat_map(BitsIterMS::new) } }
+
+pub fn indices(data: &[u8], first_bit: usize, pixels: usize, planes: usize) -> Vec<u8> {
+    let mut plane_iters: Vec<_> = (0..planes).map(|plane| iterate_bits!(data).skip(first_bit + plane * pixels)).collect();
+    let mut out: Vec<u8> = Vec::with_capacity(pixels);
+    for _ in 0..pixels {
+        let mut index = 0;
+        for (plane, iter) in plane_iters.iter_mut().enumerate() {
+            index += iter.next().unwrap() << plane;
+        }
+        out.push(index);
+    }
+    out
+}
+
+pub fn decode(data: &[u8], first_bit: usize, pixels: usize, planes: usize, palette: &[u32]) -> Vec<u32> {
+    indices(data, first_bit, pixels, planes).iter().map(|&index| palette[index as usize]).collect()
+}
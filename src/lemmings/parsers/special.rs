@@ -4,10 +4,7 @@
 use std::io::{Error, ErrorKind, Result};
 use std::slice::Iter;
 use crate::lemmings::models::Image;
-use super::helpers::BitsIterMS;
-
-// Creates a bit iterator from [u8].
-macro_rules! iterate_bits { ($data:expr) => { $data.iter().flat_map(BitsIterMS::new) } }
+use super::planar;
 
 // Reads a byte, failing gracefully if none are left.
 fn read_u8(data: &mut Iter<u8>) -> Result<u8> {
@@ -73,19 +70,8 @@ pub fn parse(data: &[u8]) -> Result<Image> {
                 decompressed.push(b);
             }
         } else { // End of section. Each section should be of size 14400 bytes.
-            { // Apply this section to the pixels. Braces to scope the iterators.
-                let mut image_iter_0 = iterate_bits!(decompressed);
-                let mut image_iter_1 = iterate_bits!(decompressed).skip(SECTION_PIXELS);
-                let mut image_iter_2 = iterate_bits!(decompressed).skip(SECTION_PIXELS * 2);
-                for _ in 0..SECTION_PIXELS {
-                    let colour_index =
-                        image_iter_0.next().unwrap() +
-                        (image_iter_1.next().unwrap() << 1) +
-                        (image_iter_2.next().unwrap() << 2);
-                    let colour = palette[colour_index as usize];
-                    bitmap.push(colour);
-                }
-            }
+            // Apply this section to the pixels, it's 3 planes.
+            bitmap.extend(planar::decode(&decompressed, 0, SECTION_PIXELS, 3, &palette));
             decompressed.clear(); // This happily keeps the capacity.
         }
     }
@@ -1,36 +1,20 @@
 // This extracts sprites from data.
 
-use super::helpers::BitsIterMS;
+use super::planar;
 
 use crate::lemmings::models::*;
 use crate::lemmings::models::Image;
 
-// Creates a bit iterator from [u8].
-macro_rules! iterate_bits { ($data:expr) => { $data.iter().flat_map(BitsIterMS::new) } }
-
 // Extract a single sprite.
 // Sprites are stored as 4 planes, eg all the 1 bits, then all the 2 bits, then so on. It seems they did this so
 // they could reuse one of the bits as the mask plane. But it means parsing is weird.
 // Palette entries are 0xRRGGBBAA
 fn extract_frame(data: &[u8], width: usize, height: usize, image_loc: usize, mask_loc: usize, palette: &[u32; 16]) -> Vec<u32> {
-    let image_data: &[u8] = &data[image_loc..];
-    let mask_data: &[u8] = &data[mask_loc..];
     let pixels: usize = width * height;
-    let mut image_iter_0 = iterate_bits!(image_data);
-    let mut image_iter_1 = iterate_bits!(image_data).skip(pixels);
-    let mut image_iter_2 = iterate_bits!(image_data).skip(pixels * 2);
-    let mut image_iter_3 = iterate_bits!(image_data).skip(pixels * 3);
-    let mut mask_iter = iterate_bits!(mask_data);
-    let mut sprite: Vec<u32> = Vec::new();
-    for _ in 0..pixels {
-        let colour_index =
-            image_iter_0.next().unwrap() +
-            (image_iter_1.next().unwrap() << 1) +
-            (image_iter_2.next().unwrap() << 2) +
-            (image_iter_3.next().unwrap() << 3);
-        let colour: u32 = palette[colour_index as usize];
-        let masked_colour: u32 = if mask_iter.next().unwrap() == 0 { 0 } else { colour };
-        sprite.push(masked_colour);
+    let mut sprite = planar::decode(&data[image_loc..], 0, pixels, 4, palette);
+    let mask = planar::indices(&data[mask_loc..], 0, pixels, 1);
+    for (colour, is_visible) in sprite.iter_mut().zip(mask) {
+        if is_visible == 0 { *colour = 0 }
     }
     sprite
 }
@@ -0,0 +1,157 @@
+// This encodes and decodes the 10-letter level codes the original game shows before each level, eg IJJLDNCCCN for
+// Lemmings 1's first level and NJLDNCADCL for its second. Letters are 'A' plus a nibble. What's known of the layout,
+// from the DOS version's codes:
+// * Letters 0-6 are 7 data nibbles, rotated left by the level number, so consecutive codes look nothing alike.
+//   Unrotated, they're a fixed pattern with bits of the level number (counted from 1) flipped in. Nibble 6 never
+//   changes.
+// * Letters 7-8 are the low and high nibbles of the level number plus 0x22.
+// * Letter 9 is a check letter.
+// The bits that the upper level numbers flip, and how the check letter is worked out, aren't known. So decoding only
+// checks what every known code agrees on, and so accepts all of the game's codes, and encoding sets what's known and
+// works the check letter out the way that matches the known codes. Other variants' codes haven't been looked at yet,
+// so only Lemmings 1 has codes: the other games don't offer the code screen, and encode and decode refuse them.
+// Level numbers count across all the ratings from 0, eg Lemmings 1 Tricky 1 is level 30.
+
+use crate::lemmings::levels_per_game_and_skill::{levels_per_rating, ratings_per_game};
+
+pub const CODE_LENGTH: usize = 10;
+const DATA_NIBBLES: usize = 7;
+const LEVEL_OFFSET: usize = 0x22; // Added to the level number in letters 7 and 8.
+const FIXED_NIBBLE: usize = 6; // Always DATA_PATTERN's value.
+
+// The unrotated data nibbles before any level bits are flipped in.
+const DATA_PATTERN: [u8; DATA_NIBBLES] = [0x0, 0x9, 0x9, 0xb, 0x3, 0xd, 0x2];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelCode {
+    pub level: usize, // Across all ratings, 0-based.
+}
+
+impl LevelCode {
+    pub fn rating(&self, game_id: &str) -> usize {
+        self.level / levels_per_rating(game_id).max(1)
+    }
+
+    pub fn index_in_rating(&self, game_id: &str) -> usize {
+        self.level % levels_per_rating(game_id).max(1)
+    }
+}
+
+pub fn has_codes(game_id: &str) -> bool {
+    game_id == "lemmings"
+}
+
+fn level_count(game_id: &str) -> usize {
+    levels_per_rating(game_id) * ratings_per_game(game_id)
+}
+
+// The unrotated data nibbles for a level, with the known bits of its 1-based number flipped in.
+fn data_for_level(level: usize) -> [u8; DATA_NIBBLES] {
+    let number = level + 1;
+    let mut data = DATA_PATTERN;
+    data[0] ^= ((number & 1) << 3) as u8;
+    data[1] ^= (((number >> 1) & 1) << 2) as u8;
+    if (number >> 2) & 1 != 0 {
+        data[2] ^= 0x4;
+        data[5] ^= 0x6;
+    }
+    data
+}
+
+fn check_letter(nibbles: &[u8]) -> u8 {
+    let sum: usize = nibbles[..DATA_NIBBLES].iter().map(|n| *n as usize).sum::<usize>() + nibbles[7] as usize * 2 + nibbles[8] as usize;
+    (sum & 0xf) as u8
+}
+
+/// Makes the code that takes you straight to the given level.
+/// Returns none if the game has no codes, or there's no such level.
+pub fn encode(game_id: &str, code: LevelCode) -> Option<String> {
+    if !has_codes(game_id) || code.level >= level_count(game_id) { return None }
+    let data = data_for_level(code.level);
+    let mut nibbles = [0u8; CODE_LENGTH];
+    for (i, nibble) in nibbles.iter_mut().take(DATA_NIBBLES).enumerate() {
+        *nibble = data[(i + code.level) % DATA_NIBBLES];
+    }
+    let level = code.level + LEVEL_OFFSET;
+    nibbles[7] = (level & 0xf) as u8;
+    nibbles[8] = (level >> 4) as u8;
+    nibbles[9] = check_letter(&nibbles);
+    Some(nibbles.iter().map(|n| (b'A' + n) as char).collect())
+}
+
+/// Decodes a code that the player typed in. Case and surrounding whitespace don't matter.
+/// Returns none if it isn't a valid code for this game.
+pub fn decode(game_id: &str, text: &str) -> Option<LevelCode> {
+    if !has_codes(game_id) { return None }
+    let text = text.trim().to_ascii_uppercase();
+    if text.len() != CODE_LENGTH || !text.bytes().all(|c| c.is_ascii_uppercase()) { return None }
+    let nibbles: Vec<u8> = text.bytes().map(|c| c - b'A').collect();
+    if nibbles[..9].iter().any(|n| *n > 0xf) { return None } // Only the check letter may be past 'P'.
+
+    let level = (nibbles[7] as usize | (nibbles[8] as usize) << 4).checked_sub(LEVEL_OFFSET)?;
+    if level >= level_count(game_id) { return None }
+
+    // Undo the rotation, then check the bits that are the same in every code.
+    let mut data = [0u8; DATA_NIBBLES];
+    for (i, nibble) in nibbles.iter().take(DATA_NIBBLES).enumerate() {
+        data[(i + level) % DATA_NIBBLES] = *nibble;
+    }
+    let expected = data_for_level(level);
+    if data[FIXED_NIBBLE] != expected[FIXED_NIBBLE] { return None }
+    if data[0] & 0x8 != expected[0] & 0x8 || data[1] & 0x4 != expected[1] & 0x4 { return None }
+
+    Some(LevelCode { level })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // As the DOS version shows them before Fun 1 and 2.
+    const KNOWN_CODES: [(&str, usize); 2] = [("IJJLDNCCCN", 0), ("NJLDNCADCL", 1)];
+
+    #[test]
+    fn decodes_known_codes() {
+        for (text, level) in KNOWN_CODES {
+            assert_eq!(decode("lemmings", text), Some(LevelCode { level }), "{}", text);
+            assert_eq!(decode("lemmings", &format!(" {} ", text.to_lowercase())), Some(LevelCode { level }), "{}", text);
+        }
+    }
+
+    #[test]
+    fn encodes_known_codes() {
+        for (text, level) in KNOWN_CODES {
+            assert_eq!(encode("lemmings", LevelCode { level }).as_deref(), Some(text));
+        }
+    }
+
+    #[test]
+    fn round_trips_every_level() {
+        for level in 0..120 {
+            let text = encode("lemmings", LevelCode { level }).unwrap();
+            assert_eq!(decode("lemmings", &text), Some(LevelCode { level }), "{}", text);
+        }
+        assert_eq!(encode("lemmings", LevelCode { level: 120 }), None);
+    }
+
+    #[test]
+    fn rejects_other_codes() {
+        assert_eq!(decode("lemmings", "IJJLDNCCC"), None); // Too short.
+        assert_eq!(decode("lemmings", "IJJLDNCC1N"), None);
+        assert_eq!(decode("lemmings", "IJJLDNZCCN"), None); // Past 'P' before the check letter.
+        assert_eq!(decode("lemmings", "IJJLDNDCCN"), None); // The fixed nibble's wrong.
+        assert_eq!(decode("lemmings", "AJJLDNCCCN"), None); // The level's low bit doesn't match.
+        assert_eq!(decode("lemmings", "IJJLDNCAAN"), None); // Below the first level.
+        assert_eq!(decode("lemmings", "IJJLDNCKJN"), None); // Past the last level.
+        assert_eq!(decode("ohnomore", "IJJLDNCCCN"), None);
+    }
+
+    #[test]
+    fn other_games_have_no_codes() {
+        for game_id in ["ohnomore", "christmas1991", "christmas1992", "holiday1993", "holiday1994"] {
+            assert!(!has_codes(game_id), "{}", game_id);
+            assert_eq!(encode(game_id, LevelCode { level: 0 }), None, "{}", game_id);
+        }
+        assert!(has_codes("lemmings"));
+    }
+}
@@ -1,48 +1,23 @@
-// This file contains enough code to write a very naive PNG format without needing
-// a massive tree of dependencies.
-
-// Converts into an RFC1951 'raw deflate stream' format in a simple non-compressing way.
-// An uncompressed deflate stream looks like N blocks, each being:
-// [is_final, 2 bytes length, 2 bytes length 1's complement, data].
-// Motivation: Avoid dependencies, simple implementation, meet requirements of PNG storage.
-// See: https://datatracker.ietf.org/doc/html/rfc1951
-// Test output with: ruby -rzlib -e 'print Zlib::Inflate.new(-15).inflate(STDIN.read)' < foo.deflateStream
-// https://yob.id.au/2020/06/16/zlib-gzip-and-deflate-in-ruby.html
-fn to_deflate_stream(input: &[u8]) -> Vec<u8> {
-    if input.is_empty() {
-        return vec![1, 0, 0, 0xff, 0xff]; // 1 block with no content.
-    }
-    let mut output = Vec::<u8>::new();
-    let chunks = input.chunks(0xffff);
-    let final_index = chunks.len() - 1;
-    for (index, chunk) in chunks.enumerate() {
-        let is_final = index == final_index;
-        output.push(if is_final { 1 } else { 0 });
-        let len = chunk.len();
-        let len_lsb = (len & 0xff) as u8;
-        let len_msb = (len >> 8) as u8;
-        output.push(len_lsb); // Max len.
-        output.push(len_msb);
-        output.push(!len_lsb); // 1's complement of len.
-        output.push(!len_msb);
-        output.extend_from_slice(chunk);
-    }
-    output
-}
+// This file contains enough code to write a PNG format without needing a massive tree of dependencies.
+// Images with 256 colours or fewer, which is nearly everything in the game, are written with a palette, packed down to
+// as few bits per pixel as the palette needs. Anything else is written as RGB, or RGBA if any of it is see-through.
+// Compression is deflate.rs, and truecolour rows are each filtered whichever way looks like it'll compress best.
+// Animations are written as APNG, which browsers play, and which shows as its first frame to anything that doesn't.
 
-// Converts to an uncompressed RFC1950 zlib stream.
+use std::collections::HashMap;
+use super::deflate;
+
+// Converts to an RFC1950 zlib stream, which is a header, the deflate stream, and a checksum.
 // See: https://datatracker.ietf.org/doc/html/rfc1950
-// In the simple uncompressed case, this boils down to adding a header and checksum.
 // Test output with: ruby -rzlib -e 'print Zlib::Inflate.new.inflate(STDIN.read)' < foo.zlib
-fn to_zlib_stream(input: &[u8]) -> Vec<u8> {
+pub fn to_zlib_stream(input: &[u8]) -> Vec<u8> {
     // Header.
     let mut output = Vec::<u8>::new();
     output.push(0x78); // CMF byte. Bits 0-3=method, 4-7=info/window size. Method=8, Window size=7.
-    output.push(1); // FLG byte. Bits 0-4=fcheck, 5=fdict which we dont want so 0, 6-7=flevel where 0 means fastest.
+    output.push(0xda); // FLG byte. Bits 0-4=fcheck, 5=fdict which we dont want so 0, 6-7=flevel where 3 means best.
 
     // Body.
-    let deflated = to_deflate_stream(input);
-    output.extend(deflated);
+    output.extend(deflate::compress(input));
 
     // Checksum.
     // See: https://en.wikipedia.org/wiki/Adler-32#Example_implementation
@@ -61,25 +36,28 @@ fn to_zlib_stream(input: &[u8]) -> Vec<u8> {
 }
 
 // http://libpng.org/pub/png/spec/1.0/PNG-CRCAppendix.html
-fn crc(data: &[u8]) -> u32 {
-    // Make the CRC table first.
-    let mut crc_table: [u32; 256] = [0; 256];
-    for n in 0..256 {
-        let mut c: u32 = n as u32;
-        for _k in 0..8 {
-            if c & 1 == 1 {
-                c = 0xedb88320u32 ^ (c >> 1);
-            } else {
-                c = c >> 1;
-            }
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
         }
-        crc_table[n] = c;
+        table[n] = c;
+        n += 1;
     }
+    table
+}
 
-    // Calculate the CRC.
+pub fn crc(data: &[u8]) -> u32 {
     let mut crc: u32 = 0xffffffff;
     for b in data {
-        crc = crc_table[((crc ^ (*b as u32)) & 0xff) as usize] ^ (crc >> 8);        
+        crc = CRC_TABLE[((crc ^ (*b as u32)) & 0xff) as usize] ^ (crc >> 8);
     }
     !crc
 }
@@ -92,75 +70,300 @@ fn append_msb(vec: &mut Vec<u8>, value: u32) {
     vec.push((value & 0xff) as u8);
 }
 
+// Length, type, data, then the CRC of the type and data.
+pub fn append_chunk(output: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    append_msb(output, data.len() as u32);
+    let mut type_and_data = Vec::<u8>::with_capacity(data.len() + 4);
+    type_and_data.extend_from_slice(chunk_type);
+    type_and_data.extend_from_slice(data);
+    output.extend_from_slice(&type_and_data);
+    append_msb(output, crc(&type_and_data));
+}
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]; // Then Cr, Lf, Eof, Lf.
+
+pub const COLOUR_TYPE_RGB: u8 = 2;
+pub const COLOUR_TYPE_INDEXED: u8 = 3;
+pub const COLOUR_TYPE_RGBA: u8 = 6;
+
+pub const FILTER_NONE: u8 = 0;
+pub const FILTER_SUB: u8 = 1;
+pub const FILTER_UP: u8 = 2;
+pub const FILTER_AVERAGE: u8 = 3;
+pub const FILTER_PAETH: u8 = 4;
+
+pub fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let p = left as i16 + up as i16 - up_left as i16;
+    let (pa, pb, pc) = ((p - left as i16).abs(), (p - up as i16).abs(), (p - up_left as i16).abs());
+    if pa <= pb && pa <= pc { left } else if pb <= pc { up } else { up_left }
+}
+
+// The row with the filter applied. Previous is the unfiltered row above, or zeros for the first.
+fn filter_row(filter: u8, row: &[u8], previous: &[u8], bytes_per_pixel: usize) -> Vec<u8> {
+    row.iter().enumerate().map(|(i, &x)| {
+        let left = if i >= bytes_per_pixel { row[i - bytes_per_pixel] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= bytes_per_pixel { previous[i - bytes_per_pixel] } else { 0 };
+        match filter {
+            FILTER_SUB => x.wrapping_sub(left),
+            FILTER_UP => x.wrapping_sub(up),
+            FILTER_AVERAGE => x.wrapping_sub(((left as u16 + up as u16) / 2) as u8),
+            FILTER_PAETH => x.wrapping_sub(paeth(left, up, up_left)),
+            _ => x,
+        }
+    }).collect()
+}
+
+// Picks each row's filter by the usual rule of thumb: whichever leaves the smallest sum when the bytes are taken as
+// signed, since that means more values near zero, which compress well.
+fn filter_adaptively(rows: &[Vec<u8>], bytes_per_pixel: usize) -> Vec<u8> {
+    let mut output = Vec::<u8>::new();
+    let mut previous = vec![0u8; rows.first().map_or(0, |r| r.len())];
+    for row in rows {
+        let (filter, filtered) = [FILTER_NONE, FILTER_SUB, FILTER_UP, FILTER_AVERAGE, FILTER_PAETH].iter()
+            .map(|&filter| (filter, filter_row(filter, row, &previous, bytes_per_pixel)))
+            .min_by_key(|(_, filtered)| filtered.iter().map(|&b| (b as i8).unsigned_abs() as usize).sum::<usize>())
+            .unwrap();
+        output.push(filter);
+        output.extend_from_slice(&filtered);
+        previous.copy_from_slice(row);
+    }
+    output
+}
+
+fn png_from_rows(width: u32, height: u32, bit_depth: u8, colour_type: u8, extra_chunks: &[(&[u8; 4], Vec<u8>)], image_data: Vec<u8>) -> Vec<u8> {
+    let mut output = SIGNATURE.to_vec();
+
+    let mut ihdr = Vec::<u8>::new();
+    append_msb(&mut ihdr, width);
+    append_msb(&mut ihdr, height);
+    ihdr.push(bit_depth);
+    ihdr.push(colour_type);
+    ihdr.push(0); // Compression method: zlib.
+    ihdr.push(0); // Filter method.
+    ihdr.push(0); // No interlace.
+    append_chunk(&mut output, b"IHDR", &ihdr);
+    for (chunk_type, data) in extra_chunks {
+        append_chunk(&mut output, chunk_type, data);
+    }
+    append_chunk(&mut output, b"IDAT", &to_zlib_stream(&image_data));
+    append_chunk(&mut output, b"IEND", &[]);
+    output
+}
+
+// The fewest bits per pixel that can index a palette this size.
+fn bit_depth_for(colours: usize) -> u8 {
+    match colours {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    }
+}
+
+// A PNG with a palette, of 0xRRGGBBAA colours, which can have up to 256. Indices are a byte per pixel.
+// Rows aren't filtered, as the PNG spec recommends for palettes: neighbouring indices don't tend to be close in value.
+pub fn indexed_png_data(width: u32, height: u32, palette: &[u32], indices: &[u8]) -> Vec<u8> {
+    let bit_depth = bit_depth_for(palette.len());
+    let image_data = packed_indices(width, height, bit_depth, indices);
+    png_from_rows(width, height, bit_depth, COLOUR_TYPE_INDEXED, &palette_chunks(palette), image_data)
+}
+
+fn packed_indices(width: u32, height: u32, bit_depth: u8, indices: &[u8]) -> Vec<u8> {
+    let pixels_per_byte = 8 / bit_depth as usize;
+    let mut image_data = Vec::<u8>::new();
+    for row in indices.chunks(width.max(1) as usize).take(height as usize) {
+        image_data.push(FILTER_NONE);
+        for group in row.chunks(pixels_per_byte) {
+            let mut byte = 0u8;
+            for (i, &index) in group.iter().enumerate() {
+                byte |= index << (8 - bit_depth as usize * (i + 1)); // Leftmost pixel in the highest bits.
+            }
+            image_data.push(byte);
+        }
+    }
+    image_data
+}
+
+fn palette_chunks(palette: &[u32]) -> Vec<(&'static [u8; 4], Vec<u8>)> {
+    let plte: Vec<u8> = palette.iter().flat_map(|&c| [(c >> 24) as u8, (c >> 16) as u8, (c >> 8) as u8]).collect();
+    let mut chunks = vec![(b"PLTE", plte)];
+    if let Some(last_see_through) = palette.iter().rposition(|&c| c & 0xff != 0xff) {
+        // Alphas for the palette, which can stop after the last one that isn't opaque.
+        chunks.push((b"tRNS", palette[..=last_see_through].iter().map(|&c| c as u8).collect()));
+    }
+    chunks
+}
+
+// The image's colours and each pixel's index into them, or None if there are too many for a palette.
+fn to_palette(image_data: &[u32]) -> Option<(Vec<u32>, Vec<u8>)> {
+    let mut palette = Vec::<u32>::new();
+    let mut lookup = HashMap::<u32, u8>::new();
+    let mut indices = Vec::<u8>::with_capacity(image_data.len());
+    for &pixel in image_data {
+        // Fully see-through pixels all look the same, whatever colour they say they are.
+        let pixel = if pixel & 0xff == 0 { 0 } else { pixel };
+        let index = match lookup.get(&pixel) {
+            Some(&index) => index,
+            None => {
+                if palette.len() == 256 { return None }
+                let index = palette.len() as u8;
+                palette.push(pixel);
+                lookup.insert(pixel, index);
+                index
+            },
+        };
+        indices.push(index);
+    }
+    Some((palette, indices))
+}
+
 // https://en.wikipedia.org/wiki/Portable_Network_Graphics#File_format
+// Pixels are 0xRRGGBBAA, left-right, then top-bottom.
 pub fn png_data(width: u32, height: u32, image_data: &[u32]) -> Vec<u8> {
-    let mut output = Vec::<u8>::new();
+    if let Some((palette, indices)) = to_palette(image_data) {
+        return indexed_png_data(width, height, &palette, &indices)
+    }
+    let bytes_per_pixel = truecolour_bytes_per_pixel(image_data);
+    let colour_type = if bytes_per_pixel == 3 { COLOUR_TYPE_RGB } else { COLOUR_TYPE_RGBA };
+    png_from_rows(width, height, 8, colour_type, &[], truecolour_rows(width, height, image_data, bytes_per_pixel))
+}
 
-    // Header.
-    output.push(0x89);
-    output.push(b'P');
-    output.push(b'N');
-    output.push(b'G');
-    output.push(0x0d); // Cr
-    output.push(0x0a); // Lf
-    output.push(0x1a); // Eof
-    output.push(0x0a); // Lf
-
-    // Build IHDR.
-    let mut ihdr_type_and_data = Vec::<u8>::new();
-    ihdr_type_and_data.push(b'I');
-    ihdr_type_and_data.push(b'H');
-    ihdr_type_and_data.push(b'D');
-    ihdr_type_and_data.push(b'R');
-    append_msb(&mut ihdr_type_and_data, width);
-    append_msb(&mut ihdr_type_and_data, height);
-    ihdr_type_and_data.push(8); // 8bpp.
-    ihdr_type_and_data.push(6); // RGBA.
-    ihdr_type_and_data.push(0); // Compression method: zlib.
-    ihdr_type_and_data.push(0); // Filter method.
-    ihdr_type_and_data.push(0); // No interlace.
-    let ihdr_len = ihdr_type_and_data.len() - 4; // Minus the type.
-    let ihdr_crc = crc(&ihdr_type_and_data);
-    // Append IHDR to output.
-    append_msb(&mut output, ihdr_len as u32);
-    output.extend_from_slice(&ihdr_type_and_data);
-    append_msb(&mut output, ihdr_crc);
-
-    // Build image data.
-    // Left-right, then Top-bottom.
-    // Each line is prepended a filter type byte (0).
-    let mut idat_data = Vec::<u8>::new();
-    let mut data_iter = image_data.iter();
-    for _y in 0..height {
-        idat_data.push(0); // Filter.
-        for _x in 0..width {
-            append_msb(&mut idat_data, *data_iter.next().unwrap());
+// RGB if it's all opaque, otherwise RGBA.
+fn truecolour_bytes_per_pixel(image_data: &[u32]) -> usize {
+    if image_data.iter().all(|&pixel| pixel & 0xff == 0xff) { 3 } else { 4 }
+}
+
+fn truecolour_rows(width: u32, height: u32, image_data: &[u32], bytes_per_pixel: usize) -> Vec<u8> {
+    let rows: Vec<Vec<u8>> = image_data.chunks(width.max(1) as usize).take(height as usize)
+        .map(|row| row.iter().flat_map(|&pixel| pixel.to_be_bytes()[..bytes_per_pixel].to_vec()).collect())
+        .collect();
+    filter_adaptively(&rows, bytes_per_pixel)
+}
+
+// The smallest rect holding every pixel that's changed since the previous frame, as x, y, width and height.
+fn changed_rect(previous: &[u32], frame: &[u32], width: usize) -> Option<(usize, usize, usize, usize)> {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (usize::MAX, usize::MAX, 0, 0);
+    for (i, (a, b)) in previous.iter().zip(frame).enumerate() {
+        if a == b { continue }
+        let (x, y) = (i % width, i / width);
+        min_x = min_x.min(x);
+        min_y = min_y.min(y);
+        max_x = max_x.max(x);
+        max_y = max_y.max(y);
+    }
+    if min_x == usize::MAX { return None }
+    Some((min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
+
+fn crop(frame: &[u32], width: usize, (x, y, crop_width, crop_height): (usize, usize, usize, usize)) -> Vec<u32> {
+    (y..y + crop_height).flat_map(|row| frame[row * width + x..row * width + x + crop_width].to_vec()).collect()
+}
+
+// An animated PNG that loops forever, each frame shown for a tick at ticks_per_second.
+// See: https://wiki.mozilla.org/APNG_Specification
+// After the first, frames only hold the rect that's changed since the one before, drawn over it, and frames the same as
+// the one before are merged into it by showing it for longer, so a level with a few small moving objects stays small.
+// All the frames share a palette if they have 256 colours or fewer between them.
+pub fn apng_data(width: u32, height: u32, frames: &[Vec<u32>], ticks_per_second: u16) -> Vec<u8> {
+    let pixel_count = (width * height) as usize;
+    // Each distinct frame, with the rect to write and how many ticks it's shown for.
+    let mut parts = Vec::<(&[u32], (usize, usize, usize, usize), u16)>::new();
+    for frame in frames.iter() {
+        let frame = &frame[..pixel_count];
+        let Some((previous, _, ticks)) = parts.last_mut() else {
+            parts.push((frame, (0, 0, width as usize, height as usize), 1));
+            continue
+        };
+        match changed_rect(previous, frame, width as usize) {
+            Some(rect) => parts.push((frame, rect, 1)),
+            None if *ticks < u16::MAX => *ticks += 1,
+            // Shown for longer than a frame's delay can say, so it carries on in another frame that redraws one pixel.
+            None => parts.push((frame, (0, 0, 1, 1), 1)),
         }
     }
-    let compressed_idat_data = to_zlib_stream(&idat_data);
-
-    // Build IDAT.
-    let mut idat_type_and_data = Vec::<u8>::new();
-    idat_type_and_data.push(b'I');
-    idat_type_and_data.push(b'D');
-    idat_type_and_data.push(b'A');
-    idat_type_and_data.push(b'T');
-    idat_type_and_data.extend_from_slice(&compressed_idat_data);
-    let idat_len = idat_type_and_data.len() - 4; // Minus the type.
-    let idat_crc = crc(&idat_type_and_data);
-    // Append IDAT to output.
-    append_msb(&mut output, idat_len as u32);
-    output.extend_from_slice(&idat_type_and_data);
-    append_msb(&mut output, idat_crc);
-
-    // IEND (no data).
-    append_msb(&mut output, 0); // Length.
-    output.push(b'I'); // Type.
-    output.push(b'E');
-    output.push(b'N');
-    output.push(b'D');
-    let iend_crc = crc(b"IEND");
-    append_msb(&mut output, iend_crc);
+    let all_pixels: Vec<u32> = parts.iter().flat_map(|(frame, _, _)| frame.to_vec()).collect();
+    let palette = to_palette(&all_pixels).map(|(palette, _)| palette);
+    let (bit_depth, colour_type, bytes_per_pixel, chunks) = match &palette {
+        Some(palette) => (bit_depth_for(palette.len()), COLOUR_TYPE_INDEXED, 0, palette_chunks(palette)),
+        None => {
+            let bytes_per_pixel = truecolour_bytes_per_pixel(&all_pixels);
+            (8, if bytes_per_pixel == 3 { COLOUR_TYPE_RGB } else { COLOUR_TYPE_RGBA }, bytes_per_pixel, Vec::new())
+        },
+    };
+    let lookup: HashMap<u32, u8> = palette.iter().flatten().enumerate().map(|(i, &c)| (c, i as u8)).collect();
 
+    let mut output = SIGNATURE.to_vec();
+    let mut ihdr = Vec::<u8>::new();
+    append_msb(&mut ihdr, width);
+    append_msb(&mut ihdr, height);
+    ihdr.extend_from_slice(&[bit_depth, colour_type, 0, 0, 0]); // Compression, filter method, no interlace.
+    append_chunk(&mut output, b"IHDR", &ihdr);
+    let mut actl = Vec::<u8>::new();
+    append_msb(&mut actl, parts.len() as u32);
+    append_msb(&mut actl, 0); // Loop forever.
+    append_chunk(&mut output, b"acTL", &actl);
+    for (chunk_type, data) in chunks.iter() {
+        append_chunk(&mut output, chunk_type, data);
+    }
+
+    let mut sequence_number = 0;
+    for (i, (frame, rect, ticks)) in parts.iter().enumerate() {
+        let (x, y, part_width, part_height) = *rect;
+        let mut fctl = Vec::<u8>::new();
+        append_msb(&mut fctl, sequence_number);
+        for value in [part_width, part_height, x, y] {
+            append_msb(&mut fctl, value as u32);
+        }
+        fctl.extend_from_slice(&ticks.to_be_bytes()); // The delay's numerator and denominator, in seconds.
+        fctl.extend_from_slice(&ticks_per_second.to_be_bytes());
+        fctl.push(0); // Dispose op none: leave it for the next frame to draw over.
+        fctl.push(0); // Blend op source: replace what's there, see-through pixels too.
+        append_chunk(&mut output, b"fcTL", &fctl);
+        sequence_number += 1;
+
+        let pixels = crop(frame, width as usize, *rect);
+        let image_data = if palette.is_some() {
+            let indices: Vec<u8> = pixels.iter().map(|&pixel| lookup[&if pixel & 0xff == 0 { 0 } else { pixel }]).collect();
+            packed_indices(part_width as u32, part_height as u32, bit_depth, &indices)
+        } else {
+            truecolour_rows(part_width as u32, part_height as u32, &pixels, bytes_per_pixel)
+        };
+        let zlib = to_zlib_stream(&image_data);
+        if i == 0 {
+            append_chunk(&mut output, b"IDAT", &zlib);
+        } else {
+            let mut fdat = Vec::<u8>::with_capacity(zlib.len() + 4);
+            append_msb(&mut fdat, sequence_number);
+            fdat.extend_from_slice(&zlib);
+            append_chunk(&mut output, b"fdAT", &fdat);
+            sequence_number += 1;
+        }
+    }
+    append_chunk(&mut output, b"IEND", &[]);
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The frame count from the acTL chunk, which comes straight after IHDR.
+    fn frame_count(apng: &[u8]) -> u32 {
+        let actl = SIGNATURE.len() + 12 + 13 + 8;
+        u32::from_be_bytes(apng[actl..actl + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn identical_frames_are_merged() {
+        let frames = vec![vec![0xff0000ff], vec![0xff0000ff], vec![0x00ff00ff], vec![0x00ff00ff]];
+        assert_eq!(frame_count(&apng_data(1, 1, &frames, 10)), 2);
+    }
+
+    #[test]
+    fn frames_held_past_the_longest_delay_are_split() {
+        let mut frames = vec![vec![0xff0000ff]; u16::MAX as usize + 1];
+        frames.push(vec![0x00ff00ff]);
+        assert_eq!(frame_count(&apng_data(1, 1, &frames, 10)), 3);
+    }
+}
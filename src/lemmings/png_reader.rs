@@ -0,0 +1,328 @@
+// This reads PNGs into Images, so modders' own graphics can come in without converting them to the game's formats.
+// It covers everything the PNG spec allows: greyscale, RGB, palette, with or without alpha, 1 to 16 bits, every filter,
+// and interlacing. 16 bit samples are cut down to 8, as that's all Image has.
+// See: https://www.w3.org/TR/png/
+
+use std::io::{Error, ErrorKind, Result};
+use crate::lemmings::inflate;
+use crate::lemmings::models::Image;
+use crate::lemmings::png::*;
+
+const COLOUR_TYPE_GREY: u8 = 0;
+const COLOUR_TYPE_GREY_ALPHA: u8 = 4;
+const MAX_PIXELS: usize = 1 << 26; // Some sanity, so a bad header can't ask for gigabytes.
+
+// Adam7 interlacing's passes: where each starts and how far apart its pixels are, x then y.
+const ADAM7: [(usize, usize, usize, usize); 7] = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    colour_type: u8,
+    is_interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.colour_type {
+            COLOUR_TYPE_RGB => 3,
+            COLOUR_TYPE_GREY_ALPHA => 2,
+            COLOUR_TYPE_RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    // How far back the filters look for the 'left' byte: a whole pixel, or 1 byte when pixels are smaller.
+    fn filter_distance(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+fn parse_header(data: &[u8]) -> Result<Header> {
+    if data.len() != 13 { return Err(invalid("IHDR wrong length")) }
+    let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let (bit_depth, colour_type) = (data[8], data[9]);
+    let is_valid_depth = match colour_type {
+        COLOUR_TYPE_GREY => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+        COLOUR_TYPE_INDEXED => matches!(bit_depth, 1 | 2 | 4 | 8),
+        COLOUR_TYPE_RGB | COLOUR_TYPE_GREY_ALPHA | COLOUR_TYPE_RGBA => matches!(bit_depth, 8 | 16),
+        _ => return Err(invalid("Unknown colour type")),
+    };
+    if !is_valid_depth { return Err(invalid("Bit depth not allowed for the colour type")) }
+    if data[10] != 0 || data[11] != 0 { return Err(invalid("Unknown compression or filter method")) }
+    if data[12] > 1 { return Err(invalid("Unknown interlace method")) }
+    if width == 0 || height == 0 || width.saturating_mul(height) > MAX_PIXELS { return Err(invalid("Bad size")) }
+    Ok(Header { width, height, bit_depth, colour_type, is_interlaced: data[12] == 1 })
+}
+
+// The chunks as (type, data), having checked their CRCs.
+fn chunks(data: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    if !data.starts_with(&SIGNATURE) { return Err(invalid("Not a PNG")) }
+    let mut chunks = Vec::new();
+    let mut position = SIGNATURE.len();
+    while position < data.len() {
+        let Some(length_bytes) = data.get(position..position + 4) else { return Err(invalid("Chunk cut short")) };
+        let length = u32::from_be_bytes(length_bytes.try_into().unwrap()) as usize;
+        let Some(type_and_data) = data.get(position + 4..position + 8 + length) else { return Err(invalid("Chunk cut short")) };
+        let Some(crc_bytes) = data.get(position + 8 + length..position + 12 + length) else { return Err(invalid("Chunk cut short")) };
+        if crc(type_and_data) != u32::from_be_bytes(crc_bytes.try_into().unwrap()) { return Err(invalid("Chunk CRC doesn't match")) }
+        let chunk_type: [u8; 4] = type_and_data[..4].try_into().unwrap();
+        chunks.push((chunk_type, &type_and_data[4..]));
+        position += 12 + length;
+        if &chunk_type == b"IEND" { break }
+    }
+    Ok(chunks)
+}
+
+// The other half of png.rs's to_zlib_stream.
+fn from_zlib_stream(input: &[u8]) -> Result<Vec<u8>> {
+    if input.len() < 6 { return Err(invalid("zlib stream too short")) }
+    let (cmf, flg) = (input[0], input[1]);
+    if cmf & 0x0f != 8 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) { return Err(invalid("Bad zlib header")) }
+    if flg & 0x20 != 0 { return Err(invalid("zlib preset dictionaries aren't used by PNG")) }
+    let (output, used) = inflate::decompress(&input[2..])?;
+    let Some(checksum) = input.get(2 + used..2 + used + 4) else { return Err(invalid("No zlib checksum")) };
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in &output {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    if u32::from_be_bytes(checksum.try_into().unwrap()) != (b << 16) | a { return Err(invalid("zlib checksum doesn't match")) }
+    Ok(output)
+}
+
+// Undoes the filters in place, leaving each row's filter byte alone.
+fn unfilter(data: &mut [u8], row_bytes: usize, rows: usize, filter_distance: usize) -> Result<()> {
+    for row in 0..rows {
+        let start = row * (row_bytes + 1);
+        let filter = data[start];
+        for i in 0..row_bytes {
+            let at = start + 1 + i;
+            let left = if i >= filter_distance { data[at - filter_distance] } else { 0 };
+            let up = if row > 0 { data[at - row_bytes - 1] } else { 0 };
+            let up_left = if row > 0 && i >= filter_distance { data[at - row_bytes - 1 - filter_distance] } else { 0 };
+            data[at] = data[at].wrapping_add(match filter {
+                FILTER_NONE => 0,
+                FILTER_SUB => left,
+                FILTER_UP => up,
+                FILTER_AVERAGE => ((left as u16 + up as u16) / 2) as u8,
+                FILTER_PAETH => paeth(left, up, up_left),
+                _ => return Err(invalid("Unknown filter")),
+            });
+        }
+    }
+    Ok(())
+}
+
+// What's needed to turn samples into colours.
+struct Colours {
+    palette: Vec<u32>, // 0xRRGGBBAA, with tRNS's alphas already in.
+    transparent: Option<[u16; 3]>, // The one see-through grey or RGB, from tRNS, at the image's bit depth.
+}
+
+// The pixel's samples, at the image's bit depth.
+fn samples(row: &[u8], x: usize, header: &Header, out: &mut [u16; 4]) {
+    let channels = header.channels();
+    match header.bit_depth {
+        8 => for c in 0..channels { out[c] = row[x * channels + c] as u16 },
+        16 => for c in 0..channels { out[c] = u16::from_be_bytes([row[(x * channels + c) * 2], row[(x * channels + c) * 2 + 1]]) },
+        depth => {
+            // Only greyscale and palette have these, so it's 1 channel. Leftmost pixel in the highest bits.
+            let depth = depth as usize;
+            let bit = x * depth;
+            out[0] = ((row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8) as u16;
+        },
+    }
+}
+
+fn to_colour(samples: &[u16; 4], header: &Header, colours: &Colours) -> u32 {
+    let to_8_bits = |sample: u16| -> u32 {
+        match header.bit_depth {
+            16 => (sample >> 8) as u32,
+            8 => sample as u32,
+            depth => sample as u32 * 255 / ((1 << depth) - 1), // Stretched so the brightest is still white.
+        }
+    };
+    let is_transparent = |count: usize| colours.transparent.is_some_and(|t| t[..count] == samples[..count]);
+    let colour = match header.colour_type {
+        COLOUR_TYPE_INDEXED => colours.palette.get(samples[0] as usize).copied().unwrap_or(0),
+        COLOUR_TYPE_GREY => {
+            let grey = to_8_bits(samples[0]);
+            let alpha = if is_transparent(1) { 0 } else { 0xff };
+            grey << 24 | grey << 16 | grey << 8 | alpha
+        },
+        COLOUR_TYPE_GREY_ALPHA => {
+            let grey = to_8_bits(samples[0]);
+            grey << 24 | grey << 16 | grey << 8 | to_8_bits(samples[1])
+        },
+        COLOUR_TYPE_RGB => {
+            let alpha = if is_transparent(3) { 0 } else { 0xff };
+            to_8_bits(samples[0]) << 24 | to_8_bits(samples[1]) << 16 | to_8_bits(samples[2]) << 8 | alpha
+        },
+        _ => to_8_bits(samples[0]) << 24 | to_8_bits(samples[1]) << 16 | to_8_bits(samples[2]) << 8 | to_8_bits(samples[3]),
+    };
+    // Fully see-through is always 0, as it is in the game's own images, whatever colour the file gives it.
+    if colour & 0xff == 0 { 0 } else { colour }
+}
+
+fn colours(header: &Header, palette: Option<&[u8]>, transparency: Option<&[u8]>) -> Result<Colours> {
+    let mut colours = Colours { palette: Vec::new(), transparent: None };
+    if header.colour_type == COLOUR_TYPE_INDEXED {
+        let Some(palette) = palette else { return Err(invalid("No palette")) };
+        if palette.len() % 3 != 0 || palette.len() > 256 * 3 { return Err(invalid("Bad palette")) }
+        colours.palette = palette.chunks(3).map(|c| (c[0] as u32) << 24 | (c[1] as u32) << 16 | (c[2] as u32) << 8 | 0xff).collect();
+        for (colour, &alpha) in colours.palette.iter_mut().zip(transparency.unwrap_or(&[])) {
+            *colour = (*colour & 0xffffff00) | alpha as u32;
+        }
+    } else if let Some(transparency) = transparency {
+        let values: Vec<u16> = transparency.chunks(2).map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)])).collect();
+        let mut transparent = [0u16; 3];
+        let count = if header.colour_type == COLOUR_TYPE_GREY { 1 } else { 3 };
+        if values.len() < count { return Err(invalid("tRNS too short")) }
+        transparent[..count].copy_from_slice(&values[..count]);
+        colours.transparent = Some(transparent);
+    }
+    Ok(colours)
+}
+
+// Decodes a PNG file's contents.
+pub fn read_png(data: &[u8]) -> Result<Image> {
+    let chunks = chunks(data)?;
+    let Some((b"IHDR", ihdr)) = chunks.first().map(|(t, d)| (t, *d)) else { return Err(invalid("IHDR isn't first")) };
+    let header = parse_header(ihdr)?;
+    let find = |wanted: &[u8; 4]| chunks.iter().find(|(t, _)| t == wanted).map(|(_, d)| *d);
+    let colours = colours(&header, find(b"PLTE"), find(b"tRNS"))?;
+    let compressed: Vec<u8> = chunks.iter().filter(|(t, _)| t == b"IDAT").flat_map(|(_, d)| d.iter().copied()).collect();
+    let mut filtered = from_zlib_stream(&compressed)?;
+
+    // Each pass is its own little image, rows and filters and all. Without interlacing there's one, the whole thing.
+    let passes: Vec<(usize, usize, usize, usize)> = if header.is_interlaced { ADAM7.to_vec() } else { vec![(0, 0, 1, 1)] };
+    let mut bitmap = vec![0u32; header.width * header.height];
+    let mut offset = 0;
+    let mut pixel_samples = [0u16; 4];
+    for (x0, y0, dx, dy) in passes {
+        let pass_width = (header.width + dx - 1 - x0) / dx;
+        let pass_height = (header.height + dy - 1 - y0) / dy;
+        if header.width <= x0 || header.height <= y0 { continue }
+        let row_bytes = header.row_bytes(pass_width);
+        let size = (row_bytes + 1) * pass_height;
+        let Some(pass) = filtered.get_mut(offset..offset + size) else { return Err(invalid("Image data cut short")) };
+        unfilter(pass, row_bytes, pass_height, header.filter_distance())?;
+        for row in 0..pass_height {
+            let row_data = &pass[row * (row_bytes + 1) + 1..(row + 1) * (row_bytes + 1)];
+            for column in 0..pass_width {
+                samples(row_data, column, &header, &mut pixel_samples);
+                bitmap[(y0 + row * dy) * header.width + x0 + column * dx] = to_colour(&pixel_samples, &header, &colours);
+            }
+        }
+        offset += size;
+    }
+    Ok(Image { bitmap, width: header.width, height: header.height })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The same made-up pixels each run, so any difference can be reproduced. Colours is how many there are to pick from.
+    fn made_up_bitmap(width: usize, height: usize, colours: u32, seed: u32) -> Vec<u32> {
+        let mut state = seed.max(1);
+        (0..width * height).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let colour = (state % colours).wrapping_mul(0x9e3779b1) & 0xffffff00;
+            let alpha = [0xff, 0xff, 0x80, 0][(state >> 28) as usize % 4];
+            if alpha == 0 { 0 } else { colour | alpha }
+        }).collect()
+    }
+
+    // A PNG from rows that are already filtered, for the kinds png.rs never writes.
+    fn hand_made_png(width: u32, height: u32, bit_depth: u8, colour_type: u8, is_interlaced: bool, extra_chunks: &[(&[u8; 4], &[u8])], rows: &[u8]) -> Vec<u8> {
+        let mut output = SIGNATURE.to_vec();
+        let mut ihdr = Vec::<u8>::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[bit_depth, colour_type, 0, 0, is_interlaced as u8]);
+        append_chunk(&mut output, b"IHDR", &ihdr);
+        for (chunk_type, data) in extra_chunks {
+            append_chunk(&mut output, chunk_type, data);
+        }
+        append_chunk(&mut output, b"IDAT", &to_zlib_stream(rows));
+        append_chunk(&mut output, b"IEND", &[]);
+        output
+    }
+
+    fn grey(value: u32) -> u32 {
+        value << 24 | value << 16 | value << 8 | 0xff
+    }
+
+    #[test]
+    fn what_png_rs_writes_reads_back_the_same() {
+        for (i, colours) in [1, 2, 3, 4, 5, 16, 17, 256, 257, u32::MAX].into_iter().enumerate() {
+            for (width, height) in [(1, 1), (7, 3), (33, 17), (320, 40)] {
+                let pixels = made_up_bitmap(width, height, colours, (i * 1000 + width) as u32);
+                let image = read_png(&png_data(width as u32, height as u32, &pixels)).unwrap();
+                assert_eq!((image.width, image.height), (width, height));
+                assert!(image.bitmap == pixels, "{}x{} of {} colours differs", width, height, colours);
+            }
+        }
+    }
+
+    #[test]
+    fn adam7_interlacing_puts_each_pass_in_place() {
+        // 8 bit greyscale where each pixel's value is its index, so any misplaced one shows. 9x10 has every pass, 2x1 has
+        // some empty ones.
+        for (width, height) in [(9usize, 10usize), (2, 1)] {
+            let mut rows = Vec::<u8>::new();
+            for (x0, y0, dx, dy) in ADAM7 {
+                if width <= x0 || height <= y0 { continue }
+                for y in (y0..height).step_by(dy) {
+                    rows.push(FILTER_NONE);
+                    rows.extend((x0..width).step_by(dx).map(|x| (y * width + x) as u8));
+                }
+            }
+            let image = read_png(&hand_made_png(width as u32, height as u32, 8, COLOUR_TYPE_GREY, true, &[], &rows)).unwrap();
+            let expected: Vec<u32> = (0..width * height).map(|i| grey(i as u32)).collect();
+            assert_eq!(image.bitmap, expected, "{}x{}", width, height);
+        }
+    }
+
+    #[test]
+    fn sixteen_bit_samples_keep_their_high_byte_and_trns_matches_all_16_bits() {
+        let rows = [FILTER_NONE, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0x12, 0x35, 0x56, 0x78, 0x9a, 0xbc];
+        let transparent = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc];
+        let image = read_png(&hand_made_png(2, 1, 16, COLOUR_TYPE_RGB, false, &[(b"tRNS", &transparent)], &rows)).unwrap();
+        assert_eq!(image.bitmap, vec![0, 0x12569aff]);
+    }
+
+    #[test]
+    fn greyscale_trns_makes_one_grey_see_through() {
+        let rows = [FILTER_NONE, 0x56, 0xf0]; // 4 bit: 5, 6, 15, 0.
+        let image = read_png(&hand_made_png(4, 1, 4, COLOUR_TYPE_GREY, false, &[(b"tRNS", &[0, 5])], &rows)).unwrap();
+        assert_eq!(image.bitmap, vec![0, grey(0x66), grey(0xff), grey(0)]);
+    }
+
+    #[test]
+    fn palette_trns_gives_the_first_entries_alpha() {
+        let palette = [0xff, 0, 0, 0, 0xff, 0, 0, 0, 0xff];
+        let rows = [FILTER_NONE, 0b00_01_10_01]; // 2 bit: 0, 1, 2, 1.
+        let chunks: [(&[u8; 4], &[u8]); 2] = [(b"PLTE", &palette), (b"tRNS", &[0, 0x80])];
+        let image = read_png(&hand_made_png(4, 1, 2, COLOUR_TYPE_INDEXED, false, &chunks, &rows)).unwrap();
+        assert_eq!(image.bitmap, vec![0, 0x00ff0080, 0x0000ffff, 0x00ff0080]);
+    }
+}
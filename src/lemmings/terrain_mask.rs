@@ -0,0 +1,63 @@
+// This is which points of a level are solid, at the original game's resolution, for collision.
+// One bit per point, so a whole level is only a few KB, and lookups don't depend on how the graphics got scaled.
+
+use crate::lemmings::level_renderer::RenderedLevel;
+
+pub struct TerrainMask {
+    min_x: isize, // Game point x of the first column, as levels don't start at 0.
+    width: usize,
+    height: usize,
+    bits: Vec<u64>, // Row-major, each row padded to a whole number of words.
+    words_per_row: usize,
+}
+
+impl TerrainMask {
+    pub fn new(min_x: isize, width: usize, height: usize) -> TerrainMask {
+        let words_per_row = width.div_ceil(64);
+        TerrainMask {
+            min_x,
+            width,
+            height,
+            bits: vec![0; words_per_row * height],
+            words_per_row,
+        }
+    }
+
+    // Anything that isn't see-through in the render is solid. Render it without objects, as those aren't terrain.
+    pub fn from_rendered_level(render: &RenderedLevel) -> TerrainMask {
+        let mut mask = TerrainMask::new(render.size.min_x, render.image.width, render.image.height);
+        for y in 0..render.image.height {
+            let row = &render.image.bitmap[y * render.image.width..(y + 1) * render.image.width];
+            for (x, pixel) in row.iter().enumerate() {
+                if pixel & 0xff != 0 { // Alpha is the low byte.
+                    mask.bits[y * mask.words_per_row + x / 64] |= 1 << (x % 64);
+                }
+            }
+        }
+        mask
+    }
+
+    // Converts game points to an index into bits and the bit in that word. None if out of bounds.
+    fn locate(&self, x: i32, y: i32) -> Option<(usize, u64)> {
+        let column = x as isize - self.min_x;
+        if column < 0 || column >= self.width as isize || y < 0 || y as usize >= self.height {
+            return None
+        }
+        let column = column as usize;
+        Some((y as usize * self.words_per_row + column / 64, 1 << (column % 64)))
+    }
+
+    // xy are game points, eg y=0=top. Outside the level is never solid.
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        self.locate(x, y).is_some_and(|(index, bit)| self.bits[index] & bit != 0)
+    }
+
+    pub fn set_solid(&mut self, x: i32, y: i32, is_solid: bool) {
+        let Some((index, bit)) = self.locate(x, y) else { return };
+        if is_solid {
+            self.bits[index] |= bit;
+        } else {
+            self.bits[index] &= !bit;
+        }
+    }
+}
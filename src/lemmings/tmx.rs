@@ -0,0 +1,251 @@
+// This converts levels to and from Tiled's .tmx maps, so levels can be designed in Tiled: https://www.mapeditor.org
+// The map has two image collection tilesets, the ground's terrain pieces and its objects, with a tile per piece whose id
+// is the piece's id, and three object layers:
+//   terrain: a tile object per piece, upside down ones flipped vertically, and the other flags as bool properties.
+//   objects: a tile object per object, flipped the same way, with the modifier as a property.
+//   steel: a rectangle per steel area, in points.
+// The globals are the map's properties. Positions are as in Level, but as Tiled places tile objects by their bottom left
+// corner, y is the bottom of the piece there. Everything is kept, so from_tmx(&to_tmx(..)) is the same level.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::{Error, ErrorKind, Result};
+use crate::lemmings::level_text::{global_value_mut, global_values, modifier_name, modifier_named};
+use crate::lemmings::models::*;
+use crate::lemmings::xml::{self, escape, Element};
+
+const TILE_SIZE: usize = 8; // Only used for the map's grid, as everything's an object.
+const MIN_WIDTH: usize = 320;
+const MAP_HEIGHT: usize = 160;
+const TERRAIN_FIRST_GID: u32 = 1;
+const OBJECTS_FIRST_GID: u32 = 65; // After the 64 terrain ids.
+const FLIPPED_HORIZONTALLY: u32 = 0x80000000;
+const FLIPPED_VERTICALLY: u32 = 0x40000000;
+const FLIPPED_DIAGONALLY: u32 = 0x20000000;
+const ROTATED_HEXAGONAL: u32 = 0x10000000;
+const FLIP_FLAGS: u32 = FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL;
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// The file names the tilesets' images are expected to have, in the image dir.
+pub fn terrain_image_name(id: i32) -> String {
+    format!("terrain_{}.png", id)
+}
+
+pub fn object_image_name(id: i32) -> String {
+    format!("object_{}.png", id)
+}
+
+fn write_tileset(text: &mut String, name: &str, first_gid: u32, image_dir: &str, tiles: &[(i32, usize, usize, String)]) {
+    let tile_width = tiles.iter().map(|t| t.1).max().unwrap_or(0);
+    let tile_height = tiles.iter().map(|t| t.2).max().unwrap_or(0);
+    writeln!(text, " <tileset firstgid=\"{}\" name=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" tilecount=\"{}\" columns=\"0\">",
+        first_gid, name, tile_width, tile_height, tiles.len()).unwrap();
+    writeln!(text, "  <grid orientation=\"orthogonal\" width=\"1\" height=\"1\"/>").unwrap();
+    for (id, width, height, file_name) in tiles {
+        writeln!(text, "  <tile id=\"{}\">", id).unwrap();
+        writeln!(text, "   <image width=\"{}\" height=\"{}\" source=\"{}/{}\"/>", width, height, escape(image_dir), escape(file_name)).unwrap();
+        writeln!(text, "  </tile>").unwrap();
+    }
+    writeln!(text, " </tileset>").unwrap();
+}
+
+fn bool_property(name: &str, value: bool) -> String {
+    format!("    <property name=\"{}\" type=\"bool\" value=\"{}\"/>\n", name, value)
+}
+
+// The map, with the tilesets' images in image_dir relative to the map, named as in terrain_image_name and
+// object_image_name. Writing those is up to the caller, as they're shared by every level on the ground.
+pub fn to_tmx(level: &Level, ground: &GroundCombined, image_dir: &str) -> String {
+    let terrain_size = |id: usize| ground.ground.terrain_info.get(id).map_or((0, 0), |info| (info.width, info.height));
+    let object_size = |id: usize| ground.ground.object_info.get(id).map_or((0, 0), |info| (info.width, info.height));
+    let mut terrain_ids: Vec<i32> = ground.terrain_sprites.keys().copied().collect();
+    terrain_ids.sort();
+    let mut object_ids: Vec<i32> = ground.object_sprites.keys().copied().collect();
+    object_ids.sort();
+
+    let right = level.terrain.iter().map(|t| (t.x.max(0) as usize) + terrain_size(t.terrain_id).0)
+        .chain(level.objects.iter().map(|o| (o.x.max(0) as usize) + object_size(o.obj_id).0))
+        .max().unwrap_or(0).max(MIN_WIDTH);
+    let object_count = level.terrain.len() + level.objects.len() + level.steel.len();
+
+    let mut text = String::new();
+    // Writing to a String can't fail, hence the unwraps.
+    writeln!(text, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+    writeln!(text, "<map version=\"1.10\" tiledversion=\"1.10.2\" orientation=\"orthogonal\" renderorder=\"right-down\" width=\"{}\" height=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" infinite=\"0\" nextlayerid=\"4\" nextobjectid=\"{}\">",
+        right.div_ceil(TILE_SIZE), MAP_HEIGHT / TILE_SIZE, TILE_SIZE, TILE_SIZE, object_count + 1).unwrap();
+    writeln!(text, " <properties>").unwrap();
+    writeln!(text, "  <property name=\"name\" value=\"{}\"/>", escape(&level.name)).unwrap();
+    for (name, value) in global_values(level) {
+        writeln!(text, "  <property name=\"{}\" type=\"int\" value=\"{}\"/>", name, value).unwrap();
+    }
+    writeln!(text, " </properties>").unwrap();
+
+    let terrain_tiles: Vec<_> = terrain_ids.iter().map(|&id| {
+        let sprite = &ground.terrain_sprites[&id];
+        (id, sprite.width, sprite.height, terrain_image_name(id))
+    }).collect();
+    write_tileset(&mut text, "terrain", TERRAIN_FIRST_GID, image_dir, &terrain_tiles);
+    let object_tiles: Vec<_> = object_ids.iter().map(|&id| {
+        let sprite = &ground.object_sprites[&id];
+        (id, sprite.width, sprite.height, object_image_name(id))
+    }).collect();
+    write_tileset(&mut text, "objects", OBJECTS_FIRST_GID, image_dir, &object_tiles);
+
+    let mut next_id = 1;
+    writeln!(text, " <objectgroup id=\"1\" name=\"terrain\">").unwrap();
+    for terrain in level.terrain.iter() {
+        let (width, height) = terrain_size(terrain.terrain_id);
+        let flip = if terrain.is_upside_down { FLIPPED_VERTICALLY } else { 0 };
+        writeln!(text, "  <object id=\"{}\" gid=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\">",
+            next_id, (TERRAIN_FIRST_GID + terrain.terrain_id as u32) | flip, terrain.x, terrain.y + height as isize, width, height).unwrap();
+        writeln!(text, "   <properties>").unwrap();
+        text.push_str(&bool_property("do_not_overwrite_existing_terrain", terrain.do_not_overwrite_existing_terrain));
+        text.push_str(&bool_property("remove_terrain", terrain.remove_terrain));
+        writeln!(text, "   </properties>").unwrap();
+        writeln!(text, "  </object>").unwrap();
+        next_id += 1;
+    }
+    writeln!(text, " </objectgroup>").unwrap();
+
+    writeln!(text, " <objectgroup id=\"2\" name=\"objects\">").unwrap();
+    for object in level.objects.iter() {
+        let (width, height) = object_size(object.obj_id);
+        let flip = if object.is_upside_down { FLIPPED_VERTICALLY } else { 0 };
+        writeln!(text, "  <object id=\"{}\" gid=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\">",
+            next_id, (OBJECTS_FIRST_GID + object.obj_id as u32) | flip, object.x, object.y + height as i32, width, height).unwrap();
+        writeln!(text, "   <properties>").unwrap();
+        writeln!(text, "    <property name=\"modifier\" value=\"{}\"/>", modifier_name(&object.modifier)).unwrap();
+        writeln!(text, "   </properties>").unwrap();
+        writeln!(text, "  </object>").unwrap();
+        next_id += 1;
+    }
+    writeln!(text, " </objectgroup>").unwrap();
+
+    writeln!(text, " <objectgroup id=\"3\" name=\"steel\" color=\"#80c0ff\">").unwrap();
+    for steel in level.steel.iter() {
+        let rect = steel.rect();
+        writeln!(text, "  <object id=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>", next_id, rect.x, rect.y, rect.width, rect.height).unwrap();
+        next_id += 1;
+    }
+    writeln!(text, " </objectgroup>").unwrap();
+    writeln!(text, "</map>").unwrap();
+    text
+}
+
+// Tiled writes positions as decimals once things have been dragged about, so they're rounded to whole points.
+fn number_attribute(element: &Element, name: &str) -> Result<Option<i64>> {
+    let Some(value) = element.attribute(name) else { return Ok(None) };
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(Some(number.round() as i64)),
+        _ => Err(invalid(format!("{} '{}' isn't a number", name, value))),
+    }
+}
+
+fn required_number(element: &Element, name: &str, what: &str) -> Result<i64> {
+    number_attribute(element, name)?.ok_or_else(|| invalid(format!("{} has no {}", what, name)))
+}
+
+// Properties' values are usually attributes, but can be the text inside for multi-line strings.
+fn properties(element: &Element) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    for properties_element in element.children_named("properties") {
+        for property in properties_element.children_named("property") {
+            let Some(name) = property.attribute("name") else { continue };
+            let value = property.attribute("value").map(|v| v.to_string()).unwrap_or_else(|| property.text.clone());
+            properties.insert(name.to_string(), value);
+        }
+    }
+    properties
+}
+
+fn bool_value(properties: &HashMap<String, String>, name: &str, what: &str) -> Result<bool> {
+    match properties.get(name).map(|v| v.as_str()) {
+        None | Some("false") => Ok(false),
+        Some("true") => Ok(true),
+        Some(value) => Err(invalid(format!("{}: {} '{}' isn't true or false", what, name, value))),
+    }
+}
+
+// Which tileset a gid is from, by name, the tile id within it, and whether it's flipped vertically.
+fn tile_for_gid<'a>(tilesets: &'a [(u32, String)], gid: u32, what: &str) -> Result<(&'a str, usize, bool)> {
+    if gid & (FLIPPED_HORIZONTALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL) != 0 {
+        return Err(invalid(format!("{}: only vertical flips are possible in the game", what)))
+    }
+    let plain_gid = gid & !FLIP_FLAGS;
+    let Some((first_gid, name)) = tilesets.iter().filter(|(first_gid, _)| *first_gid <= plain_gid).max_by_key(|(first_gid, _)| *first_gid) else {
+        return Err(invalid(format!("{}: gid {} isn't in any tileset", what, plain_gid)))
+    };
+    Ok((name.as_str(), (plain_gid - first_gid) as usize, gid & FLIPPED_VERTICALLY != 0))
+}
+
+// Positions and sizes are checked to fit, but not against the game's rules, see level_rules.rs for those.
+pub fn from_tmx(text: &str) -> Result<Level> {
+    let map = xml::parse(text)?;
+    if map.name != "map" { return Err(invalid("Not a Tiled map".to_string())) }
+    let mut level = Level::default();
+
+    for (name, value) in properties(&map) {
+        if name == "name" {
+            level.name = value;
+            continue
+        }
+        let Some(global) = global_value_mut(&mut level, &name) else { return Err(invalid(format!("Unknown map property {}", name))) };
+        *global = value.parse().map_err(|_| invalid(format!("Map property {} '{}' isn't a number in range", name, value)))?;
+    }
+
+    let mut tilesets = Vec::<(u32, String)>::new();
+    for tileset in map.children_named("tileset") {
+        if tileset.attribute("source").is_some() { return Err(invalid("External tilesets need embedding in the map".to_string())) }
+        let first_gid = required_number(tileset, "firstgid", "A tileset")? as u32;
+        tilesets.push((first_gid, tileset.attribute("name").unwrap_or("").to_string()));
+    }
+
+    for group in map.children_named("objectgroup") {
+        let layer = group.attribute("name").unwrap_or("");
+        for (i, object) in group.children_named("object").enumerate() {
+            let what = format!("{} {}", layer, i + 1);
+            if number_attribute(object, "rotation")?.unwrap_or(0) != 0 { return Err(invalid(format!("{}: rotation isn't possible in the game", what))) }
+            let x = required_number(object, "x", &what)?;
+            let y = required_number(object, "y", &what)?;
+            let properties = properties(object);
+            match layer {
+                "terrain" | "objects" => {
+                    let gid = required_number(object, "gid", &what)? as u32;
+                    let height = required_number(object, "height", &what)?;
+                    let (tileset, id, is_upside_down) = tile_for_gid(&tilesets, gid, &what)?;
+                    if tileset != layer { return Err(invalid(format!("{}: its tile is from the {} tileset", what, tileset))) }
+                    if layer == "terrain" {
+                        level.terrain.push(Terrain {
+                            do_not_overwrite_existing_terrain: bool_value(&properties, "do_not_overwrite_existing_terrain", &what)?,
+                            is_upside_down,
+                            remove_terrain: bool_value(&properties, "remove_terrain", &what)?,
+                            x: x as isize,
+                            y: (y - height) as isize,
+                            terrain_id: id,
+                        });
+                    } else {
+                        let modifier = match properties.get("modifier") {
+                            None => ObjectModifier::Normal,
+                            Some(name) => modifier_named(name).ok_or_else(|| invalid(format!("{}: unknown modifier {}", what, name)))?,
+                        };
+                        level.objects.push(Object { x: x as i32, y: (y - height) as i32, obj_id: id, modifier, is_upside_down });
+                    }
+                },
+                "steel" => {
+                    let width = required_number(object, "width", &what)?;
+                    let height = required_number(object, "height", &what)?;
+                    // See SteelArea::rect, this is the other way.
+                    if x % 4 != 0 || width % 4 != 0 || height % 4 != 0 || !(4..=64).contains(&width) || !(4..=64).contains(&height) {
+                        return Err(invalid(format!("{}: steel is in 4 point steps, up to 64 square", what)))
+                    }
+                    level.steel.push(SteelArea { x: (x / 4) as isize, y: y as isize, width: (width / 4 - 1) as u8, height: (height / 4 - 1) as u8 });
+                },
+                _ => return Err(invalid(format!("Unknown layer {}, it should be terrain, objects or steel", layer))),
+            }
+        }
+    }
+    Ok(level)
+}
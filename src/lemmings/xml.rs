@@ -0,0 +1,183 @@
+// Just enough XML for Tiled's map files: elements, attributes, text, comments and the usual entities. No DTDs,
+// namespaces or CDATA, which Tiled doesn't write.
+
+use std::io::{Error, ErrorKind, Result};
+
+#[derive(Debug, Default, Clone)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String, // All the text directly inside, joined.
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// Escapes text for use in attributes or between tags.
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find(';') else { return Err(invalid(format!("Unfinished entity in '{}'", s))) };
+        let entity = &rest[start + 1..start + end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else {
+                    entity.strip_prefix('#').and_then(|decimal| decimal.parse::<u32>().ok())
+                };
+                match code.and_then(char::from_u32) {
+                    Some(c) => c,
+                    None => return Err(invalid(format!("Unknown entity &{};", entity))),
+                }
+            },
+        };
+        out.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.position = self.text.len() - trimmed.len();
+    }
+
+    // Moves past the next occurrence of the string.
+    fn skip_past(&mut self, end: &str) -> Result<()> {
+        let Some(at) = self.rest().find(end) else { return Err(invalid(format!("Missing {}", end))) };
+        self.position += at + end.len();
+        Ok(())
+    }
+
+    // Declarations, comments and doctypes, which say nothing the map needs.
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("<!") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(())
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let rest = self.rest();
+        let length = rest.find(|c: char| c.is_whitespace() || c == '>' || c == '/' || c == '=').unwrap_or(rest.len());
+        if length == 0 { return Err(invalid(format!("Expected a name at byte {}", self.position))) }
+        self.position += length;
+        Ok(rest[..length].to_string())
+    }
+
+    fn element(&mut self) -> Result<Element> {
+        if !self.rest().starts_with('<') { return Err(invalid(format!("Expected an element at byte {}", self.position))) }
+        self.position += 1;
+        let mut element = Element { name: self.name()?, ..Default::default() };
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.position += 2;
+                return Ok(element)
+            }
+            if self.rest().starts_with('>') {
+                self.position += 1;
+                break
+            }
+            let name = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') { return Err(invalid(format!("Expected = after {}", name))) }
+            self.position += 1;
+            self.skip_whitespace();
+            let Some(quote) = self.rest().chars().next().filter(|&c| c == '"' || c == '\'') else { return Err(invalid(format!("Expected a quoted value for {}", name))) };
+            self.position += 1;
+            let Some(length) = self.rest().find(quote) else { return Err(invalid(format!("Unfinished value for {}", name))) };
+            let value = unescape(&self.rest()[..length])?;
+            self.position += length + 1;
+            element.attributes.push((name, value));
+        }
+        // Children and text, up to the closing tag.
+        loop {
+            let rest = self.rest();
+            let Some(next_tag) = rest.find('<') else { return Err(invalid(format!("No closing tag for {}", element.name))) };
+            element.text.push_str(&unescape(&rest[..next_tag])?);
+            self.position += next_tag;
+            if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("</") {
+                self.position += 2;
+                let name = self.name()?;
+                if name != element.name { return Err(invalid(format!("</{}> closes <{}>", name, element.name))) }
+                self.skip_whitespace();
+                if !self.rest().starts_with('>') { return Err(invalid(format!("Bad closing tag for {}", name))) }
+                self.position += 1;
+                return Ok(element)
+            } else {
+                element.children.push(self.element()?);
+            }
+        }
+    }
+}
+
+// The document's root element.
+pub fn parse(text: &str) -> Result<Element> {
+    let mut parser = Parser { text, position: 0 };
+    parser.skip_misc()?;
+    let root = parser.element()?;
+    parser.skip_misc()?;
+    if !parser.rest().is_empty() { return Err(invalid("More after the root element".to_string())) }
+    Ok(root)
+}
@@ -1,22 +1,119 @@
 use bevy::{prelude::*, render::render_resource::{Extent3d}};
-use crate::lemmings::models::Game;
+use bevy::tasks::AsyncComputeTaskPool;
+use std::sync::{Arc, Mutex};
+use crate::lemmings::models::{Animation, Game};
 use crate::lemmings_to_bevy::image_doctor::*;
-use crate::helpers::{make_image, make_atlas_from_animation};
+use crate::helpers::{bevy_image, multi_scale_cached, scale_animation_to_atlas, make_atlas_from_animation_unscaled, AtlasLayout};
+use crate::scale;
+use crate::scaler::ScalerKind;
+use crate::settings::Settings;
+
+// This makes all the bevy textures from the game's graphics.
+// Upscaling them is slow, so it's done on a thread pool: the handles are made straight away, pointing at see-through
+// placeholders, and the real images get swapped in as each one finishes. The loading screen waits for them all.
 
 pub struct LoadLemmingsTexturesPlugin;
 
 impl Plugin for LoadLemmingsTexturesPlugin {
 	fn build(&self, app: &mut App) {
         app.add_startup_system(load_lemmings_textures_startup.in_base_set(StartupSet::PreStartup));
+        app.add_system(swap_in_scaled_textures);
 	}
 }
 
+// One image being scaled on another thread.
+struct ScaleJob {
+    handle: Handle<Image>, // Points at the placeholder until it's done.
+    width: usize, // Of the scaled image.
+    height: usize,
+    result: Arc<Mutex<Option<Vec<u32>>>>, // Filled in when done.
+}
+
+#[derive(Resource)]
+pub struct TextureLoadingProgress {
+    jobs: Vec<ScaleJob>, // Still going.
+    total: usize,
+}
+
+impl TextureLoadingProgress {
+    pub fn done(&self) -> usize { self.total - self.jobs.len() }
+    pub fn total(&self) -> usize { self.total }
+    pub fn is_finished(&self) -> bool { self.jobs.is_empty() }
+}
+
+/// The menu font without upscaling, for the loading screen, as it's ready immediately.
+#[derive(Resource)]
+pub struct LoadingFont(pub Handle<TextureAtlas>);
+
+// Hands out handles for images, and starts scaling them in the background.
+struct TextureLoader<'a> {
+    images: &'a mut Assets<Image>,
+    texture_atlases: &'a mut Assets<TextureAtlas>,
+    jobs: Vec<ScaleJob>,
+}
+
+impl<'a> TextureLoader<'a> {
+    fn placeholder(&mut self) -> Handle<Image> {
+        self.images.add(bevy_image(&[0], 1, 1))
+    }
+
+    fn spawn<F: FnOnce() -> Vec<u32> + Send + 'static>(&mut self, handle: Handle<Image>, width: usize, height: usize, scale: F) {
+        let result = Arc::new(Mutex::new(None));
+        let thread_result = result.clone();
+        AsyncComputeTaskPool::get().spawn(async move {
+            let scaled = scale();
+            *thread_result.lock().unwrap() = Some(scaled);
+        }).detach();
+        self.jobs.push(ScaleJob { handle, width, height, result });
+    }
+
+    fn image(&mut self, image: &crate::lemmings::models::Image, should_add_then_remove_margin: bool, scaler: ScalerKind) -> Handle<Image> {
+        let handle = self.placeholder();
+        let (bitmap, width, height) = (image.bitmap.clone(), image.width, image.height);
+        self.spawn(handle.clone(), width * scale(), height * scale(), move || {
+            multi_scale_cached(&bitmap, width, height, should_add_then_remove_margin, scaler)
+        });
+        handle
+    }
+
+    // The atlas's frame rects don't depend on the pixels, so it can be made now.
+    fn atlas(&mut self, animation: &Animation, should_add_then_remove_margin: bool, scaler: ScalerKind) -> Handle<TextureAtlas> {
+        let layout = AtlasLayout::for_scaled_animation(animation);
+        let handle = self.placeholder();
+        let atlas_handle = self.texture_atlases.add(layout.make_atlas(handle.clone()));
+        let animation = animation.clone();
+        let (width, height) = (layout.width, layout.height);
+        self.spawn(handle, width, height, move || {
+            scale_animation_to_atlas(&animation, &layout, should_add_then_remove_margin, scaler)
+        });
+        atlas_handle
+    }
+}
+
+fn swap_in_scaled_textures(
+    progress: Option<ResMut<TextureLoadingProgress>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(mut progress) = progress else { return };
+    if progress.jobs.is_empty() { return }
+    progress.jobs.retain(|job| {
+        let Some(scaled) = job.result.lock().unwrap().take() else { return true }; // Still going.
+        if let Some(image) = images.get_mut(&job.handle) {
+            *image = bevy_image(&scaled, job.width, job.height);
+        }
+        false
+    });
+}
+
 fn load_lemmings_textures_startup(
     game: Res<Game>,
+    settings: Res<Settings>,
 	mut commands: Commands,
 	mut images: ResMut<Assets<Image>>,
 	mut texture_atlases: ResMut<Assets<TextureAtlas>>,
 ) {
+    commands.insert_resource(LoadingFont(make_atlas_from_animation_unscaled(&game.main.main_menu.menu_font, &mut images, &mut texture_atlases)));
+
     // Some of them need doctoring a bit.
     let background = doctor_clear_to_black(&game.main.main_menu.background);
     let f1 = doctor_f1(&game.main.main_menu.f1);
@@ -38,7 +135,9 @@ fn load_lemmings_textures_startup(
         bevy::render::render_resource::TextureFormat::Rgba8UnormSrgb);
     let white = images.add(white_image);
 
-    let skill_number_digits = SkillNumberDigits::make_images(&game.main.skill_number_digits, &mut images);
+    let (menus, sprites) = (settings.menu_scaler, settings.sprite_scaler);
+    let mut loader = TextureLoader { images: &mut images, texture_atlases: &mut texture_atlases, jobs: Vec::new() };
+    let skill_number_digits = SkillNumberDigits::make_images(&game.main.skill_number_digits, &mut loader, menus);
 
     // For inspecting the images conveniently:
     // {
@@ -52,63 +151,63 @@ fn load_lemmings_textures_startup(
 
 	let game_textures = GameTextures {
         // Menu:
-        background: make_image(&background, &mut images, false),
-        logo: make_image(&game.main.main_menu.logo, &mut images, true),
-        f1: make_image(&f1, &mut images, true),
-        f2: make_image(&f2, &mut images, true),
-        f3: make_image(&f3, &mut images, true),
-        f4_settings: make_image(&f4, &mut images, true),
-        level_rating: make_image(&level_rating, &mut images, true),
-        exit_to_dos: make_image(&exit_to_dos, &mut images, true),
-        music_note: make_image(&game.main.main_menu.music_note, &mut images, true),
-        fx: make_image(&game.main.main_menu.fx, &mut images, true),
-        blink1: make_atlas_from_animation(&game.main.main_menu.blink1, &mut images, &mut texture_atlases, false),
-        blink2: make_atlas_from_animation(&game.main.main_menu.blink2, &mut images, &mut texture_atlases, false),
-        blink3: make_atlas_from_animation(&game.main.main_menu.blink3, &mut images, &mut texture_atlases, false),
-        blink4: make_atlas_from_animation(&game.main.main_menu.blink4, &mut images, &mut texture_atlases, false),
-        blink5: make_atlas_from_animation(&game.main.main_menu.blink5, &mut images, &mut texture_atlases, false),
-        blink6: make_atlas_from_animation(&game.main.main_menu.blink6, &mut images, &mut texture_atlases, false),
-        blink7: make_atlas_from_animation(&game.main.main_menu.blink7, &mut images, &mut texture_atlases, false),
-        left_scroller: make_atlas_from_animation(&game.main.main_menu.left_scroller, &mut images, &mut texture_atlases, true),
-        right_scroller: make_atlas_from_animation(&game.main.main_menu.right_scroller, &mut images, &mut texture_atlases, true),
-        reel: make_image(&game.main.main_menu.reel, &mut images, true),
-        mayhem: make_image(&mayhem, &mut images, true),
-        taxing: make_image(&taxing, &mut images, true),
-        tricky: make_image(&tricky, &mut images, true),
-        fun: make_image(&fun, &mut images, true),
-        menu_font: make_atlas_from_animation(&game.main.main_menu.menu_font, &mut images, &mut texture_atlases, true),
+        background: loader.image(&background, false, menus),
+        logo: loader.image(&game.main.main_menu.logo, true, menus),
+        f1: loader.image(&f1, true, menus),
+        f2: loader.image(&f2, true, menus),
+        f3: loader.image(&f3, true, menus),
+        f4_settings: loader.image(&f4, true, menus),
+        level_rating: loader.image(&level_rating, true, menus),
+        exit_to_dos: loader.image(&exit_to_dos, true, menus),
+        music_note: loader.image(&game.main.main_menu.music_note, true, menus),
+        fx: loader.image(&game.main.main_menu.fx, true, menus),
+        blink1: loader.atlas(&game.main.main_menu.blink1, false, menus),
+        blink2: loader.atlas(&game.main.main_menu.blink2, false, menus),
+        blink3: loader.atlas(&game.main.main_menu.blink3, false, menus),
+        blink4: loader.atlas(&game.main.main_menu.blink4, false, menus),
+        blink5: loader.atlas(&game.main.main_menu.blink5, false, menus),
+        blink6: loader.atlas(&game.main.main_menu.blink6, false, menus),
+        blink7: loader.atlas(&game.main.main_menu.blink7, false, menus),
+        left_scroller: loader.atlas(&game.main.main_menu.left_scroller, true, menus),
+        right_scroller: loader.atlas(&game.main.main_menu.right_scroller, true, menus),
+        reel: loader.image(&game.main.main_menu.reel, true, menus),
+        mayhem: loader.image(&mayhem, true, menus),
+        taxing: loader.image(&taxing, true, menus),
+        tricky: loader.image(&tricky, true, menus),
+        fun: loader.image(&fun, true, menus),
+        menu_font: loader.atlas(&game.main.main_menu.menu_font, true, menus),
     
         // Lemmings:
-        walking_right: make_atlas_from_animation(&game.main.lemming_animations.walking_right, &mut images, &mut texture_atlases, true),
-        jumping_right: make_atlas_from_animation(&game.main.lemming_animations.jumping_right, &mut images, &mut texture_atlases, true),
-        walking_left: make_atlas_from_animation(&game.main.lemming_animations.walking_left, &mut images, &mut texture_atlases, true),
-        jumping_left: make_atlas_from_animation(&game.main.lemming_animations.jumping_left, &mut images, &mut texture_atlases, true),
-        digging: make_atlas_from_animation(&game.main.lemming_animations.digging, &mut images, &mut texture_atlases, true),
-        climbing_right: make_atlas_from_animation(&game.main.lemming_animations.climbing_right, &mut images, &mut texture_atlases, true),
-        climbing_left: make_atlas_from_animation(&game.main.lemming_animations.climbing_left, &mut images, &mut texture_atlases, true),
-        drowning: make_atlas_from_animation(&game.main.lemming_animations.drowning, &mut images, &mut texture_atlases, true),
-        post_climb_right: make_atlas_from_animation(&game.main.lemming_animations.post_climb_right, &mut images, &mut texture_atlases, true),
-        post_climb_left: make_atlas_from_animation(&game.main.lemming_animations.post_climb_left, &mut images, &mut texture_atlases, true),
-        brick_laying_right: make_atlas_from_animation(&game.main.lemming_animations.brick_laying_right, &mut images, &mut texture_atlases, true),
-        brick_laying_left: make_atlas_from_animation(&game.main.lemming_animations.brick_laying_left, &mut images, &mut texture_atlases, true),
-        bashing_right: make_atlas_from_animation(&game.main.lemming_animations.bashing_right, &mut images, &mut texture_atlases, true),
-        bashing_left: make_atlas_from_animation(&game.main.lemming_animations.bashing_left, &mut images, &mut texture_atlases, true),
-        mining_right: make_atlas_from_animation(&game.main.lemming_animations.mining_right, &mut images, &mut texture_atlases, true),
-        mining_left: make_atlas_from_animation(&game.main.lemming_animations.mining_left, &mut images, &mut texture_atlases, true),
-        falling_right: make_atlas_from_animation(&game.main.lemming_animations.falling_right, &mut images, &mut texture_atlases, true),
-        falling_left: make_atlas_from_animation(&game.main.lemming_animations.falling_left, &mut images, &mut texture_atlases, true),
-        pre_umbrella_right: make_atlas_from_animation(&game.main.lemming_animations.pre_umbrella_right, &mut images, &mut texture_atlases, true),
-        umbrella_right: make_atlas_from_animation(&game.main.lemming_animations.umbrella_right, &mut images, &mut texture_atlases, true),
-        pre_umbrella_left: make_atlas_from_animation(&game.main.lemming_animations.pre_umbrella_left, &mut images, &mut texture_atlases, true),
-        umbrella_left: make_atlas_from_animation(&game.main.lemming_animations.umbrella_left, &mut images, &mut texture_atlases, true),
-        splatting: make_atlas_from_animation(&game.main.lemming_animations.splatting, &mut images, &mut texture_atlases, true),
-        exiting: make_atlas_from_animation(&game.main.lemming_animations.exiting, &mut images, &mut texture_atlases, true),
-        fried: make_atlas_from_animation(&game.main.lemming_animations.fried, &mut images, &mut texture_atlases, true),
-        blocking: make_atlas_from_animation(&game.main.lemming_animations.blocking, &mut images, &mut texture_atlases, true),
-        shrugging_right: make_atlas_from_animation(&game.main.lemming_animations.shrugging_right, &mut images, &mut texture_atlases, true), // Builder running out of bricks.
-        shrugging_left: make_atlas_from_animation(&game.main.lemming_animations.shrugging_left, &mut images, &mut texture_atlases, true),
-        oh_no_ing: make_atlas_from_animation(&game.main.lemming_animations.oh_no_ing, &mut images, &mut texture_atlases, true),
-        explosion: make_atlas_from_animation(&game.main.lemming_animations.explosion, &mut images, &mut texture_atlases, true),
+        walking_right: loader.atlas(&game.main.lemming_animations.walking_right, true, sprites),
+        jumping_right: loader.atlas(&game.main.lemming_animations.jumping_right, true, sprites),
+        walking_left: loader.atlas(&game.main.lemming_animations.walking_left, true, sprites),
+        jumping_left: loader.atlas(&game.main.lemming_animations.jumping_left, true, sprites),
+        digging: loader.atlas(&game.main.lemming_animations.digging, true, sprites),
+        climbing_right: loader.atlas(&game.main.lemming_animations.climbing_right, true, sprites),
+        climbing_left: loader.atlas(&game.main.lemming_animations.climbing_left, true, sprites),
+        drowning: loader.atlas(&game.main.lemming_animations.drowning, true, sprites),
+        post_climb_right: loader.atlas(&game.main.lemming_animations.post_climb_right, true, sprites),
+        post_climb_left: loader.atlas(&game.main.lemming_animations.post_climb_left, true, sprites),
+        brick_laying_right: loader.atlas(&game.main.lemming_animations.brick_laying_right, true, sprites),
+        brick_laying_left: loader.atlas(&game.main.lemming_animations.brick_laying_left, true, sprites),
+        bashing_right: loader.atlas(&game.main.lemming_animations.bashing_right, true, sprites),
+        bashing_left: loader.atlas(&game.main.lemming_animations.bashing_left, true, sprites),
+        mining_right: loader.atlas(&game.main.lemming_animations.mining_right, true, sprites),
+        mining_left: loader.atlas(&game.main.lemming_animations.mining_left, true, sprites),
+        falling_right: loader.atlas(&game.main.lemming_animations.falling_right, true, sprites),
+        falling_left: loader.atlas(&game.main.lemming_animations.falling_left, true, sprites),
+        pre_umbrella_right: loader.atlas(&game.main.lemming_animations.pre_umbrella_right, true, sprites),
+        umbrella_right: loader.atlas(&game.main.lemming_animations.umbrella_right, true, sprites),
+        pre_umbrella_left: loader.atlas(&game.main.lemming_animations.pre_umbrella_left, true, sprites),
+        umbrella_left: loader.atlas(&game.main.lemming_animations.umbrella_left, true, sprites),
+        splatting: loader.atlas(&game.main.lemming_animations.splatting, true, sprites),
+        exiting: loader.atlas(&game.main.lemming_animations.exiting, true, sprites),
+        fried: loader.atlas(&game.main.lemming_animations.fried, true, sprites),
+        blocking: loader.atlas(&game.main.lemming_animations.blocking, true, sprites),
+        shrugging_right: loader.atlas(&game.main.lemming_animations.shrugging_right, true, sprites), // Builder running out of bricks.
+        shrugging_left: loader.atlas(&game.main.lemming_animations.shrugging_left, true, sprites),
+        oh_no_ing: loader.atlas(&game.main.lemming_animations.oh_no_ing, true, sprites),
+        explosion: loader.atlas(&game.main.lemming_animations.explosion, true, sprites),
 
         walking_right_count: game.main.lemming_animations.walking_right.frames.len(),
         jumping_right_count: game.main.lemming_animations.jumping_right.frames.len(),
@@ -141,17 +240,19 @@ fn load_lemmings_textures_startup(
         oh_no_ing_count: game.main.lemming_animations.oh_no_ing.frames.len(),
         explosion_count: game.main.lemming_animations.explosion.frames.len(),
 
-        skill_panel: make_image(&game.main.skill_panel, &mut images, true),
-        skill_selection: make_image(&game.main.skill_selection, &mut images, true),
-        speed_selection: make_image(&game.main.speed_selection, &mut images, true),
-        pause_selection: make_image(&game.main.pause_selection, &mut images, true),
-        nuke_selection: make_image(&game.main.nuke_selection, &mut images, true),
+        skill_panel: loader.image(&game.main.skill_panel, true, menus),
+        skill_selection: loader.image(&game.main.skill_selection, true, menus),
+        speed_selection: loader.image(&game.main.speed_selection, true, menus),
+        pause_selection: loader.image(&game.main.pause_selection, true, menus),
+        nuke_selection: loader.image(&game.main.nuke_selection, true, menus),
         skill_number_digits, 
         
         white,
-        mouse_cursor: make_image(&game.main.mouse_cursor, &mut images, true),
-        mouse_cursor_hovering: make_image(&game.main.mouse_cursor_hovering, &mut images, true),
+        mouse_cursor: loader.image(&game.main.mouse_cursor, true, sprites),
+        mouse_cursor_hovering: loader.image(&game.main.mouse_cursor_hovering, true, sprites),
 	};
+	let total = loader.jobs.len();
+	commands.insert_resource(TextureLoadingProgress { jobs: loader.jobs, total });
 	commands.insert_resource(game_textures);
 }
 
@@ -161,32 +262,32 @@ pub struct SkillNumberDigits {
 }
 
 impl SkillNumberDigits {
-    fn make_images(data: &crate::lemmings::models::SkillNumberDigits, images: &mut ResMut<Assets<Image>>) -> SkillNumberDigits {
+    fn make_images(data: &crate::lemmings::models::SkillNumberDigits, loader: &mut TextureLoader, scaler: ScalerKind) -> SkillNumberDigits {
         // This ugly thing is because a map returns an array of references, not actual images.
         SkillNumberDigits {
             left: [
-                make_image(&data.left[0], images, true),
-                make_image(&data.left[1], images, true),
-                make_image(&data.left[2], images, true),
-                make_image(&data.left[3], images, true),
-                make_image(&data.left[4], images, true),
-                make_image(&data.left[5], images, true),
-                make_image(&data.left[6], images, true),
-                make_image(&data.left[7], images, true),
-                make_image(&data.left[8], images, true),
-                make_image(&data.left[9], images, true),
+                loader.image(&data.left[0], true, scaler),
+                loader.image(&data.left[1], true, scaler),
+                loader.image(&data.left[2], true, scaler),
+                loader.image(&data.left[3], true, scaler),
+                loader.image(&data.left[4], true, scaler),
+                loader.image(&data.left[5], true, scaler),
+                loader.image(&data.left[6], true, scaler),
+                loader.image(&data.left[7], true, scaler),
+                loader.image(&data.left[8], true, scaler),
+                loader.image(&data.left[9], true, scaler),
             ],
             right: [
-                make_image(&data.right[0], images, true),
-                make_image(&data.right[1], images, true),
-                make_image(&data.right[2], images, true),
-                make_image(&data.right[3], images, true),
-                make_image(&data.right[4], images, true),
-                make_image(&data.right[5], images, true),
-                make_image(&data.right[6], images, true),
-                make_image(&data.right[7], images, true),
-                make_image(&data.right[8], images, true),
-                make_image(&data.right[9], images, true),
+                loader.image(&data.right[0], true, scaler),
+                loader.image(&data.right[1], true, scaler),
+                loader.image(&data.right[2], true, scaler),
+                loader.image(&data.right[3], true, scaler),
+                loader.image(&data.right[4], true, scaler),
+                loader.image(&data.right[5], true, scaler),
+                loader.image(&data.right[6], true, scaler),
+                loader.image(&data.right[7], true, scaler),
+                loader.image(&data.right[8], true, scaler),
+                loader.image(&data.right[9], true, scaler),
             ]
         }
     }
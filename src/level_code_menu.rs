@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+use crate::fadeout::*;
+use crate::{GameTextures, GameState};
+use crate::menu_common::{spawn_menu_background, text_size, spawn_text};
+use crate::level_preview::LevelSelectionResource;
+use crate::level_selection_menu::MainMenuSkillSelection;
+use crate::lemmings::campaign::Campaign;
+use crate::lemmings::models::Game;
+use crate::lemmings::password;
+
+// This is the screen for typing in a level code to jump straight to a level.
+
+pub struct LevelCodeMenuPlugin;
+
+impl Plugin for LevelCodeMenuPlugin {
+	fn build(&self, app: &mut App) {
+		app.insert_resource(EnteredCode::default());
+		app.add_systems((
+            spawn_background,
+            spawn_prompt,
+        ).in_schedule(OnEnter(GameState::LevelCodeMenu)));
+        app.add_systems((
+            keyboard_system.run_if(screen_fade_is_not_transitioning),
+            update_code_text,
+        ).chain().in_set(OnUpdate(GameState::LevelCodeMenu)));
+        app.add_systems((
+            exit,
+        ).in_schedule(OnExit(GameState::LevelCodeMenu)));
+	}
+}
+
+#[derive(Component)]
+struct LevelCodeMenuComponent;
+
+#[derive(Component)]
+struct CodeTextComponent; // The line showing what's been typed so far.
+
+#[derive(Component)]
+struct MessageTextComponent; // The line under that, for 'invalid code' etc.
+
+#[derive(Resource, Default)]
+struct EnteredCode {
+	code: String,
+	message: String,
+	is_changed: bool,
+}
+
+fn exit(
+    mut commands: Commands,
+    components: Query<Entity, With<LevelCodeMenuComponent>>,
+) {
+    for e in components.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn spawn_background(
+	mut commands: Commands,
+	game_textures: Res<GameTextures>,
+) {
+	commands
+		.spawn(SpatialBundle::default())
+		.insert(LevelCodeMenuComponent)
+		.with_children(|parent| {
+			spawn_menu_background(parent, &game_textures);
+		});
+}
+
+fn spawn_prompt(
+	mut commands: Commands,
+	game_textures: Res<GameTextures>,
+	mut entered: ResMut<EnteredCode>,
+) {
+	*entered = EnteredCode { is_changed: true, ..default() };
+	let size = text_size();
+	let line = size * 1.5;
+	commands
+		.spawn(SpatialBundle::default())
+		.insert(LevelCodeMenuComponent)
+		.with_children(|parent| {
+			parent.spawn(SpatialBundle{
+				transform: Transform::from_xyz(0., line * 2., 2.),
+				..default()
+			}).with_children(|parent| {
+				spawn_text("Enter Code", parent, &game_textures);
+			});
+			parent.spawn(SpatialBundle{
+				transform: Transform::from_xyz(0., 0., 2.),
+				..default()
+			}).insert(CodeTextComponent);
+			parent.spawn(SpatialBundle{
+				transform: Transform::from_xyz(0., -line * 2., 2.),
+				..default()
+			}).insert(MessageTextComponent);
+			parent.spawn(SpatialBundle{
+				transform: Transform::from_xyz(0., -line * 4., 2.),
+				..default()
+			}).with_children(|parent| {
+				spawn_text("Press Escape to go back", parent, &game_textures);
+			});
+		});
+}
+
+fn keyboard_system(
+	mut fadeout: Fadeout,
+	mut characters: EventReader<ReceivedCharacter>,
+	keys: Res<Input<KeyCode>>,
+	mut entered: ResMut<EnteredCode>,
+	game: Res<Game>,
+	mut level_selection: ResMut<LevelSelectionResource>,
+	mut skill_selection: ResMut<MainMenuSkillSelection>,
+) {
+	for c in characters.iter() {
+		if c.char.is_ascii_alphabetic() && entered.code.len() < password::CODE_LENGTH {
+			entered.code.push(c.char.to_ascii_uppercase());
+			entered.message.clear();
+			entered.is_changed = true;
+		}
+	}
+	if keys.just_pressed(KeyCode::Back) {
+		entered.code.pop();
+		entered.message.clear();
+		entered.is_changed = true;
+	}
+	if keys.just_pressed(KeyCode::Escape) {
+		fadeout.start(GameState::MainMenu);
+		return;
+	}
+	if keys.just_pressed(KeyCode::Return) && entered.code.len() == password::CODE_LENGTH {
+		if !password::has_codes(&game.id) {
+			entered.message = "This game's codes aren't supported".to_string();
+			entered.is_changed = true;
+			return;
+		}
+		let Some(code) = password::decode(&game.id, &entered.code) else {
+			entered.message = "Invalid code".to_string();
+			entered.is_changed = true;
+			return;
+		};
+		let campaign = Campaign::for_game(&game);
+		let position = campaign.position_of_level_number(code.level);
+		let Some((position, level)) = position.and_then(|p| Some((p, campaign.level(p)?))) else {
+			entered.message = "That level isn't available".to_string();
+			entered.is_changed = true;
+			return;
+		};
+		let skill = position.rating as isize;
+		level_selection.skill = skill;
+		level_selection.level_key = level.key;
+		level_selection.level_name = level.name.clone();
+		skill_selection.0 = skill;
+		fadeout.start(GameState::LevelPreview);
+	}
+}
+
+fn update_code_text(
+	mut commands: Commands,
+	mut entered: ResMut<EnteredCode>,
+	game_textures: Res<GameTextures>,
+	code_text: Query<Entity, With<CodeTextComponent>>,
+	message_text: Query<Entity, With<MessageTextComponent>>,
+) {
+	if !entered.is_changed { return }
+	entered.is_changed = false;
+
+	// Show dots for the letters still to type.
+	let mut shown = entered.code.clone();
+	while shown.len() < password::CODE_LENGTH {
+		shown.push('.');
+	}
+	for e in code_text.iter() {
+		commands.entity(e).despawn_descendants();
+		commands.entity(e).with_children(|parent| {
+			spawn_text(&shown, parent, &game_textures);
+		});
+	}
+	for e in message_text.iter() {
+		commands.entity(e).despawn_descendants();
+		commands.entity(e).with_children(|parent| {
+			spawn_text(&entered.message, parent, &game_textures);
+		});
+	}
+}
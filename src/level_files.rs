@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use crate::lemmings::{level_rules, level_text, loader, tmx};
+use crate::lemmings::models::{GroundCombined, Level};
+use crate::lemmings::parsers::level;
+use crate::lemmings::png::png_data;
+use crate::render_levels::slug;
+
+// These are the commands for the text level format, see level_text.rs.
+//   level-to-text <in.lvl> <out.txt>: converts a 2048 byte LVL file, as custom levels come, to text.
+//   text-to-level <in.txt> <out.lvl>: and back, if it passes level_rules.rs, like tmx-to-level.
+//   export-levels <dir>: writes every level in the game data as text, checking each comes back the same, both from
+//     the text and from LVL.
+// And for Tiled maps, see tmx.rs:
+//   export-tmx <dir>: writes every level as a .tmx, with each ground's pieces as PNGs in ground<N> next to them,
+//     checking each comes back the same.
+//   tmx-to-level <in.tmx> <out>: converts a map to LVL, or to text if out ends in .txt, if it passes level_rules.rs.
+//     With the game data, its pieces are also checked to exist in its ground. Exits with 1 without writing if not.
+// All exit with 0 if all went well, 1 if any level didn't come back the same or pass, 2 if the files couldn't be used.
+
+fn report(result: Result<()>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("{}", e);
+            2
+        },
+    }
+}
+
+pub fn run_level_to_text(in_path: &str, out_path: &str) -> i32 {
+    report((|| {
+        let level = level::parse(&fs::read(in_path)?)?;
+        fs::write(out_path, level_text::to_text(&level))
+    })())
+}
+
+pub fn run_text_to_level(in_path: &str, out_path: &str) -> i32 {
+    match fs::read_to_string(in_path).and_then(|text| level_text::from_text(&text)) {
+        Ok(level) => write_if_playable(&level, out_path),
+        Err(e) => {
+            println!("{}", e);
+            2
+        },
+    }
+}
+
+pub fn run_export(out_dir: &str) -> i32 {
+    let Some(game) = loader::load_lemmings() else { return 2 };
+    if let Err(e) = fs::create_dir_all(out_dir) {
+        println!("Couldn't create {}: {}", out_dir, e);
+        return 2
+    }
+    let keys = game.index.level_keys();
+    let mut differences = 0;
+    for &key in keys.iter() {
+        let level = match game.index.level(key) {
+            Ok(level) => level,
+            Err(e) => {
+                println!("Couldn't load level {}: {}", key, e);
+                return 2
+            },
+        };
+        let text = level_text::to_text(&level);
+        let from_text = level_text::from_text(&text);
+        let from_lvl = level::write(&level).and_then(|data| level::parse(&data));
+        if from_text.ok().as_ref() != Some(&*level) || from_lvl.ok().as_ref() != Some(&*level) {
+            println!("{} ({}) doesn't come back the same", level.name, key);
+            differences += 1;
+        }
+        let file_name = format!("{}_{}_{}.txt", game.id, key, slug(&level.name));
+        if let Err(e) = fs::write(Path::new(out_dir).join(file_name), text) {
+            println!("Couldn't write {}: {}", level.name, e);
+            return 2
+        }
+    }
+    println!("Exported {} levels to {}: {} don't come back the same", keys.len(), out_dir, differences);
+    if differences == 0 { 0 } else { 1 }
+}
+
+fn write_ground_images(dir: &Path, ground: &GroundCombined) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    for (&id, image) in ground.terrain_sprites.iter() {
+        fs::write(dir.join(tmx::terrain_image_name(id)), png_data(image.width as u32, image.height as u32, &image.bitmap))?;
+    }
+    for (&id, animation) in ground.object_sprites.iter() {
+        let Some(frame) = animation.frames.first() else { continue };
+        fs::write(dir.join(tmx::object_image_name(id)), png_data(animation.width as u32, animation.height as u32, frame))?;
+    }
+    Ok(())
+}
+
+pub fn run_export_tmx(out_dir: &str) -> i32 {
+    let Some(game) = loader::load_lemmings() else { return 2 };
+    let keys = game.index.level_keys();
+    let mut written_grounds = HashSet::<u16>::new();
+    let mut differences = 0;
+    for &key in keys.iter() {
+        let result = (|| -> Result<bool> {
+            let level = game.index.level(key)?;
+            let ground = game.ground_for(&level)?;
+            let graphic_set = level.globals.normal_graphic_set;
+            let image_dir = format!("ground{}", graphic_set);
+            if written_grounds.insert(graphic_set) {
+                write_ground_images(&Path::new(out_dir).join(&image_dir), &ground)?;
+            }
+            let text = tmx::to_tmx(&level, &ground, &image_dir);
+            let file_name = format!("{}_{}_{}.tmx", game.id, key, slug(&level.name));
+            fs::write(Path::new(out_dir).join(file_name), &text)?;
+            let same = tmx::from_tmx(&text).ok().as_ref() == Some(&*level);
+            if !same { println!("{} ({}) doesn't come back the same", level.name, key) }
+            Ok(same)
+        })();
+        match result {
+            Ok(true) => {},
+            Ok(false) => differences += 1,
+            Err(e) => {
+                println!("Couldn't export level {}: {}", key, e);
+                return 2
+            },
+        }
+    }
+    println!("Exported {} levels to {}: {} don't come back the same", keys.len(), out_dir, differences);
+    if differences == 0 { 0 } else { 1 }
+}
+
+// The ground the level uses, if the game data's there. It's fine if not, the rules that don't need it are still checked.
+fn ground_if_available(level: &Level) -> Option<std::sync::Arc<GroundCombined>> {
+    let game = loader::load().ok()?.lemmings?;
+    game.ground_for(level).ok()
+}
+
+// Checks the level against level_rules.rs, then writes it as LVL, or as text if the path ends in .txt.
+fn write_if_playable(level: &Level, out_path: &str) -> i32 {
+    let ground = ground_if_available(level);
+    if ground.is_none() { println!("Without the game data, the pieces aren't checked against the ground") }
+    let problems = level_rules::problems(level, ground.as_deref());
+    if !problems.is_empty() {
+        for problem in problems.iter() {
+            println!("{}", problem);
+        }
+        return 1
+    }
+    report((|| {
+        let data = if out_path.ends_with(".txt") { level_text::to_text(level).into_bytes() } else { level::write(level)? };
+        fs::write(out_path, data)
+    })())
+}
+
+pub fn run_tmx_to_level(in_path: &str, out_path: &str) -> i32 {
+    match fs::read_to_string(in_path).and_then(|text| tmx::from_tmx(&text)) {
+        Ok(level) => write_if_playable(&level, out_path),
+        Err(e) => {
+            println!("{}", e);
+            2
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lemmings::models::*;
+
+    // A small level using every kind of thing, and the flags and modifiers, all within what LVL can hold.
+    fn made_up_level() -> Level {
+        let mut level = Level { name: "Made up".to_string(), ..Default::default() };
+        level.globals.release_rate = 50;
+        level.globals.num_of_lemmings = 20;
+        level.globals.num_to_rescue = 10;
+        level.globals.time_limit = 5;
+        level.globals.skills.diggers = 3;
+        level.globals.start_screen_xpos = 160;
+        level.objects.push(Object { x: 200, y: 40, obj_id: 1, modifier: ObjectModifier::Normal, is_upside_down: false });
+        level.objects.push(Object { x: 600, y: 100, obj_id: 0, modifier: ObjectModifier::MustHaveTerrainUnderneathToBeVisible, is_upside_down: true });
+        level.terrain.push(Terrain { do_not_overwrite_existing_terrain: false, is_upside_down: false, remove_terrain: false, x: 180, y: 120, terrain_id: 4 });
+        level.terrain.push(Terrain { do_not_overwrite_existing_terrain: true, is_upside_down: true, remove_terrain: false, x: 400, y: -20, terrain_id: 63 });
+        level.terrain.push(Terrain { do_not_overwrite_existing_terrain: false, is_upside_down: false, remove_terrain: true, x: 4095, y: 251, terrain_id: 0 });
+        level.steel.push(SteelArea { x: 100, y: 36, width: 3, height: 15 });
+        level
+    }
+
+    #[test]
+    fn text_to_lvl_and_back_is_the_same() {
+        let level = made_up_level();
+        assert!(level_rules::problems(&level, None).is_empty());
+        let text = level_text::to_text(&level);
+        let from_text = level_text::from_text(&text).unwrap();
+        assert_eq!(from_text, level);
+        let from_lvl = level::parse(&level::write(&from_text).unwrap()).unwrap();
+        assert_eq!(level_text::to_text(&from_lvl), text);
+    }
+
+    #[test]
+    fn levels_that_dont_fit_arent_written() {
+        let mut too_many_objects = made_up_level();
+        too_many_objects.objects = vec![too_many_objects.objects[0].clone(); 33];
+        let mut bad_object_id = made_up_level();
+        bad_object_id.objects[0].obj_id = 16;
+        let mut bad_terrain_id = made_up_level();
+        bad_terrain_id.terrain[0].terrain_id = 0x80 + 4;
+        for level in [too_many_objects, bad_object_id, bad_terrain_id] {
+            assert!(level::write(&level).is_err());
+            assert!(!level_rules::problems(&level, None).is_empty());
+        }
+    }
+}
@@ -2,9 +2,11 @@ use bevy::prelude::*;
 use crate::fadeout::*;
 use crate::{GameTextures, GameState, POINT_SIZE};
 use crate::menu_common::{spawn_menu_background, text_size, spawn_text};
-use crate::lemmings::levels_per_game_and_skill::names_per_game_and_skill;
+use crate::lemmings::campaign::{Campaign, CampaignPosition};
 use crate::level_preview::LevelSelectionResource;
 use crate::lemmings::models::Game;
+use crate::progress::Progress;
+use crate::screen::Cursor;
 
 #[derive(Component)]
 struct LevelSelectionMenuComponent; // Marker component so the menu can be despawned.
@@ -32,29 +34,26 @@ impl Plugin for LevelSelectionMenuPlugin {
 }
 
 fn button_highlight_system(
-    windows: Query<&Window>,
+    cursor: Cursor,
     mouse_buttons: Res<Input<MouseButton>>,
-    mut buttons: Query<(&Transform, &Children), With<LevelSelectionButton>>,
+    mut buttons: Query<(&Transform, &Children, &LevelSelectionButton)>,
 	mut letters: Query<&mut TextureAtlasSprite>,
 ) {
-    if let Some(window) = windows.iter().next() {
-        let position = window.cursor_position().unwrap_or(Vec2::NEG_ONE);
-        let y = position.y - window.height() / 2.;
-        for (transform, children) in &mut buttons {			
-            let is_over = transform.translation.y - 16. * transform.scale.y < y &&
-				y < transform.translation.y + 16. * transform.scale.y;
-            let a: f32 = if is_over { if mouse_buttons.pressed(MouseButton::Left) { 0.5 } else { 0.8 } } else { 1. };
-			for &child in children {
-				if let Ok(mut letter) = letters.get_mut(child) {
-					letter.color.set_a(a);
-				}
+    let y = cursor.position().map_or(f32::MAX, |p| p.y);
+    for (transform, children, button) in &mut buttons {			
+        let is_over = transform.translation.y - 16. * transform.scale.y < y &&
+			y < transform.translation.y + 16. * transform.scale.y;
+        let a: f32 = if button.is_locked { 0.3 } else if is_over { if mouse_buttons.pressed(MouseButton::Left) { 0.5 } else { 0.8 } } else { 1. };
+		for &child in children {
+			if let Ok(mut letter) = letters.get_mut(child) {
+				letter.color.set_a(a);
 			}
-        }
+		}
     }
 }
 
 fn button_system(
-    windows: Query<&Window>,
+    cursor: Cursor,
     mouse_buttons: Res<Input<MouseButton>>,
     buttons: Query<(&Transform, &LevelSelectionButton)>,
     game_textures: Res<GameTextures>,
@@ -63,20 +62,20 @@ fn button_system(
     mut commands: Commands,
 ) {
     if mouse_buttons.just_released(MouseButton::Left) {
-        if let Some(window) = windows.iter().next() {
-            if let Some(position) = window.cursor_position() {
-                let y = position.y - window.height() / 2.;
-                let button_o = buttons.iter().find(|&b| {
-                    b.0.translation.y - 16. * b.0.scale.y < y && y < b.0.translation.y + 16. * b.0.scale.y
-                });
-                if let Some(button) = button_o {
-                    let lsb: &LevelSelectionButton = button.1;
-					level_selection.level_name = lsb.level_name.to_string();
-					level_selection.skill = lsb.skill;
-					create_fadeout(&mut commands, GameState::LevelPreview, &game_textures, is_transitioning);
-                }
+        if let Some(position) = cursor.position() {
+            let y = position.y;
+            let button_o = buttons.iter().find(|&b| {
+                b.0.translation.y - 16. * b.0.scale.y < y && y < b.0.translation.y + 16. * b.0.scale.y
+            });
+            if let Some(button) = button_o {
+                let lsb: &LevelSelectionButton = button.1;
+				if lsb.is_locked { return }
+				level_selection.level_key = lsb.level_key;
+				level_selection.level_name = lsb.level_name.to_string();
+				level_selection.skill = lsb.skill;
+				create_fadeout(&mut commands, GameState::LevelPreview, &game_textures, is_transitioning);
             }
-        }    
+        }
     }
 }
 
@@ -104,10 +103,14 @@ fn spawn_background(
 #[derive(Component)]
 pub struct LevelSelectionButton{
 	pub skill: isize,
+	pub level_key: i32,
 	pub level_name: String,
+	pub is_locked: bool,
 }
 
-fn spawn_level_button(parent: &mut ChildBuilder, game_textures: &Res<GameTextures>, name: &str, scale: f32, y: f32, skill: isize) {
+fn spawn_level_button(parent: &mut ChildBuilder, game_textures: &Res<GameTextures>, button: LevelSelectionButton, scale: f32, y: f32, is_completed: bool) {
+	// Completed levels get a star either side, so the name stays centered.
+	let text = if is_completed { format!("* {} *", button.level_name) } else { button.level_name.clone() };
 	parent.spawn(SpatialBundle{
 		transform: Transform {
 			translation: Vec3::new(0., y, 2.),
@@ -115,11 +118,8 @@ fn spawn_level_button(parent: &mut ChildBuilder, game_textures: &Res<GameTexture
 			..default()
 		},        
 		..default()
-	}).insert(LevelSelectionButton{
-		skill,
-		level_name: name.to_owned(),		
-	}).with_children(|parent| {
-		spawn_text(name, parent, game_textures);
+	}).insert(button).with_children(|parent| {
+		spawn_text(&text, parent, game_textures);
 	});
 }
 
@@ -128,19 +128,29 @@ fn spawn_levels(
 	game_textures: Res<GameTextures>,
 	skill_selection: Res<MainMenuSkillSelection>,
 	game: Res<Game>,
+	progress: Res<Progress>,
 ) {
 	commands
 		.spawn(SpatialBundle::default())
 		.insert(LevelSelectionMenuComponent)
 		.with_children(|parent| {
-			let names = names_per_game_and_skill(&game.id, skill_selection.0);
-			let scale: f32 = if names.len() >= 16 { 0.5 } else { 1. };
+			let campaign = Campaign::for_game(&game);
+			let levels = campaign.ratings.get(skill_selection.0 as usize).cloned().unwrap_or_default();
+			let scale: f32 = if levels.len() >= 16 { 0.5 } else { 1. };
 			let padding: f32 = POINT_SIZE * 4. * scale;
 			let size = text_size() * scale;
-			let all_size: f32 = ((names.len() - 1) as f32) * (size + padding);
+			let all_size: f32 = (levels.len().saturating_sub(1) as f32) * (size + padding);
 			let mut y: f32 = all_size / 2.;
-			for name in names {
-				spawn_level_button(parent, &game_textures, &name, scale, y, skill_selection.0);
+			for (index, level) in levels.into_iter().enumerate() {
+				let is_completed = progress.record(&game.id, level.key).completed;
+				let position = CampaignPosition { rating: skill_selection.0 as usize, index };
+				let button = LevelSelectionButton{
+					skill: skill_selection.0,
+					is_locked: !progress.is_unlocked(&game.id, &campaign, position),
+					level_key: level.key,
+					level_name: level.name,
+				};
+				spawn_level_button(parent, &game_textures, button, scale, y, is_completed);
 				y -= size + padding;
 			}
 		});
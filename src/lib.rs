@@ -0,0 +1,12 @@
+#![allow(dead_code)] // TODO disable once the app is mostly complete.
+
+// The parts that don't need Bevy: reading and writing the game's files, and scaling. main.rs is the game itself, and
+// uses these from here, as do the benches.
+
+pub mod lemmings;
+pub mod xbrz;
+#[cfg(any(test, feature = "reference"))]
+pub mod xbrz_reference;
+
+pub const ORIGINAL_GAME_W: usize = 320;
+pub const ORIGINAL_GAME_H: usize = 200;
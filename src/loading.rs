@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+use crate::fadeout::*;
+use crate::{GameTextures, GameState, POINT_SIZE};
+use crate::menu_common::text_size;
+use crate::lemmings_to_bevy::load_lemmings_textures::{LoadingFont, TextureLoadingProgress};
+
+// This is shown while the textures are upscaled in the background, so the window is responsive from the start.
+// It's drawn with the menu font straight from main.dat, since the upscaled one isn't ready yet.
+
+const BAR_CELLS: usize = 20;
+const FILLED_CHAR: char = '#';
+const EMPTY_CHAR: char = '-';
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems((
+            spawn_loading_screen,
+        ).in_schedule(OnEnter(GameState::Loading)));
+        app.add_systems((
+            update_progress_bar,
+            finish_loading.run_if(screen_fade_is_not_transitioning),
+        ).in_set(OnUpdate(GameState::Loading)));
+        app.add_systems((
+            exit,
+        ).in_schedule(OnExit(GameState::Loading)));
+	}
+}
+
+#[derive(Component)]
+struct LoadingComponent;
+
+#[derive(Component)]
+struct LoadingBarCell(usize);
+
+fn font_index(c: char) -> usize {
+	(c as usize).saturating_sub(33) // Menu font is '!'(33) - '~'(126)
+}
+
+fn exit(
+    mut commands: Commands,
+    components: Query<Entity, With<LoadingComponent>>,
+) {
+    for e in components.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn char_sprite(font: &LoadingFont, c: char, x: f32, y: f32) -> SpriteSheetBundle {
+	let scale = POINT_SIZE / 2.; // The font is SVGA, so half size.
+	SpriteSheetBundle {
+		texture_atlas: font.0.clone(),
+		sprite: TextureAtlasSprite{index: font_index(c), ..default()},
+		transform: Transform {
+			scale: Vec3::new(scale, scale, 1.),
+			translation: Vec3::new(x, y, 3.),
+			..default()
+		},
+		..default()
+	}
+}
+
+fn spawn_loading_screen(
+	mut commands: Commands,
+	font: Res<LoadingFont>,
+) {
+	let size = text_size();
+	let text = "Loading";
+	commands
+		.spawn(SpatialBundle::default())
+		.insert(LoadingComponent)
+		.with_children(|parent| {
+			for (i, c) in text.chars().enumerate() {
+				parent.spawn(char_sprite(&font, c, (i as f32 - (text.len() as f32 - 1.) / 2.) * size, size));
+			}
+			for i in 0..BAR_CELLS {
+				let x = (i as f32 - (BAR_CELLS as f32 - 1.) / 2.) * size;
+				parent.spawn((char_sprite(&font, EMPTY_CHAR, x, -size), LoadingBarCell(i)));
+			}
+		});
+}
+
+fn update_progress_bar(
+	progress: Res<TextureLoadingProgress>,
+	mut cells: Query<(&LoadingBarCell, &mut TextureAtlasSprite)>,
+) {
+	let filled = (progress.done() * BAR_CELLS).checked_div(progress.total()).unwrap_or(BAR_CELLS);
+	for (cell, mut sprite) in cells.iter_mut() {
+		sprite.index = font_index(if cell.0 < filled { FILLED_CHAR } else { EMPTY_CHAR });
+	}
+}
+
+fn finish_loading(
+	mut commands: Commands,
+	progress: Res<TextureLoadingProgress>,
+	game_textures: Res<GameTextures>,
+	is_transitioning: ResMut<ScreenFadeIsTransitioning>,
+) {
+	if !progress.is_finished() { return }
+	create_fadeout(&mut commands, GameState::MainMenu, &game_textures, is_transitioning);
+}
@@ -1,20 +1,40 @@
 #![allow(dead_code)] // TODO disable once the app is mostly complete.
 
-mod lemmings;
 mod lemmings_to_bevy;
-mod xbrz;
+mod png_check;
 mod main_menu;
 mod level_selection_menu;
 mod menu_common;
 mod fadeout;
 mod level_preview;
 mod helpers;
+mod scale_cache;
+mod scaler;
+mod settings;
+mod settings_menu;
 mod ingame;
 mod mouse_cursor;
+mod postview;
+mod level_code_menu;
+mod progress;
+mod congratulations;
+mod replay;
+mod verify;
+mod replay_frames;
+mod extract;
+mod sprite_sheets;
+mod render_levels;
+mod info;
+mod level_files;
+mod json;
+mod loading;
+mod screen;
 
 use bevy::prelude::*;
 use bevy::window::PresentMode;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use lemmings_to_bevy::load_lemmings_textures::GameTextures;
+use rusty_lemmings::{lemmings, xbrz, ORIGINAL_GAME_W, ORIGINAL_GAME_H};
 use lemmings::loader;
 
 // Tested by watching frame-by-frame youtube captures.
@@ -30,26 +50,41 @@ const FRAME_DURATION: f32 = 1. / FPS;
 // Realistically: 6x then 2x to get 12: good enough for 4k.
 // Or should we do 5x then 2x to get 10 and have a little margin for 4k?
 // For a 720p window, we want an original pixels to be 720/200 = 3.6high. Divided by scale that is 0.3.
-const SCALE: usize = 12; // Must be A*B.
-const SCALE_A: usize = 6;
-const SCALE_B: usize = 2;
+// The scale comes from the settings, and is set once at startup before anything gets scaled.
+static SCALE: AtomicUsize = AtomicUsize::new(settings::DEFAULT_SCALE);
 
-const RES_W: usize = 1280;
+fn scale() -> usize {
+    SCALE.load(Ordering::Relaxed)
+}
+
+fn set_scale(scale: usize) {
+    SCALE.store(scale, Ordering::Relaxed);
+}
+
+const RES_W: usize = 1280; // The window's starting size. It can be resized or made fullscreen, see screen.rs.
 const RES_H: usize = 720;
-const ORIGINAL_GAME_W: usize = 320;
-const ORIGINAL_GAME_H: usize = 200;
 // I'm declaring an 'original game pixel' to be called a 'point'.
-const POINT_SIZE: f32 = (RES_H as f32) / (ORIGINAL_GAME_H as f32); // How many bevy transform values to get one 'point' (pixel) in the original game.
-const TEXTURE_SCALE: f32 = POINT_SIZE / (SCALE as f32);
+// How many bevy transform values to get one 'point' (pixel) in the original game. This is fixed whatever the window size,
+// as the camera is what fits the original screen to the window.
+const POINT_SIZE: f32 = (RES_H as f32) / (ORIGINAL_GAME_H as f32);
+
+// What to scale a sprite by to show an upscaled texture at the right size.
+fn texture_scale() -> f32 {
+    POINT_SIZE / (scale() as f32)
+}
 
 #[derive(States, PartialEq, Eq, Debug, Clone, Hash, Default, Copy)]
 pub enum GameState {
-    // #[default]
+    #[default]
+    Loading, // Upscaling the textures.
     MainMenu,
     LevelSelectionMenu,
     LevelPreview,
-    #[default]
     InGame,
+    Postview, // Results after a level.
+    LevelCodeMenu,
+    Congratulations, // After the last level of a rating.
+    SettingsMenu,
 }
 
 #[derive(Component, Deref, DerefMut)]
@@ -80,7 +115,48 @@ fn startup(
 }
 
 fn main() {
-    // TODO multithread this! https://doc.rust-lang.org/book/ch16-02-message-passing.html
+    // Command line modes, eg 'rusty-lemmings verify solution.txt'.
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() >= 3 && args[1] == "verify" {
+        std::process::exit(verify::run(&args[2]));
+    }
+    if args.len() >= 2 && args[1] == "replay-frames" {
+        std::process::exit(replay_frames::run(&args[2..]));
+    }
+    if args.len() >= 2 && args[1] == "png-check" {
+        std::process::exit(png_check::run_check());
+    }
+    if args.len() >= 3 && args[1] == "extract" {
+        std::process::exit(extract::run(&args[2]));
+    }
+    if args.len() >= 4 && args[1] == "level-to-text" {
+        std::process::exit(level_files::run_level_to_text(&args[2], &args[3]));
+    }
+    if args.len() >= 4 && args[1] == "text-to-level" {
+        std::process::exit(level_files::run_text_to_level(&args[2], &args[3]));
+    }
+    if args.len() >= 3 && args[1] == "export-levels" {
+        std::process::exit(level_files::run_export(&args[2]));
+    }
+    if args.len() >= 3 && args[1] == "export-tmx" {
+        std::process::exit(level_files::run_export_tmx(&args[2]));
+    }
+    if args.len() >= 4 && args[1] == "tmx-to-level" {
+        std::process::exit(level_files::run_tmx_to_level(&args[2], &args[3]));
+    }
+    if args.len() >= 2 && args[1] == "info" {
+        std::process::exit(info::run(&args[2..]));
+    }
+    if args.len() >= 2 && args[1] == "sprite-sheets" {
+        std::process::exit(sprite_sheets::run(&args[2..]));
+    }
+    if args.len() >= 2 && args[1] == "render-levels" {
+        std::process::exit(render_levels::run(&args[2..]));
+    }
+
+    let settings = settings::Settings::load();
+    set_scale(settings.scale);
+    let window_mode = screen::window_mode(&settings);
     let games = loader::load().unwrap();
     let game = games.lemmings.unwrap();
 
@@ -89,26 +165,31 @@ fn main() {
     App::new()
         .add_state::<GameState>()
         .insert_resource(game)
+        .insert_resource(settings)
+        .insert_resource(progress::Progress::load())
         .insert_resource(ClearColor(Color::BLACK))
-        .add_plugins(DefaultPlugins)
-        // TODO think about how to configure the window in bevy 0.10
-        // .add_plugins(DefaultPlugins.set(WindowPlugin {
-        //     window: WindowDescriptor {
-        //         title: "Rusty Lemmings".to_string(),
-        //         width: RES_W as f32,
-        //         height: RES_H as f32,
-        //         //resizable: false,
-        //         present_mode: PresentMode::Fifo, // Battery-friendly vsync.
-        //         ..Default::default()
-        //     },
-        //     ..default()
-        // }))
+        .add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: "Rusty Lemmings".to_string(),
+                resolution: (RES_W as f32, RES_H as f32).into(),
+                mode: window_mode,
+                present_mode: PresentMode::Fifo, // Battery-friendly vsync.
+                ..default()
+            }),
+            ..default()
+        }))
+        .add_plugin(screen::ScreenPlugin)
         .add_plugin(lemmings_to_bevy::load_lemmings_textures::LoadLemmingsTexturesPlugin)
         .add_plugin(fadeout::FadeoutPlugin)
+        .add_plugin(loading::LoadingPlugin)
         .add_plugin(main_menu::MainMenuPlugin)
         .add_plugin(level_selection_menu::LevelSelectionMenuPlugin)
         .add_plugin(level_preview::LevelPreviewPlugin)
         .add_plugin(ingame::InGamePlugin)
+        .add_plugin(postview::PostviewPlugin)
+        .add_plugin(level_code_menu::LevelCodeMenuPlugin)
+        .add_plugin(congratulations::CongratulationsPlugin)
+        .add_plugin(settings_menu::SettingsMenuPlugin)
         .add_plugin(mouse_cursor::MouseCursorPlugin)
         .add_startup_system(startup)
         .add_system(animate_sprite)
@@ -3,9 +3,15 @@ use bevy::app::AppExit;
 use crate::GameTextures;
 use crate::GameState;
 use crate::level_selection_menu::MainMenuSkillSelection;
-use crate::{POINT_SIZE, TEXTURE_SCALE, FRAME_DURATION};
-use crate::menu_common::{spawn_menu_background};
+use crate::{POINT_SIZE, texture_scale, FRAME_DURATION};
+use crate::menu_common::{spawn_menu_background, spawn_text, text_size};
 use crate::fadeout::*;
+use crate::screen::Cursor;
+use crate::lemmings::models::Game;
+use crate::lemmings::password;
+
+const SIGN_WIDTH: f32 = 120.; // The signs are SVGA pixels, so half this in points.
+const SIGN_HEIGHT: f32 = 61.;
 
 #[derive(Component)]
 struct MainMenuComponent; // Marker component so the menu can be despawned.
@@ -56,11 +62,13 @@ impl Plugin for MainMenuPlugin {
         app.add_systems((
             spawn_menu_logo,
             spawn_menu_buttons,
+            spawn_text_buttons,
             spawn_background,
         ).in_schedule(OnEnter(GameState::MainMenu)));
         app.add_systems((
             button_system.run_if(screen_fade_is_not_transitioning),
             button_highlight_system.run_if(screen_fade_is_not_transitioning),
+            text_button_system.run_if(screen_fade_is_not_transitioning),
             animate_blinking_sprites,
         ).in_set(OnUpdate(GameState::MainMenu)));
         app.add_systems((
@@ -75,32 +83,48 @@ pub enum MainMenuButtonAction {
     Exit,
 }
 
+// What the words-only buttons do.
+pub enum MainMenuTextButtonAction {
+    EnterCode,
+}
+
 #[derive(Component)]
 pub struct MainMenuButton{
     pub action: MainMenuButtonAction,
 }
 
+impl MainMenuButton {
+    fn is_over(transform: &Transform, position: Vec2) -> bool {
+        let half_width = SIGN_WIDTH / 2. * POINT_SIZE / 2.;
+        let half_height = SIGN_HEIGHT / 2. * POINT_SIZE / 2.;
+        transform.translation.x - half_width <= position.x && position.x <= transform.translation.x + half_width &&
+            transform.translation.y - half_height <= position.y && position.y <= transform.translation.y + half_height
+    }
+}
+
+// A button that's just words in the menu font, rather than one of the signs.
+#[derive(Component)]
+pub struct MainMenuTextButton{
+    pub action: MainMenuTextButtonAction,
+    pub half_width: f32, // In bevy transform coords.
+    pub half_height: f32,
+}
+
 fn button_highlight_system(
-    windows: Query<&Window>,
+    cursor: Cursor,
     mouse_buttons: Res<Input<MouseButton>>,
     mut buttons: Query<(&mut Sprite, &Transform), With<MainMenuButton>>,
 ) {
-    if let Some(window) = windows.iter().next() {
-        let position = window.cursor_position().unwrap_or(Vec2::NEG_ONE);
-        let x = position.x - window.width() / 2.;
-        let y = position.y - window.height() / 2.;
-        for (mut sprite, transform) in &mut buttons {
-            let is_over = 
-                transform.translation.x - 120. <= x && x <= transform.translation.x + 120. &&
-                transform.translation.y - 61. <= y && y <= transform.translation.y + 61.;
-            let a: f32 = if is_over { if mouse_buttons.pressed(MouseButton::Left) { 0.5 } else { 0.8 } } else { 1. };
-            sprite.color.set_a(a);
-        }
+    let position = cursor.position();
+    for (mut sprite, transform) in &mut buttons {
+        let is_over = position.is_some_and(|p| MainMenuButton::is_over(transform, p));
+        let a: f32 = if is_over { if mouse_buttons.pressed(MouseButton::Left) { 0.5 } else { 0.8 } } else { 1. };
+        sprite.color.set_a(a);
     }
 }
 
 fn button_system(
-    windows: Query<&Window>,
+    cursor: Cursor,
     mouse_buttons: Res<Input<MouseButton>>,
     buttons: Query<(&Transform, &MainMenuButton)>,
     game_textures: Res<GameTextures>,
@@ -110,31 +134,54 @@ fn button_system(
     mut exit: EventWriter<AppExit>,
 ) {
     if mouse_buttons.just_released(MouseButton::Left) {
-        if let Some(window) = windows.iter().next() {
-            if let Some(position) = window.cursor_position() {
-                let x = position.x - window.width() / 2.;
-                let y = position.y - window.height() / 2.;
-                let button_o = buttons.iter().find(|&b| {
-                    b.0.translation.x - 120. <= x && x <= b.0.translation.x + 120. &&
-                    b.0.translation.y - 61. <= y && y <= b.0.translation.y + 61.
-                });
-                if let Some(button) = button_o {
-                    let mmb: &MainMenuButton = button.1;
-                    match mmb.action {
-                        MainMenuButtonAction::Skill(skill_level) => {
-                            skill.0 = skill_level;
-                            create_fadeout(&mut commands, GameState::LevelSelectionMenu, &game_textures, is_transitioning);
-                        },
-                        MainMenuButtonAction::Settings => {
-                            println!("Settings TODO");
-                        },
-                        MainMenuButtonAction::Exit => {
-                            exit.send(AppExit);
-                        },
-                    }
+        if let Some(position) = cursor.position() {
+            let button_o = buttons.iter().find(|&b| MainMenuButton::is_over(b.0, position));
+            if let Some(button) = button_o {
+                let mmb: &MainMenuButton = button.1;
+                match mmb.action {
+                    MainMenuButtonAction::Skill(skill_level) => {
+                        skill.0 = skill_level;
+                        create_fadeout(&mut commands, GameState::LevelSelectionMenu, &game_textures, is_transitioning);
+                    },
+                    MainMenuButtonAction::Settings => {
+                        create_fadeout(&mut commands, GameState::SettingsMenu, &game_textures, is_transitioning);
+                    },
+                    MainMenuButtonAction::Exit => {
+                        exit.send(AppExit);
+                    },
                 }
             }
-        }    
+        }
+    }
+}
+
+fn text_button_system(
+    cursor: Cursor,
+    mouse_buttons: Res<Input<MouseButton>>,
+    buttons: Query<(&Transform, &MainMenuTextButton, &Children)>,
+    mut letters: Query<&mut TextureAtlasSprite>,
+    game_textures: Res<GameTextures>,
+    is_transitioning: ResMut<ScreenFadeIsTransitioning>,
+    mut commands: Commands,
+) {
+    let Vec2 { x, y } = cursor.position().unwrap_or(Vec2::splat(f32::MAX));
+    let mut clicked: Option<&MainMenuTextButtonAction> = None;
+    for (transform, button, children) in buttons.iter() {
+        let is_over =
+            transform.translation.x - button.half_width <= x && x <= transform.translation.x + button.half_width &&
+            transform.translation.y - button.half_height <= y && y <= transform.translation.y + button.half_height;
+        let a: f32 = if is_over { if mouse_buttons.pressed(MouseButton::Left) { 0.5 } else { 0.8 } } else { 1. };
+        for &child in children {
+            if let Ok(mut letter) = letters.get_mut(child) {
+                letter.color.set_a(a);
+            }
+        }
+        if is_over && mouse_buttons.just_released(MouseButton::Left) {
+            clicked = Some(&button.action);
+        }
+    }
+    if let Some(MainMenuTextButtonAction::EnterCode) = clicked {
+        create_fadeout(&mut commands, GameState::LevelCodeMenu, &game_textures, is_transitioning);
     }
 }
 
@@ -159,16 +206,19 @@ fn spawn_background(
         });
 }
 
+fn logo_scale() -> f32 {
+    texture_scale() / 2. // Logo is SVGA so halve it.
+}
+
 fn spawn_menu_logo(
     mut commands: Commands,
     game_textures: Res<GameTextures>,
 ) {
-    const SCALE: f32 = TEXTURE_SCALE / 2.; // Logo is SVGA so halve it.
     fn spawn_blink(parent: &mut ChildBuilder, atlas: Handle<TextureAtlas>, x: f32, y: f32, index: isize, dwell: isize) {
         parent.spawn(SpriteSheetBundle {
             texture_atlas: atlas,
             transform: Transform{
-                translation: Vec3::new(x * POINT_SIZE / SCALE, y * POINT_SIZE / SCALE, 2.),
+                translation: Vec3::new(x * POINT_SIZE / logo_scale(), y * POINT_SIZE / logo_scale(), 2.),
                 ..default()
             },        
             ..default()
@@ -184,7 +234,7 @@ fn spawn_menu_logo(
             texture: game_textures.logo.clone(),
             transform: Transform{
                 translation: Vec3::new(0., 52. * POINT_SIZE, 1.), // 52 -> make it overlap the background-tile-seam.
-                scale: Vec3::new(SCALE, SCALE, 1.),
+                scale: Vec3::new(logo_scale(), logo_scale(), 1.),
                 ..default()
             },        
             ..default()
@@ -206,7 +256,7 @@ fn spawn_menu_buttons(
             texture: sign,
             transform: Transform{
                 translation: Vec3::new(x * POINT_SIZE, y * POINT_SIZE, 2.),
-                scale: Vec3::new(TEXTURE_SCALE / 2., TEXTURE_SCALE / 2., 1.), // Menu is svga, so halve everything.
+                scale: Vec3::new(texture_scale() / 2., texture_scale() / 2., 1.), // Menu is svga, so halve everything.
                 ..default()
             },
             ..default()
@@ -240,3 +290,27 @@ fn spawn_menu_buttons(
             spawn_button(parent, game_textures.f4_settings.clone(), None, 33., -40., MainMenuButtonAction::Settings);
         });
 }
+
+fn spawn_text_buttons(
+    mut commands: Commands,
+    game_textures: Res<GameTextures>,
+    game: Res<Game>,
+) {
+    if !password::has_codes(&game.id) { return } // Only offer codes for the games whose codes are known.
+    let text = "Enter Code";
+    let size = text_size();
+    commands
+        .spawn(SpatialBundle {
+            transform: Transform::from_xyz(0., -75. * POINT_SIZE, 2.),
+            ..default()
+        })
+        .insert(MainMenuComponent)
+        .insert(MainMenuTextButton{
+            action: MainMenuTextButtonAction::EnterCode,
+            half_width: text.len() as f32 * size / 2.,
+            half_height: size / 2.,
+        })
+        .with_children(|parent| {
+            spawn_text(text, parent, &game_textures);
+        });
+}
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 use crate::GameTextures;
-use crate::{POINT_SIZE, TEXTURE_SCALE};
+use crate::{POINT_SIZE, texture_scale};
 
 pub const NORMAL_BUTTON: Color = Color::NONE;
 pub const HOVERED_BUTTON: Color = Color::rgba(0., 0., 0., 0.5);
@@ -17,21 +17,18 @@ pub fn spawn_menu_background(
             texture: game_textures.background.clone(),
             transform: Transform{
                 translation: Vec3::new(x * POINT_SIZE, y * POINT_SIZE, 0.),
-                scale: Vec3::new(TEXTURE_SCALE, TEXTURE_SCALE, 1.),
+                scale: Vec3::new(texture_scale(), texture_scale(), 1.),
                 ..default()
             },        
             ..default()
         });
     }
-    spawn(parent, &game_textures, BG_WIDTH, BG_HEIGHT);
-    spawn(parent, &game_textures, 0., BG_HEIGHT);
-    spawn(parent, &game_textures, -BG_WIDTH, BG_HEIGHT);
-    spawn(parent, &game_textures, BG_WIDTH, 0.);
-    spawn(parent, &game_textures, 0., 0.);
-    spawn(parent, &game_textures, -BG_WIDTH, 0.);
-    spawn(parent, &game_textures, BG_WIDTH, -BG_HEIGHT);
-    spawn(parent, &game_textures, 0., -BG_HEIGHT);
-    spawn(parent, &game_textures, -BG_WIDTH, -BG_HEIGHT);    
+    // Enough to fill the window when it's a different shape to the original screen.
+    for row in -2..=2 {
+        for column in -2..=2 {
+            spawn(parent, game_textures, column as f32 * BG_WIDTH, row as f32 * BG_HEIGHT);
+        }
+    }
 }
 
 pub fn text_size() -> f32 {
@@ -39,7 +36,7 @@ pub fn text_size() -> f32 {
 }
 
 pub fn spawn_text(text: &str, parent: &mut ChildBuilder, game_textures: &Res<GameTextures>) {
-	let texture_scale = TEXTURE_SCALE / 2.; // Logo is SVGA so halve it.
+	let texture_scale = texture_scale() / 2.; // Logo is SVGA so halve it.
 	let size = text_size();
 	let scale = Vec3::new(texture_scale, texture_scale, 1.);
 	let mut x: f32 = -((text.len() as f32) - 1.) / 2. * size;
@@ -1,7 +1,8 @@
 use bevy::prelude::*;
 use bevy::input::mouse::MouseMotion;
-use crate::TEXTURE_SCALE;
+use crate::texture_scale;
 use crate::GameTextures;
+use crate::screen::Cursor;
 
 const MOUSE_Z: f32 = 999.; // On top of all.
 
@@ -33,7 +34,7 @@ fn spawn_mouse_cursor(
         texture: game_textures.mouse_cursor.clone(),
         transform: Transform{
             translation: Vec3::new(99999., 99999., MOUSE_Z),
-            scale: Vec3::new(TEXTURE_SCALE, TEXTURE_SCALE, 1.),
+            scale: Vec3::new(texture_scale(), texture_scale(), 1.),
             ..default()
         },        
         ..default()
@@ -77,20 +78,17 @@ pub fn reset_mouse_cursor_system(
 }
 
 fn mouse_motion_system(
-    windows: Query<&Window>,
+    cursor: Cursor,
     mut mouse_motion_events: EventReader<MouseMotion>,
     mut mouse_cursor_component_query: Query<&mut Transform, With<MouseCursorComponent>>,
 ) {
     // Is there some motion? Otherwise dont bother with the remainder, as an optimisation.
     let Some(_) = mouse_motion_events.iter().next() else { return };
 
-    // Find the window.
-    let Some(window) = windows.iter().next() else { return };
-
     for mut transform in &mut mouse_cursor_component_query {
-        if let Some(position) = window.cursor_position() {            
+        if let Some(position) = cursor.position() {
             // Move and show it.
-            transform.translation = Vec3::new(position.x - window.width() / 2., position.y - window.height() / 2., MOUSE_Z);
+            transform.translation = position.extend(MOUSE_Z);
         } else {
             // Off-window, so hide it.
             transform.translation = Vec3::new(99999., 99999., MOUSE_Z);
@@ -0,0 +1,46 @@
+use crate::lemmings::png::png_data;
+use crate::lemmings::png_reader::read_png;
+use crate::lemmings::loader::{all_bitmaps, load_lemmings, Bitmap};
+
+// This is the 'png-check' command, for working on png.rs, deflate.rs and the reader. It writes every sprite and level in
+// the game data, reads them back, and reports any that don't come out the same, and how big the files are. The same
+// round trip over made-up images is png_reader.rs's tests, which don't need the game data.
+// Exits with 0 if all went well, 1 if any differ, 2 if the game data couldn't be loaded.
+
+// How many differ, and the total size of the PNGs.
+fn round_trip(bitmaps: &[Bitmap]) -> (usize, usize) {
+    let mut differences = 0;
+    let mut bytes = 0;
+    for bitmap in bitmaps {
+        let data = png_data(bitmap.width as u32, bitmap.height as u32, &bitmap.pixels);
+        bytes += data.len();
+        match read_png(&data) {
+            Ok(image) if image.bitmap == bitmap.pixels && image.width == bitmap.width && image.height == bitmap.height => {},
+            Ok(_) => {
+                println!("{} differs", bitmap.name);
+                differences += 1;
+            },
+            Err(e) => {
+                println!("{} couldn't be read back: {}", bitmap.name, e);
+                differences += 1;
+            },
+        }
+    }
+    (differences, bytes)
+}
+
+pub fn run_check() -> i32 {
+    let Some(game) = load_lemmings() else { return 2 };
+    let bitmaps = match all_bitmaps(&game) {
+        Ok(bitmaps) => bitmaps,
+        Err(e) => {
+            println!("Couldn't load the game data: {}", e);
+            return 2
+        },
+    };
+    let (differences, bytes) = round_trip(&bitmaps);
+    let raw_bytes: usize = bitmaps.iter().map(|b| b.pixels.len() * 4).sum();
+    println!("Checked {} images from the game data: {} differ", bitmaps.len(), differences);
+    println!("As PNGs they take {} bytes, from {} raw", bytes, raw_bytes);
+    if differences == 0 { 0 } else { 1 }
+}
@@ -0,0 +1,193 @@
+use bevy::prelude::*;
+use crate::fadeout::*;
+use crate::{GameTextures, GameState};
+use crate::menu_common::{spawn_menu_background, text_size, spawn_text};
+use crate::level_preview::LevelSelectionResource;
+use crate::level_selection_menu::MainMenuSkillSelection;
+use crate::congratulations::CompletedRatingResource;
+use crate::lemmings::campaign::{AfterLevel, Campaign};
+use crate::lemmings::models::Game;
+use crate::lemmings::password;
+use crate::progress::Progress;
+
+// This is the screen after a level which tells you how you went.
+
+pub struct PostviewPlugin;
+
+impl Plugin for PostviewPlugin {
+	fn build(&self, app: &mut App) {
+		app.insert_resource(LevelResultResource::default());
+		app.add_systems((
+            spawn_background,
+            record_progress,
+            spawn_results,
+        ).in_schedule(OnEnter(GameState::Postview)));
+        app.add_systems((
+            button_system.run_if(screen_fade_is_not_transitioning),
+        ).in_set(OnUpdate(GameState::Postview)));
+        app.add_systems((
+            exit,
+        ).in_schedule(OnExit(GameState::Postview)));
+	}
+}
+
+#[derive(Component)]
+struct PostviewComponent;
+
+/// Filled in by the game when a level finishes.
+#[derive(Resource, Default)]
+pub struct LevelResultResource {
+	pub saved: usize,
+	pub total: usize,
+	pub to_rescue: usize,
+	pub skills_used: usize,
+	pub seconds: usize,
+}
+
+impl LevelResultResource {
+	pub fn percent_saved(&self) -> usize {
+		(self.saved * 100).checked_div(self.total).unwrap_or(0)
+	}
+
+	pub fn percent_needed(&self) -> usize {
+		(self.to_rescue * 100).checked_div(self.total).unwrap_or(0)
+	}
+
+	pub fn is_success(&self) -> bool {
+		self.saved >= self.to_rescue
+	}
+}
+
+// Where the campaign goes after the level just played, if it was won.
+fn after_level(game: &Game, level_selection: &LevelSelectionResource, result: &LevelResultResource) -> Option<AfterLevel> {
+	if !result.is_success() { return None }
+	let campaign = Campaign::for_game(game);
+	let position = campaign.position_of(level_selection.skill as usize, level_selection.level_key)?;
+	Some(campaign.after(position))
+}
+
+// The level code for the level after the one just played, if there is one.
+fn next_level_code(game: &Game, after: &AfterLevel) -> Option<(usize, String)> {
+	let campaign = Campaign::for_game(game);
+	let next = match after {
+		AfterLevel::NextLevel(position) => *position,
+		AfterLevel::RatingComplete { next_rating: Some(rating) } => campaign.first_in_rating(*rating)?,
+		AfterLevel::RatingComplete { next_rating: None } => return None,
+	};
+	let code = password::encode(&game.id, password::LevelCode { level: campaign.level_number(next)? })?;
+	Some((next.index, code))
+}
+
+fn record_progress(
+	level_selection: Res<LevelSelectionResource>,
+	result: Res<LevelResultResource>,
+	game: Res<Game>,
+	mut progress: ResMut<Progress>,
+) {
+	progress.add_attempt(&game.id, level_selection.level_key, result.is_success(),
+		result.percent_saved(), result.skills_used, result.seconds);
+	if let Err(e) = progress.save() {
+		println!("Couldn't save progress: {}", e);
+	}
+}
+
+fn exit(
+    mut commands: Commands,
+    components: Query<Entity, With<PostviewComponent>>,
+) {
+    for e in components.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+// Winning moves on to the next level, or the congratulations screen at the end of a rating.
+// Losing goes back to the preview to try again.
+fn button_system(
+	mut fadeout: Fadeout,
+    mouse_buttons: Res<Input<MouseButton>>,
+	result: Res<LevelResultResource>,
+	game: Res<Game>,
+	mut level_selection: ResMut<LevelSelectionResource>,
+	mut skill_selection: ResMut<MainMenuSkillSelection>,
+	mut completed_rating: ResMut<CompletedRatingResource>,
+) {
+    if !mouse_buttons.just_released(MouseButton::Left) { return }
+	let next_state = match after_level(&game, &level_selection, &result) {
+		Some(AfterLevel::NextLevel(position)) => {
+			let campaign = Campaign::for_game(&game);
+			if let Some(level) = campaign.level(position) {
+				level_selection.level_key = level.key;
+				level_selection.level_name = level.name.clone();
+			}
+			GameState::LevelPreview
+		},
+		Some(AfterLevel::RatingComplete { next_rating }) => {
+			*completed_rating = CompletedRatingResource {
+				rating: level_selection.skill as usize,
+				next_rating,
+			};
+			GameState::Congratulations
+		},
+		None if result.is_success() => GameState::LevelSelectionMenu, // Not a campaign level, eg a renamed duplicate.
+		None => GameState::LevelPreview,
+	};
+	skill_selection.0 = level_selection.skill;
+	fadeout.start(next_state);
+}
+
+fn spawn_background(
+	mut commands: Commands,
+	game_textures: Res<GameTextures>,
+) {
+	commands
+		.spawn(SpatialBundle::default())
+		.insert(PostviewComponent)
+		.with_children(|parent| {
+			spawn_menu_background(parent, &game_textures);
+		});
+}
+
+fn spawn_results(
+	mut commands: Commands,
+	game_textures: Res<GameTextures>,
+	level_selection: Res<LevelSelectionResource>,
+	result: Res<LevelResultResource>,
+	game: Res<Game>,
+) {
+	let mut text: Vec<String> = vec![
+		if result.saved == result.total { "Superb! You rescued every lemming.".to_string() } else { "All lemmings accounted for.".to_string() },
+		"".to_string(),
+		format!("You rescued {}%", result.percent_saved()),
+		format!("You needed {}%", result.percent_needed()),
+		"".to_string(),
+	];
+	if result.is_success() {
+		let after = after_level(&game, &level_selection, &result);
+		if let Some((next_index, code)) = after.and_then(|a| next_level_code(&game, &a)) {
+			text.push(format!("Your Access Code for Level {}", next_index + 1));
+			text.push(format!("is {}", code));
+			text.push("".to_string());
+		}
+	} else {
+		text.push("You'll have to try again.".to_string());
+		text.push("".to_string());
+	}
+	text.push("Press mouse button to continue".to_string());
+
+	let size = text_size();
+	let gap = (size / 2.).round();
+	let all_height = (size + gap) * ((text.len() - 1) as f32); // From center of topmost to center of bottom-most.
+	commands
+		.spawn(SpatialBundle::default())
+		.insert(PostviewComponent)
+		.with_children(|parent| {
+			for (i, t) in text.iter().enumerate() {
+				parent.spawn(SpatialBundle{
+					transform: Transform::from_xyz(0., all_height / 2. - ((i as f32) * (size + gap)), 2.),
+					..default()
+				}).with_children(|parent| {
+					spawn_text(t, parent, &game_textures);
+				});
+			}
+		});
+}
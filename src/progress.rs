@@ -0,0 +1,197 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Result;
+use std::path::PathBuf;
+use crate::lemmings::campaign::{Campaign, CampaignPosition};
+
+// This is the player's progress, saved between runs in their config directory.
+// The file is plain text so it can be poked at by hand:
+// * 'lock_levels=true' makes each rating's levels stay locked until the one before is beaten, like the original.
+// * Every other line is one level: game id, level key, completed (0/1), best % saved, fewest skills, fastest seconds.
+//   Tab separated. Skills and seconds are '-' until the level is completed. The key is the level's key in the game's
+//   index, see models.rs, rather than its name, as some names are used by more than one level.
+
+const FILE_NAME: &str = "progress.txt";
+const LOCK_LEVELS_SETTING: &str = "lock_levels=";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LevelRecord {
+    pub completed: bool,
+    pub best_percent_saved: usize,
+    pub fewest_skills_used: Option<usize>, // Only counts attempts that completed the level.
+    pub fastest_seconds: Option<usize>, // Ditto.
+}
+
+#[derive(Resource, Default)]
+pub struct Progress {
+    pub lock_levels: bool,
+    records: HashMap<(String, i32), LevelRecord>, // Keyed by game id and level key.
+}
+
+impl Progress {
+    pub fn record(&self, game_id: &str, level_key: i32) -> LevelRecord {
+        self.records.get(&(game_id.to_string(), level_key)).copied().unwrap_or_default()
+    }
+
+    // Merges an attempt into the records, keeping the best of each.
+    pub fn add_attempt(&mut self, game_id: &str, level_key: i32, completed: bool, percent_saved: usize, skills_used: usize, seconds: usize) {
+        let record = self.records.entry((game_id.to_string(), level_key)).or_default();
+        record.best_percent_saved = record.best_percent_saved.max(percent_saved);
+        if completed {
+            record.completed = true;
+            record.fewest_skills_used = Some(record.fewest_skills_used.map_or(skills_used, |s| s.min(skills_used)));
+            record.fastest_seconds = Some(record.fastest_seconds.map_or(seconds, |s| s.min(seconds)));
+        }
+    }
+
+    // The first level of each rating is always open, the rest need the one before to be completed if locking is on.
+    pub fn is_unlocked(&self, game_id: &str, campaign: &Campaign, position: CampaignPosition) -> bool {
+        if !self.lock_levels || position.index == 0 { return true }
+        let Some(before) = campaign.level(CampaignPosition { index: position.index - 1, ..position }) else { return true };
+        self.record(game_id, before.key).completed
+    }
+
+    pub fn parse(text: &str) -> Progress {
+        let mut progress = Progress::default();
+        for line in text.lines() {
+            if let Some(value) = line.strip_prefix(LOCK_LEVELS_SETTING) {
+                progress.lock_levels = value.trim() == "true";
+                continue;
+            }
+            // Skip anything that doesn't look right rather than losing the whole file.
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 6 { continue }
+            let Ok(level_key) = fields[1].parse::<i32>() else { continue }; // Eg a name, from before levels had keys.
+            let Ok(best_percent_saved) = fields[3].parse::<usize>() else { continue };
+            let record = LevelRecord {
+                completed: fields[2] == "1",
+                best_percent_saved,
+                fewest_skills_used: fields[4].parse::<usize>().ok(),
+                fastest_seconds: fields[5].parse::<usize>().ok(),
+            };
+            progress.records.insert((fields[0].to_string(), level_key), record);
+        }
+        progress
+    }
+
+    pub fn to_text(&self) -> String {
+        fn optional(value: Option<usize>) -> String {
+            value.map_or("-".to_string(), |v| v.to_string())
+        }
+        let mut keys: Vec<&(String, i32)> = self.records.keys().collect();
+        keys.sort(); // Keeps the file stable between saves.
+        let mut text = format!("{}{}\n", LOCK_LEVELS_SETTING, self.lock_levels);
+        for key in keys {
+            let record = &self.records[key];
+            text.push_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\n",
+                key.0,
+                key.1,
+                if record.completed { 1 } else { 0 },
+                record.best_percent_saved,
+                optional(record.fewest_skills_used),
+                optional(record.fastest_seconds)));
+        }
+        text
+    }
+
+    // A missing or unreadable file just means no progress yet.
+    pub fn load() -> Progress {
+        let Some(path) = file_path() else { return Progress::default() };
+        let Ok(text) = fs::read_to_string(path) else { return Progress::default() };
+        Progress::parse(&text)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = file_path() else { return Ok(()) };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write then rename so a crash mid-save can't leave a half-written file.
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, self.to_text())?;
+        fs::rename(&temp_path, &path)
+    }
+}
+
+// Eg ~/.config/rusty-lemmings, or under %APPDATA% on windows.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if let Ok(xdg) = env::var("XDG_CONFIG_HOME") {
+        PathBuf::from(xdg)
+    } else if let Ok(app_data) = env::var("APPDATA") {
+        PathBuf::from(app_data)
+    } else if let Ok(home) = env::var("HOME") {
+        PathBuf::from(home).join(".config")
+    } else {
+        return None
+    };
+    Some(base.join("rusty-lemmings"))
+}
+
+fn file_path() -> Option<PathBuf> {
+    Some(config_dir()?.join(FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lemmings::campaign::CampaignLevel;
+    use crate::lemmings::models::ODD_TABLE_LEVEL_KEY;
+
+    const ODD_KEY: i32 = ODD_TABLE_LEVEL_KEY + 1;
+
+    // Two ratings, the first ending with a renamed re-use from the odd table.
+    fn campaign() -> Campaign {
+        let level = |key: i32| CampaignLevel { key, name: format!("Level {}", key) };
+        Campaign::new(vec![vec![level(0), level(1), level(ODD_KEY)], vec![level(2)]], 3)
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let mut progress = Progress { lock_levels: true, ..Default::default() };
+        progress.add_attempt("lemmings", 1, true, 100, 3, 45);
+        progress.add_attempt("lemmings", ODD_KEY, false, 20, 1, 12);
+        progress.add_attempt("ohnomore", 1, true, 50, 0, 100);
+        let text = progress.to_text();
+        assert_eq!(text, "lock_levels=true\nlemmings\t1\t1\t100\t3\t45\nlemmings\t100001\t0\t20\t-\t-\nohnomore\t1\t1\t50\t0\t100\n");
+        let parsed = Progress::parse(&text);
+        assert!(parsed.lock_levels);
+        assert_eq!(parsed.to_text(), text);
+        assert_eq!(parsed.record("lemmings", ODD_KEY), LevelRecord { completed: false, best_percent_saved: 20, fewest_skills_used: None, fastest_seconds: None });
+    }
+
+    #[test]
+    fn keeps_the_best_of_each() {
+        let mut progress = Progress::default();
+        progress.add_attempt("lemmings", 0, true, 80, 5, 60);
+        progress.add_attempt("lemmings", 0, true, 60, 2, 90);
+        progress.add_attempt("lemmings", 0, false, 90, 0, 10);
+        assert_eq!(progress.record("lemmings", 0), LevelRecord { completed: true, best_percent_saved: 90, fewest_skills_used: Some(2), fastest_seconds: Some(60) });
+    }
+
+    #[test]
+    fn skips_lines_it_cant_read() {
+        let progress = Progress::parse("lemmings\tJust dig!\t1\t100\t1\t30\nlemmings\t0\t1\tlots\t1\t30\nnonsense\nlemmings\t2\t1\t100\t1\t30\n");
+        assert_eq!(progress.to_text(), "lock_levels=false\nlemmings\t2\t1\t100\t1\t30\n");
+    }
+
+    #[test]
+    fn each_level_unlocks_when_the_one_before_it_in_the_campaign_is_completed() {
+        let campaign = campaign();
+        let mut progress = Progress { lock_levels: true, ..Default::default() };
+        let at = |rating, index| CampaignPosition { rating, index };
+        assert!(progress.is_unlocked("lemmings", &campaign, at(0, 0)));
+        assert!(progress.is_unlocked("lemmings", &campaign, at(1, 0)));
+        assert!(!progress.is_unlocked("lemmings", &campaign, at(0, 1)));
+        progress.add_attempt("lemmings", 0, true, 100, 1, 10);
+        assert!(progress.is_unlocked("lemmings", &campaign, at(0, 1)));
+        assert!(!progress.is_unlocked("lemmings", &campaign, at(0, 2)));
+        progress.add_attempt("ohnomore", 1, true, 100, 1, 10); // The same key in another game doesn't count.
+        assert!(!progress.is_unlocked("lemmings", &campaign, at(0, 2)));
+        progress.add_attempt("lemmings", 1, true, 100, 1, 10);
+        assert!(progress.is_unlocked("lemmings", &campaign, at(0, 2)));
+        assert!(!Progress { lock_levels: true, ..Default::default() }.is_unlocked("lemmings", &campaign, at(0, 2)));
+        assert!(Progress::default().is_unlocked("lemmings", &campaign, at(0, 2)));
+    }
+}
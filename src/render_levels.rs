@@ -0,0 +1,172 @@
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use std::sync::Arc;
+use crate::FPS;
+use crate::lemmings::level_overlay::{self, Overlays};
+use crate::lemmings::level_renderer;
+use crate::lemmings::levels_per_game_and_skill::{levels_per_game_and_skill, rating_name, ratings_per_game};
+use crate::lemmings::loader;
+use crate::lemmings::loader::parallel_map;
+use crate::lemmings::models::{Game, Image, Level};
+use crate::lemmings::png::{apng_data, png_data};
+
+// This is the 'render-levels <dir> [options]' command: it writes a PNG of every level, for reviewing them side by side.
+//   --game <id>       Only this game, eg 'lemmings'.
+//   --rating <r>      Only this rating, by name (eg 'Taxing') or number from 1, in the order they're played.
+//   --level <name>    Only the level with this name.
+//   --overlays        All of the below.
+//   --steel, --triggers, --entrances, --start, --header
+//                     Mark steel, objects' trigger areas, entrances and exits, where the screen starts, and put the
+//                     level's numbers above it.
+//   --animated        Write each as an animated PNG with its objects moving through their frames, for a full cycle of
+//                     them, up to 10 seconds. The overlays are drawn on every frame.
+// Exits with 0 if all went well, 1 if the options matched no levels, 2 if the game data or files couldn't be used.
+
+const MAX_ANIMATED_TICKS: usize = 150; // 10 seconds.
+
+#[derive(Default)]
+struct Options {
+    out_dir: String,
+    game_id: Option<String>,
+    rating: Option<String>,
+    level_name: Option<String>,
+    overlays: Overlays,
+    is_animated: bool,
+}
+
+struct Job {
+    file_name: String,
+    game: Game,
+    level: Arc<Level>,
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_options(args) {
+        Ok(options) => options,
+        Err(message) => {
+            println!("{}", message);
+            return 2
+        },
+    };
+    let games = match loader::load() {
+        Ok(games) => games,
+        Err(e) => {
+            println!("Couldn't load the game data: {}", e);
+            return 2
+        },
+    };
+    let games: Vec<&Game> = games.as_vec().into_iter().filter(|g| options.game_id.as_ref().is_none_or(|id| *id == g.id)).collect();
+    if games.is_empty() {
+        println!("Couldn't find the game data");
+        return 2
+    }
+    let mut jobs = Vec::<Job>::new();
+    for game in games {
+        match jobs_for_game(game, &options) {
+            Ok(mut game_jobs) => jobs.append(&mut game_jobs),
+            Err(e) => {
+                println!("Couldn't list {}'s levels: {}", game.id, e);
+                return 2
+            },
+        }
+    }
+    if jobs.is_empty() {
+        println!("No levels matched");
+        return 1
+    }
+    if let Err(e) = fs::create_dir_all(&options.out_dir) {
+        println!("Couldn't create {}: {}", options.out_dir, e);
+        return 2
+    }
+    let dir = Path::new(&options.out_dir);
+    match parallel_map(&jobs, |job| render_job(job, dir, options.overlays, options.is_animated)) {
+        Ok(_) => {
+            println!("Rendered {} levels to {}", jobs.len(), options.out_dir);
+            0
+        },
+        Err(e) => {
+            println!("Couldn't render: {}", e);
+            2
+        },
+    }
+}
+
+fn parse_options(args: &[String]) -> std::result::Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--game" => options.game_id = Some(value()?),
+            "--rating" => options.rating = Some(value()?),
+            "--level" => options.level_name = Some(value()?),
+            "--overlays" => options.overlays = Overlays::all(),
+            "--steel" => options.overlays.steel = true,
+            "--triggers" => options.overlays.triggers = true,
+            "--entrances" => options.overlays.entrances_and_exits = true,
+            "--start" => options.overlays.start = true,
+            "--header" => options.overlays.header = true,
+            "--animated" => options.is_animated = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ if options.out_dir.is_empty() => options.out_dir = arg.clone(),
+            _ => return Err(format!("Unexpected {}", arg)),
+        }
+    }
+    if options.out_dir.is_empty() { return Err("Usage: render-levels <dir> [--game id] [--rating r] [--level name] [--overlays] [--animated]".to_string()) }
+    Ok(options)
+}
+
+// Levels in a rating are named for their place in it, the rest by their key in the index, eg 'lemmings_taxing_07_...'.
+fn jobs_for_game(game: &Game, options: &Options) -> Result<Vec<Job>> {
+    let mut named = Vec::<(String, Arc<Level>)>::new();
+    if let Some(rating) = &options.rating {
+        let Some(rating) = rating_number(&game.id, rating) else { return Ok(Vec::new()) };
+        for (i, level) in levels_per_game_and_skill(game, rating as isize).into_iter().enumerate() {
+            named.push((format!("{}_{}_{:02}", game.id, rating_name(&game.id, rating).to_lowercase(), i + 1), level));
+        }
+    } else {
+        for key in game.index.level_keys() {
+            named.push((format!("{}_{}", game.id, key), game.index.level(key)?));
+        }
+    }
+    Ok(named.into_iter()
+        .filter(|(_, level)| options.level_name.as_ref().is_none_or(|name| level.name.trim().eq_ignore_ascii_case(name.trim())))
+        .map(|(prefix, level)| Job { file_name: format!("{}_{}.png", prefix, slug(&level.name)), game: game.clone(), level })
+        .collect())
+}
+
+// From 0. Either the rating's name, or its number from 1.
+fn rating_number(game_id: &str, rating: &str) -> Option<usize> {
+    let count = ratings_per_game(game_id);
+    if let Ok(number) = rating.parse::<usize>() {
+        return if (1..=count).contains(&number) { Some(number - 1) } else { None }
+    }
+    (0..count).find(|&r| rating_name(game_id, r).eq_ignore_ascii_case(rating))
+}
+
+// Eg 'Just dig!' to 'just_dig'.
+pub fn slug(name: &str) -> String {
+    let lower = name.trim().to_lowercase();
+    let words: Vec<&str> = lower.split(|c: char| !c.is_ascii_alphanumeric()).filter(|w| !w.is_empty()).collect();
+    words.join("_")
+}
+
+fn render_job(job: &Job, dir: &Path, overlays: Overlays, is_animated: bool) -> Result<()> {
+    let ground = job.game.ground_for(&job.level)?;
+    let special = job.game.special_for(&job.level)?;
+    let font = &job.game.main.game_font;
+    let data = if is_animated {
+        let images: Vec<Image> = level_renderer::render_animated(&job.level, &ground, special.as_deref(), MAX_ANIMATED_TICKS).iter()
+            .map(|rendered| level_overlay::draw(&job.level, &ground, rendered, font, overlays))
+            .collect();
+        let (width, height) = (images[0].width as u32, images[0].height as u32); // There's always a tick.
+        let frames: Vec<Vec<u32>> = images.into_iter().map(|image| image.bitmap).collect();
+        apng_data(width, height, &frames, FPS as u16)
+    } else {
+        let rendered = level_renderer::render(&job.level, &ground, special.as_deref(), true);
+        let image = level_overlay::draw(&job.level, &ground, &rendered, font, overlays);
+        png_data(image.width as u32, image.height as u32, &image.bitmap)
+    };
+    fs::write(dir.join(&job.file_name), data)
+}
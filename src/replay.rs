@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use crate::ingame::is_playable_skill_index;
+use crate::progress::config_dir;
+
+// This is a recording of the player's actions in a level, so it can be played back, eg by the 'verify' command.
+// The file is plain text, one thing per line:
+//   game lemmings
+//   level Just dig!
+//   level_key 0              <- The level's key in the game's index, as names aren't unique. Older replays only have the name.
+//   120 skill 0 dig          <- At game frame 120, give lemming #0 (in the order they dropped) the dig skill.
+//   200 release_rate 60      <- At game frame 200, set the release rate to 60.
+
+const LAST_REPLAY_FILE_NAME: &str = "last-replay.txt";
+
+// Indexed like SkillPanelSelection, so the ingame code can map them back. Empty for the non-skill buttons.
+const SKILL_NAMES: [&str; 10] = ["", "", "climb", "umbrella", "explode", "block", "build", "bash", "mine", "dig"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayActionKind {
+    AssignSkill { lemming: usize, skill_index: isize },
+    ReleaseRate(isize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayAction {
+    pub frame: i32, // How many game frames had passed when it happened.
+    pub kind: ReplayActionKind,
+}
+
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Replay {
+    pub game_id: String,
+    pub level_name: String,
+    pub level_key: Option<i32>,
+    pub actions: Vec<ReplayAction>,
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn skill_index_from_name(name: &str) -> Option<isize> {
+    SKILL_NAMES.iter().position(|n| !n.is_empty() && *n == name).map(|i| i as isize)
+}
+
+impl Replay {
+    pub fn parse(text: &str) -> Result<Replay> {
+        let mut replay = Replay::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue }
+            if let Some(game_id) = line.strip_prefix("game ") {
+                replay.game_id = game_id.trim().to_string();
+                continue;
+            }
+            if let Some(level_key) = line.strip_prefix("level_key ") {
+                replay.level_key = Some(level_key.trim().parse().map_err(|_| invalid(&format!("Bad level key: {}", line)))?);
+                continue;
+            }
+            if let Some(level_name) = line.strip_prefix("level ") {
+                replay.level_name = level_name.trim().to_string();
+                continue;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let frame: i32 = parts[0].parse().map_err(|_| invalid(&format!("Bad frame: {}", line)))?;
+            let kind = match parts[1..] {
+                ["skill", lemming, skill] => {
+                    let skill_index = skill_index_from_name(skill).ok_or_else(|| invalid(&format!("Bad skill: {}", line)))?;
+                    if !is_playable_skill_index(skill_index) {
+                        return Err(invalid(&format!("The {} skill can't be played yet: {}", skill, line)))
+                    }
+                    ReplayActionKind::AssignSkill {
+                        lemming: lemming.parse().map_err(|_| invalid(&format!("Bad lemming: {}", line)))?,
+                        skill_index,
+                    }
+                },
+                ["release_rate", rate] => ReplayActionKind::ReleaseRate(
+                    rate.parse().map_err(|_| invalid(&format!("Bad release rate: {}", line)))?),
+                _ => return Err(invalid(&format!("Unknown action: {}", line))),
+            };
+            replay.actions.push(ReplayAction { frame, kind });
+        }
+        if replay.level_name.is_empty() {
+            return Err(invalid("Replay has no level"))
+        }
+        Ok(replay)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("game {}\nlevel {}\n", self.game_id, self.level_name);
+        if let Some(level_key) = self.level_key {
+            text.push_str(&format!("level_key {}\n", level_key));
+        }
+        for action in &self.actions {
+            match &action.kind {
+                ReplayActionKind::AssignSkill { lemming, skill_index } => {
+                    let name = SKILL_NAMES.get(*skill_index as usize).unwrap_or(&"");
+                    text.push_str(&format!("{} skill {} {}\n", action.frame, lemming, name));
+                },
+                ReplayActionKind::ReleaseRate(rate) => {
+                    text.push_str(&format!("{} release_rate {}\n", action.frame, rate));
+                },
+            }
+        }
+        text
+    }
+
+    pub fn load(path: &str) -> Result<Replay> {
+        Replay::parse(&fs::read_to_string(path)?)
+    }
+
+    // Keeps the most recent attempt, so a good one can be copied off to keep as a solution.
+    pub fn save_as_last(&self) -> Result<()> {
+        let Some(dir) = config_dir() else { return Ok(()) };
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(LAST_REPLAY_FILE_NAME), self.to_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_text() {
+        let text = "game lemmings\nlevel Just dig!\nlevel_key 0\n120 skill 0 dig\n200 release_rate 60\n";
+        assert_eq!(Replay::parse(text).unwrap().to_text(), text);
+    }
+
+    #[test]
+    fn a_skill_that_cant_be_played_yet_is_an_error() {
+        let error = Replay::parse("game lemmings\nlevel Just dig!\n120 skill 0 bash\n").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("bash"), "{}", error);
+    }
+}
@@ -0,0 +1,149 @@
+use bevy::prelude::*;
+use std::fs;
+use std::io::Result;
+use std::path::PathBuf;
+use crate::{GameState, FPS};
+use crate::ingame::{InGameCpuFrame, InGameUpdateSet};
+use crate::lemmings::png::{apng_data, png_data};
+use crate::replay::Replay;
+use crate::scaler::ScalerKind;
+use crate::settings::Settings;
+use crate::verify;
+
+// This is the 'replay-frames <replay> <out> [options]' command: it plays a replay the way verify does, without a
+// window or GPU, and draws every game frame on the CPU as the player would see it, for bug reports and visual
+// regression tests. If out ends in .png it's written as one animated PNG, otherwise it's a directory that gets a
+// numbered PNG per frame, eg frame_00001.png.
+// Frames are composed at the original resolution, then upscaled. An animated PNG holds every frame in memory until
+// the end, so for long replays use --every or numbered frames.
+//   --scale <n>       Upscale by this, by default 1.
+//   --scaler <name>   Upscale with this, by default the settings' terrain scaler.
+//   --full-width      The whole level, rather than the screen's worth that the replay has scrolled to.
+//   --every <n>       Only every nth frame, eg 15 for one a second. An animated PNG then plays n times as fast.
+// Exits with 0 if all went well, 2 if the options were wrong, or the replay couldn't be run or the files written.
+
+struct Options {
+    replay_path: String,
+    out_path: String,
+    scale: usize,
+    scaler: ScalerKind,
+    is_full_width: bool,
+    every: usize,
+}
+
+#[derive(Resource)]
+struct FrameWriter {
+    scale: usize,
+    scaler: ScalerKind,
+    every: usize,
+    dir: Option<PathBuf>, // None for an animated PNG.
+    frame_count: usize, // Game frames seen so far.
+    written_count: usize,
+    size: (usize, usize),
+    frames: Vec<Vec<u32>>, // For the animated PNG.
+    error: Option<String>,
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_options(args, &Settings::load()) {
+        Ok(options) => options,
+        Err(message) => {
+            println!("{}", message);
+            return 2
+        },
+    };
+    match run_replay(&options) {
+        Ok(count) => {
+            println!("Wrote {} frames to {}", count, options.out_path);
+            0
+        },
+        Err(e) => {
+            println!("Couldn't write the replay's frames: {}", e);
+            2
+        },
+    }
+}
+
+fn parse_options(args: &[String], settings: &Settings) -> std::result::Result<Options, String> {
+    let mut options = Options { replay_path: String::new(), out_path: String::new(), scale: 1, scaler: settings.terrain_scaler, is_full_width: false, every: 1 };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--scale" => {
+                let value = value()?;
+                options.scale = value.parse().ok().filter(|n| *n >= 1).ok_or(format!("Scale {} isn't a number from 1", value))?;
+            },
+            "--scaler" => {
+                let value = value()?;
+                options.scaler = ScalerKind::from_name(&value).ok_or(format!("Unknown scaler {}", value))?;
+            },
+            "--full-width" => options.is_full_width = true,
+            "--every" => {
+                let value = value()?;
+                options.every = value.parse().ok().filter(|n| *n >= 1).ok_or(format!("Every {} isn't a number from 1", value))?;
+            },
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ if options.replay_path.is_empty() => options.replay_path = arg.clone(),
+            _ if options.out_path.is_empty() => options.out_path = arg.clone(),
+            _ => return Err(format!("Unexpected {}", arg)),
+        }
+    }
+    if options.out_path.is_empty() { return Err("Usage: replay-frames <replay> <out.png or dir> [--scale n] [--scaler name] [--full-width] [--every n]".to_string()) }
+    Ok(options)
+}
+
+fn run_replay(options: &Options) -> Result<usize> {
+    let is_animated = options.out_path.to_lowercase().ends_with(".png");
+    let dir = if is_animated { None } else { Some(PathBuf::from(&options.out_path)) };
+    if let Some(dir) = &dir { fs::create_dir_all(dir)? }
+    let mut app = verify::headless_app(Replay::load(&options.replay_path)?)?;
+    app.insert_resource(InGameCpuFrame { is_full_width: options.is_full_width, image: None })
+        .insert_resource(FrameWriter {
+            scale: options.scale,
+            scaler: options.scaler,
+            every: options.every,
+            dir,
+            frame_count: 0,
+            written_count: 0,
+            size: (0, 0),
+            frames: Vec::new(),
+            error: None,
+        })
+        .add_system(write_frame.after(InGameUpdateSet).in_set(OnUpdate(GameState::InGame)));
+    app.run();
+
+    let writer = app.world.resource::<FrameWriter>();
+    if let Some(error) = &writer.error {
+        return Err(std::io::Error::other(error.clone()))
+    }
+    if is_animated && !writer.frames.is_empty() {
+        let (width, height) = writer.size;
+        fs::write(&options.out_path, apng_data(width as u32, height as u32, &writer.frames, FPS as u16))?;
+    }
+    Ok(writer.written_count)
+}
+
+// Takes each frame as the compositor draws it, scales it, and writes it or keeps it for the animated PNG.
+fn write_frame(
+    mut cpu_frame: ResMut<InGameCpuFrame>,
+    mut writer: ResMut<FrameWriter>,
+) {
+    let Some(image) = cpu_frame.image.take() else { return };
+    if writer.error.is_some() { return }
+    writer.frame_count += 1;
+    if !(writer.frame_count - 1).is_multiple_of(writer.every) { return }
+    let scale = writer.scale;
+    let bitmap = if scale == 1 { image.bitmap } else { writer.scaler.scaler().scale(scale, &image.bitmap, image.width, image.height) };
+    let (width, height) = (image.width * scale, image.height * scale);
+    writer.written_count += 1;
+    if let Some(dir) = &writer.dir {
+        let path = dir.join(format!("frame_{:05}.png", writer.written_count));
+        if let Err(e) = fs::write(&path, png_data(width as u32, height as u32, &bitmap)) {
+            writer.error = Some(format!("{}: {}", path.display(), e));
+        }
+    } else {
+        writer.size = (width, height);
+        writer.frames.push(bitmap);
+    }
+}
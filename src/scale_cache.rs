@@ -0,0 +1,182 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::time::SystemTime;
+use crate::scale;
+use crate::scaler::{ScalerKind, ALL_SCALERS};
+
+// This keeps upscaled images on disk between runs, since scaling everything at startup is slow.
+// Files are named after a hash of everything that affects the output: the source pixels and size, the scale factor,
+// the scaler and its version, and what sort of image it is (eg with the sprite margin trick, or a whole atlas).
+// They live in a folder per scaler and version, so when a scaler's version changes its old folder is deleted.
+// Each file is just the scaled pixels, little-endian u32s. Anything unreadable or the wrong size gets redone.
+// Whole levels get cached too, and at big scales those run to a hundred MB or so each, so each scaler's folder is kept
+// under a budget: reading a file marks it as used by touching its modified time, and after a save the least recently
+// used files are deleted until the folder fits.
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+const BUDGET_BYTES: u64 = 1024 * 1024 * 1024; // Per scaler.
+
+static PRUNE: Once = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScaleKey(u64);
+
+// FNV-1a, rather than std's hasher, because this needs to be the same from one build to the next.
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+}
+
+impl ScaleKey {
+    // 'kind' separates different ways of scaling the same pixels.
+    pub fn new(scaler: ScalerKind, kind: &str, width: usize, height: usize, bitmaps: &[&[u32]]) -> ScaleKey {
+        let mut hash = Fnv(FNV_OFFSET);
+        hash.write(scaler.scaler().name().as_bytes());
+        hash.write_u32(scaler.scaler().version());
+        hash.write_u32(scale() as u32);
+        hash.write(kind.as_bytes());
+        hash.write_u32(width as u32);
+        hash.write_u32(height as u32);
+        hash.write_u32(bitmaps.len() as u32);
+        for bitmap in bitmaps {
+            for pixel in *bitmap {
+                hash.write_u32(*pixel);
+            }
+        }
+        ScaleKey(hash.0)
+    }
+
+    fn file_name(&self) -> String {
+        format!("{:016x}.rgba", self.0)
+    }
+}
+
+fn cache_root() -> Option<PathBuf> {
+    let base = if let Ok(xdg) = env::var("XDG_CACHE_HOME") {
+        PathBuf::from(xdg)
+    } else if let Ok(local_app_data) = env::var("LOCALAPPDATA") {
+        PathBuf::from(local_app_data)
+    } else if let Ok(home) = env::var("HOME") {
+        PathBuf::from(home).join(".cache")
+    } else {
+        return None
+    };
+    Some(base.join("rusty-lemmings").join("scaled"))
+}
+
+fn scaler_dir_name(scaler: ScalerKind) -> String {
+    format!("{}-v{}", scaler.scaler().name(), scaler.scaler().version())
+}
+
+// Deletes folders from old scaler versions, as nothing will ever read them again.
+fn prune_stale(root: &Path) {
+    let Ok(entries) = fs::read_dir(root) else { return };
+    let current: Vec<String> = ALL_SCALERS.iter().map(|s| scaler_dir_name(*s)).collect();
+    for entry in entries.flatten() {
+        if !current.contains(&entry.file_name().to_string_lossy().to_string()) {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+fn cache_dir(scaler: ScalerKind) -> Option<PathBuf> {
+    let root = cache_root()?;
+    PRUNE.call_once(|| prune_stale(&root));
+    Some(root.join(scaler_dir_name(scaler)))
+}
+
+// Deletes the least recently used files until what's left fits the budget.
+fn evict_over_budget(dir: &Path, budget: u64) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries.flatten()
+        .filter_map(|e| {
+            let metadata = e.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), e.path()))
+        })
+        .collect();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    files.sort();
+    for (_, len, path) in files {
+        if total <= budget { break }
+        if fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+}
+
+fn load(scaler: ScalerKind, key: ScaleKey, expected_len: usize) -> Option<Vec<u32>> {
+    let path = cache_dir(scaler)?.join(key.file_name());
+    let bytes = fs::read(&path).ok()?;
+    if bytes.len() != expected_len * 4 { return None }
+    let _ = File::options().write(true).open(&path).and_then(|f| f.set_modified(SystemTime::now())); // Mark it as used.
+    Some(bytes.chunks_exact(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+}
+
+fn save(scaler: ScalerKind, key: ScaleKey, pixels: &[u32]) -> Result<()> {
+    let Some(dir) = cache_dir(scaler) else { return Ok(()) };
+    fs::create_dir_all(&dir)?;
+    let mut bytes = Vec::<u8>::with_capacity(pixels.len() * 4);
+    for pixel in pixels {
+        bytes.extend_from_slice(&pixel.to_le_bytes());
+    }
+    // Write then rename, so a half-written file is never picked up, eg if two copies of the game start at once.
+    let path = dir.join(key.file_name());
+    let temp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&temp, bytes)?;
+    fs::rename(&temp, &path)?;
+    evict_over_budget(&dir, BUDGET_BYTES);
+    Ok(())
+}
+
+// Returns the cached pixels if there are any, otherwise scales them and caches the result.
+// Fast scalers skip the cache, as reading the file would take longer.
+pub fn get_or_scale<F: FnOnce() -> Vec<u32>>(scaler: ScalerKind, key: ScaleKey, expected_len: usize, scale: F) -> Vec<u32> {
+    if !scaler.scaler().is_slow() {
+        return scale()
+    }
+    if let Some(pixels) = load(scaler, key, expected_len) {
+        return pixels
+    }
+    let pixels = scale();
+    if let Err(e) = save(scaler, key, &pixels) {
+        println!("Couldn't cache a scaled image: {}", e);
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let dir = env::temp_dir().join(format!("rusty-lemmings-scale-cache-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        for (name, age) in [("old", 30), ("newest", 10), ("middle", 20)] {
+            let path = dir.join(name);
+            fs::write(&path, [0u8; 100]).unwrap();
+            File::options().write(true).open(&path).unwrap().set_modified(now - Duration::from_secs(age)).unwrap();
+        }
+        evict_over_budget(&dir, 250);
+        let mut left: Vec<String> = fs::read_dir(&dir).unwrap().flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect();
+        left.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, vec!["middle".to_string(), "newest".to_string()]);
+    }
+}
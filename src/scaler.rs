@@ -0,0 +1,330 @@
+// This is the choice of ways to upscale the pixel art, since it's a matter of taste.
+// Each scaler does one or more factors in a single pass; bigger factors are done in several passes, eg 12 = 6 then 2.
+// If a factor can't be made from the scaler's passes, the remainder is done with nearest neighbour.
+
+use crate::xbrz;
+
+pub trait Scaler: Send + Sync {
+    fn name(&self) -> &'static str; // As used in the settings file.
+    fn version(&self) -> u32; // Bump when the output changes, so the disk cache gets redone.
+    fn pass_factors(&self) -> &'static [usize]; // Factors that can be done in one pass, largest first.
+    fn scale_pass(&self, factor: usize, image: &[u32], width: usize, height: usize) -> Vec<u32>;
+
+    // Whether it's slow enough to be worth caching on disk.
+    fn is_slow(&self) -> bool { true }
+
+    fn scale(&self, factor: usize, image: &[u32], width: usize, height: usize) -> Vec<u32> {
+        let mut scaled = image.to_vec();
+        let (mut scaled_width, mut scaled_height) = (width, height);
+        let mut remaining = factor.max(1);
+        while let Some(&pass) = self.pass_factors().iter().find(|f| remaining.is_multiple_of(**f)) {
+            scaled = self.scale_pass(pass, &scaled, scaled_width, scaled_height);
+            scaled_width *= pass;
+            scaled_height *= pass;
+            remaining /= pass;
+        }
+        if remaining > 1 {
+            scaled = nearest(remaining, &scaled, scaled_width, scaled_height);
+        }
+        scaled
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScalerKind {
+    Nearest,
+    #[default]
+    Xbrz,
+    Epx, // Aka Scale2x/Scale3x.
+    Soft,
+}
+
+pub const ALL_SCALERS: [ScalerKind; 4] = [ScalerKind::Nearest, ScalerKind::Xbrz, ScalerKind::Epx, ScalerKind::Soft];
+
+impl ScalerKind {
+    pub fn scaler(&self) -> &'static dyn Scaler {
+        match self {
+            ScalerKind::Nearest => &NearestScaler,
+            ScalerKind::Xbrz => &XbrzScaler,
+            ScalerKind::Epx => &EpxScaler,
+            ScalerKind::Soft => &SoftScaler,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ScalerKind> {
+        ALL_SCALERS.iter().copied().find(|k| k.scaler().name() == name)
+    }
+
+    // The next one along, wrapping around, eg for a settings button that steps through them.
+    pub fn next(&self) -> ScalerKind {
+        let index = ALL_SCALERS.iter().position(|k| k == self).unwrap_or(0);
+        ALL_SCALERS[(index + 1) % ALL_SCALERS.len()]
+    }
+}
+
+fn nearest(factor: usize, image: &[u32], width: usize, height: usize) -> Vec<u32> {
+    let mut scaled = Vec::<u32>::with_capacity(width * height * factor * factor);
+    for y in 0..height * factor {
+        let row = &image[(y / factor) * width..(y / factor + 1) * width];
+        for x in 0..width * factor {
+            scaled.push(row[x / factor]);
+        }
+    }
+    scaled
+}
+
+// Clamped to the edges, so the borders act like they carry on.
+fn pixel_at(image: &[u32], width: usize, height: usize, x: isize, y: isize) -> u32 {
+    let x = x.clamp(0, width as isize - 1) as usize;
+    let y = y.clamp(0, height as isize - 1) as usize;
+    image[y * width + x]
+}
+
+// Crisp big pixels, like the original.
+pub struct NearestScaler;
+
+impl Scaler for NearestScaler {
+    fn name(&self) -> &'static str { "nearest" }
+    fn version(&self) -> u32 { 1 }
+    fn pass_factors(&self) -> &'static [usize] { &[] } // Handled by the remainder in scale.
+    fn scale_pass(&self, factor: usize, image: &[u32], width: usize, height: usize) -> Vec<u32> {
+        nearest(factor, image, width, height)
+    }
+    fn is_slow(&self) -> bool { false }
+}
+
+pub struct XbrzScaler;
+
+impl Scaler for XbrzScaler {
+    fn name(&self) -> &'static str { "xbrz" }
+    fn version(&self) -> u32 { xbrz::VERSION }
+    fn pass_factors(&self) -> &'static [usize] { &[6, 5, 4, 3, 2] }
+    fn scale_pass(&self, factor: usize, image: &[u32], width: usize, height: usize) -> Vec<u32> {
+        xbrz::scale(factor as u8, image, width as u32, height as u32)
+    }
+}
+
+// Only ever copies existing colours, so it keeps the palette but rounds off diagonals.
+pub struct EpxScaler;
+
+impl Scaler for EpxScaler {
+    fn name(&self) -> &'static str { "epx" }
+    fn version(&self) -> u32 { 1 }
+    fn pass_factors(&self) -> &'static [usize] { &[3, 2] }
+    fn scale_pass(&self, factor: usize, image: &[u32], width: usize, height: usize) -> Vec<u32> {
+        if factor == 3 { scale3x(image, width, height) } else { scale2x(image, width, height) }
+    }
+}
+
+fn scale2x(image: &[u32], width: usize, height: usize) -> Vec<u32> {
+    let out_width = width * 2;
+    let mut out = vec![0; out_width * height * 2];
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx: isize, dy: isize| pixel_at(image, width, height, x as isize + dx, y as isize + dy);
+            let (p, a, b, c, d) = (at(0, 0), at(0, -1), at(1, 0), at(-1, 0), at(0, 1)); // Centre, up, right, left, down.
+            let top_left = if c == a && c != d && a != b { a } else { p };
+            let top_right = if a == b && a != c && b != d { b } else { p };
+            let bottom_left = if d == c && d != b && c != a { c } else { p };
+            let bottom_right = if b == d && b != a && d != c { d } else { p };
+            let o = y * 2 * out_width + x * 2;
+            out[o] = top_left;
+            out[o + 1] = top_right;
+            out[o + out_width] = bottom_left;
+            out[o + out_width + 1] = bottom_right;
+        }
+    }
+    out
+}
+
+fn scale3x(image: &[u32], width: usize, height: usize) -> Vec<u32> {
+    let out_width = width * 3;
+    let mut out = vec![0; out_width * height * 3];
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx: isize, dy: isize| pixel_at(image, width, height, x as isize + dx, y as isize + dy);
+            // A B C
+            // D E F
+            // G H I
+            let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+            let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+            let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+            let o = y * 3 * out_width + x * 3;
+            if b != h && d != f {
+                out[o] = if d == b { d } else { e };
+                out[o + 1] = if (d == b && e != c) || (b == f && e != a) { b } else { e };
+                out[o + 2] = if b == f { f } else { e };
+                out[o + out_width] = if (d == b && e != g) || (d == h && e != a) { d } else { e };
+                out[o + out_width + 1] = e;
+                out[o + out_width + 2] = if (b == f && e != i) || (h == f && e != c) { f } else { e };
+                out[o + out_width * 2] = if d == h { d } else { e };
+                out[o + out_width * 2 + 1] = if (d == h && e != i) || (h == f && e != g) { h } else { e };
+                out[o + out_width * 2 + 2] = if h == f { f } else { e };
+            } else {
+                for row in 0..3 {
+                    out[o + out_width * row..o + out_width * row + 3].fill(e);
+                }
+            }
+        }
+    }
+    out
+}
+
+// Like EPX, but neighbours count as the same colour if they're close in YUV, and edges get blended rather than
+// copied, which gives softer results. It borrows the idea from hqx, but only has a few simple corner rules rather than
+// hqx's table of patterns, so it isn't hqx.
+pub struct SoftScaler;
+
+impl Scaler for SoftScaler {
+    fn name(&self) -> &'static str { "soft" }
+    fn version(&self) -> u32 { 1 }
+    fn pass_factors(&self) -> &'static [usize] { &[2] }
+    fn scale_pass(&self, _factor: usize, image: &[u32], width: usize, height: usize) -> Vec<u32> {
+        soft2x(image, width, height)
+    }
+}
+
+// hqx's thresholds for Y, U and V, though on a cheaper YUV than hqx's, so it's not quite what hqx counts as similar.
+fn is_similar(a: u32, b: u32) -> bool {
+    if a == b { return true }
+    let yuv = |p: u32| {
+        let (r, g, b) = ((p >> 24) as i32, ((p >> 16) & 0xff) as i32, ((p >> 8) & 0xff) as i32);
+        ((r + g + b) / 3, (r - b) / 2 + 128, (g * 2 - r - b) / 4 + 128)
+    };
+    let ((y1, u1, v1), (y2, u2, v2)) = (yuv(a), yuv(b));
+    let alpha_difference = ((a & 0xff) as i32 - (b & 0xff) as i32).abs();
+    (y1 - y2).abs() <= 0x30 && (u1 - u2).abs() <= 7 && (v1 - v2).abs() <= 6 && alpha_difference <= 0x30
+}
+
+// Weighted average of each channel, alpha included.
+fn blend(pixels: &[(u32, u32)]) -> u32 {
+    let total: u32 = pixels.iter().map(|(_, weight)| weight).sum();
+    let mut out = 0;
+    for shift in [24, 16, 8, 0] {
+        let sum: u32 = pixels.iter().map(|(p, weight)| ((p >> shift) & 0xff) * weight).sum();
+        out |= (sum / total) << shift;
+    }
+    out
+}
+
+fn soft2x(image: &[u32], width: usize, height: usize) -> Vec<u32> {
+    let out_width = width * 2;
+    let mut out = vec![0; out_width * height * 2];
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx: isize, dy: isize| pixel_at(image, width, height, x as isize + dx, y as isize + dy);
+            let centre = at(0, 0);
+            // Each output corner looks at the two neighbours on its sides, and the one diagonally out from it.
+            let corner = |dx: isize, dy: isize| {
+                let (side_x, side_y, diagonal) = (at(dx, 0), at(0, dy), at(dx, dy));
+                if is_similar(side_x, side_y) && !is_similar(centre, side_x) {
+                    if is_similar(diagonal, side_x) {
+                        blend(&[(centre, 2), (side_x, 1), (side_y, 1)]) // Along a solid edge.
+                    } else {
+                        blend(&[(centre, 6), (side_x, 1), (side_y, 1)]) // A thin line passing the corner.
+                    }
+                } else if !is_similar(centre, diagonal) && is_similar(centre, side_x) && is_similar(centre, side_y) {
+                    blend(&[(centre, 3), (diagonal, 1)]) // Just the diagonal is different.
+                } else {
+                    centre
+                }
+            };
+            let o = y * 2 * out_width + x * 2;
+            out[o] = corner(-1, -1);
+            out[o + 1] = corner(1, -1);
+            out[o + out_width] = corner(-1, 1);
+            out[o + out_width + 1] = corner(1, 1);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: u32 = 0xffffffff;
+    const B: u32 = 0x000000ff;
+
+    // A white pixel in the top left corner of black, so there's one diagonal for the scalers to round off.
+    const CORNER: [u32; 4] = [W, B, B, B];
+
+    fn scaled(kind: ScalerKind, factor: usize) -> Vec<u32> {
+        kind.scaler().scale(factor, &CORNER, 2, 2)
+    }
+
+    #[test]
+    fn names_round_trip_and_theres_no_hqx() {
+        for kind in ALL_SCALERS {
+            assert_eq!(ScalerKind::from_name(kind.scaler().name()), Some(kind));
+        }
+        assert_eq!(ScalerKind::from_name("hqx"), None);
+    }
+
+    #[test]
+    fn every_scaler_gives_every_factor_its_size() {
+        let image: Vec<u32> = (0..15).map(|i| (i * 0x11111100) | 0xff).collect();
+        for kind in ALL_SCALERS {
+            for factor in 1..=12 {
+                let (width, height) = (5, 3);
+                assert_eq!(kind.scaler().scale(factor, &image, width, height).len(), width * height * factor * factor, "{:?} {}x", kind, factor);
+            }
+        }
+    }
+
+    #[test]
+    fn one_colour_stays_that_colour() {
+        for kind in ALL_SCALERS {
+            assert!(kind.scaler().scale(6, &[0x4169e1ff; 12], 4, 3).iter().all(|&p| p == 0x4169e1ff), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn nearest_makes_blocks() {
+        assert_eq!(scaled(ScalerKind::Nearest, 2), vec![
+            W, W, B, B,
+            W, W, B, B,
+            B, B, B, B,
+            B, B, B, B,
+        ]);
+    }
+
+    #[test]
+    fn epx_rounds_off_the_corner() {
+        assert_eq!(scaled(ScalerKind::Epx, 2), vec![
+            W, W, B, B,
+            W, B, B, B,
+            B, B, B, B,
+            B, B, B, B,
+        ]);
+        assert_eq!(scaled(ScalerKind::Epx, 3), vec![
+            W, W, W, B, B, B,
+            W, W, B, B, B, B,
+            W, B, B, B, B, B,
+            B, B, B, B, B, B,
+            B, B, B, B, B, B,
+            B, B, B, B, B, B,
+        ]);
+    }
+
+    #[test]
+    fn soft_blends_along_the_corner() {
+        let (edge, diagonal) = (0x7f7f7fff, 0x3f3f3fff);
+        assert_eq!(scaled(ScalerKind::Soft, 2), vec![
+            W, W, B, B,
+            W, edge, B, B,
+            B, B, diagonal, B,
+            B, B, B, B,
+        ]);
+    }
+
+    #[test]
+    fn xbrz_only_changes_pixels_along_the_corner() {
+        let (xbrz, nearest) = (scaled(ScalerKind::Xbrz, 2), scaled(ScalerKind::Nearest, 2));
+        for (i, (&x, &n)) in xbrz.iter().zip(&nearest).enumerate() {
+            let (x_at, y_at) = (i % 4, i / 4);
+            if !(1..=2).contains(&(x_at + y_at)) { assert_eq!(x, n, "{},{}", x_at, y_at) }
+        }
+        assert_ne!(xbrz, nearest);
+    }
+}
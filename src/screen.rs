@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use bevy::render::camera::{ScalingMode, Viewport};
+use bevy::window::{PrimaryWindow, WindowMode};
+use crate::settings::Settings;
+use crate::{ORIGINAL_GAME_W, ORIGINAL_GAME_H, POINT_SIZE, RES_W, RES_H};
+
+// This fits the original game's 320x200 screen (the canvas) into the window, whatever its size, shape or DPI.
+// Everything is laid out in world units, where a point (an original pixel) is POINT_SIZE, so the canvas is always
+// CANVAS_W x CANVAS_H around the origin; the camera's projection is what makes that fill the window.
+// The canvas is made as big as fits. With integer scaling a point is a whole number of physical pixels, so nothing
+// shimmers. With letterboxing, anything outside the canvas is left black; otherwise wider windows see more level.
+// F11 or Alt+Enter toggles fullscreen.
+
+pub const CANVAS_W: f32 = ORIGINAL_GAME_W as f32 * POINT_SIZE;
+pub const CANVAS_H: f32 = ORIGINAL_GAME_H as f32 * POINT_SIZE;
+
+pub struct ScreenPlugin;
+
+impl Plugin for ScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScreenLayout>();
+        app.add_system(update_layout);
+        app.add_system(toggle_fullscreen);
+    }
+}
+
+// Where the canvas is in the window. All in physical pixels, with y going up from the bottom like the cursor position.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct ScreenLayout {
+    pub window_size: Vec2,
+    pub viewport_position: Vec2, // Bottom left of the part of the window that's drawn to.
+    pub viewport_size: Vec2,
+    pub pixels_per_point: f32,
+    pub scale_factor: f32, // Physical pixels per logical pixel, ie the DPI scaling.
+    pub is_letterboxed: bool,
+}
+
+// As if it's the default window, eg when headless.
+impl Default for ScreenLayout {
+    fn default() -> Self {
+        ScreenLayout::new(Vec2::new(RES_W as f32, RES_H as f32), 1., &Settings::default())
+    }
+}
+
+impl ScreenLayout {
+    pub fn new(window_size: Vec2, scale_factor: f32, settings: &Settings) -> ScreenLayout {
+        let fit = (window_size.x / ORIGINAL_GAME_W as f32).min(window_size.y / ORIGINAL_GAME_H as f32);
+        let pixels_per_point = if settings.integer_scaling { fit.floor().max(1.) } else { fit.max(f32::EPSILON) };
+        let (viewport_position, viewport_size) = if settings.letterbox {
+            let canvas = (Vec2::new(ORIGINAL_GAME_W as f32, ORIGINAL_GAME_H as f32) * pixels_per_point).round().min(window_size);
+            (((window_size - canvas) / 2.).floor(), canvas)
+        } else {
+            (Vec2::ZERO, window_size)
+        };
+        ScreenLayout { window_size, viewport_position, viewport_size, pixels_per_point, scale_factor, is_letterboxed: settings.letterbox }
+    }
+
+    pub fn world_units_per_pixel(&self) -> f32 {
+        POINT_SIZE / self.pixels_per_point
+    }
+
+    // How much of the world can be seen, in world units.
+    pub fn visible_size(&self) -> Vec2 {
+        self.viewport_size * self.world_units_per_pixel()
+    }
+
+    // Where the mouse is, in world units from the middle of the screen, +y up. It can be outside the visible area when
+    // letterboxed, eg over the black bars.
+    pub fn cursor(&self, window: &Window) -> Option<Vec2> {
+        let position = window.cursor_position()? * self.scale_factor;
+        let centre = self.viewport_position + self.viewport_size / 2.;
+        Some((position - centre) * self.world_units_per_pixel())
+    }
+}
+
+// For systems that need to know where the mouse is.
+#[derive(SystemParam)]
+pub struct Cursor<'w, 's> {
+    windows: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    layout: Res<'w, ScreenLayout>,
+}
+
+impl<'w, 's> Cursor<'w, 's> {
+    // In world units from the middle of the screen, see ScreenLayout::cursor. None if it's outside the window.
+    pub fn position(&self) -> Option<Vec2> {
+        self.layout.cursor(self.windows.get_single().ok()?)
+    }
+}
+
+fn update_layout(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    settings: Res<Settings>,
+    mut layout: ResMut<ScreenLayout>,
+    mut cameras: Query<(&mut Camera, &mut OrthographicProjection)>,
+    new_cameras: Query<(), Added<Camera>>,
+) {
+    let Ok(window) = windows.get_single() else { return };
+    let window_size = Vec2::new(window.physical_width() as f32, window.physical_height() as f32);
+    if window_size.x < 1. || window_size.y < 1. { return } // Minimised.
+    let new_layout = ScreenLayout::new(window_size, window.scale_factor() as f32, &settings);
+    if *layout == new_layout && new_cameras.is_empty() { return }
+    *layout = new_layout;
+
+    for (mut camera, mut projection) in cameras.iter_mut() {
+        camera.viewport = if layout.is_letterboxed {
+            Some(Viewport {
+                // Viewports go down from the top left.
+                physical_position: UVec2::new(layout.viewport_position.x as u32, (window_size.y - layout.viewport_position.y - layout.viewport_size.y) as u32),
+                physical_size: layout.viewport_size.as_uvec2(),
+                ..default()
+            })
+        } else {
+            None
+        };
+        // This is in logical pixels per world unit.
+        projection.scaling_mode = ScalingMode::WindowSize(1. / (layout.world_units_per_pixel() * layout.scale_factor));
+    }
+}
+
+fn toggle_fullscreen(
+    keys: Res<Input<KeyCode>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut settings: ResMut<Settings>,
+) {
+    let is_alt_pressed = keys.any_pressed([KeyCode::LAlt, KeyCode::RAlt]);
+    if !keys.just_pressed(KeyCode::F11) && !(is_alt_pressed && keys.just_pressed(KeyCode::Return)) { return }
+    let Ok(mut window) = windows.get_single_mut() else { return };
+    settings.fullscreen = !settings.fullscreen;
+    window.mode = window_mode(&settings);
+    if let Err(e) = settings.save() {
+        println!("Couldn't save settings: {}", e);
+    }
+}
+
+pub fn window_mode(settings: &Settings) -> WindowMode {
+    if settings.fullscreen { WindowMode::BorderlessFullscreen } else { WindowMode::Windowed }
+}
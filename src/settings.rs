@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+use std::fs;
+use std::io::Result;
+use crate::progress::config_dir;
+use crate::scaler::ScalerKind;
+
+// These are the player's display settings, saved as settings.txt next to their progress. One 'name=value' per line:
+//   scale=12              <- How many times bigger the graphics get upscaled. Bigger is sharper but slower to load.
+//   terrain_scaler=xbrz   <- nearest, xbrz, epx or soft, separately for the level terrain, sprites and menus.
+//   sprite_scaler=xbrz
+//   menu_scaler=xbrz
+//   integer_scaling=false <- Only ever show original pixels as a whole number of screen pixels.
+//   letterbox=false       <- Black out anything outside the original 320x200 screen.
+//   fullscreen=false
+// Anything missing or unrecognised is left at the default.
+
+const FILE_NAME: &str = "settings.txt";
+pub const DEFAULT_SCALE: usize = 12;
+const MAX_SCALE: usize = 24; // Any more and the level terrain textures get too big for some GPUs.
+
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub scale: usize,
+    pub terrain_scaler: ScalerKind,
+    pub sprite_scaler: ScalerKind, // Lemmings, level objects and the mouse cursor.
+    pub menu_scaler: ScalerKind, // Menus, fonts and the skill panel.
+    pub integer_scaling: bool,
+    pub letterbox: bool,
+    pub fullscreen: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            scale: DEFAULT_SCALE,
+            terrain_scaler: ScalerKind::default(),
+            sprite_scaler: ScalerKind::default(),
+            menu_scaler: ScalerKind::default(),
+            integer_scaling: false,
+            letterbox: false,
+            fullscreen: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn parse(text: &str) -> Settings {
+        let mut settings = Settings::default();
+        for line in text.lines() {
+            let Some((name, value)) = line.split_once('=') else { continue };
+            let value = value.trim();
+            match name.trim() {
+                "scale" => if let Ok(scale) = value.parse::<usize>() {
+                    settings.scale = scale.clamp(1, MAX_SCALE);
+                },
+                "terrain_scaler" => settings.terrain_scaler = ScalerKind::from_name(value).unwrap_or(settings.terrain_scaler),
+                "sprite_scaler" => settings.sprite_scaler = ScalerKind::from_name(value).unwrap_or(settings.sprite_scaler),
+                "menu_scaler" => settings.menu_scaler = ScalerKind::from_name(value).unwrap_or(settings.menu_scaler),
+                "integer_scaling" => settings.integer_scaling = value.parse().unwrap_or(settings.integer_scaling),
+                "letterbox" => settings.letterbox = value.parse().unwrap_or(settings.letterbox),
+                "fullscreen" => settings.fullscreen = value.parse().unwrap_or(settings.fullscreen),
+                _ => {},
+            }
+        }
+        settings
+    }
+
+    pub fn to_text(&self) -> String {
+        format!("scale={}\nterrain_scaler={}\nsprite_scaler={}\nmenu_scaler={}\ninteger_scaling={}\nletterbox={}\nfullscreen={}\n",
+            self.scale,
+            self.terrain_scaler.scaler().name(),
+            self.sprite_scaler.scaler().name(),
+            self.menu_scaler.scaler().name(),
+            self.integer_scaling,
+            self.letterbox,
+            self.fullscreen)
+    }
+
+    // A missing or unreadable file just means the defaults.
+    pub fn load() -> Settings {
+        let Some(dir) = config_dir() else { return Settings::default() };
+        let Ok(text) = fs::read_to_string(dir.join(FILE_NAME)) else { return Settings::default() };
+        Settings::parse(&text)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(dir) = config_dir() else { return Ok(()) };
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(FILE_NAME);
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, self.to_text())?;
+        fs::rename(&temp_path, &path)
+    }
+}
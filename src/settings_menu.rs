@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+use crate::fadeout::*;
+use crate::{GameTextures, GameState};
+use crate::menu_common::{spawn_menu_background, text_size, spawn_text};
+use crate::scaler::ScalerKind;
+use crate::screen::Cursor;
+use crate::settings::Settings;
+
+// This is the screen for choosing how the graphics get upscaled. Clicking a line steps it on to the next scaler, and
+// the settings are saved straight away. The terrain is scaled as each level starts, so its scaler applies from the next
+// level, but sprites and menus are only scaled as the game starts.
+
+pub struct SettingsMenuPlugin;
+
+impl Plugin for SettingsMenuPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems((
+            spawn_background,
+            spawn_options,
+        ).in_schedule(OnEnter(GameState::SettingsMenu)));
+        app.add_systems((
+            button_highlight_system.run_if(screen_fade_is_not_transitioning),
+            button_system.run_if(screen_fade_is_not_transitioning),
+            keyboard_system.run_if(screen_fade_is_not_transitioning),
+        ).in_set(OnUpdate(GameState::SettingsMenu)));
+        app.add_systems((
+            exit,
+        ).in_schedule(OnExit(GameState::SettingsMenu)));
+	}
+}
+
+#[derive(Component)]
+struct SettingsMenuComponent;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum ScalerSetting {
+	Terrain,
+	Sprites,
+	Menus,
+}
+
+impl ScalerSetting {
+	fn label(&self) -> &'static str {
+		match self {
+			ScalerSetting::Terrain => "Terrain",
+			ScalerSetting::Sprites => "Sprites",
+			ScalerSetting::Menus => "Menus",
+		}
+	}
+
+	fn scaler<'a>(&self, settings: &'a mut Settings) -> &'a mut ScalerKind {
+		match self {
+			ScalerSetting::Terrain => &mut settings.terrain_scaler,
+			ScalerSetting::Sprites => &mut settings.sprite_scaler,
+			ScalerSetting::Menus => &mut settings.menu_scaler,
+		}
+	}
+
+	fn text(&self, settings: &mut Settings) -> String {
+		format!("{}: {}", self.label(), self.scaler(settings).scaler().name())
+	}
+}
+
+fn exit(
+    mut commands: Commands,
+    components: Query<Entity, With<SettingsMenuComponent>>,
+) {
+    for e in components.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn spawn_background(
+	mut commands: Commands,
+	game_textures: Res<GameTextures>,
+) {
+	commands
+		.spawn(SpatialBundle::default())
+		.insert(SettingsMenuComponent)
+		.with_children(|parent| {
+			spawn_menu_background(parent, &game_textures);
+		});
+}
+
+fn spawn_options(
+	mut commands: Commands,
+	game_textures: Res<GameTextures>,
+	mut settings: ResMut<Settings>,
+) {
+	let line = text_size() * 1.5;
+	let lines: [(f32, &str); 4] = [
+		(line * 4., "Upscaling"),
+		(-line * 2., "Sprites and menus change"),
+		(-line * 3., "when the game next starts"),
+		(-line * 5., "Press Escape to go back"),
+	];
+	commands
+		.spawn(SpatialBundle::default())
+		.insert(SettingsMenuComponent)
+		.with_children(|parent| {
+			for (y, text) in lines {
+				parent.spawn(SpatialBundle{
+					transform: Transform::from_xyz(0., y, 2.),
+					..default()
+				}).with_children(|parent| {
+					spawn_text(text, parent, &game_textures);
+				});
+			}
+			for (i, setting) in [ScalerSetting::Terrain, ScalerSetting::Sprites, ScalerSetting::Menus].into_iter().enumerate() {
+				let text = setting.text(&mut settings);
+				parent.spawn(SpatialBundle{
+					transform: Transform::from_xyz(0., line * (2 - i as isize) as f32, 2.),
+					..default()
+				}).insert(setting).with_children(|parent| {
+					spawn_text(&text, parent, &game_textures);
+				});
+			}
+		});
+}
+
+fn is_over(transform: &Transform, y: f32) -> bool {
+	let half_height = text_size() / 2.;
+	transform.translation.y - half_height < y && y < transform.translation.y + half_height
+}
+
+fn button_highlight_system(
+    cursor: Cursor,
+    mouse_buttons: Res<Input<MouseButton>>,
+    buttons: Query<(&Transform, &Children), With<ScalerSetting>>,
+	mut letters: Query<&mut TextureAtlasSprite>,
+) {
+    let y = cursor.position().map_or(f32::MAX, |p| p.y);
+    for (transform, children) in &buttons {
+        let a: f32 = if is_over(transform, y) { if mouse_buttons.pressed(MouseButton::Left) { 0.5 } else { 0.8 } } else { 1. };
+		for &child in children {
+			if let Ok(mut letter) = letters.get_mut(child) {
+				letter.color.set_a(a);
+			}
+		}
+    }
+}
+
+// Steps the clicked line on to the next scaler, saves, and redraws the line.
+fn button_system(
+    mut commands: Commands,
+    cursor: Cursor,
+    mouse_buttons: Res<Input<MouseButton>>,
+    buttons: Query<(Entity, &Transform, &ScalerSetting)>,
+    game_textures: Res<GameTextures>,
+    mut settings: ResMut<Settings>,
+) {
+    if !mouse_buttons.just_released(MouseButton::Left) { return }
+    let Some(position) = cursor.position() else { return };
+    let Some((entity, _, setting)) = buttons.iter().find(|(_, t, _)| is_over(t, position.y)) else { return };
+    let scaler = setting.scaler(&mut settings);
+    *scaler = scaler.next();
+    if let Err(e) = settings.save() {
+        println!("Couldn't save settings: {}", e);
+    }
+    let text = setting.text(&mut settings);
+    commands.entity(entity).despawn_descendants();
+    commands.entity(entity).with_children(|parent| {
+        spawn_text(&text, parent, &game_textures);
+    });
+}
+
+fn keyboard_system(
+	mut fadeout: Fadeout,
+	keys: Res<Input<KeyCode>>,
+) {
+	if keys.just_pressed(KeyCode::Escape) {
+		fadeout.start(GameState::MainMenu);
+	}
+}
@@ -0,0 +1,190 @@
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use crate::{FPS, set_scale};
+use crate::extract::trigger_of;
+use crate::helpers::{scale_animation_to_atlas, AtlasLayout};
+use crate::json::json_string;
+use crate::lemmings::geometry::Rect;
+use crate::lemmings::loader;
+use crate::lemmings::models::{Animation, AnimationOrImage, Game};
+use crate::lemmings::png::png_data;
+use crate::scaler::ScalerKind;
+use crate::settings::Settings;
+
+// This is the 'sprite-sheets <dir> [options]' command: it writes every animation packed into a sprite sheet, the same
+// grid the game makes its texture atlases with, for artists. That's the lemmings', the main menu's and every ground's
+// objects. Each sheet is {name}.png with a {name}.json beside it in Aseprite's array format, which TexturePacker reads
+// too: each frame's rect and duration, a tag for the whole animation, and as slices the anchor the game draws it from
+// (with the pivot) and for objects the trigger area, with the effect id as the slice's data.
+// Sheets go in 1x at the original size, and in {scale}x upscaled as the game would.
+//   --scale <n>       The upscaled size, by default the one in the settings. 1 writes only 1x.
+//   --scaler <name>   Upscale everything with this, eg 'nearest', rather than the settings' sprite and menu scalers.
+// Exits with 0 if all went well, 2 if the options were wrong, the game data couldn't be loaded or the files written.
+
+struct Sheet<'a> {
+    name: String,
+    animation: &'a Animation,
+    should_add_then_remove_margin: bool,
+    scaler: ScalerKind,
+    pivot: (usize, usize), // Where the game anchors it, in the frame.
+    trigger: Option<(Rect, u8)>,
+}
+
+struct Options {
+    out_dir: String,
+    scale: usize,
+    scaler: Option<ScalerKind>,
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let settings = Settings::load();
+    let Options { out_dir, scale, scaler } = match parse_options(args, &settings) {
+        Ok(options) => options,
+        Err(message) => {
+            println!("{}", message);
+            return 2
+        },
+    };
+    let Some(game) = loader::load_lemmings() else { return 2 };
+    let result = (|| -> Result<usize> {
+        let grounds: Vec<_> = game.index.ground_ids().into_iter().map(|id| Ok((id, game.index.ground(id)?))).collect::<Result<_>>()?;
+        let mut sheets = sheets(&game, &settings, scaler);
+        for (ground_id, ground) in grounds.iter() {
+            let mut object_ids: Vec<&i32> = ground.object_sprites.keys().collect();
+            object_ids.sort();
+            for id in object_ids {
+                sheets.push(Sheet {
+                    name: format!("ground{}.object.{}", ground_id, id),
+                    animation: &ground.object_sprites[id],
+                    should_add_then_remove_margin: true,
+                    scaler: scaler.unwrap_or(settings.sprite_scaler),
+                    pivot: (0, 0), // Levels place objects by their top left.
+                    trigger: ground.ground.object_info.get(*id as usize).and_then(trigger_of),
+                });
+            }
+        }
+        let mut scales = vec![1];
+        if scale > 1 { scales.push(scale) }
+        for scale in scales {
+            let dir = Path::new(&out_dir).join(format!("{}x", scale));
+            fs::create_dir_all(&dir)?;
+            set_scale(scale);
+            for sheet in sheets.iter() {
+                write_sheet(&dir, sheet, scale)?;
+            }
+        }
+        Ok(sheets.len())
+    })();
+    match result {
+        Ok(count) => {
+            println!("Wrote {} sprite sheets to {}", count, out_dir);
+            0
+        },
+        Err(e) => {
+            println!("Couldn't write the sprite sheets: {}", e);
+            2
+        },
+    }
+}
+
+fn parse_options(args: &[String], settings: &Settings) -> std::result::Result<Options, String> {
+    let mut options = Options { out_dir: String::new(), scale: settings.scale, scaler: None };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--scale" => {
+                let value = value()?;
+                options.scale = value.parse().ok().filter(|n| *n >= 1).ok_or(format!("Scale {} isn't a number from 1", value))?;
+            },
+            "--scaler" => {
+                let value = value()?;
+                options.scaler = Some(ScalerKind::from_name(&value).ok_or(format!("Unknown scaler {}", value))?);
+            },
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ if options.out_dir.is_empty() => options.out_dir = arg.clone(),
+            _ => return Err(format!("Unexpected {}", arg)),
+        }
+    }
+    if options.out_dir.is_empty() { return Err("Usage: sprite-sheets <dir> [--scale n] [--scaler name]".to_string()) }
+    Ok(options)
+}
+
+// main.dat's animations, scaled the way load_lemmings_textures.rs does.
+fn sheets<'a>(game: &'a Game, settings: &Settings, scaler: Option<ScalerKind>) -> Vec<Sheet<'a>> {
+    let mut sheets = Vec::<Sheet>::new();
+    for asset in game.all_assets() {
+        let AnimationOrImage::Animation(animation) = asset.content else { continue };
+        let is_lemming = asset.name.starts_with("lemming.");
+        if !is_lemming && !asset.name.starts_with("main_menu.") { continue }
+        sheets.push(Sheet {
+            should_add_then_remove_margin: !asset.name.starts_with("main_menu.blink"), // The blinks sit inside the logo.
+            scaler: scaler.unwrap_or(if is_lemming { settings.sprite_scaler } else { settings.menu_scaler }),
+            pivot: if is_lemming { (animation.width / 2, animation.height) } else { (0, 0) }, // Lemmings are drawn from their feet.
+            name: asset.name,
+            animation,
+            trigger: None,
+        });
+    }
+    sheets
+}
+
+fn write_sheet(dir: &Path, sheet: &Sheet, scale: usize) -> Result<()> {
+    let animation = sheet.animation;
+    if animation.frames.is_empty() { return Ok(()) }
+    let (layout, pixels) = if scale == 1 {
+        let layout = AtlasLayout::new(animation.frames.len(), animation.width, animation.height);
+        let pixels = layout.pack(&animation.frames);
+        (layout, pixels)
+    } else {
+        let layout = AtlasLayout::for_scaled_animation(animation);
+        let pixels = scale_animation_to_atlas(animation, &layout, sheet.should_add_then_remove_margin, sheet.scaler);
+        (layout, pixels)
+    };
+    let image_name = format!("{}.png", sheet.name);
+    fs::write(dir.join(&image_name), png_data(layout.width as u32, layout.height as u32, &pixels))?;
+    fs::write(dir.join(format!("{}.json", sheet.name)), sheet_json(sheet, &layout, &image_name, scale))
+}
+
+fn bounds_json(x: isize, y: isize, width: isize, height: isize) -> String {
+    format!("{{\"x\":{},\"y\":{},\"w\":{},\"h\":{}}}", x, y, width, height)
+}
+
+fn sheet_json(sheet: &Sheet, layout: &AtlasLayout, image_name: &str, scale: usize) -> String {
+    let (frame_width, frame_height) = (layout.frame_width as isize, layout.frame_height as isize);
+    let duration = (1000. / FPS).round() as usize; // Every animation moves on a frame per game tick.
+    let frames: Vec<String> = layout.rects.iter().enumerate().map(|(i, rect)| {
+        format!(
+            "    {{\"filename\":{},\"frame\":{},\"rotated\":false,\"trimmed\":false,\"spriteSourceSize\":{},\"sourceSize\":{{\"w\":{},\"h\":{}}},\"duration\":{}}}",
+            json_string(&format!("{} {}", sheet.name, i)),
+            bounds_json(rect.min.x as isize, rect.min.y as isize, frame_width, frame_height),
+            bounds_json(0, 0, frame_width, frame_height),
+            frame_width,
+            frame_height,
+            duration,
+        )
+    }).collect();
+
+    let scale = scale as isize;
+    let mut slices = vec![format!(
+        "{{\"name\":\"anchor\",\"color\":\"#00ff00ff\",\"keys\":[{{\"frame\":0,\"bounds\":{},\"pivot\":{{\"x\":{},\"y\":{}}}}}]}}",
+        bounds_json(0, 0, frame_width, frame_height), sheet.pivot.0 as isize * scale, sheet.pivot.1 as isize * scale)];
+    if let Some((rect, effect)) = &sheet.trigger {
+        slices.push(format!(
+            "{{\"name\":\"trigger\",\"color\":\"#ff0000ff\",\"data\":\"effect {}\",\"keys\":[{{\"frame\":0,\"bounds\":{}}}]}}",
+            effect, bounds_json(rect.x * scale, rect.y * scale, rect.width * scale, rect.height * scale)));
+    }
+    format!(
+        "{{\n  \"frames\":[\n{}\n  ],\n  \"meta\":{{\"app\":\"lemmings\",\"version\":\"1\",\"image\":{},\"format\":\"RGBA8888\",\"size\":{{\"w\":{},\"h\":{}}},\"scale\":\"{}\",\"fps\":{},\n    \"frameTags\":[{{\"name\":{},\"from\":0,\"to\":{},\"direction\":\"forward\"}}],\n    \"slices\":[{}]}}\n}}\n",
+        frames.join(",\n"),
+        json_string(image_name),
+        layout.width,
+        layout.height,
+        scale,
+        FPS,
+        json_string(&sheet.name),
+        layout.rects.len() - 1,
+        slices.join(","),
+    )
+}
@@ -0,0 +1,140 @@
+use bevy::prelude::*;
+use bevy::app::{AppExit, ScheduleRunnerPlugin, ScheduleRunnerSettings};
+use bevy::render::settings::WgpuSettings;
+use bevy::render::RenderPlugin;
+use bevy::window::ExitCondition;
+use bevy::winit::WinitPlugin;
+use std::io::{Error, ErrorKind, Result};
+use std::time::Duration;
+use crate::{GameState, FPS, set_scale};
+use crate::ingame::{InGameFixedFrameStep, InGameLemmingCounts, InGameReplayPlayback, InGameUpdateSet};
+use crate::level_preview::LevelSelectionResource;
+use crate::json::json_string;
+use crate::lemmings::loader;
+use crate::lemmings::models::Game;
+use crate::replay::Replay;
+use crate::scaler::ScalerKind;
+use crate::settings::Settings;
+
+// This is the 'verify <replay>' command: it plays a replay through the real game systems without a window, as fast
+// as it can, then prints how it went as JSON. Handy for checking that physics changes don't break known solutions.
+// Exits with 0 if enough lemmings were saved, 1 if not, 2 if it couldn't run at all.
+
+#[derive(Resource, Default)]
+struct VerifyResult {
+    saved: i32,
+    dead: i32,
+    remaining: i32,
+    frames: i32,
+    to_rescue: i32,
+    is_finished: bool,
+}
+
+#[derive(Resource)]
+struct VerifyFrameLimit(i32); // The level's time limit in game frames.
+
+pub fn run(replay_path: &str) -> i32 {
+    match run_replay(replay_path) {
+        Ok(passed) => if passed { 0 } else { 1 },
+        Err(e) => {
+            println!("{{\"error\":{}}}", json_string(&e.to_string()));
+            2
+        },
+    }
+}
+
+fn run_replay(replay_path: &str) -> Result<bool> {
+    let mut app = headless_app(Replay::load(replay_path)?)?;
+    app.run();
+    let game_id = app.world.resource::<Game>().id.clone();
+    let level_name = app.world.resource::<LevelSelectionResource>().level_name.clone();
+    let result = app.world.resource::<VerifyResult>();
+    let passed = result.is_finished && result.saved >= result.to_rescue;
+    println!("{{\"game\":{},\"level\":{},\"saved\":{},\"dead\":{},\"remaining\":{},\"frames\":{},\"to_rescue\":{},\"passed\":{}}}",
+        json_string(&game_id),
+        json_string(&level_name),
+        result.saved,
+        result.dead,
+        result.remaining,
+        result.frames,
+        result.to_rescue,
+        passed);
+    Ok(passed)
+}
+
+// The real game playing the replay without a window or GPU, as fast as it can, exiting when the level's over. Add
+// systems after InGameUpdateSet to see each frame, then app.run().
+pub fn headless_app(replay: Replay) -> Result<App> {
+    let game = game_for_replay(&replay)?;
+    let Some(level_key) = replay.level_key.or_else(|| game.index.level_key_named(&replay.level_name)) else {
+        return Err(Error::new(ErrorKind::NotFound, format!("No level named '{}'", replay.level_name)))
+    };
+    let level = game.index.level(level_key)?;
+    let frame_limit = level.globals.time_limit.max(1) as i32 * 60 * FPS as i32;
+    let to_rescue = level.globals.num_to_rescue as i32;
+
+    // Nothing gets drawn, so don't waste time upscaling.
+    let settings = Settings { scale: 1, terrain_scaler: ScalerKind::Nearest, sprite_scaler: ScalerKind::Nearest, menu_scaler: ScalerKind::Nearest, ..Settings::default() };
+    set_scale(settings.scale);
+
+    let mut app = App::new();
+    app.add_state::<GameState>()
+        .insert_resource(State(GameState::InGame)) // Straight in, no need to wait for the textures as nothing is drawn.
+        .insert_resource(game)
+        .insert_resource(settings)
+        .add_plugins(DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                ..default()
+            })
+            .set(RenderPlugin {
+                wgpu_settings: WgpuSettings { backends: None, ..default() }, // Don't need a GPU, nothing gets drawn.
+            })
+            .disable::<WinitPlugin>()
+            .disable::<bevy::audio::AudioPlugin>())
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::ZERO))
+        .add_plugin(ScheduleRunnerPlugin)
+        .add_plugin(crate::lemmings_to_bevy::load_lemmings_textures::LoadLemmingsTexturesPlugin)
+        .add_plugin(crate::fadeout::FadeoutPlugin)
+        .add_plugin(crate::level_preview::LevelPreviewPlugin)
+        .add_plugin(crate::ingame::InGamePlugin)
+        .add_plugin(crate::postview::PostviewPlugin)
+        .add_plugin(crate::mouse_cursor::MouseCursorPlugin)
+        .add_plugin(crate::screen::ScreenPlugin)
+        .insert_resource(LevelSelectionResource { skill: 0, level_key, level_name: replay.level_name.clone() })
+        .insert_resource(InGameReplayPlayback(Some(replay)))
+        .insert_resource(InGameFixedFrameStep)
+        .insert_resource(VerifyFrameLimit(frame_limit))
+        .insert_resource(VerifyResult { to_rescue, ..default() })
+        .add_system(watch_for_level_end.after(InGameUpdateSet).in_set(OnUpdate(GameState::InGame)));
+    Ok(app)
+}
+
+fn game_for_replay(replay: &Replay) -> Result<Game> {
+    let games = loader::load()?;
+    let game = if replay.game_id.is_empty() {
+        games.lemmings // Default to the original, as that's all that loads for now.
+    } else {
+        games.into_iter().find(|g| g.id == replay.game_id)
+    };
+    game.ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Game '{}' isn't installed", replay.game_id)))
+}
+
+// Stops once every lemming is saved or dead, or time runs out.
+fn watch_for_level_end(
+    counts: Res<InGameLemmingCounts>,
+    frame_limit: Res<VerifyFrameLimit>,
+    mut result: ResMut<VerifyResult>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if result.is_finished { return }
+    let is_all_accounted_for = counts.to_drop > 0 && counts.saved + counts.died >= counts.to_drop;
+    if !is_all_accounted_for && counts.frames < frame_limit.0 { return }
+    result.saved = counts.saved;
+    result.dead = counts.died;
+    result.remaining = counts.to_drop - counts.saved - counts.died;
+    result.frames = counts.frames;
+    result.is_finished = true;
+    exit.send(AppExit);
+}